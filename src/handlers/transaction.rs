@@ -3,9 +3,12 @@ use std::sync::Arc;
 
 use crate::{
     handlers::AppState,
+    models::card_date::CardDate,
+    models::emv::Tlv,
     models::transaction::{
         AttestationRequest, AttestationResponse, EmvDataForAttestation, TransactionRequest,
     },
+    services::{Field55Spec, IadDecoders},
 };
 
 /// Attest Transaction Handler
@@ -21,21 +24,58 @@ pub async fn attest_transaction(
         req.amount
     );
 
+    let amount = req.validate()?;
+
+    if let Some(tvr) = req.emv_data.tvr {
+        tracing::info!("TVR {}: {:?}", tvr, tvr.explain());
+    }
+
+    if let Some(iad) = &req.emv_data.issuer_application_data {
+        let decoded = hex::decode(&req.card_data.aid)
+            .and_then(|aid| hex::decode(iad).map(|iad| (aid, iad)))
+            .map_err(|e| e.to_string())
+            .and_then(|(aid, iad)| IadDecoders::default().decode(&aid, &iad));
+        match decoded {
+            Ok(decoded) => tracing::info!("IAD {}: {:?}", iad, decoded),
+            Err(e) => tracing::info!("IAD {} not decoded: {}", iad, e),
+        }
+    }
+
+    if req.card_data.expiry.is_expired_on(&CardDate::today()) {
+        return Err(format!("Card expired on {}", req.card_data.expiry));
+    }
+
+    let icc_data = match &req.emv_data.data_record {
+        Some(record) => {
+            let data = hex::decode(record)
+                .map_err(|e| format!("Invalid data record hex: {}", e))
+                .and_then(|data| Tlv::parse_flattened(&data))?;
+            let aid = hex::decode(&req.card_data.aid).map_err(|e| e.to_string())?;
+            Some(hex::encode_upper(
+                Field55Spec::for_aid(&aid).assemble(&data)?,
+            ))
+        }
+        None => None,
+    };
+
     // Build attestation request for backend
     let attestation_req = AttestationRequest {
         device_id: req.device_id.clone(),
         amount: req.amount,
-        currency_code: req.currency.clone(),
+        currency_code: amount.currency.alpha.to_string(),
         transaction_type: format!("{:?}", req.emv_data.transaction_type).to_lowercase(),
-        card_pan: req.card_data.pan.clone(),
-        card_expiry: req.card_data.expiry.clone(),
+        processing_code: req.emv_data.transaction_type.processing_code(),
+        card_pan: req.card_data.pan.masked(),
+        card_expiry: req.card_data.expiry.yymm(),
         track2_data: req.card_data.track2.clone(),
         emv_data: EmvDataForAttestation {
             aid: req.card_data.aid.clone(),
-            tvr: req.emv_data.tvr.clone(),
-            tsi: req.emv_data.tsi.clone(),
+            tvr: req.emv_data.tvr,
+            tsi: req.emv_data.tsi,
             cryptogram: req.emv_data.cryptogram.clone(),
             cid: req.emv_data.cid,
+            issuer_script_results: req.emv_data.issuer_script_results.clone(),
+            icc_data,
         },
         client_ip: None,
     };
@@ -57,23 +97,34 @@ pub async fn attest_transaction(
 /// Get Transaction Status Handler
 ///
 /// GET /api/transactions/:id/status
+///
+/// The backend owns transaction state; its status and history are passed on
+/// as reported, with any departures from the lifecycle rules listed.
 pub async fn get_transaction_status(
-    State(_state): State<Arc<AppState>>,
+    State(state): State<Arc<AppState>>,
     axum::extract::Path(transaction_id): axum::extract::Path<String>,
 ) -> Result<impl IntoResponse, String> {
     tracing::info!("Get transaction status: {}", transaction_id);
 
-    // TODO: Query backend for transaction status
-    #[derive(serde::Serialize)]
-    struct StatusResponse {
-        transaction_id: String,
-        status: String,
-    }
+    let backend_status = state
+        .backend_client
+        .get_transaction_status(&transaction_id)
+        .await?;
+    let lifecycle = backend_status.into_lifecycle()?;
 
-    let response = StatusResponse {
-        transaction_id,
-        status: "approved".to_string(),
-    };
+    tracing::info!(
+        "Transaction {} is {:?} since {:?}",
+        lifecycle.transaction_id,
+        lifecycle.status,
+        lifecycle.updated_at()
+    );
+    if !lifecycle.is_consistent() {
+        tracing::warn!(
+            "Transaction {} history is inconsistent: {:?}",
+            lifecycle.transaction_id,
+            lifecycle.inconsistencies
+        );
+    }
 
-    Ok((StatusCode::OK, Json(response)))
+    Ok((StatusCode::OK, Json(lifecycle)))
 }
@@ -0,0 +1,73 @@
+use serde::{Deserialize, Serialize};
+
+/// One entry of the Application File Locator (tag 0x94)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AflEntry {
+    /// Short File Identifier
+    pub sfi: u8,
+    pub first_record: u8,
+    pub last_record: u8,
+    /// Number of records involved in offline data authentication
+    pub oda_records: u8,
+}
+
+impl AflEntry {
+    /// Parse the AFL (4 bytes per entry)
+    pub fn parse_all(afl: &[u8]) -> Result<Vec<Self>, String> {
+        if afl.is_empty() || !afl.len().is_multiple_of(4) {
+            return Err(format!("Invalid AFL length: {}", afl.len()));
+        }
+
+        afl.chunks(4)
+            .map(|entry| {
+                let sfi = entry[0] >> 3;
+                if sfi == 0 || sfi > 30 || entry[1] == 0 || entry[2] < entry[1] {
+                    return Err(format!("Invalid AFL entry: {}", hex::encode_upper(entry)));
+                }
+                Ok(Self {
+                    sfi,
+                    first_record: entry[1],
+                    last_record: entry[2],
+                    oda_records: entry[3],
+                })
+            })
+            .collect()
+    }
+
+    /// (SFI, record number) pairs to read, in order
+    pub fn records(entries: &[Self]) -> Vec<(u8, u8)> {
+        entries
+            .iter()
+            .flat_map(|entry| (entry.first_record..=entry.last_record).map(|r| (entry.sfi, r)))
+            .collect()
+    }
+
+    /// Whether a record is part of the Static Data to be Authenticated
+    pub fn is_oda_record(entries: &[Self], sfi: u8, record: u8) -> bool {
+        entries.iter().any(|entry| {
+            entry.sfi == sfi
+                && record >= entry.first_record
+                && u16::from(record) < u16::from(entry.first_record) + u16::from(entry.oda_records)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_afl() {
+        let entries = AflEntry::parse_all(&hex::decode("0801010010010300").unwrap()).unwrap();
+        assert_eq!(entries[0].sfi, 1);
+        assert_eq!(entries[1].sfi, 2);
+        assert_eq!(
+            AflEntry::records(&entries),
+            vec![(1, 1), (2, 1), (2, 2), (2, 3)]
+        );
+        let oda = AflEntry::parse_all(&[0x08, 0x01, 0x02, 0x01]).unwrap();
+        assert!(AflEntry::is_oda_record(&oda, 1, 1));
+        assert!(!AflEntry::is_oda_record(&oda, 1, 2));
+        assert!(AflEntry::parse_all(&[0x08, 0x02, 0x01, 0x00]).is_err());
+    }
+}
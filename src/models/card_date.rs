@@ -0,0 +1,198 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Application Expiration Date (tag 0x5F24), Application Effective Date
+/// (tag 0x5F25) or Transaction Date (tag 0x9A), BCD `YYMMDD`
+///
+/// Two-digit years are windowed per EMV Book 4 Section 6.7.3: 00-49 is
+/// 2000-2049 and 50-99 is 1950-1999. Serialized as `YYMM`; a `YYMM` value
+/// read back means the last day of that month, as on a card's expiry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CardDate {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+}
+
+impl CardDate {
+    pub fn new(year: u16, month: u8, day: u8) -> Result<Self, String> {
+        if !(1..=12).contains(&month) || day == 0 || day > days_in_month(year, month) {
+            return Err(format!("Invalid date: {:04}-{:02}-{:02}", year, month, day));
+        }
+        Ok(Self { year, month, day })
+    }
+
+    /// Parse a 3-byte BCD `YYMMDD` value
+    pub fn from_bcd(bcd: &[u8]) -> Result<Self, String> {
+        let digits = hex::encode(bcd);
+        if bcd.len() != 3 || !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(format!("Invalid date: {}", digits.to_uppercase()));
+        }
+        Self::from_digits(&digits)
+    }
+
+    /// Parse `YYMMDD`, or `YYMM` meaning the last day of the month
+    pub fn from_digits(digits: &str) -> Result<Self, String> {
+        let number = |range: std::ops::Range<usize>| {
+            digits
+                .get(range)
+                .filter(|d| d.bytes().all(|b| b.is_ascii_digit()))
+                .and_then(|d| d.parse::<u8>().ok())
+                .ok_or_else(|| format!("Invalid date: {}", digits))
+        };
+        let year = window(number(0..2)?);
+        let month = number(2..4)?;
+        match digits.len() {
+            4 if (1..=12).contains(&month) => Self::new(year, month, days_in_month(year, month)),
+            6 => Self::new(year, month, number(4..6)?),
+            _ => Err(format!("Invalid date: {}", digits)),
+        }
+    }
+
+    /// Today's date in UTC
+    pub fn today() -> Self {
+        use chrono::Datelike;
+        let today = chrono::Utc::now().date_naive();
+        Self {
+            year: today.year() as u16,
+            month: today.month() as u8,
+            day: today.day() as u8,
+        }
+    }
+
+    pub fn to_bcd(&self) -> [u8; 3] {
+        let bcd = |n: u8| ((n / 10) << 4) | (n % 10);
+        [bcd((self.year % 100) as u8), bcd(self.month), bcd(self.day)]
+    }
+
+    pub fn yymm(&self) -> String {
+        format!("{:02}{:02}", self.year % 100, self.month)
+    }
+
+    /// An expiration date is passed once the transaction date is after it
+    pub fn is_expired_on(&self, transaction_date: &CardDate) -> bool {
+        transaction_date > self
+    }
+
+    /// An effective date is reached on the day itself
+    pub fn is_effective_on(&self, transaction_date: &CardDate) -> bool {
+        transaction_date >= self
+    }
+}
+
+impl std::fmt::Display for CardDate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+impl Serialize for CardDate {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.yymm())
+    }
+}
+
+impl<'de> Deserialize<'de> for CardDate {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        CardDate::from_digits(&value).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Serde for an optional Application Effective Date: written as `YYMMDD`,
+/// and a `YYMM` value read back means the first day of that month
+pub mod effective_date {
+    use super::CardDate;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        date: &Option<CardDate>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match date {
+            Some(date) => serializer.serialize_some(&hex::encode(date.to_bcd())),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<CardDate>, D::Error> {
+        Option::<String>::deserialize(deserializer)?
+            .map(|value| match value.len() {
+                4 => CardDate::from_digits(&format!("{}01", value)),
+                _ => CardDate::from_digits(&value),
+            })
+            .transpose()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+fn window(yy: u8) -> u16 {
+    if yy < 50 {
+        2000 + u16::from(yy)
+    } else {
+        1900 + u16::from(yy)
+    }
+}
+
+fn days_in_month(year: u16, month: u8) -> u8 {
+    match month {
+        4 | 6 | 9 | 11 => 30,
+        2 if year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400)) => {
+            29
+        }
+        2 => 28,
+        _ => 31,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_window() {
+        let expiry = CardDate::from_bcd(&[0x30, 0x12, 0x31]).unwrap();
+        assert_eq!(expiry, CardDate::new(2030, 12, 31).unwrap());
+        assert_eq!(expiry.to_bcd(), [0x30, 0x12, 0x31]);
+        assert_eq!(CardDate::from_bcd(&[0x99, 0x12, 0x31]).unwrap().year, 1999);
+
+        assert!(CardDate::from_bcd(&[0x30, 0x13, 0x01]).is_err());
+        assert!(CardDate::from_bcd(&[0x27, 0x02, 0x29]).is_err());
+        assert!(CardDate::from_bcd(&[0x28, 0x02, 0x29]).is_ok());
+        assert!(CardDate::from_bcd(&[0x30, 0x1A, 0x01]).is_err());
+    }
+
+    #[test]
+    fn test_expiry_comparison_and_serde() {
+        let expiry: CardDate = serde_json::from_str("\"2602\"").unwrap();
+        assert_eq!(expiry, CardDate::new(2026, 2, 28).unwrap());
+        assert_eq!(serde_json::to_string(&expiry).unwrap(), "\"2602\"");
+
+        assert!(!expiry.is_expired_on(&CardDate::new(2026, 2, 28).unwrap()));
+        assert!(expiry.is_expired_on(&CardDate::new(2026, 3, 1).unwrap()));
+        // 1999 is before 2001 even though 99 > 01
+        let old = CardDate::from_bcd(&[0x99, 0x12, 0x31]).unwrap();
+        assert!(old.is_expired_on(&CardDate::from_bcd(&[0x01, 0x01, 0x01]).unwrap()));
+
+        let effective = CardDate::from_digits("260301").unwrap();
+        assert!(!effective.is_effective_on(&CardDate::new(2026, 2, 28).unwrap()));
+        assert!(effective.is_effective_on(&CardDate::new(2026, 3, 1).unwrap()));
+
+        // An effective date keeps its day, and YYMM means the start of the month
+        #[derive(Serialize, Deserialize)]
+        struct Card {
+            #[serde(with = "effective_date")]
+            effective: Option<CardDate>,
+        }
+        let card = Card {
+            effective: Some(effective),
+        };
+        let json = serde_json::to_string(&card).unwrap();
+        assert_eq!(json, r#"{"effective":"260301"}"#);
+        let card: Card = serde_json::from_str(&json).unwrap();
+        assert_eq!(card.effective, Some(effective));
+        let card: Card = serde_json::from_str(r#"{"effective":"2603"}"#).unwrap();
+        assert_eq!(card.effective, Some(effective));
+    }
+}
@@ -0,0 +1,143 @@
+use serde::{Deserialize, Serialize};
+
+use super::emv::{CryptogramType, EmvTransactionData, Tlv};
+
+/// Reason/advice code in CID b3-b1
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CidReason {
+    NoInformation,
+    ServiceNotAllowed,
+    PinTryLimitExceeded,
+    IssuerAuthenticationFailed,
+    Rfu(u8),
+}
+
+/// Cryptogram Information Data (tag 0x9F27)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CryptogramInformationData(pub u8);
+
+impl CryptogramInformationData {
+    /// Type of cryptogram returned (b8-b7)
+    pub fn cryptogram_type(&self) -> Result<CryptogramType, String> {
+        CryptogramType::from_bits(self.0)
+    }
+
+    /// Payment system-specific cryptogram bits (b6-b5)
+    pub fn payment_system_bits(&self) -> u8 {
+        (self.0 >> 4) & 0x03
+    }
+
+    /// Advice required (b4)
+    pub fn advice_required(&self) -> bool {
+        self.0 & 0x08 != 0
+    }
+
+    /// Reason/advice code (b3-b1)
+    pub fn reason(&self) -> CidReason {
+        match self.0 & 0x07 {
+            0x00 => CidReason::NoInformation,
+            0x01 => CidReason::ServiceNotAllowed,
+            0x02 => CidReason::PinTryLimitExceeded,
+            0x03 => CidReason::IssuerAuthenticationFailed,
+            other => CidReason::Rfu(other),
+        }
+    }
+}
+
+/// Parsed GENERATE AC response (EMV Book 3 Section 6.5.5.4)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GenerateAcResponse {
+    /// Cryptogram Information Data (tag 0x9F27)
+    pub cid: CryptogramInformationData,
+    /// Application Transaction Counter (tag 0x9F36)
+    pub atc: u16,
+    /// Application Cryptogram (tag 0x9F26)
+    pub cryptogram: [u8; 8],
+    /// Issuer Application Data (tag 0x9F10)
+    pub issuer_application_data: Option<Vec<u8>>,
+    /// Signed Dynamic Application Data (tag 0x9F4B), format 2 with CDA only
+    pub signed_dynamic_data: Option<Vec<u8>>,
+}
+
+impl GenerateAcResponse {
+    /// Parse response data in format 1 (tag 0x80) or format 2 (tag 0x77)
+    pub fn parse(data: &[u8]) -> Result<Self, String> {
+        let tlvs = Tlv::parse(data)?;
+
+        if let Some(format1) = Tlv::find_by_tag(&tlvs, &[0x80]) {
+            return Self::parse_format1(&format1.value);
+        }
+
+        let template = Tlv::find_by_tag(&tlvs, &[0x77])
+            .ok_or("GENERATE AC response is neither format 1 nor format 2")?;
+        Self::parse_format2(&template.value)
+    }
+
+    /// Format 1: CID | ATC | AC | IAD (optional), concatenated without tags
+    fn parse_format1(value: &[u8]) -> Result<Self, String> {
+        if value.len() < 11 {
+            return Err(format!("Format 1 response too short: {}", value.len()));
+        }
+
+        let mut cryptogram = [0u8; 8];
+        cryptogram.copy_from_slice(&value[3..11]);
+
+        Ok(Self {
+            cid: CryptogramInformationData(value[0]),
+            atc: u16::from_be_bytes([value[1], value[2]]),
+            cryptogram,
+            issuer_application_data: (value.len() > 11).then(|| value[11..].to_vec()),
+            signed_dynamic_data: None,
+        })
+    }
+
+    /// Format 2: constructed template with TLV-coded data objects
+    fn parse_format2(value: &[u8]) -> Result<Self, String> {
+        let tlvs = Tlv::parse(value)?;
+
+        let cid = Tlv::find_by_tag(&tlvs, &[0x9F, 0x27])
+            .and_then(|tlv| tlv.value.first().copied())
+            .ok_or("Cryptogram Information Data not found")?;
+
+        let atc = Tlv::find_by_tag(&tlvs, &[0x9F, 0x36])
+            .and_then(|tlv| <[u8; 2]>::try_from(tlv.value.as_slice()).ok())
+            .ok_or("Application Transaction Counter not found")?;
+
+        let cryptogram = Tlv::find_by_tag(&tlvs, &[0x9F, 0x26])
+            .and_then(|tlv| <[u8; 8]>::try_from(tlv.value.as_slice()).ok())
+            .ok_or("Application Cryptogram not found")?;
+
+        Ok(Self {
+            cid: CryptogramInformationData(cid),
+            atc: u16::from_be_bytes(atc),
+            cryptogram,
+            issuer_application_data: Tlv::find_by_tag(&tlvs, &[0x9F, 0x10])
+                .map(|tlv| tlv.value.clone()),
+            signed_dynamic_data: Tlv::find_by_tag(&tlvs, &[0x9F, 0x4B])
+                .map(|tlv| tlv.value.clone()),
+        })
+    }
+
+    /// Check the card did not return a higher cryptogram than requested
+    /// (AAC < TC < ARQC)
+    pub fn validate_against(&self, requested: CryptogramType) -> Result<CryptogramType, String> {
+        let returned = self.cid.cryptogram_type()?;
+        if returned > requested {
+            return Err(format!(
+                "Card returned {:?} but {:?} was requested",
+                returned, requested
+            ));
+        }
+        Ok(returned)
+    }
+
+    /// Record the card's cryptogram in the transaction data
+    pub fn apply_to(&self, transaction: &mut EmvTransactionData) {
+        transaction.cryptogram = Some(hex::encode_upper(self.cryptogram));
+        transaction.cid = Some(self.cid.0);
+        transaction.atc = Some(self.atc);
+        transaction.issuer_application_data =
+            self.issuer_application_data.as_ref().map(hex::encode_upper);
+    }
+}
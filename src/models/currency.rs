@@ -0,0 +1,378 @@
+use serde::{Deserialize, Serialize};
+
+/// ISO 4217 currency
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+pub struct Currency {
+    pub alpha: &'static str,
+    pub numeric: u16,
+    /// Digits after the decimal point
+    pub minor_units: u8,
+}
+
+/// ISO 3166-1 country
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+pub struct Country {
+    pub alpha2: &'static str,
+    pub alpha3: &'static str,
+    pub numeric: u16,
+}
+
+const fn currency(alpha: &'static str, numeric: u16, minor_units: u8) -> Currency {
+    Currency {
+        alpha,
+        numeric,
+        minor_units,
+    }
+}
+
+const fn country(alpha2: &'static str, alpha3: &'static str, numeric: u16) -> Country {
+    Country {
+        alpha2,
+        alpha3,
+        numeric,
+    }
+}
+
+/// Currencies of the markets the terminal is deployed in
+pub const CURRENCIES: &[Currency] = &[
+    currency("AED", 784, 2),
+    currency("AUD", 36, 2),
+    currency("BHD", 48, 3),
+    currency("BRL", 986, 2),
+    currency("CAD", 124, 2),
+    currency("CHF", 756, 2),
+    currency("CLP", 152, 0),
+    currency("CNY", 156, 2),
+    currency("CZK", 203, 2),
+    currency("DKK", 208, 2),
+    currency("EUR", 978, 2),
+    currency("GBP", 826, 2),
+    currency("HKD", 344, 2),
+    currency("HUF", 348, 2),
+    currency("IDR", 360, 2),
+    currency("ILS", 376, 2),
+    currency("INR", 356, 2),
+    currency("ISK", 352, 0),
+    currency("JOD", 400, 3),
+    currency("JPY", 392, 0),
+    currency("KRW", 410, 0),
+    currency("KWD", 414, 3),
+    currency("MOP", 446, 2),
+    currency("MXN", 484, 2),
+    currency("MYR", 458, 2),
+    currency("NOK", 578, 2),
+    currency("NZD", 554, 2),
+    currency("OMR", 512, 3),
+    currency("PHP", 608, 2),
+    currency("PLN", 985, 2),
+    currency("QAR", 634, 2),
+    currency("RUB", 643, 2),
+    currency("SAR", 682, 2),
+    currency("SEK", 752, 2),
+    currency("SGD", 702, 2),
+    currency("THB", 764, 2),
+    currency("TRY", 949, 2),
+    currency("TWD", 901, 2),
+    currency("USD", 840, 2),
+    currency("VND", 704, 0),
+    currency("ZAR", 710, 2),
+];
+
+/// Countries of the markets the terminal is deployed in
+pub const COUNTRIES: &[Country] = &[
+    country("AE", "ARE", 784),
+    country("AT", "AUT", 40),
+    country("AU", "AUS", 36),
+    country("BE", "BEL", 56),
+    country("BH", "BHR", 48),
+    country("BR", "BRA", 76),
+    country("CA", "CAN", 124),
+    country("CH", "CHE", 756),
+    country("CL", "CHL", 152),
+    country("CN", "CHN", 156),
+    country("CZ", "CZE", 203),
+    country("DE", "DEU", 276),
+    country("DK", "DNK", 208),
+    country("ES", "ESP", 724),
+    country("FI", "FIN", 246),
+    country("FR", "FRA", 250),
+    country("GB", "GBR", 826),
+    country("GR", "GRC", 300),
+    country("HK", "HKG", 344),
+    country("HU", "HUN", 348),
+    country("ID", "IDN", 360),
+    country("IE", "IRL", 372),
+    country("IL", "ISR", 376),
+    country("IN", "IND", 356),
+    country("IS", "ISL", 352),
+    country("IT", "ITA", 380),
+    country("JO", "JOR", 400),
+    country("JP", "JPN", 392),
+    country("KR", "KOR", 410),
+    country("KW", "KWT", 414),
+    country("MO", "MAC", 446),
+    country("MX", "MEX", 484),
+    country("MY", "MYS", 458),
+    country("NL", "NLD", 528),
+    country("NO", "NOR", 578),
+    country("NZ", "NZL", 554),
+    country("OM", "OMN", 512),
+    country("PH", "PHL", 608),
+    country("PL", "POL", 616),
+    country("PT", "PRT", 620),
+    country("QA", "QAT", 634),
+    country("RU", "RUS", 643),
+    country("SA", "SAU", 682),
+    country("SE", "SWE", 752),
+    country("SG", "SGP", 702),
+    country("TH", "THA", 764),
+    country("TR", "TUR", 792),
+    country("TW", "TWN", 158),
+    country("US", "USA", 840),
+    country("VN", "VNM", 704),
+    country("ZA", "ZAF", 710),
+];
+
+/// Largest value of an n12 amount (tags 0x9F02 and 0x9F03)
+pub const MAX_AMOUNT: u64 = 999_999_999_999;
+
+/// Numeric (n, BCD) encoding of `value` on `len` bytes, e.g. n12 amounts
+pub(crate) fn u64_to_bcd(value: u64, len: usize) -> Vec<u8> {
+    let digits = format!("{:0width$}", value, width = len * 2);
+    let digits = &digits.as_bytes()[digits.len() - len * 2..];
+    digits
+        .chunks(2)
+        .map(|pair| ((pair[0] - b'0') << 4) | (pair[1] - b'0'))
+        .collect()
+}
+
+/// Numeric code as n3 in two BCD bytes, e.g. 156 -> `01 56`
+fn numeric_to_bcd(numeric: u16) -> [u8; 2] {
+    let bytes = u64_to_bcd(u64::from(numeric), 2);
+    [bytes[0], bytes[1]]
+}
+
+fn numeric_from_bcd(bcd: &[u8]) -> Option<u16> {
+    let digits = hex::encode(bcd);
+    if bcd.len() != 2 || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    digits.parse().ok()
+}
+
+impl Currency {
+    /// Look up by alpha code (`"CNY"`) or numeric code (`"156"`, `"0156"`)
+    pub fn find(code: &str) -> Option<&'static Currency> {
+        let code = code.trim();
+        match code.parse::<u16>() {
+            Ok(numeric) => Self::from_numeric(numeric),
+            Err(_) => CURRENCIES
+                .iter()
+                .find(|c| c.alpha.eq_ignore_ascii_case(code)),
+        }
+    }
+
+    pub fn from_numeric(numeric: u16) -> Option<&'static Currency> {
+        CURRENCIES.iter().find(|c| c.numeric == numeric)
+    }
+
+    /// Transaction Currency Code (tag 0x5F2A) or Application Currency Code
+    /// (tag 0x9F42) value
+    pub fn from_bcd(bcd: &[u8]) -> Option<&'static Currency> {
+        numeric_from_bcd(bcd).and_then(Self::from_numeric)
+    }
+
+    pub fn to_bcd(&self) -> [u8; 2] {
+        numeric_to_bcd(self.numeric)
+    }
+
+    /// Three-digit numeric code, as used in ISO 8583 messages
+    pub fn numeric_code(&self) -> String {
+        format!("{:03}", self.numeric)
+    }
+
+    /// Transaction Currency Exponent (tag 0x5F36)
+    pub fn exponent(&self) -> u8 {
+        self.minor_units
+    }
+}
+
+impl Country {
+    /// Look up by alpha-2 (`"CN"`), alpha-3 (`"CHN"`) or numeric (`"156"`)
+    pub fn find(code: &str) -> Option<&'static Country> {
+        let code = code.trim();
+        match code.parse::<u16>() {
+            Ok(numeric) => Self::from_numeric(numeric),
+            Err(_) => COUNTRIES.iter().find(|c| {
+                c.alpha2.eq_ignore_ascii_case(code) || c.alpha3.eq_ignore_ascii_case(code)
+            }),
+        }
+    }
+
+    pub fn from_numeric(numeric: u16) -> Option<&'static Country> {
+        COUNTRIES.iter().find(|c| c.numeric == numeric)
+    }
+
+    /// Terminal Country Code (tag 0x9F1A) or Issuer Country Code (0x5F28)
+    pub fn from_bcd(bcd: &[u8]) -> Option<&'static Country> {
+        numeric_from_bcd(bcd).and_then(Self::from_numeric)
+    }
+
+    pub fn to_bcd(&self) -> [u8; 2] {
+        numeric_to_bcd(self.numeric)
+    }
+
+    pub fn numeric_code(&self) -> String {
+        format!("{:03}", self.numeric)
+    }
+}
+
+/// Deserialized from any code [`Currency::find`] accepts
+impl<'de> Deserialize<'de> for &'static Currency {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let code = String::deserialize(deserializer)?;
+        Currency::find(&code)
+            .ok_or_else(|| serde::de::Error::custom(format!("Unknown currency: {}", code)))
+    }
+}
+
+/// Amount in minor units of a currency
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Amount {
+    pub value: u64,
+    pub currency: &'static Currency,
+}
+
+impl Amount {
+    pub fn new(value: u64, currency: &'static Currency) -> Result<Self, String> {
+        if value > MAX_AMOUNT {
+            return Err(format!("Amount {} exceeds 12 digits", value));
+        }
+        Ok(Self { value, currency })
+    }
+
+    /// Parse a decimal amount such as `"12.50"`, with no more decimals than
+    /// the currency has
+    pub fn parse(amount: &str, currency: &'static Currency) -> Result<Self, String> {
+        let (major, minor) = amount.trim().split_once('.').unwrap_or((amount.trim(), ""));
+        let decimals = usize::from(currency.minor_units);
+        let numeric = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+        if !numeric(major) || (!minor.is_empty() && !numeric(minor)) || minor.len() > decimals {
+            return Err(format!("Invalid {} amount: {}", currency.alpha, amount));
+        }
+        let value = format!("{}{:0<width$}", major, minor, width = decimals)
+            .parse::<u64>()
+            .map_err(|_| format!("Invalid {} amount: {}", currency.alpha, amount))?;
+        Self::new(value, currency)
+    }
+
+    /// Amount, Authorised (tag 0x9F02) or Amount, Other (tag 0x9F03), n12
+    pub fn to_n12(&self) -> [u8; 6] {
+        let mut bytes = [0u8; 6];
+        bytes.copy_from_slice(&u64_to_bcd(self.value, 6));
+        bytes
+    }
+
+    pub fn from_n12(bcd: &[u8], currency: &'static Currency) -> Result<Self, String> {
+        let digits = hex::encode(bcd);
+        if bcd.len() != 6 || !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(format!("Invalid n12 amount: {}", digits.to_uppercase()));
+        }
+        Self::new(digits.parse().unwrap_or(0), currency)
+    }
+
+    /// Plain decimal amount with the currency's minor units, e.g. `"1234.50"`
+    pub fn to_decimal(&self) -> String {
+        let decimals = usize::from(self.currency.minor_units);
+        if decimals == 0 {
+            return self.value.to_string();
+        }
+        let divisor = 10u64.pow(u32::from(self.currency.minor_units));
+        format!(
+            "{}.{:0width$}",
+            self.value / divisor,
+            self.value % divisor,
+            width = decimals
+        )
+    }
+
+    /// Display the amount for a locale such as `"en-US"`, `"de-DE"`,
+    /// `"de-CH"`, `"fr-FR"` or `"zh-CN"`
+    pub fn format(&self, locale: &str) -> String {
+        let mut subtags = locale.split(['-', '_']);
+        let language = subtags.next().unwrap_or("en").to_ascii_lowercase();
+        let region = subtags.next_back().unwrap_or_default().to_ascii_uppercase();
+        let (group, decimal, code_first) = match (language.as_str(), region.as_str()) {
+            // Switzerland and Liechtenstein group with apostrophes
+            (_, "CH" | "LI") => ("'", ".", true),
+            ("de" | "es" | "it" | "nl" | "pt" | "tr" | "id" | "da", _) => (".", ",", false),
+            ("fr" | "ru" | "pl" | "cs" | "sv" | "fi" | "nb" | "no", _) => ("\u{a0}", ",", false),
+            _ => (",", ".", true),
+        };
+
+        let divisor = 10u64.pow(u32::from(self.currency.minor_units));
+        let major = (self.value / divisor).to_string();
+        let mut grouped = String::new();
+        for (i, digit) in major.chars().enumerate() {
+            if i > 0 && (major.len() - i).is_multiple_of(3) {
+                grouped.push_str(group);
+            }
+            grouped.push(digit);
+        }
+        if self.currency.minor_units > 0 {
+            grouped.push_str(decimal);
+            grouped.push_str(&format!(
+                "{:0width$}",
+                self.value % divisor,
+                width = usize::from(self.currency.minor_units)
+            ));
+        }
+
+        if code_first {
+            format!("{} {}", self.currency.alpha, grouped)
+        } else {
+            format!("{} {}", grouped, self.currency.alpha)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lookup_and_bcd() {
+        let cny = Currency::find("CNY").unwrap();
+        assert_eq!(Currency::find("0156"), Some(cny));
+        assert_eq!(cny.to_bcd(), [0x01, 0x56]);
+        assert_eq!(Currency::from_bcd(&[0x09, 0x78]).unwrap().alpha, "EUR");
+        assert!(Currency::find("XXX").is_none());
+
+        let china = Country::find("CHN").unwrap();
+        assert_eq!(Country::find("cn"), Some(china));
+        assert_eq!(china.to_bcd(), [0x01, 0x56]);
+        assert_eq!(Country::from_bcd(&[0x08, 0x40]).unwrap().alpha2, "US");
+    }
+
+    #[test]
+    fn test_amounts() {
+        let eur = Currency::find("978").unwrap();
+        let amount = Amount::parse("1234567.8", eur).unwrap();
+        assert_eq!(amount.value, 123_456_780);
+        assert_eq!(amount.to_n12(), [0x00, 0x01, 0x23, 0x45, 0x67, 0x80]);
+        assert_eq!(Amount::from_n12(&amount.to_n12(), eur).unwrap(), amount);
+        assert_eq!(amount.format("en-US"), "EUR 1,234,567.80");
+        assert_eq!(amount.format("de-DE"), "1.234.567,80 EUR");
+        assert_eq!(amount.format("de-CH"), "EUR 1'234'567.80");
+        assert_eq!(amount.format("fr_CH"), "EUR 1'234'567.80");
+        assert_eq!(amount.to_decimal(), "1234567.80");
+
+        let jpy = Currency::find("JPY").unwrap();
+        assert!(Amount::parse("100.5", jpy).is_err());
+        assert_eq!(
+            Amount::parse("1500", jpy).unwrap().format("ja-JP"),
+            "JPY 1,500"
+        );
+        assert!(Amount::new(MAX_AMOUNT + 1, jpy).is_err());
+    }
+}
@@ -0,0 +1,262 @@
+use serde::{Deserialize, Serialize};
+
+/// Cardholder Verification Method (CVM code, EMV Book 3 Annex C3)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CvMethod {
+    /// Fail CVM processing
+    Fail,
+    /// Plaintext PIN verification performed by ICC
+    OfflinePlaintextPin,
+    /// Enciphered PIN verified online
+    OnlinePin,
+    /// Plaintext PIN verification performed by ICC and signature
+    OfflinePlaintextPinAndSignature,
+    /// Enciphered PIN verification performed by ICC
+    OfflineEncipheredPin,
+    /// Enciphered PIN verification performed by ICC and signature
+    OfflineEncipheredPinAndSignature,
+    /// Signature (paper)
+    Signature,
+    /// No CVM required
+    NoCvm,
+    /// Consumer Device CVM, performed on the cardholder's device
+    Cdcvm,
+    /// Code not recognised by this terminal
+    Unrecognised(u8),
+}
+
+impl CvMethod {
+    /// Decode from the low six bits of CVM code byte
+    pub fn from_code(code: u8) -> Self {
+        match code & 0x3F {
+            0x00 => CvMethod::Fail,
+            0x01 => CvMethod::OfflinePlaintextPin,
+            0x02 => CvMethod::OnlinePin,
+            0x03 => CvMethod::OfflinePlaintextPinAndSignature,
+            0x04 => CvMethod::OfflineEncipheredPin,
+            0x05 => CvMethod::OfflineEncipheredPinAndSignature,
+            0x1E => CvMethod::Signature,
+            0x1F => CvMethod::NoCvm,
+            other => CvMethod::Unrecognised(other),
+        }
+    }
+
+    /// CVM code as written to CVM Results (byte 1, b6-b1)
+    pub fn code(&self) -> u8 {
+        match self {
+            CvMethod::Fail => 0x00,
+            CvMethod::OfflinePlaintextPin => 0x01,
+            CvMethod::OnlinePin => 0x02,
+            CvMethod::OfflinePlaintextPinAndSignature => 0x03,
+            CvMethod::OfflineEncipheredPin => 0x04,
+            CvMethod::OfflineEncipheredPinAndSignature => 0x05,
+            CvMethod::Signature => 0x1E,
+            // CDCVM is reported in CVM Results as "No CVM required"
+            CvMethod::NoCvm | CvMethod::Cdcvm => 0x1F,
+            CvMethod::Unrecognised(code) => *code,
+        }
+    }
+
+    /// Whether the method needs PIN entry on the terminal
+    pub fn requires_pin(&self) -> bool {
+        matches!(
+            self,
+            CvMethod::OfflinePlaintextPin
+                | CvMethod::OnlinePin
+                | CvMethod::OfflinePlaintextPinAndSignature
+                | CvMethod::OfflineEncipheredPin
+                | CvMethod::OfflineEncipheredPinAndSignature
+        )
+    }
+
+    /// Whether the PIN is verified offline by the card
+    pub fn is_offline_pin(&self) -> bool {
+        self.requires_pin() && *self != CvMethod::OnlinePin
+    }
+
+    /// Whether the method needs a signature on the receipt
+    pub fn requires_signature(&self) -> bool {
+        matches!(
+            self,
+            CvMethod::Signature
+                | CvMethod::OfflinePlaintextPinAndSignature
+                | CvMethod::OfflineEncipheredPinAndSignature
+        )
+    }
+}
+
+/// CVM Condition Code (EMV Book 3 Annex C3)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CvmCondition {
+    Always,
+    UnattendedCash,
+    NotUnattendedCashNotManualCashNotCashback,
+    TerminalSupportsCvm,
+    ManualCash,
+    PurchaseWithCashback,
+    UnderX,
+    OverX,
+    UnderY,
+    OverY,
+    /// Condition code not recognised by this terminal
+    Unrecognised(u8),
+}
+
+impl CvmCondition {
+    pub fn from_code(code: u8) -> Self {
+        match code {
+            0x00 => CvmCondition::Always,
+            0x01 => CvmCondition::UnattendedCash,
+            0x02 => CvmCondition::NotUnattendedCashNotManualCashNotCashback,
+            0x03 => CvmCondition::TerminalSupportsCvm,
+            0x04 => CvmCondition::ManualCash,
+            0x05 => CvmCondition::PurchaseWithCashback,
+            0x06 => CvmCondition::UnderX,
+            0x07 => CvmCondition::OverX,
+            0x08 => CvmCondition::UnderY,
+            0x09 => CvmCondition::OverY,
+            other => CvmCondition::Unrecognised(other),
+        }
+    }
+
+    pub fn code(&self) -> u8 {
+        match self {
+            CvmCondition::Always => 0x00,
+            CvmCondition::UnattendedCash => 0x01,
+            CvmCondition::NotUnattendedCashNotManualCashNotCashback => 0x02,
+            CvmCondition::TerminalSupportsCvm => 0x03,
+            CvmCondition::ManualCash => 0x04,
+            CvmCondition::PurchaseWithCashback => 0x05,
+            CvmCondition::UnderX => 0x06,
+            CvmCondition::OverX => 0x07,
+            CvmCondition::UnderY => 0x08,
+            CvmCondition::OverY => 0x09,
+            CvmCondition::Unrecognised(code) => *code,
+        }
+    }
+}
+
+/// A single Cardholder Verification Rule
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CvmRule {
+    /// Cardholder verification method
+    pub method: CvMethod,
+    /// Apply succeeding CV Rule if this CVM is unsuccessful (byte 1 b7)
+    pub apply_next_if_unsuccessful: bool,
+    /// Condition under which the rule applies
+    pub condition: CvmCondition,
+}
+
+impl CvmRule {
+    /// Parse a two-byte CV Rule
+    pub fn from_bytes(method_byte: u8, condition_byte: u8) -> Self {
+        Self {
+            method: CvMethod::from_code(method_byte),
+            apply_next_if_unsuccessful: method_byte & 0x40 != 0,
+            condition: CvmCondition::from_code(condition_byte),
+        }
+    }
+
+    /// CVM code byte as it appeared in the list
+    pub fn method_byte(&self) -> u8 {
+        let next = if self.apply_next_if_unsuccessful {
+            0x40
+        } else {
+            0x00
+        };
+        next | self.method.code()
+    }
+}
+
+/// Cardholder Verification Method List (tag 0x8E)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CvmList {
+    /// Amount X, in application currency minor units
+    pub amount_x: u32,
+    /// Amount Y, in application currency minor units
+    pub amount_y: u32,
+    /// CV Rules in priority order
+    pub rules: Vec<CvmRule>,
+}
+
+impl CvmList {
+    /// Parse CVM List value (8 bytes of amounts followed by 2-byte rules)
+    pub fn parse(data: &[u8]) -> Result<Self, String> {
+        if data.len() < 8 || !(data.len() - 8).is_multiple_of(2) {
+            return Err(format!("Invalid CVM list length: {}", data.len()));
+        }
+
+        let amount_x = u32::from_be_bytes([data[0], data[1], data[2], data[3]]);
+        let amount_y = u32::from_be_bytes([data[4], data[5], data[6], data[7]]);
+        let rules = data[8..]
+            .chunks(2)
+            .map(|rule| CvmRule::from_bytes(rule[0], rule[1]))
+            .collect();
+
+        Ok(Self {
+            amount_x,
+            amount_y,
+            rules,
+        })
+    }
+}
+
+/// Result byte of CVM Results
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CvmResult {
+    Unknown,
+    Failed,
+    Successful,
+}
+
+impl CvmResult {
+    pub fn code(&self) -> u8 {
+        match self {
+            CvmResult::Unknown => 0x00,
+            CvmResult::Failed => 0x01,
+            CvmResult::Successful => 0x02,
+        }
+    }
+}
+
+/// Cardholder Verification Method Results (tag 0x9F34)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CvmResults {
+    /// CVM performed (byte 1), 0x3F when no CVM was performed
+    pub method_code: u8,
+    /// CVM condition (byte 2)
+    pub condition_code: u8,
+    /// CVM result (byte 3)
+    pub result: CvmResult,
+}
+
+impl CvmResults {
+    /// "No CVM performed"
+    pub fn not_performed(result: CvmResult) -> Self {
+        Self {
+            method_code: 0x3F,
+            condition_code: 0x00,
+            result,
+        }
+    }
+
+    /// Results for a rule that was attempted
+    pub fn for_rule(rule: &CvmRule, result: CvmResult) -> Self {
+        Self {
+            method_code: rule.method_byte(),
+            condition_code: rule.condition.code(),
+            result,
+        }
+    }
+
+    pub fn to_bytes(&self) -> [u8; 3] {
+        [self.method_code, self.condition_code, self.result.code()]
+    }
+
+    pub fn to_hex(&self) -> String {
+        hex::encode_upper(self.to_bytes())
+    }
+}
@@ -0,0 +1,136 @@
+use serde::{Deserialize, Serialize};
+
+use super::emv::Tlv;
+
+/// Tags with numeric (n) format, right-justified and zero-padded on the left
+const NUMERIC_TAGS: &[&[u8]] = &[
+    &[0x5F, 0x24],
+    &[0x5F, 0x25],
+    &[0x5F, 0x2A],
+    &[0x5F, 0x36],
+    &[0x9A],
+    &[0x9C],
+    &[0x9F, 0x02],
+    &[0x9F, 0x03],
+    &[0x9F, 0x1A],
+    &[0x9F, 0x21],
+    &[0x9F, 0x41],
+    &[0x9F, 0x42],
+];
+
+/// One entry of a Data Object List: tag and expected length
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DolEntry {
+    pub tag: Vec<u8>,
+    pub length: usize,
+}
+
+/// Data Object List (PDOL 0x9F38, CDOL1 0x8C, CDOL2 0x8D, DDOL 0x9F49, TDOL 0x97)
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Dol {
+    pub entries: Vec<DolEntry>,
+}
+
+impl Dol {
+    /// Parse a DOL (concatenated tag/length pairs, no values)
+    pub fn parse(data: &[u8]) -> Result<Self, String> {
+        let mut entries = Vec::new();
+        let mut pos = 0;
+
+        while pos < data.len() {
+            let tag_start = pos;
+            let first_byte = data[pos];
+            pos += 1;
+
+            if (first_byte & 0x1F) == 0x1F {
+                while pos < data.len() && (data[pos] & 0x80) == 0x80 {
+                    pos += 1;
+                }
+                pos += 1;
+            }
+
+            if pos >= data.len() {
+                return Err("Invalid DOL: missing length".to_string());
+            }
+
+            let tag = data[tag_start..pos].to_vec();
+            let length = data[pos] as usize;
+            pos += 1;
+
+            entries.push(DolEntry { tag, length });
+        }
+
+        Ok(Self { entries })
+    }
+
+    /// Whether the DOL requests a tag
+    pub fn contains(&self, tag: &[u8]) -> bool {
+        self.entries.iter().any(|entry| entry.tag == tag)
+    }
+
+    /// Total length of the DOL-related data
+    pub fn data_length(&self) -> usize {
+        self.entries.iter().map(|entry| entry.length).sum()
+    }
+
+    /// Build DOL-related data from available data objects (EMV Book 3 Section 5.4)
+    ///
+    /// Missing data objects are filled with zeros; values of the wrong
+    /// length are padded or truncated according to their format.
+    pub fn build(&self, data: &[Tlv]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(self.data_length());
+
+        for entry in &self.entries {
+            let value = Tlv::find_by_tag(data, &entry.tag).map(|tlv| tlv.value.as_slice());
+            out.extend(fit_value(&entry.tag, value.unwrap_or(&[]), entry.length));
+        }
+
+        out
+    }
+}
+
+fn fit_value(tag: &[u8], value: &[u8], length: usize) -> Vec<u8> {
+    let numeric = NUMERIC_TAGS.contains(&tag);
+
+    if value.len() == length {
+        return value.to_vec();
+    }
+
+    if value.len() > length {
+        return if numeric {
+            value[value.len() - length..].to_vec()
+        } else {
+            value[..length].to_vec()
+        };
+    }
+
+    let padding = vec![0u8; length - value.len()];
+    if numeric {
+        [padding.as_slice(), value].concat()
+    } else {
+        [value, padding.as_slice()].concat()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_build() {
+        // Amount, TVR, Terminal Country Code, Unpredictable Number
+        let dol = Dol::parse(&hex::decode("9F020695059F1A029F3704").unwrap()).unwrap();
+        assert_eq!(dol.entries.len(), 4);
+        assert_eq!(dol.data_length(), 17);
+
+        let data = vec![
+            Tlv::new(&[0x9F, 0x02], vec![0x10, 0x00]),
+            Tlv::new(&[0x95], vec![0x80, 0x00, 0x00, 0x00, 0x00]),
+            Tlv::new(&[0x9F, 0x1A], vec![0x01, 0x56]),
+        ];
+        assert_eq!(
+            hex::encode_upper(dol.build(&data)),
+            "0000000010008000000000015600000000"
+        );
+    }
+}
@@ -1,7 +1,17 @@
-use serde::{Deserialize, Serialize};
+use serde::ser::{SerializeSeq, SerializeStruct};
+use serde::{Deserialize, Serialize, Serializer};
+use zeroize::Zeroize;
+
+use super::card_date::CardDate;
+use super::pan::Pan;
+use super::secret::{expose, SecretString};
+use super::tvr::{Tsi, Tvr};
 
 /// APDU Command structure (ISO 7816-4)
-#[derive(Debug, Clone, Serialize, Deserialize)]
+///
+/// Command data may hold a PIN block; it is zeroized on drop and left out of
+/// `Debug` output.
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct ApduCommand {
     /// Class byte
     pub cla: u8,
@@ -42,6 +52,32 @@ impl ApduCommand {
         self
     }
 
+    /// Parse a command APDU (case 1, 2, 3 or 4, short length)
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        if bytes.len() < 4 {
+            return Err("Command too short".to_string());
+        }
+
+        let mut command = Self::new(bytes[0], bytes[1], bytes[2], bytes[3]);
+        match bytes.len() {
+            4 => {}
+            5 => command.le = Some(bytes[4]),
+            len => {
+                let lc = bytes[4] as usize;
+                if len == 5 + lc {
+                    command.data = Some(bytes[5..].to_vec());
+                } else if len == 6 + lc {
+                    command.data = Some(bytes[5..5 + lc].to_vec());
+                    command.le = Some(bytes[5 + lc]);
+                } else {
+                    return Err(format!("Invalid command length: Lc={}, total={}", lc, len));
+                }
+            }
+        }
+
+        Ok(command)
+    }
+
     /// Convert to bytes for transmission
     pub fn to_bytes(&self) -> Vec<u8> {
         let mut bytes = vec![self.cla, self.ins, self.p1, self.p2];
@@ -60,7 +96,10 @@ impl ApduCommand {
 }
 
 /// APDU Response structure
-#[derive(Debug, Clone, Serialize, Deserialize)]
+///
+/// Response data may hold cardholder data; it is zeroized on drop and left
+/// out of `Debug` output.
+#[derive(Clone, Serialize, Deserialize)]
 pub struct ApduResponse {
     /// Response data
     pub data: Vec<u8>,
@@ -99,14 +138,20 @@ impl ApduResponse {
 /// EMV Card Data
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CardData {
-    /// Primary Account Number (masked)
-    pub pan: String,
-    /// Card expiry date (YYMM)
-    pub expiry: String,
+    /// Primary Account Number, masked when serialized
+    pub pan: Pan,
+    /// Application Expiration Date (tag 0x5F24), serialized as YYMM
+    pub expiry: CardDate,
+    /// Application Effective Date (tag 0x5F25), if the card has one,
+    /// serialized as YYMMDD
+    #[serde(default, with = "super::card_date::effective_date")]
+    pub effective_date: Option<CardDate>,
     /// Cardholder name (optional)
-    pub cardholder_name: Option<String>,
-    /// Track 2 equivalent data
-    pub track2: Option<String>,
+    #[serde(serialize_with = "expose::serialize")]
+    pub cardholder_name: Option<SecretString>,
+    /// Track 2 equivalent data, hex
+    #[serde(serialize_with = "expose::serialize")]
+    pub track2: Option<SecretString>,
     /// Application ID (AID)
     pub aid: String,
     /// Application label
@@ -123,33 +168,159 @@ pub struct EmvTransactionData {
     /// Transaction type
     pub transaction_type: TransactionType,
     /// Terminal Verification Results
-    pub tvr: Option<String>,
+    pub tvr: Option<Tvr>,
     /// Transaction Status Information
-    pub tsi: Option<String>,
+    pub tsi: Option<Tsi>,
     /// Application Cryptogram
     pub cryptogram: Option<String>,
     /// Cryptogram Information Data
     pub cid: Option<u8>,
+    /// Application Transaction Counter
+    pub atc: Option<u16>,
+    /// Issuer Application Data
+    pub issuer_application_data: Option<String>,
+    /// Issuer Script Results (tag 0x9F5B), hex
+    pub issuer_script_results: Option<String>,
+    /// Kernel data record as BER-TLV, hex; the source of field 55
+    pub data_record: Option<String>,
 }
 
 /// Transaction Type
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum TransactionType {
     Purchase,
+    /// Purchase with cashback; the cashback is Amount, Other (0x9F03) and is
+    /// included in Amount, Authorised (0x9F02)
+    PurchaseWithCashback,
+    /// Cash at an ATM
     Withdrawal,
     Refund,
+    /// Manual cash at an attended terminal
+    #[serde(alias = "manualcash")]
     CashAdvance,
+    /// Authorisation of an estimated amount, completed later
+    PreAuthorization,
+    /// Completion of a pre-authorisation with the final amount
+    Completion,
+    /// Cancellation of an earlier transaction
+    Void,
+    BalanceInquiry,
+}
+
+impl TransactionType {
+    /// Transaction Type (tag 0x9C)
+    ///
+    /// Pre-authorisations and completions are purchases to the card; the
+    /// message type tells them apart.
+    pub fn code(&self) -> u8 {
+        match self {
+            TransactionType::Purchase
+            | TransactionType::PreAuthorization
+            | TransactionType::Completion => 0x00,
+            TransactionType::Withdrawal => 0x01,
+            TransactionType::Void => 0x02,
+            TransactionType::PurchaseWithCashback => 0x09,
+            TransactionType::CashAdvance => 0x17,
+            TransactionType::Refund => 0x20,
+            TransactionType::BalanceInquiry => 0x31,
+        }
+    }
+
+    /// Decode a Transaction Type (tag 0x9C); `0x00` is read as a purchase
+    pub fn from_code(code: u8) -> Option<Self> {
+        match code {
+            0x00 => Some(TransactionType::Purchase),
+            0x01 => Some(TransactionType::Withdrawal),
+            0x02 => Some(TransactionType::Void),
+            0x09 => Some(TransactionType::PurchaseWithCashback),
+            0x17 => Some(TransactionType::CashAdvance),
+            0x20 => Some(TransactionType::Refund),
+            0x30 | 0x31 => Some(TransactionType::BalanceInquiry),
+            _ => None,
+        }
+    }
+
+    /// ISO 8583 processing code (field 3): the Transaction Type (0x9C),
+    /// then from and to account types, both default (`00`)
+    pub fn processing_code(&self) -> String {
+        format!("{:02X}0000", self.code())
+    }
+
+    /// Cash dispensed, at an ATM or over the counter
+    pub fn is_cash(&self) -> bool {
+        matches!(
+            self,
+            TransactionType::Withdrawal | TransactionType::CashAdvance
+        )
+    }
+
+    /// Goods or services are paid for
+    pub fn is_purchase(&self) -> bool {
+        matches!(
+            self,
+            TransactionType::Purchase
+                | TransactionType::PurchaseWithCashback
+                | TransactionType::PreAuthorization
+                | TransactionType::Completion
+        )
+    }
+}
+
+/// Application Cryptogram type requested in / returned by GENERATE AC
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum CryptogramType {
+    /// Application Authentication Cryptogram (decline)
+    Aac,
+    /// Transaction Certificate (approve offline)
+    Tc,
+    /// Authorisation Request Cryptogram (go online)
+    Arqc,
+}
+
+impl CryptogramType {
+    /// Reference control parameter for GENERATE AC P1 (b8-b7)
+    pub fn p1(&self) -> u8 {
+        match self {
+            CryptogramType::Aac => 0x00,
+            CryptogramType::Tc => 0x40,
+            CryptogramType::Arqc => 0x80,
+        }
+    }
+
+    /// Decode from GENERATE AC P1 or CID (b8-b7)
+    pub fn from_bits(value: u8) -> Result<Self, String> {
+        match value & 0xC0 {
+            0x00 => Ok(CryptogramType::Aac),
+            0x40 => Ok(CryptogramType::Tc),
+            0x80 => Ok(CryptogramType::Arqc),
+            _ => Err(format!("Invalid cryptogram type: {:02X}", value)),
+        }
+    }
 }
 
 /// TLV (Tag-Length-Value) structure
-#[derive(Debug, Clone)]
+///
+/// `Debug` output and serialization leave out the values of cardholder data
+/// objects; a list of TLVs opts in to its values with
+/// `#[serde(serialize_with = "expose::serialize")]`.
+#[derive(Clone, PartialEq, Eq, Deserialize)]
 pub struct Tlv {
     pub tag: Vec<u8>,
+    #[serde(default)]
     pub value: Vec<u8>,
 }
 
 impl Tlv {
+    /// Create a TLV from tag and value
+    pub fn new(tag: &[u8], value: Vec<u8>) -> Self {
+        Self {
+            tag: tag.to_vec(),
+            value,
+        }
+    }
+
     /// Parse TLV data
     pub fn parse(data: &[u8]) -> Result<Vec<Tlv>, String> {
         let mut tlvs = Vec::new();
@@ -212,8 +383,153 @@ impl Tlv {
         Ok(tlvs)
     }
 
+    /// Parse TLV data, also descending into constructed templates
+    /// (e.g. 0x6F, 0xA5, 0x70, 0x77) so nested data objects are found by tag
+    pub fn parse_flattened(data: &[u8]) -> Result<Vec<Tlv>, String> {
+        let mut out = Vec::new();
+        for tlv in Tlv::parse(data)? {
+            let constructed = tlv.tag.first().is_some_and(|b| b & 0x20 != 0);
+            if constructed {
+                out.extend(Tlv::parse_flattened(&tlv.value)?);
+            }
+            out.push(tlv);
+        }
+        Ok(out)
+    }
+
     /// Find TLV by tag
     pub fn find_by_tag<'a>(tlvs: &'a [Tlv], tag: &[u8]) -> Option<&'a Tlv> {
         tlvs.iter().find(|tlv| tlv.tag == tag)
     }
+
+    /// BER-TLV encoding of this data object
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let length = match self.value.len() {
+            len @ 0..=0x7F => vec![len as u8],
+            len @ 0x80..=0xFF => vec![0x81, len as u8],
+            len => vec![0x82, (len >> 8) as u8, len as u8],
+        };
+        [self.tag.as_slice(), &length, &self.value].concat()
+    }
+
+    /// BER-TLV encoding of a list of data objects, in order
+    pub fn encode(tlvs: &[Tlv]) -> Vec<u8> {
+        tlvs.iter().flat_map(Tlv::to_bytes).collect()
+    }
+
+    /// Replace the value of an existing tag or append a new TLV
+    pub fn upsert(tlvs: &mut Vec<Tlv>, tag: &[u8], value: Vec<u8>) {
+        match tlvs.iter_mut().find(|tlv| tlv.tag == tag) {
+            Some(tlv) => tlv.value = value,
+            None => tlvs.push(Tlv::new(tag, value)),
+        }
+    }
+}
+
+/// Tags whose values are cardholder data: PAN, track data, cardholder name,
+/// PIN data and mag-stripe track images
+const SENSITIVE_TAGS: &[&[u8]] = &[
+    &[0x56],
+    &[0x57],
+    &[0x5A],
+    &[0x5F, 0x20],
+    &[0x99],
+    &[0x9F, 0x0B],
+    &[0x9F, 0x1F],
+    &[0x9F, 0x20],
+    &[0x9F, 0x6B],
+];
+
+impl std::fmt::Debug for ApduCommand {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ApduCommand")
+            .field("cla", &self.cla)
+            .field("ins", &self.ins)
+            .field("p1", &self.p1)
+            .field("p2", &self.p2)
+            .field("lc", &self.data.as_ref().map(Vec::len))
+            .field("le", &self.le)
+            .finish()
+    }
+}
+
+impl Drop for ApduCommand {
+    fn drop(&mut self) {
+        self.data.zeroize();
+    }
+}
+
+impl std::fmt::Debug for ApduResponse {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ApduResponse")
+            .field("data_len", &self.data.len())
+            .field("sw1", &self.sw1)
+            .field("sw2", &self.sw2)
+            .finish()
+    }
+}
+
+impl Drop for ApduResponse {
+    fn drop(&mut self) {
+        self.data.zeroize();
+    }
+}
+
+impl Tlv {
+    /// Whether the value is cardholder data
+    pub fn is_sensitive(&self) -> bool {
+        SENSITIVE_TAGS.contains(&self.tag.as_slice())
+    }
+
+    fn serialize_with_value<S: Serializer>(
+        &self,
+        serializer: S,
+        exposed: bool,
+    ) -> Result<S::Ok, S::Error> {
+        let mut tlv = serializer.serialize_struct("Tlv", 2)?;
+        tlv.serialize_field("tag", &self.tag)?;
+        if exposed || !self.is_sensitive() {
+            tlv.serialize_field("value", &self.value)?;
+        } else {
+            tlv.skip_field("value")?;
+        }
+        tlv.end()
+    }
+}
+
+impl Serialize for Tlv {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.serialize_with_value(serializer, false)
+    }
+}
+
+/// Cardholder data in clear, for lists that must keep every value
+impl expose::ExposeSecret for Vec<Tlv> {
+    fn serialize_exposed<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        struct Exposed<'a>(&'a Tlv);
+        impl Serialize for Exposed<'_> {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                self.0.serialize_with_value(serializer, true)
+            }
+        }
+
+        let mut seq = serializer.serialize_seq(Some(self.len()))?;
+        for tlv in self {
+            seq.serialize_element(&Exposed(tlv))?;
+        }
+        seq.end()
+    }
+}
+
+impl std::fmt::Debug for Tlv {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut tlv = f.debug_struct("Tlv");
+        tlv.field("tag", &hex::encode_upper(&self.tag));
+        if self.is_sensitive() {
+            tlv.field("value", &format_args!("[REDACTED; {}]", self.value.len()));
+        } else {
+            tlv.field("value", &hex::encode_upper(&self.value));
+        }
+        tlv.finish()
+    }
 }
@@ -0,0 +1,159 @@
+use serde::{Deserialize, Serialize};
+
+use super::emv::{ApduCommand, Tlv};
+
+/// When an issuer script is delivered to the card
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScriptTiming {
+    /// Issuer Script Template 1 (tag 0x71)
+    BeforeFinalAc,
+    /// Issuer Script Template 2 (tag 0x72)
+    AfterFinalAc,
+}
+
+/// One issuer script: identifier and Issuer Script Commands (tag 0x86)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IssuerScript {
+    pub timing: ScriptTiming,
+    /// Issuer Script Identifier (tag 0x9F18)
+    pub script_id: Option<[u8; 4]>,
+    pub commands: Vec<ApduCommand>,
+}
+
+impl IssuerScript {
+    /// Parse concatenated Issuer Script Templates (tags 0x71 and 0x72)
+    pub fn parse_all(data: &[u8]) -> Result<Vec<Self>, String> {
+        Tlv::parse(data)?
+            .into_iter()
+            .map(|template| {
+                let timing = match template.tag.as_slice() {
+                    [0x71] => ScriptTiming::BeforeFinalAc,
+                    [0x72] => ScriptTiming::AfterFinalAc,
+                    other => {
+                        return Err(format!(
+                            "Unexpected issuer script tag: {}",
+                            hex::encode_upper(other)
+                        ))
+                    }
+                };
+                Self::parse_template(timing, &template.value)
+            })
+            .collect()
+    }
+
+    fn parse_template(timing: ScriptTiming, value: &[u8]) -> Result<Self, String> {
+        let mut script_id = None;
+        let mut commands = Vec::new();
+
+        for tlv in Tlv::parse(value)? {
+            match tlv.tag.as_slice() {
+                [0x9F, 0x18] => {
+                    script_id = Some(
+                        <[u8; 4]>::try_from(tlv.value.as_slice())
+                            .map_err(|_| "Issuer Script Identifier must be 4 bytes")?,
+                    )
+                }
+                [0x86] => commands.push(ApduCommand::from_bytes(&tlv.value)?),
+                _ => {}
+            }
+        }
+
+        Ok(Self {
+            timing,
+            script_id,
+            commands,
+        })
+    }
+}
+
+/// Processing result of one script, Issuer Script Results byte 1 b8-b5
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScriptStatus {
+    NotPerformed,
+    /// Failed at the given command (1-based)
+    Failed(u8),
+    Successful,
+}
+
+/// Result of one script, 5 bytes of Issuer Script Results (tag 0x9F5B)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScriptResult {
+    pub status: ScriptStatus,
+    pub script_id: Option<[u8; 4]>,
+}
+
+impl ScriptResult {
+    /// Result byte followed by the Script Identifier (zeros if absent)
+    pub fn to_bytes(&self) -> [u8; 5] {
+        let first = match self.status {
+            ScriptStatus::NotPerformed => 0x00,
+            // Sequence numbers of 15 and above are coded as F
+            ScriptStatus::Failed(sequence) => 0x10 | sequence.min(0x0F),
+            ScriptStatus::Successful => 0x20,
+        };
+        let id = self.script_id.unwrap_or([0x00; 4]);
+        [first, id[0], id[1], id[2], id[3]]
+    }
+}
+
+/// Issuer Script Results (tag 0x9F5B) for the advice or reversal message
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IssuerScriptResults(pub Vec<ScriptResult>);
+
+impl IssuerScriptResults {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.0.iter().flat_map(|result| result.to_bytes()).collect()
+    }
+
+    pub fn to_hex(&self) -> String {
+        hex::encode_upper(self.to_bytes())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_scripts() {
+        // 71: script ID 11223344, PUT DATA; 72: APPLICATION BLOCK without ID
+        let data = hex::decode(
+            "71139F180411223344860A84DA9F58050011223344720F860D841E0000081122334455667788",
+        )
+        .unwrap();
+        let scripts = IssuerScript::parse_all(&data).unwrap();
+
+        assert_eq!(scripts.len(), 2);
+        assert_eq!(scripts[0].timing, ScriptTiming::BeforeFinalAc);
+        assert_eq!(scripts[0].script_id, Some([0x11, 0x22, 0x33, 0x44]));
+        assert_eq!(
+            scripts[0].commands[0].data,
+            Some(vec![0x00, 0x11, 0x22, 0x33, 0x44])
+        );
+        assert_eq!(scripts[1].timing, ScriptTiming::AfterFinalAc);
+        assert_eq!(scripts[1].script_id, None);
+        assert_eq!(scripts[1].commands[0].ins, 0x1E);
+        assert_eq!(scripts[1].commands[0].le, None);
+    }
+
+    #[test]
+    fn test_script_results_encoding() {
+        let results = IssuerScriptResults(vec![
+            ScriptResult {
+                status: ScriptStatus::Successful,
+                script_id: Some([0x11, 0x22, 0x33, 0x44]),
+            },
+            ScriptResult {
+                status: ScriptStatus::Failed(2),
+                script_id: None,
+            },
+        ]);
+        assert_eq!(results.to_hex(), "20112233441200000000");
+    }
+}
@@ -1,5 +1,31 @@
+pub mod afl;
+pub mod card_date;
+pub mod cryptogram;
+pub mod currency;
+pub mod cvm;
+pub mod dol;
 pub mod emv;
+pub mod issuer_script;
+pub mod outcome;
+pub mod pan;
+pub mod secret;
+pub mod terminal;
+pub mod track;
 pub mod transaction;
+pub mod tvr;
 
+pub use afl::*;
+pub use card_date::*;
+pub use cryptogram::*;
+pub use currency::{Amount, Country, Currency};
+pub use cvm::*;
+pub use dol::*;
 pub use emv::*;
+pub use issuer_script::*;
+pub use outcome::*;
+pub use pan::*;
+pub use secret::{SecretBytes, SecretString};
+pub use terminal::*;
+pub use track::*;
 pub use transaction::*;
+pub use tvr::{ApplicationUsageControl, Tsi, Tvr};
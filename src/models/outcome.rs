@@ -0,0 +1,382 @@
+use serde::{Deserialize, Serialize};
+
+/// Outcome status, Outcome Parameter Set byte 1
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutcomeStatus {
+    Approved,
+    Declined,
+    OnlineRequest,
+    EndApplication,
+    SelectNext,
+    TryAnotherInterface,
+    TryAgain,
+    NotApplicable,
+}
+
+impl OutcomeStatus {
+    pub fn code(&self) -> u8 {
+        match self {
+            OutcomeStatus::Approved => 0x10,
+            OutcomeStatus::Declined => 0x20,
+            OutcomeStatus::OnlineRequest => 0x30,
+            OutcomeStatus::EndApplication => 0x40,
+            OutcomeStatus::SelectNext => 0x50,
+            OutcomeStatus::TryAnotherInterface => 0x60,
+            OutcomeStatus::TryAgain => 0x70,
+            OutcomeStatus::NotApplicable => 0xF0,
+        }
+    }
+}
+
+/// Where Entry Point restarts, Outcome Parameter Set byte 2
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutcomeStart {
+    A,
+    B,
+    C,
+    D,
+    NotApplicable,
+}
+
+impl OutcomeStart {
+    pub fn code(&self) -> u8 {
+        match self {
+            OutcomeStart::A => 0x00,
+            OutcomeStart::B => 0x10,
+            OutcomeStart::C => 0x20,
+            OutcomeStart::D => 0x30,
+            OutcomeStart::NotApplicable => 0xF0,
+        }
+    }
+}
+
+/// CVM to perform, Outcome Parameter Set byte 4
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutcomeCvm {
+    NoCvm,
+    ObtainSignature,
+    OnlinePin,
+    ConfirmationCodeVerified,
+    NotApplicable,
+}
+
+impl OutcomeCvm {
+    pub fn code(&self) -> u8 {
+        match self {
+            OutcomeCvm::NoCvm => 0x00,
+            OutcomeCvm::ObtainSignature => 0x10,
+            OutcomeCvm::OnlinePin => 0x20,
+            OutcomeCvm::ConfirmationCodeVerified => 0x30,
+            OutcomeCvm::NotApplicable => 0xF0,
+        }
+    }
+}
+
+/// Outcome Parameter Set (tag 0xDF8129), EMV Book C-2 Annex A
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OutcomeParameterSet {
+    pub status: OutcomeStatus,
+    pub start: OutcomeStart,
+    pub cvm: OutcomeCvm,
+    pub ui_request_on_outcome: bool,
+    pub ui_request_on_restart: bool,
+    pub data_record_present: bool,
+    pub discretionary_data_present: bool,
+    pub receipt: bool,
+    /// Field off request in units of 100 ms, `None` when not applicable
+    pub field_off_request: Option<u8>,
+    /// Removal timeout in units of 100 ms
+    pub removal_timeout: u8,
+}
+
+impl OutcomeParameterSet {
+    /// Outcome with no restart, no CVM and nothing else requested
+    pub fn new(status: OutcomeStatus) -> Self {
+        Self {
+            status,
+            start: OutcomeStart::NotApplicable,
+            cvm: OutcomeCvm::NotApplicable,
+            ui_request_on_outcome: false,
+            ui_request_on_restart: false,
+            data_record_present: false,
+            discretionary_data_present: false,
+            receipt: false,
+            field_off_request: None,
+            removal_timeout: 0,
+        }
+    }
+
+    pub fn to_bytes(&self) -> [u8; 8] {
+        let mut flags = 0u8;
+        if self.ui_request_on_outcome {
+            flags |= 0x80;
+        }
+        if self.ui_request_on_restart {
+            flags |= 0x40;
+        }
+        if self.data_record_present {
+            flags |= 0x20;
+        }
+        if self.discretionary_data_present {
+            flags |= 0x10;
+        }
+        if self.receipt {
+            flags |= 0x08;
+        }
+
+        [
+            self.status.code(),
+            self.start.code(),
+            // Online Response Data: not applicable
+            0xF0,
+            self.cvm.code(),
+            flags,
+            // Alternate Interface Preference: not applicable
+            0xF0,
+            self.field_off_request.unwrap_or(0xFF),
+            self.removal_timeout,
+        ]
+    }
+
+    pub fn to_hex(&self) -> String {
+        hex::encode_upper(self.to_bytes())
+    }
+}
+
+/// Message Identifier of a User Interface Request (EMV Book A Table 9-5)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UiMessage {
+    Approved,
+    NotAuthorised,
+    PleaseEnterYourPin,
+    ProcessingError,
+    RemoveCard,
+    Welcome,
+    PresentCard,
+    Processing,
+    CardReadOkPleaseRemoveCard,
+    PleaseInsertOrSwipeCard,
+    PleasePresentOneCardOnly,
+    ApprovedPleaseSign,
+    AuthorisingPleaseWait,
+    InsertSwipeOrTryAnotherCard,
+    PleaseInsertCard,
+    ClearDisplay,
+    SeePhoneForInstructions,
+    PresentCardAgain,
+    NotApplicable,
+}
+
+impl UiMessage {
+    pub fn code(&self) -> u8 {
+        match self {
+            UiMessage::Approved => 0x03,
+            UiMessage::NotAuthorised => 0x07,
+            UiMessage::PleaseEnterYourPin => 0x09,
+            UiMessage::ProcessingError => 0x0F,
+            UiMessage::RemoveCard => 0x10,
+            UiMessage::Welcome => 0x14,
+            UiMessage::PresentCard => 0x15,
+            UiMessage::Processing => 0x16,
+            UiMessage::CardReadOkPleaseRemoveCard => 0x17,
+            UiMessage::PleaseInsertOrSwipeCard => 0x18,
+            UiMessage::PleasePresentOneCardOnly => 0x19,
+            UiMessage::ApprovedPleaseSign => 0x1A,
+            UiMessage::AuthorisingPleaseWait => 0x1B,
+            UiMessage::InsertSwipeOrTryAnotherCard => 0x1C,
+            UiMessage::PleaseInsertCard => 0x1D,
+            UiMessage::ClearDisplay => 0x1E,
+            UiMessage::SeePhoneForInstructions => 0x20,
+            UiMessage::PresentCardAgain => 0x21,
+            UiMessage::NotApplicable => 0xFF,
+        }
+    }
+
+    /// Standard English text to display
+    pub fn text(&self) -> &'static str {
+        match self {
+            UiMessage::Approved => "Approved",
+            UiMessage::NotAuthorised => "Not Authorised",
+            UiMessage::PleaseEnterYourPin => "Please Enter Your PIN",
+            UiMessage::ProcessingError => "Processing Error",
+            UiMessage::RemoveCard => "Remove Card",
+            UiMessage::Welcome => "Welcome",
+            UiMessage::PresentCard => "Present Card",
+            UiMessage::Processing => "Processing",
+            UiMessage::CardReadOkPleaseRemoveCard => "Card Read OK Please Remove Card",
+            UiMessage::PleaseInsertOrSwipeCard => "Please Insert or Swipe Card",
+            UiMessage::PleasePresentOneCardOnly => "Please Present One Card Only",
+            UiMessage::ApprovedPleaseSign => "Approved Please Sign",
+            UiMessage::AuthorisingPleaseWait => "Authorising Please Wait",
+            UiMessage::InsertSwipeOrTryAnotherCard => "Insert, Swipe or Try Another Card",
+            UiMessage::PleaseInsertCard => "Please Insert Card",
+            UiMessage::ClearDisplay => "",
+            UiMessage::SeePhoneForInstructions => "See Phone for Instructions",
+            UiMessage::PresentCardAgain => "Present Card Again",
+            UiMessage::NotApplicable => "",
+        }
+    }
+}
+
+/// Reader status of a User Interface Request (EMV Book A Table 9-6)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UiStatus {
+    NotReady,
+    Idle,
+    ReadyToRead,
+    Processing,
+    CardReadSuccessfully,
+    ProcessingError,
+    NotApplicable,
+}
+
+impl UiStatus {
+    pub fn code(&self) -> u8 {
+        match self {
+            UiStatus::NotReady => 0x00,
+            UiStatus::Idle => 0x01,
+            UiStatus::ReadyToRead => 0x02,
+            UiStatus::Processing => 0x03,
+            UiStatus::CardReadSuccessfully => 0x04,
+            UiStatus::ProcessingError => 0x05,
+            UiStatus::NotApplicable => 0xFF,
+        }
+    }
+}
+
+/// User Interface Request Data (tag 0xDF8116)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UiRequest {
+    pub message: UiMessage,
+    pub status: UiStatus,
+    /// Hold time in units of 100 ms
+    pub hold_time: u32,
+    /// Cardholder language preference (tag 0x5F2D), e.g. "enzh"
+    pub language_preference: Option<String>,
+}
+
+impl UiRequest {
+    pub fn new(message: UiMessage, status: UiStatus) -> Self {
+        Self {
+            message,
+            status,
+            hold_time: 0,
+            language_preference: None,
+        }
+    }
+
+    pub fn with_hold_time(mut self, hold_time: u32) -> Self {
+        self.hold_time = hold_time;
+        self
+    }
+
+    pub fn with_language_preference(mut self, language_preference: String) -> Self {
+        self.language_preference = Some(language_preference);
+        self
+    }
+
+    /// Standard message shown with an outcome that requests one
+    /// (EMV Book A Section 9.4)
+    pub fn on_outcome(parameters: &OutcomeParameterSet) -> Option<Self> {
+        if !parameters.ui_request_on_outcome {
+            return None;
+        }
+        let request = match parameters.status {
+            OutcomeStatus::Approved if parameters.cvm == OutcomeCvm::ObtainSignature => Self::new(
+                UiMessage::ApprovedPleaseSign,
+                UiStatus::CardReadSuccessfully,
+            ),
+            OutcomeStatus::Approved => {
+                Self::new(UiMessage::Approved, UiStatus::CardReadSuccessfully)
+            }
+            OutcomeStatus::Declined => {
+                Self::new(UiMessage::NotAuthorised, UiStatus::CardReadSuccessfully)
+            }
+            OutcomeStatus::OnlineRequest if parameters.cvm == OutcomeCvm::OnlinePin => Self::new(
+                UiMessage::PleaseEnterYourPin,
+                UiStatus::CardReadSuccessfully,
+            ),
+            OutcomeStatus::OnlineRequest => Self::new(
+                UiMessage::AuthorisingPleaseWait,
+                UiStatus::CardReadSuccessfully,
+            ),
+            OutcomeStatus::TryAnotherInterface => Self::new(
+                UiMessage::PleaseInsertOrSwipeCard,
+                UiStatus::ProcessingError,
+            ),
+            // The consumer device needs the cardholder's attention first
+            OutcomeStatus::TryAgain => {
+                Self::new(UiMessage::SeePhoneForInstructions, UiStatus::NotReady).with_hold_time(13)
+            }
+            OutcomeStatus::EndApplication => Self::new(
+                UiMessage::InsertSwipeOrTryAnotherCard,
+                UiStatus::ProcessingError,
+            ),
+            OutcomeStatus::SelectNext | OutcomeStatus::NotApplicable => return None,
+        };
+        Some(request)
+    }
+
+    /// Standard message shown when Entry Point restarts
+    pub fn on_restart(parameters: &OutcomeParameterSet) -> Option<Self> {
+        parameters
+            .ui_request_on_restart
+            .then(|| Self::new(UiMessage::PresentCardAgain, UiStatus::ReadyToRead))
+    }
+
+    pub fn to_bytes(&self) -> [u8; 22] {
+        let mut bytes = [0u8; 22];
+        bytes[0] = self.message.code();
+        bytes[1] = self.status.code();
+        // Hold Time, n6
+        let hold_time = format!("{:06}", self.hold_time.min(999_999));
+        bytes[2..5].copy_from_slice(&hex::decode(hold_time).unwrap_or_default());
+        if let Some(language) = &self.language_preference {
+            let language = language.as_bytes();
+            let len = language.len().min(8);
+            bytes[5..5 + len].copy_from_slice(&language[..len]);
+        }
+        // Value Qualifier, Value and Currency Code: none
+        bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_outcome_parameter_set_encoding() {
+        let mut outcome = OutcomeParameterSet::new(OutcomeStatus::OnlineRequest);
+        outcome.cvm = OutcomeCvm::OnlinePin;
+        outcome.data_record_present = true;
+        assert_eq!(outcome.to_hex(), "30F0F02020F0FF00");
+    }
+
+    #[test]
+    fn test_standard_ui_requests() {
+        let mut outcome = OutcomeParameterSet::new(OutcomeStatus::OnlineRequest);
+        assert_eq!(UiRequest::on_outcome(&outcome), None);
+
+        outcome.ui_request_on_outcome = true;
+        outcome.cvm = OutcomeCvm::OnlinePin;
+        let request = UiRequest::on_outcome(&outcome).unwrap();
+        assert_eq!(request.message, UiMessage::PleaseEnterYourPin);
+        assert_eq!(request.message.text(), "Please Enter Your PIN");
+
+        let mut outcome = OutcomeParameterSet::new(OutcomeStatus::TryAgain);
+        outcome.ui_request_on_outcome = true;
+        outcome.ui_request_on_restart = true;
+        let request = UiRequest::on_outcome(&outcome).unwrap();
+        assert_eq!(hex::encode_upper(&request.to_bytes()[..5]), "2000000013");
+        assert_eq!(
+            UiRequest::on_restart(&outcome).unwrap().message,
+            UiMessage::PresentCardAgain
+        );
+    }
+}
@@ -0,0 +1,350 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use zeroize::Zeroize;
+
+/// Primary Account Number (tag 0x5A)
+///
+/// `Debug`, `Display` and `Serialize` only ever show the first six and last
+/// four digits; the clear digits are available through [`Pan::expose`].
+/// A PAN that was already masked elsewhere (e.g. `476173******0010`) can be
+/// deserialized but not exposed.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct Pan(String);
+
+/// How much of a PAN to keep when it is displayed or stored
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PanTruncation {
+    /// First six and last four digits, for receipts and displays
+    FirstSixLastFour,
+    /// First eight and last four digits, for PANs of 16 digits or more
+    FirstEightLastFour,
+    /// Last four digits only
+    LastFour,
+}
+
+impl Pan {
+    /// Parse a clear PAN of 12 to 19 digits with a valid Luhn check digit
+    pub fn parse(digits: &str) -> Result<Self, String> {
+        if !(12..=19).contains(&digits.len()) || !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(format!(
+                "Invalid PAN length or format ({} digits)",
+                digits.len()
+            ));
+        }
+        if !Self::luhn_valid(digits) {
+            return Err("PAN check digit is invalid".to_string());
+        }
+        Ok(Self(digits.to_string()))
+    }
+
+    /// Parse the BCD value of tag 0x5A, padded with a trailing `F`
+    pub fn from_bcd(bcd: &[u8]) -> Result<Self, String> {
+        Self::parse(hex::encode_upper(bcd).trim_end_matches('F'))
+    }
+
+    /// Luhn (mod 10) check over all digits, check digit included
+    pub fn luhn_valid(digits: &str) -> bool {
+        let sum: u32 = digits
+            .bytes()
+            .rev()
+            .enumerate()
+            .map(|(i, b)| {
+                let digit = u32::from(b.wrapping_sub(b'0'));
+                match (i % 2 == 1, digit * 2) {
+                    (true, doubled) if doubled > 9 => doubled - 9,
+                    (true, doubled) => doubled,
+                    (false, _) => digit,
+                }
+            })
+            .sum();
+        digits.bytes().all(|b| b.is_ascii_digit()) && sum.is_multiple_of(10)
+    }
+
+    /// Whether only a masked form of this PAN is known
+    pub fn is_masked(&self) -> bool {
+        !self.0.bytes().all(|b| b.is_ascii_digit())
+    }
+
+    /// The clear digits, for the card and the acquirer only
+    pub fn expose(&self) -> Result<&str, String> {
+        if self.is_masked() {
+            return Err("PAN is masked".to_string());
+        }
+        Ok(&self.0)
+    }
+
+    /// Value of tag 0x5A
+    pub fn to_bcd(&self) -> Result<Vec<u8>, String> {
+        let mut digits = self.expose()?.to_string();
+        if digits.len() % 2 == 1 {
+            digits.push('F');
+        }
+        hex::decode(digits).map_err(|e| e.to_string())
+    }
+
+    /// Issuer Identification Number: the first six digits
+    pub fn bin(&self) -> &str {
+        &self.0[..6]
+    }
+
+    pub fn last_four(&self) -> &str {
+        &self.0[self.0.len() - 4..]
+    }
+
+    /// First six and last four digits, the rest replaced by `*`
+    pub fn masked(&self) -> String {
+        self.truncated(PanTruncation::FirstSixLastFour)
+    }
+
+    /// PAN with the digits a truncation policy removes replaced by `*`
+    pub fn truncated(&self, policy: PanTruncation) -> String {
+        let leading = match policy {
+            PanTruncation::FirstEightLastFour if self.0.len() >= 16 => 8,
+            PanTruncation::FirstSixLastFour | PanTruncation::FirstEightLastFour => 6,
+            PanTruncation::LastFour => 0,
+        };
+        let trailing = 4;
+        self.0
+            .chars()
+            .enumerate()
+            .map(|(i, c)| {
+                if i < leading || i >= self.0.len() - trailing {
+                    c
+                } else {
+                    '*'
+                }
+            })
+            .collect()
+    }
+
+    /// Brand and issuer country from the offline BIN table
+    pub fn bin_info(&self) -> Option<BinInfo> {
+        BinTable::default().lookup(self)
+    }
+
+    /// Accept a masked PAN as produced by [`Pan::masked`]
+    fn parse_masked(value: &str) -> Result<Self, String> {
+        // ASCII only, so the byte offsets below fall on char boundaries
+        let valid = value.is_ascii()
+            && (12..=19).contains(&value.len())
+            && value[..6].bytes().all(|b| b.is_ascii_digit())
+            && value[value.len() - 4..].bytes().all(|b| b.is_ascii_digit())
+            && value[6..value.len() - 4]
+                .bytes()
+                .all(|b| b == b'*' || b.is_ascii_digit());
+        if !valid {
+            return Err("Invalid masked PAN".to_string());
+        }
+        Ok(Self(value.to_string()))
+    }
+}
+
+impl Drop for Pan {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl std::fmt::Debug for Pan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Pan({})", self.masked())
+    }
+}
+
+impl std::fmt::Display for Pan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.masked())
+    }
+}
+
+impl Serialize for Pan {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.masked())
+    }
+}
+
+impl<'de> Deserialize<'de> for Pan {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        if value.bytes().all(|b| b.is_ascii_digit()) {
+            Pan::parse(&value)
+        } else {
+            Pan::parse_masked(&value)
+        }
+        .map_err(serde::de::Error::custom)
+    }
+}
+
+/// Card brand, from the IIN ranges the schemes publish
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CardBrand {
+    Visa,
+    Mastercard,
+    Maestro,
+    AmericanExpress,
+    Discover,
+    DinersClub,
+    Jcb,
+    UnionPay,
+}
+
+/// Result of a BIN lookup
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BinInfo {
+    pub brand: CardBrand,
+    /// Issuer country, ISO 3166-1 alpha-2, when the range is country specific
+    pub issuer_country: Option<String>,
+}
+
+/// One range of the BIN table, bounds compared on the first `low.len()` digits
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BinRange {
+    pub low: String,
+    pub high: String,
+    pub brand: CardBrand,
+    pub issuer_country: Option<String>,
+}
+
+impl BinRange {
+    pub fn new(low: &str, high: &str, brand: CardBrand) -> Self {
+        Self {
+            low: low.to_string(),
+            high: high.to_string(),
+            brand,
+            issuer_country: None,
+        }
+    }
+
+    pub fn with_issuer_country(mut self, country: &str) -> Self {
+        self.issuer_country = Some(country.to_string());
+        self
+    }
+
+    fn contains(&self, pan: &Pan) -> bool {
+        pan.0
+            .get(..self.low.len())
+            .is_some_and(|prefix| prefix >= self.low.as_str() && prefix <= self.high.as_str())
+    }
+
+    /// Longer bounds and narrower ranges are more specific
+    fn specificity(&self) -> (usize, std::cmp::Reverse<u64>) {
+        let width = self
+            .high
+            .parse::<u64>()
+            .unwrap_or(0)
+            .saturating_sub(self.low.parse::<u64>().unwrap_or(0));
+        (self.low.len(), std::cmp::Reverse(width))
+    }
+}
+
+/// Offline BIN table; the most specific matching range wins
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BinTable {
+    pub ranges: Vec<BinRange>,
+}
+
+impl Default for BinTable {
+    /// Brand ranges of the international schemes, without issuer countries
+    fn default() -> Self {
+        Self {
+            ranges: vec![
+                BinRange::new("4", "4", CardBrand::Visa),
+                BinRange::new("51", "55", CardBrand::Mastercard),
+                BinRange::new("2221", "2720", CardBrand::Mastercard),
+                BinRange::new("50", "50", CardBrand::Maestro),
+                BinRange::new("56", "58", CardBrand::Maestro),
+                BinRange::new("6304", "6304", CardBrand::Maestro),
+                BinRange::new("6759", "6759", CardBrand::Maestro),
+                BinRange::new("34", "34", CardBrand::AmericanExpress),
+                BinRange::new("37", "37", CardBrand::AmericanExpress),
+                BinRange::new("6011", "6011", CardBrand::Discover),
+                BinRange::new("644", "649", CardBrand::Discover),
+                BinRange::new("65", "65", CardBrand::Discover),
+                BinRange::new("300", "305", CardBrand::DinersClub),
+                BinRange::new("36", "36", CardBrand::DinersClub),
+                BinRange::new("38", "39", CardBrand::DinersClub),
+                BinRange::new("3528", "3589", CardBrand::Jcb),
+                BinRange::new("62", "62", CardBrand::UnionPay),
+                BinRange::new("81", "81", CardBrand::UnionPay),
+            ],
+        }
+    }
+}
+
+impl BinTable {
+    /// Add an issuer range, e.g. one from the acquirer's BIN file
+    pub fn with_range(mut self, range: BinRange) -> Self {
+        self.ranges.push(range);
+        self
+    }
+
+    pub fn lookup(&self, pan: &Pan) -> Option<BinInfo> {
+        self.ranges
+            .iter()
+            .filter(|range| range.contains(pan))
+            .max_by_key(|range| range.specificity())
+            .map(|range| BinInfo {
+                brand: range.brand,
+                issuer_country: range.issuer_country.clone(),
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_luhn_and_masking() {
+        assert!(Pan::parse("4761739001010011").is_err());
+        assert!(Pan::parse("47617390").is_err());
+
+        let pan = Pan::from_bcd(&[0x37, 0x42, 0x45, 0x45, 0x54, 0x00, 0x12, 0x6F]).unwrap();
+        assert_eq!(pan.expose().unwrap(), "374245455400126");
+        assert_eq!(
+            pan.to_bcd().unwrap(),
+            [0x37, 0x42, 0x45, 0x45, 0x54, 0x00, 0x12, 0x6F]
+        );
+        assert_eq!(pan.masked(), "374245*****0126");
+        assert_eq!(pan.truncated(PanTruncation::LastFour), "***********0126");
+        assert_eq!(format!("{:?}", pan), "Pan(374245*****0126)");
+
+        // Serialized PANs stay masked, and masked PANs round-trip
+        let json = serde_json::to_string(&pan).unwrap();
+        assert_eq!(json, "\"374245*****0126\"");
+        let masked: Pan = serde_json::from_str(&json).unwrap();
+        assert!(masked.is_masked() && masked.expose().is_err());
+        assert_eq!(masked.masked(), pan.masked());
+        assert!(serde_json::from_str::<Pan>("\"37424é*****0126\"").is_err());
+    }
+
+    #[test]
+    fn test_bin_lookup() {
+        let pan = Pan::parse("6200000000000005").unwrap();
+        assert_eq!(pan.bin_info().unwrap().brand, CardBrand::UnionPay);
+
+        let table = BinTable::default().with_range(
+            BinRange::new("620000", "620009", CardBrand::UnionPay).with_issuer_country("CN"),
+        );
+        assert_eq!(
+            table.lookup(&pan).unwrap().issuer_country.as_deref(),
+            Some("CN")
+        );
+        assert_eq!(
+            Pan::parse("5413330089010434")
+                .unwrap()
+                .bin_info()
+                .unwrap()
+                .brand,
+            CardBrand::Mastercard
+        );
+        assert_eq!(
+            table
+                .lookup(&Pan::parse("4761739001010010").unwrap())
+                .unwrap()
+                .brand,
+            CardBrand::Visa
+        );
+    }
+}
@@ -0,0 +1,169 @@
+use serde::{Deserialize, Deserializer, Serializer};
+use zeroize::Zeroize;
+
+/// Sensitive bytes (track data, PIN blocks, key components)
+///
+/// Zeroized on drop, never printed by `Debug` and not serializable unless a
+/// field opts in with `#[serde(serialize_with = "expose::serialize")]`.
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct SecretBytes(Vec<u8>);
+
+/// Sensitive text (cardholder name, track images, PINs)
+///
+/// Same guarantees as [`SecretBytes`].
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct SecretString(String);
+
+impl SecretBytes {
+    pub fn new(bytes: Vec<u8>) -> Self {
+        Self(bytes)
+    }
+
+    pub fn expose(&self) -> &[u8] {
+        &self.0
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl SecretString {
+    pub fn new(value: String) -> Self {
+        Self(value)
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<Vec<u8>> for SecretBytes {
+    fn from(bytes: Vec<u8>) -> Self {
+        Self(bytes)
+    }
+}
+
+impl From<String> for SecretString {
+    fn from(value: String) -> Self {
+        Self(value)
+    }
+}
+
+impl Drop for SecretBytes {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl Drop for SecretString {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl std::fmt::Debug for SecretBytes {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "SecretBytes([REDACTED; {}])", self.0.len())
+    }
+}
+
+impl std::fmt::Debug for SecretString {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("SecretString([REDACTED])")
+    }
+}
+
+/// Bytes are received as hex
+impl<'de> Deserialize<'de> for SecretBytes {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut value = String::deserialize(deserializer)?;
+        let bytes = hex::decode(&value).map_err(serde::de::Error::custom);
+        value.zeroize();
+        bytes.map(Self)
+    }
+}
+
+impl<'de> Deserialize<'de> for SecretString {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(Self)
+    }
+}
+
+/// Explicit opt-in serialization of secrets, for fields that must reach the
+/// acquirer or the app: `#[serde(serialize_with = "expose::serialize")]`
+pub mod expose {
+    use super::{SecretBytes, SecretString};
+    use serde::Serializer;
+
+    /// Secrets that may be serialized in the clear when a field asks for it
+    pub trait ExposeSecret {
+        fn serialize_exposed<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error>;
+    }
+
+    impl ExposeSecret for SecretBytes {
+        fn serialize_exposed<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.serialize_str(&hex::encode_upper(self.expose()))
+        }
+    }
+
+    impl ExposeSecret for SecretString {
+        fn serialize_exposed<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.serialize_str(self.expose())
+        }
+    }
+
+    impl<T: ExposeSecret> ExposeSecret for Option<T> {
+        fn serialize_exposed<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            match self {
+                Some(secret) => secret.serialize_exposed(serializer),
+                None => serializer.serialize_none(),
+            }
+        }
+    }
+
+    pub fn serialize<T: ExposeSecret, S: Serializer>(
+        secret: &T,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        secret.serialize_exposed(serializer)
+    }
+}
+
+/// Serialize a secret as `"[REDACTED]"`, for fields that must never leave
+/// the kernel in the clear
+pub fn redact<T, S: Serializer>(_secret: &T, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str("[REDACTED]")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Serialize;
+
+    #[derive(Serialize, Deserialize)]
+    struct Record {
+        #[serde(serialize_with = "expose::serialize")]
+        track2: Option<SecretBytes>,
+        #[serde(serialize_with = "redact")]
+        name: SecretString,
+    }
+
+    #[test]
+    fn test_secrets_stay_out_of_logs() {
+        let record: Record =
+            serde_json::from_str(r#"{"track2":"4761D30120","name":"SMITH/JOHN"}"#).unwrap();
+        assert_eq!(
+            format!("{:?} {:?}", record.track2, record.name),
+            "Some(SecretBytes([REDACTED; 5])) SecretString([REDACTED])"
+        );
+        assert_eq!(
+            serde_json::to_string(&record).unwrap(),
+            r#"{"track2":"4761D30120","name":"[REDACTED]"}"#
+        );
+    }
+}
@@ -0,0 +1,126 @@
+use serde::{Deserialize, Serialize};
+
+/// Terminal Capabilities (tag 0x9F33)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TerminalCapabilities(pub [u8; 3]);
+
+impl TerminalCapabilities {
+    /// Plaintext PIN for ICC verification
+    pub fn supports_plaintext_pin(&self) -> bool {
+        self.0[1] & 0x80 != 0
+    }
+
+    /// Enciphered PIN for online verification
+    pub fn supports_online_pin(&self) -> bool {
+        self.0[1] & 0x40 != 0
+    }
+
+    /// Signature (paper)
+    pub fn supports_signature(&self) -> bool {
+        self.0[1] & 0x20 != 0
+    }
+
+    /// Enciphered PIN for offline verification
+    pub fn supports_enciphered_pin(&self) -> bool {
+        self.0[1] & 0x10 != 0
+    }
+
+    /// No CVM required
+    pub fn supports_no_cvm(&self) -> bool {
+        self.0[1] & 0x08 != 0
+    }
+}
+
+impl Default for TerminalCapabilities {
+    /// SoftPOS profile: IC with contacts/contactless, online PIN, No CVM, SDA/DDA/CDA
+    fn default() -> Self {
+        Self([0xE0, 0x48, 0xC8])
+    }
+}
+
+/// Random transaction selection parameters (EMV Book 3 Section 10.6.2)
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RandomSelection {
+    /// Target Percentage to be Used for Random Selection (0-99)
+    pub target_percentage: u8,
+    /// Maximum Target Percentage to be Used for Biased Random Selection (0-99)
+    pub max_target_percentage: u8,
+    /// Threshold Value for Biased Random Selection (minor units)
+    pub threshold: u64,
+}
+
+/// Terminal Action Codes (EMV Book 3 Section 10.7)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TerminalActionCodes {
+    /// TAC - Denial
+    pub denial: [u8; 5],
+    /// TAC - Online
+    pub online: [u8; 5],
+    /// TAC - Default
+    pub default: [u8; 5],
+}
+
+impl Default for TerminalActionCodes {
+    fn default() -> Self {
+        Self {
+            denial: [0x00, 0x00, 0x00, 0x00, 0x00],
+            online: [0xFC, 0x50, 0xBC, 0xF8, 0x00],
+            default: [0xFC, 0x50, 0xBC, 0xA0, 0x00],
+        }
+    }
+}
+
+/// Terminal configuration used by kernel processing steps
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TerminalConfig {
+    /// Terminal Type (tag 0x9F35)
+    pub terminal_type: u8,
+    /// Terminal Capabilities (tag 0x9F33)
+    pub capabilities: TerminalCapabilities,
+    /// PIN pad (or PIN-on-glass) available and working
+    pub pin_pad_available: bool,
+    /// Terminal Floor Limit (tag 0x9F1B), minor units
+    pub floor_limit: u64,
+    /// Random transaction selection parameters
+    pub random_selection: RandomSelection,
+    /// Terminal Action Codes
+    pub action_codes: TerminalActionCodes,
+}
+
+impl TerminalConfig {
+    /// Unattended terminals have a terminal type ending in 4, 5 or 6
+    pub fn is_unattended(&self) -> bool {
+        matches!(self.terminal_type & 0x0F, 0x04..=0x06)
+    }
+
+    /// ATMs are unattended terminals operated by a financial institution
+    /// (terminal type 14, 15 or 16)
+    pub fn is_atm(&self) -> bool {
+        matches!(self.terminal_type, 0x14..=0x16)
+    }
+
+    /// Online only terminals have a terminal type ending in 1 or 4
+    pub fn is_online_only(&self) -> bool {
+        matches!(self.terminal_type & 0x0F, 0x01 | 0x04)
+    }
+
+    /// Offline only terminals have a terminal type ending in 3 or 6
+    pub fn is_online_capable(&self) -> bool {
+        !matches!(self.terminal_type & 0x0F, 0x03 | 0x06)
+    }
+}
+
+impl Default for TerminalConfig {
+    fn default() -> Self {
+        Self {
+            // Attended, merchant operated, online with offline capability
+            terminal_type: 0x22,
+            capabilities: TerminalCapabilities::default(),
+            pin_pad_available: true,
+            // SoftPOS terminals send every transaction online by default
+            floor_limit: 0,
+            random_selection: RandomSelection::default(),
+            action_codes: TerminalActionCodes::default(),
+        }
+    }
+}
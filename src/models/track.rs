@@ -0,0 +1,250 @@
+use serde::{Deserialize, Serialize};
+
+use super::card_date::CardDate;
+use super::pan::Pan;
+use super::secret::{redact, SecretString};
+
+/// Service code of a magnetic stripe track (ISO/IEC 7813)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ServiceCode(pub [u8; 3]);
+
+impl ServiceCode {
+    pub fn parse(digits: &str) -> Result<Self, String> {
+        let bytes = digits.as_bytes();
+        if bytes.len() != 3 || !bytes.iter().all(u8::is_ascii_digit) {
+            return Err(format!("Invalid service code: {}", digits));
+        }
+        Ok(Self([bytes[0] - b'0', bytes[1] - b'0', bytes[2] - b'0']))
+    }
+
+    /// Digit 1: the card may be used outside its country
+    pub fn is_international(&self) -> bool {
+        matches!(self.0[0], 1 | 2)
+    }
+
+    /// Digit 1: the card carries a chip, so a magnetic stripe read at a chip
+    /// terminal is only acceptable as fallback
+    pub fn is_chip_card(&self) -> bool {
+        matches!(self.0[0], 2 | 6)
+    }
+
+    /// Digit 2: the issuer must authorise every transaction online
+    pub fn online_authorisation_required(&self) -> bool {
+        matches!(self.0[1], 2 | 4)
+    }
+
+    /// Digit 3: a PIN is required
+    pub fn pin_required(&self) -> bool {
+        matches!(self.0[2], 0 | 3 | 5)
+    }
+
+    /// Digit 3: the card may only be used for cash at ATMs
+    pub fn atm_only(&self) -> bool {
+        self.0[2] == 3
+    }
+}
+
+impl std::fmt::Display for ServiceCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}{}", self.0[0], self.0[1], self.0[2])
+    }
+}
+
+/// Track 2 Equivalent Data (tag 0x57)
+///
+/// PAN, separator `D`, expiry YYMM, service code and discretionary data as
+/// BCD digits, padded with a trailing `F` to a whole number of bytes.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Track2 {
+    pub pan: Pan,
+    /// Expiry date, YYMM on the track
+    pub expiry: CardDate,
+    pub service_code: ServiceCode,
+    /// May hold CVV1/iCVV/PVV; never printed or serialized in the clear
+    #[serde(serialize_with = "redact")]
+    pub discretionary_data: SecretString,
+}
+
+impl Track2 {
+    pub fn new(pan: &str, expiry: &str, service_code: ServiceCode) -> Result<Self, String> {
+        Ok(Self {
+            pan: Pan::parse(pan)?,
+            expiry: CardDate::from_digits(expiry)?,
+            service_code,
+            discretionary_data: SecretString::default(),
+        })
+    }
+
+    /// Replace the discretionary data, e.g. with the dynamic CVC3/dCVV,
+    /// ATC and Unpredictable Number of a mag-stripe mode transaction
+    pub fn with_discretionary_data(mut self, discretionary_data: &str) -> Result<Self, String> {
+        if !discretionary_data.bytes().all(|b| b.is_ascii_digit()) {
+            return Err("Track 2 discretionary data must be numeric".to_string());
+        }
+        self.discretionary_data = SecretString::new(discretionary_data.to_string());
+        Ok(self)
+    }
+
+    /// Parse the value of tag 0x57
+    pub fn parse(data: &[u8]) -> Result<Self, String> {
+        if data.is_empty() || data.len() > 19 {
+            return Err(format!("Invalid Track 2 length: {}", data.len()));
+        }
+        let digits = hex::encode_upper(data);
+        // A single pad nibble at most
+        let digits = digits.strip_suffix('F').unwrap_or(&digits);
+        Self::from_digits(digits, 'D')
+    }
+
+    /// Parse a track 2 image read from a magnetic stripe, with or without
+    /// the `;` and `?` sentinels
+    pub fn parse_image(image: &str) -> Result<Self, String> {
+        let image = image.strip_prefix(';').unwrap_or(image);
+        let image = image.strip_suffix('?').unwrap_or(image);
+        Self::from_digits(image, '=')
+    }
+
+    fn from_digits(digits: &str, separator: char) -> Result<Self, String> {
+        let (pan, rest) = digits
+            .split_once(separator)
+            .ok_or("Track 2 field separator not found")?;
+        if !rest.bytes().all(|b| b.is_ascii_digit()) {
+            return Err("Track 2 must be numeric after the separator".to_string());
+        }
+        if rest.len() < 7 {
+            return Err("Track 2 is too short for expiry and service code".to_string());
+        }
+
+        Ok(Self {
+            pan: Pan::parse(pan)?,
+            expiry: CardDate::from_digits(&rest[..4])?,
+            service_code: ServiceCode::parse(&rest[4..7])?,
+            discretionary_data: SecretString::new(rest[7..].to_string()),
+        })
+    }
+
+    /// Check the track against the Application PAN (0x5A) and Application
+    /// Expiration Date (0x5F24) read from the card
+    pub fn validate(&self, pan: Option<&[u8]>, expiry_date: Option<&[u8]>) -> Result<(), String> {
+        if let Some(pan) = pan {
+            let digits = hex::encode_upper(pan);
+            if digits.trim_end_matches('F') != self.pan_digits() {
+                return Err("Track 2 PAN does not match the Application PAN".to_string());
+            }
+        }
+        if let Some(date) = expiry_date {
+            if CardDate::from_bcd(date)?.yymm() != self.expiry.yymm() {
+                return Err(
+                    "Track 2 expiry does not match the Application Expiration Date".to_string(),
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Track data always holds the clear PAN
+    fn pan_digits(&self) -> &str {
+        self.pan.expose().unwrap_or_default()
+    }
+
+    /// Value of tag 0x57
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut digits = format!(
+            "{}D{}{}{}",
+            self.pan_digits(),
+            self.expiry.yymm(),
+            self.service_code,
+            self.discretionary_data.expose()
+        );
+        if digits.len() % 2 == 1 {
+            digits.push('F');
+        }
+        hex::decode(digits).unwrap_or_default()
+    }
+
+    /// Track 2 image as encoded on a magnetic stripe, `;PAN=YYMMSSS...?`
+    pub fn track2_image(&self) -> String {
+        format!(
+            ";{}={}{}{}?",
+            self.pan_digits(),
+            self.expiry.yymm(),
+            self.service_code,
+            self.discretionary_data.expose()
+        )
+    }
+
+    /// Track 1 image, `%B PAN ^ NAME ^ YYMMSSS...?`
+    ///
+    /// The name follows ISO/IEC 7813 (`SURNAME/GIVEN`, at most 26 characters);
+    /// without one the empty name ` /` is used.
+    pub fn track1_image(&self, cardholder_name: Option<&str>) -> String {
+        let name: String = cardholder_name
+            .map(|name| name.trim().to_uppercase())
+            .filter(|name| !name.is_empty())
+            .unwrap_or_else(|| " /".to_string())
+            .chars()
+            .filter(|c| *c != '^')
+            .take(26)
+            .collect();
+        format!(
+            "%B{}^{}^{}{}{}?",
+            self.pan_digits(),
+            name,
+            self.expiry.yymm(),
+            self.service_code,
+            self.discretionary_data.expose()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_track2_equivalent_data() {
+        let data = hex::decode("4761739001010010D3012201114387808F").unwrap();
+        let track = Track2::parse(&data).unwrap();
+        assert_eq!(track.pan.expose().unwrap(), "4761739001010010");
+        assert_eq!(track.expiry.yymm(), "3012");
+        assert_eq!(track.service_code.to_string(), "201");
+        assert_eq!(track.discretionary_data.expose(), "114387808");
+        assert!(!format!("{:?}", track).contains("114387808"));
+        assert!(!serde_json::to_string(&track).unwrap().contains("114387808"));
+        assert!(track.service_code.is_chip_card() && track.service_code.is_international());
+        assert!(!track.service_code.pin_required());
+        assert_eq!(track.to_bytes(), data);
+
+        assert!(track
+            .validate(
+                Some(&hex::decode("4761739001010010").unwrap()),
+                Some(&[0x30, 0x12, 0x31])
+            )
+            .is_ok());
+        assert!(track.validate(None, Some(&[0x30, 0x11, 0x30])).is_err());
+        assert!(Track2::parse(&hex::decode("4761739001010010D3013201").unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_track_images() {
+        let track = Track2::new(
+            "5413330089010434",
+            "2512",
+            ServiceCode::parse("220").unwrap(),
+        )
+        .unwrap()
+        .with_discretionary_data("0000012300")
+        .unwrap();
+        assert!(track.service_code.pin_required());
+        assert!(track.service_code.online_authorisation_required());
+
+        let image = track.track2_image();
+        assert_eq!(image, ";5413330089010434=25122200000012300?");
+        assert_eq!(Track2::parse_image(&image).unwrap(), track);
+        assert!(Track2::parse_image("5413330089010434=251é200000012300").is_err());
+        assert_eq!(
+            track.track1_image(Some("Smith/John")),
+            "%B5413330089010434^SMITH/JOHN^25122200000012300?"
+        );
+    }
+}
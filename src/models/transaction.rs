@@ -1,6 +1,10 @@
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
+use super::currency::{Amount, Currency};
 use super::emv::{CardData, EmvTransactionData};
+use super::secret::{expose, SecretString};
+use super::tvr::{Tsi, Tvr};
 
 /// Transaction Request from device
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -9,7 +13,7 @@ pub struct TransactionRequest {
     pub device_id: String,
     /// Transaction amount (cents)
     pub amount: i64,
-    /// Currency code
+    /// Currency code, ISO 4217 alpha or numeric
     pub currency: String,
     /// Card data
     pub card_data: CardData,
@@ -17,6 +21,29 @@ pub struct TransactionRequest {
     pub emv_data: EmvTransactionData,
 }
 
+impl TransactionRequest {
+    /// Check amount and currency before the request reaches the backend
+    ///
+    /// The amount must fit the n12 Amount, Authorised (0x9F02) and the EMV
+    /// data must carry the same currency and amount as the request.
+    pub fn validate(&self) -> Result<Amount, String> {
+        let currency = Currency::find(&self.currency)
+            .ok_or_else(|| format!("Unknown currency: {}", self.currency))?;
+        if Currency::find(&self.emv_data.currency_code) != Some(currency) {
+            return Err(format!(
+                "EMV currency {} does not match transaction currency {}",
+                self.emv_data.currency_code, currency.alpha
+            ));
+        }
+        if self.emv_data.amount != self.amount {
+            return Err("EMV amount does not match transaction amount".to_string());
+        }
+        let value =
+            u64::try_from(self.amount).map_err(|_| format!("Invalid amount: {}", self.amount))?;
+        Amount::new(value, currency)
+    }
+}
+
 /// Attestation Request to backend
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AttestationRequest {
@@ -24,16 +51,20 @@ pub struct AttestationRequest {
     pub device_id: String,
     /// Transaction amount
     pub amount: i64,
-    /// Currency code
+    /// Currency code, ISO 4217 alpha
     pub currency_code: String,
     /// Transaction type
     pub transaction_type: String,
+    /// ISO 8583 processing code (field 3) of the transaction type
+    #[serde(default)]
+    pub processing_code: String,
     /// Card PAN (masked)
     pub card_pan: String,
     /// Card expiry
     pub card_expiry: String,
-    /// Track 2 data
-    pub track2_data: Option<String>,
+    /// Track 2 data, hex; sent to the acquirer only
+    #[serde(serialize_with = "expose::serialize")]
+    pub track2_data: Option<SecretString>,
     /// EMV data
     pub emv_data: EmvDataForAttestation,
     /// Client IP
@@ -46,13 +77,18 @@ pub struct EmvDataForAttestation {
     /// Application ID
     pub aid: String,
     /// Terminal Verification Results
-    pub tvr: Option<String>,
+    pub tvr: Option<Tvr>,
     /// Transaction Status Information
-    pub tsi: Option<String>,
+    pub tsi: Option<Tsi>,
     /// Application Cryptogram
     pub cryptogram: Option<String>,
     /// Cryptogram Information Data
     pub cid: Option<u8>,
+    /// Issuer Script Results (tag 0x9F5B), for advice and reversal messages
+    pub issuer_script_results: Option<String>,
+    /// ICC System Related Data (ISO 8583 field 55), BER-TLV hex, for
+    /// online cryptogram validation
+    pub icc_data: Option<String>,
 }
 
 /// Attestation Response from backend
@@ -66,14 +102,316 @@ pub struct AttestationResponse {
     pub auth_code: Option<String>,
     /// Response message
     pub message: Option<String>,
+    /// Authorisation Response Code (tag 0x8A), e.g. "00"
+    pub authorisation_response_code: Option<String>,
+    /// Issuer Authentication Data (tag 0x91), hex
+    pub issuer_authentication_data: Option<String>,
+    /// Issuer Script Templates (tags 0x71/0x72), hex
+    pub issuer_scripts: Option<String>,
+    /// Amount approved in minor units, when the status is
+    /// "partially_approved"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub approved_amount: Option<u64>,
 }
 
 /// Transaction Status
-#[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(rename_all = "lowercase")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum TransactionStatus {
-    Pending,
+    #[serde(alias = "pending")]
+    Initiated,
+    CardRead,
+    OnlineRequested,
     Approved,
     Declined,
+    Reversed,
+    Voided,
+    Refunded,
     Failed,
+    /// No response to the online request; the transaction may still be
+    /// reversed
+    TimedOut,
+}
+
+impl TransactionStatus {
+    /// Whether a transaction in this status may move to `next`
+    pub fn can_transition_to(&self, next: TransactionStatus) -> bool {
+        use TransactionStatus::*;
+        matches!(
+            (self, next),
+            (Initiated, CardRead | Failed | TimedOut)
+                // Offline approvals and declines skip the online request
+                | (CardRead, OnlineRequested | Approved | Declined | Failed | TimedOut)
+                | (OnlineRequested, Approved | Declined | Reversed | Failed | TimedOut)
+                | (Approved, Reversed | Voided | Refunded)
+                | (TimedOut, Reversed)
+        )
+    }
+
+    /// No further status change is possible
+    pub fn is_final(&self) -> bool {
+        !TransactionStatus::ALL
+            .iter()
+            .any(|next| self.can_transition_to(*next))
+    }
+
+    const ALL: [TransactionStatus; 10] = [
+        TransactionStatus::Initiated,
+        TransactionStatus::CardRead,
+        TransactionStatus::OnlineRequested,
+        TransactionStatus::Approved,
+        TransactionStatus::Declined,
+        TransactionStatus::Reversed,
+        TransactionStatus::Voided,
+        TransactionStatus::Refunded,
+        TransactionStatus::Failed,
+        TransactionStatus::TimedOut,
+    ];
+
+    /// Map a status reported by the backend
+    pub fn from_backend(status: &str) -> Result<Self, String> {
+        match status
+            .to_ascii_lowercase()
+            .replace(['-', ' '], "_")
+            .as_str()
+        {
+            "initiated" | "pending" | "created" => Ok(TransactionStatus::Initiated),
+            "card_read" => Ok(TransactionStatus::CardRead),
+            "online_requested" | "processing" | "authorising" | "authorizing" => {
+                Ok(TransactionStatus::OnlineRequested)
+            }
+            "approved" | "partially_approved" => Ok(TransactionStatus::Approved),
+            "declined" => Ok(TransactionStatus::Declined),
+            "reversed" => Ok(TransactionStatus::Reversed),
+            "voided" | "void" | "cancelled" | "canceled" => Ok(TransactionStatus::Voided),
+            "refunded" => Ok(TransactionStatus::Refunded),
+            "failed" | "error" => Ok(TransactionStatus::Failed),
+            "timed_out" | "timeout" => Ok(TransactionStatus::TimedOut),
+            _ => Err(format!("Unknown transaction status: {}", status)),
+        }
+    }
+}
+
+/// One status a transaction entered, and when
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StatusChange {
+    pub status: TransactionStatus,
+    pub at: DateTime<Utc>,
+}
+
+/// Status history of a transaction
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransactionLifecycle {
+    pub transaction_id: String,
+    /// Current status
+    pub status: TransactionStatus,
+    pub history: Vec<StatusChange>,
+    /// Where a reported history breaks the lifecycle rules
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub inconsistencies: Vec<String>,
+}
+
+impl TransactionLifecycle {
+    pub fn new(transaction_id: String, at: DateTime<Utc>) -> Self {
+        Self {
+            transaction_id,
+            status: TransactionStatus::Initiated,
+            history: vec![StatusChange {
+                status: TransactionStatus::Initiated,
+                at,
+            }],
+            inconsistencies: Vec::new(),
+        }
+    }
+
+    /// Move to `next`, rejecting illegal transitions and timestamps before
+    /// the last change
+    pub fn transition(&mut self, next: TransactionStatus, at: DateTime<Utc>) -> Result<(), String> {
+        if !self.status.can_transition_to(next) {
+            return Err(format!(
+                "Transaction {} cannot go from {:?} to {:?}",
+                self.transaction_id, self.status, next
+            ));
+        }
+        if self.updated_at().is_some_and(|last| at < last) {
+            return Err(format!(
+                "Transaction {} status change at {} precedes the last change",
+                self.transaction_id, at
+            ));
+        }
+        self.status = next;
+        self.history.push(StatusChange { status: next, at });
+        Ok(())
+    }
+
+    /// Time of the last status change
+    pub fn updated_at(&self) -> Option<DateTime<Utc>> {
+        self.history.last().map(|change| change.at)
+    }
+
+    /// Whether the history follows the lifecycle rules
+    pub fn is_consistent(&self) -> bool {
+        self.inconsistencies.is_empty()
+    }
+}
+
+/// Status change as reported by the backend
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackendStatusChange {
+    pub status: String,
+    pub at: DateTime<Utc>,
+}
+
+/// Transaction status response from the backend
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackendTransactionStatus {
+    pub transaction_id: String,
+    /// Current status
+    pub status: String,
+    /// Status changes, oldest first, if the backend keeps them
+    #[serde(default)]
+    pub history: Vec<BackendStatusChange>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+impl BackendTransactionStatus {
+    /// The backend's status history, exactly as reported
+    ///
+    /// Nothing is added or dropped. Transitions the lifecycle rules do not
+    /// allow, timestamps going backwards and a current status that differs
+    /// from the last change are listed in `inconsistencies`. Only status
+    /// names that cannot be mapped are an error.
+    pub fn into_lifecycle(self) -> Result<TransactionLifecycle, String> {
+        let history = self
+            .history
+            .iter()
+            .map(|change| {
+                Ok(StatusChange {
+                    status: TransactionStatus::from_backend(&change.status)?,
+                    at: change.at,
+                })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        let status = TransactionStatus::from_backend(&self.status)?;
+
+        let mut inconsistencies = Vec::new();
+        for pair in history.windows(2) {
+            if !pair[0].status.can_transition_to(pair[1].status) {
+                inconsistencies.push(format!(
+                    "{:?} cannot be followed by {:?}",
+                    pair[0].status, pair[1].status
+                ));
+            }
+            if pair[1].at < pair[0].at {
+                inconsistencies.push(format!(
+                    "{:?} at {} precedes {:?} at {}",
+                    pair[1].status, pair[1].at, pair[0].status, pair[0].at
+                ));
+            }
+        }
+        if let Some(last) = history.last().filter(|last| last.status != status) {
+            inconsistencies.push(format!(
+                "Current status {:?} differs from the last change {:?}",
+                status, last.status
+            ));
+        }
+
+        Ok(TransactionLifecycle {
+            transaction_id: self.transaction_id,
+            status,
+            history,
+            inconsistencies,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(seconds: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(1_790_000_000 + seconds, 0).unwrap()
+    }
+
+    #[test]
+    fn test_lifecycle_transitions() {
+        let mut lifecycle = TransactionLifecycle::new("txn-1".to_string(), at(0));
+        assert!(lifecycle
+            .transition(TransactionStatus::Approved, at(1))
+            .is_err());
+        lifecycle
+            .transition(TransactionStatus::CardRead, at(1))
+            .unwrap();
+        lifecycle
+            .transition(TransactionStatus::OnlineRequested, at(2))
+            .unwrap();
+        assert!(lifecycle
+            .transition(TransactionStatus::Approved, at(1))
+            .is_err());
+        lifecycle
+            .transition(TransactionStatus::TimedOut, at(30))
+            .unwrap();
+        lifecycle
+            .transition(TransactionStatus::Reversed, at(31))
+            .unwrap();
+        assert!(lifecycle.status.is_final());
+        assert_eq!(lifecycle.updated_at(), Some(at(31)));
+        assert_eq!(lifecycle.history.len(), 5);
+        assert!(!TransactionStatus::Approved.is_final());
+    }
+
+    #[test]
+    fn test_backend_status_mapping() {
+        let backend: BackendTransactionStatus = serde_json::from_value(serde_json::json!({
+            "transaction_id": "txn-456",
+            "status": "declined",
+            "history": [
+                { "status": "pending", "at": "2026-09-21T08:00:00Z" },
+                { "status": "card_read", "at": "2026-09-21T08:00:01Z" },
+                { "status": "declined", "at": "2026-09-21T08:00:02Z" }
+            ],
+            "updated_at": "2026-09-21T08:00:02Z"
+        }))
+        .unwrap();
+        let lifecycle = backend.into_lifecycle().unwrap();
+        assert_eq!(lifecycle.status, TransactionStatus::Declined);
+        assert!(lifecycle.is_consistent());
+        assert_eq!(lifecycle.history.len(), 3);
+        assert_eq!(
+            serde_json::to_value(lifecycle.status).unwrap(),
+            serde_json::json!("declined")
+        );
+
+        // A bare status keeps an empty history; a skipped step is flagged,
+        // not filled in
+        let voided = BackendTransactionStatus {
+            transaction_id: "txn-457".to_string(),
+            status: "void".to_string(),
+            history: Vec::new(),
+            updated_at: Some(at(5)),
+        };
+        let lifecycle = voided.into_lifecycle().unwrap();
+        assert_eq!(lifecycle.status, TransactionStatus::Voided);
+        assert!(lifecycle.history.is_empty() && lifecycle.is_consistent());
+
+        let skipped = BackendTransactionStatus {
+            transaction_id: "txn-458".to_string(),
+            status: "approved".to_string(),
+            history: vec![
+                BackendStatusChange {
+                    status: "initiated".to_string(),
+                    at: at(3),
+                },
+                BackendStatusChange {
+                    status: "approved".to_string(),
+                    at: at(2),
+                },
+            ],
+            updated_at: None,
+        };
+        let lifecycle = skipped.into_lifecycle().unwrap();
+        assert_eq!(lifecycle.history.len(), 2);
+        assert_eq!(lifecycle.inconsistencies.len(), 2);
+        assert!(TransactionStatus::from_backend("settled").is_err());
+    }
 }
@@ -0,0 +1,326 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use super::emv::TransactionType;
+
+/// Generates named getters/setters for single-bit flags and a description table
+macro_rules! bit_flags {
+    ($ty:ident { $( $getter:ident, $setter:ident, $byte:expr, $mask:expr, $desc:expr; )* }) => {
+        impl $ty {
+            $(
+                #[doc = $desc]
+                pub fn $getter(&self) -> bool {
+                    self.0[$byte] & $mask != 0
+                }
+
+                #[doc = $desc]
+                pub fn $setter(&mut self, value: bool) {
+                    if value {
+                        self.0[$byte] |= $mask;
+                    } else {
+                        self.0[$byte] &= !$mask;
+                    }
+                }
+            )*
+
+            /// (byte index, mask, description) for every named bit
+            const FLAGS: &'static [(usize, u8, &'static str)] = &[$(($byte, $mask, $desc)),*];
+        }
+    };
+}
+
+/// Generates hex conversions and hex-string serde for a fixed-size bitfield
+macro_rules! hex_bitfield {
+    ($ty:ident, $len:expr) => {
+        impl $ty {
+            pub fn from_bytes(bytes: [u8; $len]) -> Self {
+                Self(bytes)
+            }
+
+            pub fn as_bytes(&self) -> &[u8; $len] {
+                &self.0
+            }
+
+            pub fn to_hex(&self) -> String {
+                hex::encode_upper(self.0)
+            }
+
+            pub fn from_hex(value: &str) -> Result<Self, String> {
+                let bytes = hex::decode(value)
+                    .map_err(|e| format!("Invalid {} hex: {}", stringify!($ty), e))?;
+                <[u8; $len]>::try_from(bytes.as_slice())
+                    .map(Self)
+                    .map_err(|_| format!("{} must be {} bytes", stringify!($ty), $len))
+            }
+
+            /// Whether no bit is set
+            pub fn is_empty(&self) -> bool {
+                self.0.iter().all(|b| *b == 0)
+            }
+
+            /// Descriptions of the named bits that are set
+            pub fn explain(&self) -> Vec<String> {
+                let mut set: Vec<String> = Self::FLAGS
+                    .iter()
+                    .filter(|(byte, mask, _)| self.0[*byte] & mask != 0)
+                    .map(|(_, _, desc)| desc.to_string())
+                    .collect();
+                set.extend(self.explain_extra());
+                set
+            }
+        }
+
+        impl From<[u8; $len]> for $ty {
+            fn from(bytes: [u8; $len]) -> Self {
+                Self(bytes)
+            }
+        }
+
+        impl fmt::Display for $ty {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str(&self.to_hex())
+            }
+        }
+
+        impl FromStr for $ty {
+            type Err = String;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                Self::from_hex(s)
+            }
+        }
+
+        impl Serialize for $ty {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.serialize_str(&self.to_hex())
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $ty {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                let value = String::deserialize(deserializer)?;
+                Self::from_hex(&value).map_err(serde::de::Error::custom)
+            }
+        }
+    };
+}
+
+/// Terminal Verification Results (tag 0x95), EMV Book 3 Annex C5
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Tvr(pub [u8; 5]);
+
+/// Relay resistance protocol status, TVR byte 5 b2-b1
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RelayResistance {
+    NotSupported,
+    NotPerformed,
+    Performed,
+}
+
+bit_flags!(Tvr {
+    offline_data_authentication_not_performed, set_offline_data_authentication_not_performed, 0, 0x80, "Offline data authentication was not performed";
+    sda_failed, set_sda_failed, 0, 0x40, "SDA failed";
+    icc_data_missing, set_icc_data_missing, 0, 0x20, "ICC data missing";
+    card_on_exception_file, set_card_on_exception_file, 0, 0x10, "Card appears on terminal exception file";
+    dda_failed, set_dda_failed, 0, 0x08, "DDA failed";
+    cda_failed, set_cda_failed, 0, 0x04, "CDA failed";
+    sda_selected, set_sda_selected, 0, 0x02, "SDA selected";
+    different_application_versions, set_different_application_versions, 1, 0x80, "ICC and terminal have different application versions";
+    expired_application, set_expired_application, 1, 0x40, "Expired application";
+    application_not_yet_effective, set_application_not_yet_effective, 1, 0x20, "Application not yet effective";
+    service_not_allowed, set_service_not_allowed, 1, 0x10, "Requested service not allowed for card product";
+    new_card, set_new_card, 1, 0x08, "New card";
+    cardholder_verification_not_successful, set_cardholder_verification_not_successful, 2, 0x80, "Cardholder verification was not successful";
+    unrecognised_cvm, set_unrecognised_cvm, 2, 0x40, "Unrecognised CVM";
+    pin_try_limit_exceeded, set_pin_try_limit_exceeded, 2, 0x20, "PIN Try Limit exceeded";
+    pin_pad_not_present, set_pin_pad_not_present, 2, 0x10, "PIN entry required and PIN pad not present or not working";
+    pin_not_entered, set_pin_not_entered, 2, 0x08, "PIN entry required, PIN pad present, but PIN was not entered";
+    online_pin_entered, set_online_pin_entered, 2, 0x04, "Online PIN entered";
+    floor_limit_exceeded, set_floor_limit_exceeded, 3, 0x80, "Transaction exceeds floor limit";
+    lower_consecutive_offline_limit_exceeded, set_lower_consecutive_offline_limit_exceeded, 3, 0x40, "Lower consecutive offline limit exceeded";
+    upper_consecutive_offline_limit_exceeded, set_upper_consecutive_offline_limit_exceeded, 3, 0x20, "Upper consecutive offline limit exceeded";
+    randomly_selected_online, set_randomly_selected_online, 3, 0x10, "Transaction selected randomly for online processing";
+    merchant_forced_online, set_merchant_forced_online, 3, 0x08, "Merchant forced transaction online";
+    default_tdol_used, set_default_tdol_used, 4, 0x80, "Default TDOL used";
+    issuer_authentication_failed, set_issuer_authentication_failed, 4, 0x40, "Issuer authentication failed";
+    script_failed_before_final_ac, set_script_failed_before_final_ac, 4, 0x20, "Script processing failed before final GENERATE AC";
+    script_failed_after_final_ac, set_script_failed_after_final_ac, 4, 0x10, "Script processing failed after final GENERATE AC";
+    relay_resistance_threshold_exceeded, set_relay_resistance_threshold_exceeded, 4, 0x08, "Relay resistance threshold exceeded";
+    relay_resistance_time_limits_exceeded, set_relay_resistance_time_limits_exceeded, 4, 0x04, "Relay resistance time limits exceeded";
+});
+
+hex_bitfield!(Tvr, 5);
+
+impl Tvr {
+    /// Relay resistance protocol status (byte 5 b2-b1)
+    pub fn relay_resistance(&self) -> RelayResistance {
+        match self.0[4] & 0x03 {
+            0x01 => RelayResistance::NotPerformed,
+            0x02 => RelayResistance::Performed,
+            _ => RelayResistance::NotSupported,
+        }
+    }
+
+    pub fn set_relay_resistance(&mut self, status: RelayResistance) {
+        let bits = match status {
+            RelayResistance::NotSupported => 0x00,
+            RelayResistance::NotPerformed => 0x01,
+            RelayResistance::Performed => 0x02,
+        };
+        self.0[4] = (self.0[4] & !0x03) | bits;
+    }
+
+    fn explain_extra(&self) -> Vec<String> {
+        match self.relay_resistance() {
+            RelayResistance::NotSupported => Vec::new(),
+            RelayResistance::NotPerformed => vec!["Relay resistance not performed".to_string()],
+            RelayResistance::Performed => vec!["Relay resistance performed".to_string()],
+        }
+    }
+}
+
+/// Transaction Status Information (tag 0x9B), EMV Book 3 Annex C6
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Tsi(pub [u8; 2]);
+
+bit_flags!(Tsi {
+    offline_data_authentication_performed, set_offline_data_authentication_performed, 0, 0x80, "Offline data authentication was performed";
+    cardholder_verification_performed, set_cardholder_verification_performed, 0, 0x40, "Cardholder verification was performed";
+    card_risk_management_performed, set_card_risk_management_performed, 0, 0x20, "Card risk management was performed";
+    issuer_authentication_performed, set_issuer_authentication_performed, 0, 0x10, "Issuer authentication was performed";
+    terminal_risk_management_performed, set_terminal_risk_management_performed, 0, 0x08, "Terminal risk management was performed";
+    script_processing_performed, set_script_processing_performed, 0, 0x04, "Script processing was performed";
+});
+
+hex_bitfield!(Tsi, 2);
+
+impl Tsi {
+    fn explain_extra(&self) -> Vec<String> {
+        Vec::new()
+    }
+}
+
+/// Application Usage Control (tag 0x9F07), EMV Book 3 Annex C2
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct ApplicationUsageControl(pub [u8; 2]);
+
+bit_flags!(ApplicationUsageControl {
+    domestic_cash, set_domestic_cash, 0, 0x80, "Valid for domestic cash transactions";
+    international_cash, set_international_cash, 0, 0x40, "Valid for international cash transactions";
+    domestic_goods, set_domestic_goods, 0, 0x20, "Valid for domestic goods";
+    international_goods, set_international_goods, 0, 0x10, "Valid for international goods";
+    domestic_services, set_domestic_services, 0, 0x08, "Valid for domestic services";
+    international_services, set_international_services, 0, 0x04, "Valid for international services";
+    atms, set_atms, 0, 0x02, "Valid at ATMs";
+    non_atm_terminals, set_non_atm_terminals, 0, 0x01, "Valid at terminals other than ATMs";
+    domestic_cashback, set_domestic_cashback, 1, 0x80, "Domestic cashback allowed";
+    international_cashback, set_international_cashback, 1, 0x40, "International cashback allowed";
+});
+
+hex_bitfield!(ApplicationUsageControl, 2);
+
+impl ApplicationUsageControl {
+    fn explain_extra(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Application Usage Control check of processing restrictions (EMV Book 3
+    /// Section 10.4.3)
+    ///
+    /// `domestic` is whether the Issuer Country Code (0x5F28) equals the
+    /// Terminal Country Code; without an issuer country only the ATM check
+    /// applies. Refunds, voids and balance inquiries are not restricted by
+    /// the card beyond that.
+    pub fn allows(
+        &self,
+        transaction_type: TransactionType,
+        domestic: Option<bool>,
+        atm: bool,
+    ) -> bool {
+        let terminal_allowed = if atm {
+            self.atms()
+        } else {
+            self.non_atm_terminals()
+        };
+        let Some(domestic) = domestic else {
+            return terminal_allowed;
+        };
+
+        let service_allowed = if transaction_type.is_cash() {
+            if domestic {
+                self.domestic_cash()
+            } else {
+                self.international_cash()
+            }
+        } else if transaction_type.is_purchase() {
+            if domestic {
+                self.domestic_goods() || self.domestic_services()
+            } else {
+                self.international_goods() || self.international_services()
+            }
+        } else {
+            true
+        };
+        let cashback_allowed = transaction_type != TransactionType::PurchaseWithCashback
+            || if domestic {
+                self.domestic_cashback()
+            } else {
+                self.international_cashback()
+            };
+
+        terminal_allowed && service_allowed && cashback_allowed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tvr_accessors_and_explain() {
+        let mut tvr = Tvr::default();
+        tvr.set_offline_data_authentication_not_performed(true);
+        tvr.set_floor_limit_exceeded(true);
+        assert_eq!(tvr.to_hex(), "8000008000");
+        assert_eq!(
+            tvr.explain(),
+            vec![
+                "Offline data authentication was not performed",
+                "Transaction exceeds floor limit"
+            ]
+        );
+
+        tvr.set_floor_limit_exceeded(false);
+        assert_eq!(tvr, Tvr::from_hex("8000000000").unwrap());
+    }
+
+    #[test]
+    fn test_serde_round_trip() {
+        let tvr: Tvr = serde_json::from_str("\"0000048002\"").unwrap();
+        assert!(tvr.online_pin_entered());
+        assert!(tvr.floor_limit_exceeded());
+        assert_eq!(tvr.relay_resistance(), RelayResistance::Performed);
+        assert_eq!(serde_json::to_string(&tvr).unwrap(), "\"0000048002\"");
+
+        let tsi: Tsi = "E800".parse().unwrap();
+        assert!(tsi.terminal_risk_management_performed());
+        assert_eq!(tsi.explain().len(), 4);
+        assert!(Tvr::from_hex("8000").is_err());
+    }
+
+    #[test]
+    fn test_application_usage_control() {
+        // Domestic only: cash, goods and services, no cashback, not at ATMs
+        let auc: ApplicationUsageControl = "A900".parse().unwrap();
+        assert!(auc.allows(TransactionType::Purchase, Some(true), false));
+        assert!(auc.allows(TransactionType::CashAdvance, Some(true), false));
+        assert!(!auc.allows(TransactionType::Purchase, Some(false), false));
+        assert!(!auc.allows(TransactionType::PurchaseWithCashback, Some(true), false));
+        assert!(!auc.allows(TransactionType::Withdrawal, Some(true), true));
+        assert!(auc.allows(TransactionType::Refund, Some(false), false));
+        assert!(auc.allows(TransactionType::Purchase, None, false));
+    }
+}
@@ -0,0 +1,197 @@
+use serde::{Deserialize, Serialize};
+
+use crate::models::emv::{CryptogramType, Tlv};
+use crate::models::terminal::TerminalConfig;
+use crate::models::tvr::Tvr;
+
+/// Issuer Action Codes read from the card; absent codes use the EMV defaults
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IssuerActionCodes {
+    /// IAC - Default (tag 0x9F0D)
+    pub default: Option<[u8; 5]>,
+    /// IAC - Denial (tag 0x9F0E)
+    pub denial: Option<[u8; 5]>,
+    /// IAC - Online (tag 0x9F0F)
+    pub online: Option<[u8; 5]>,
+}
+
+impl IssuerActionCodes {
+    /// Collect IACs from card record TLV data
+    pub fn from_tlvs(tlvs: &[Tlv]) -> Self {
+        let code = |tag: &[u8]| {
+            Tlv::find_by_tag(tlvs, tag)
+                .and_then(|tlv| <[u8; 5]>::try_from(tlv.value.as_slice()).ok())
+        };
+
+        Self {
+            default: code(&[0x9F, 0x0D]),
+            denial: code(&[0x9F, 0x0E]),
+            online: code(&[0x9F, 0x0F]),
+        }
+    }
+
+    fn denial_or_default(&self) -> [u8; 5] {
+        self.denial.unwrap_or([0x00; 5])
+    }
+
+    fn online_or_default(&self) -> [u8; 5] {
+        self.online.unwrap_or([0xFF; 5])
+    }
+
+    fn default_or_default(&self) -> [u8; 5] {
+        self.default.unwrap_or([0xFF; 5])
+    }
+}
+
+/// Which check decided the cryptogram type
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ActionAnalysisReason {
+    /// A TVR bit matched IAC/TAC - Denial
+    Denial,
+    /// A TVR bit matched IAC/TAC - Online
+    Online,
+    /// Online-only terminal always requests authorisation
+    OnlineOnlyTerminal,
+    /// Terminal cannot go online and a TVR bit matched IAC/TAC - Default
+    Default,
+    /// No action code matched
+    NoMatch,
+}
+
+/// Decision of Terminal Action Analysis
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ActionAnalysisOutcome {
+    /// Cryptogram to request in the first GENERATE AC
+    pub cryptogram_type: CryptogramType,
+    pub reason: ActionAnalysisReason,
+}
+
+/// Terminal Action Analysis (EMV Book 3 Section 10.7)
+#[derive(Debug, Clone)]
+pub struct TerminalActionAnalysis {
+    config: TerminalConfig,
+}
+
+impl TerminalActionAnalysis {
+    pub fn new(config: TerminalConfig) -> Self {
+        Self { config }
+    }
+
+    /// Decide the cryptogram type for the first GENERATE AC
+    pub fn analyse(&self, tvr: &Tvr, iac: &IssuerActionCodes) -> ActionAnalysisOutcome {
+        let tac = &self.config.action_codes;
+
+        if matches_any(tvr, &iac.denial_or_default(), &tac.denial) {
+            return outcome(CryptogramType::Aac, ActionAnalysisReason::Denial);
+        }
+
+        if !self.config.is_online_capable() {
+            return self.default_action(tvr, iac);
+        }
+
+        if self.config.is_online_only() {
+            return outcome(
+                CryptogramType::Arqc,
+                ActionAnalysisReason::OnlineOnlyTerminal,
+            );
+        }
+
+        if matches_any(tvr, &iac.online_or_default(), &tac.online) {
+            outcome(CryptogramType::Arqc, ActionAnalysisReason::Online)
+        } else {
+            outcome(CryptogramType::Tc, ActionAnalysisReason::NoMatch)
+        }
+    }
+
+    /// Default action analysis, used by offline-only terminals and when an
+    /// ARQC was generated but the terminal was unable to go online
+    pub fn default_action(&self, tvr: &Tvr, iac: &IssuerActionCodes) -> ActionAnalysisOutcome {
+        let tac = &self.config.action_codes;
+        if matches_any(tvr, &iac.default_or_default(), &tac.default) {
+            outcome(CryptogramType::Aac, ActionAnalysisReason::Default)
+        } else {
+            outcome(CryptogramType::Tc, ActionAnalysisReason::NoMatch)
+        }
+    }
+}
+
+fn outcome(cryptogram_type: CryptogramType, reason: ActionAnalysisReason) -> ActionAnalysisOutcome {
+    ActionAnalysisOutcome {
+        cryptogram_type,
+        reason,
+    }
+}
+
+/// Whether any TVR bit is set in either action code
+fn matches_any(tvr: &Tvr, iac: &[u8; 5], tac: &[u8; 5]) -> bool {
+    tvr.as_bytes()
+        .iter()
+        .zip(iac.iter().zip(tac.iter()))
+        .any(|(t, (i, a))| t & (i | a) != 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::terminal::TerminalActionCodes;
+
+    fn analysis(terminal_type: u8) -> TerminalActionAnalysis {
+        TerminalActionAnalysis::new(TerminalConfig {
+            terminal_type,
+            action_codes: TerminalActionCodes {
+                denial: [0x00; 5],
+                online: [0x00, 0x00, 0x00, 0x80, 0x00],
+                default: [0x00, 0x00, 0x00, 0x80, 0x00],
+            },
+            ..TerminalConfig::default()
+        })
+    }
+
+    #[test]
+    fn test_denial_takes_precedence() {
+        let iac = IssuerActionCodes {
+            denial: Some([0x00, 0x00, 0x00, 0x80, 0x00]),
+            ..Default::default()
+        };
+        let result = analysis(0x22).analyse(&Tvr::from_hex("0000008000").unwrap(), &iac);
+        assert_eq!(result.cryptogram_type, CryptogramType::Aac);
+        assert_eq!(result.reason, ActionAnalysisReason::Denial);
+    }
+
+    #[test]
+    fn test_online_and_offline_approval() {
+        let iac = IssuerActionCodes {
+            denial: Some([0x00; 5]),
+            online: Some([0x00; 5]),
+            default: Some([0x00; 5]),
+        };
+        // Floor limit exceeded matches TAC - Online
+        let tvr = Tvr::from_hex("0000008000").unwrap();
+        assert_eq!(
+            analysis(0x22).analyse(&tvr, &iac).cryptogram_type,
+            CryptogramType::Arqc
+        );
+        assert_eq!(
+            analysis(0x22)
+                .analyse(&Tvr::default(), &iac)
+                .cryptogram_type,
+            CryptogramType::Tc
+        );
+        assert_eq!(
+            analysis(0x21)
+                .analyse(&Tvr::default(), &iac)
+                .cryptogram_type,
+            CryptogramType::Arqc
+        );
+    }
+
+    #[test]
+    fn test_offline_only_uses_default_codes() {
+        // Absent IAC - Default behaves as all bits set
+        let tvr = Tvr::from_hex("0000000001").unwrap();
+        let result = analysis(0x23).analyse(&tvr, &IssuerActionCodes::default());
+        assert_eq!(result.cryptogram_type, CryptogramType::Aac);
+        assert_eq!(result.reason, ActionAnalysisReason::Default);
+    }
+}
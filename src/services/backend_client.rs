@@ -0,0 +1,90 @@
+use std::time::Duration;
+
+use serde::de::DeserializeOwned;
+
+use crate::models::transaction::{
+    AttestationRequest, AttestationResponse, BackendTransactionStatus,
+};
+
+/// HTTP client for the SoftPOS backend, which owns all transaction state
+#[derive(Debug, Clone)]
+pub struct BackendClient {
+    base_url: String,
+    client: reqwest::Client,
+}
+
+impl BackendClient {
+    /// Client for the backend at `base_url`, e.g. `"http://127.0.0.1:8080"`
+    pub fn new(base_url: impl Into<String>, timeout: Duration) -> Result<Self, String> {
+        let client = reqwest::Client::builder()
+            .timeout(timeout)
+            .build()
+            .map_err(|e| format!("Failed to build backend client: {}", e))?;
+        Ok(Self {
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            client,
+        })
+    }
+
+    /// Backend health
+    pub async fn health_check(&self) -> Result<(), String> {
+        let response = self
+            .client
+            .get(format!("{}/health", self.base_url))
+            .send()
+            .await
+            .map_err(|e| format!("Backend request failed: {}", e))?;
+        if !response.status().is_success() {
+            return Err(format!("Backend unhealthy: {}", response.status()));
+        }
+        Ok(())
+    }
+
+    /// Forward a transaction for attestation
+    pub async fn attest_transaction(
+        &self,
+        request: AttestationRequest,
+    ) -> Result<AttestationResponse, String> {
+        let response = self
+            .client
+            .post(format!("{}/api/transactions/attest", self.base_url))
+            .json(&request)
+            .send()
+            .await
+            .map_err(|e| format!("Backend request failed: {}", e))?;
+        Self::parse(response).await
+    }
+
+    /// Current status and status history of a transaction
+    pub async fn get_transaction_status(
+        &self,
+        transaction_id: &str,
+    ) -> Result<BackendTransactionStatus, String> {
+        // The ID is one path segment, escaped
+        let mut url = reqwest::Url::parse(&self.base_url)
+            .map_err(|e| format!("Invalid backend URL {}: {}", self.base_url, e))?;
+        url.path_segments_mut()
+            .map_err(|_| format!("Invalid backend URL: {}", self.base_url))?
+            .pop_if_empty()
+            .extend(["api", "transactions", transaction_id, "status"]);
+        let response = self
+            .client
+            .get(url)
+            .send()
+            .await
+            .map_err(|e| format!("Backend request failed: {}", e))?;
+        Self::parse(response).await
+    }
+
+    async fn parse<T: DeserializeOwned>(response: reqwest::Response) -> Result<T, String> {
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(format!("Backend returned {}: {}", status, body));
+        }
+        response
+            .json()
+            .await
+            .map_err(|e| format!("Invalid backend response: {}", e))
+    }
+}
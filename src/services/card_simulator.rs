@@ -0,0 +1,851 @@
+use serde::{Deserialize, Serialize};
+
+use crate::models::dol::Dol;
+use crate::models::emv::{ApduCommand, ApduResponse, CryptogramType, Tlv};
+use crate::services::kernels::{ContactlessKernel, KernelOutcome, KernelStep};
+use crate::services::offline_pin::build_pin_block;
+use crate::utils::crypto::{application_cryptogram, rsa_public, sha1, RsaPublicKey};
+
+const PPSE: &[u8] = b"2PAY.SYS.DDF01";
+const PSE: &[u8] = b"1PAY.SYS.DDF01";
+/// Expiration date (MMYY) of the certificates the simulator issues
+const CERTIFICATE_EXPIRY: [u8; 2] = [0x12, 0x30];
+
+/// RSA key pair; profiles carry test keys only
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RsaKeyPair {
+    #[serde(with = "hex_bytes")]
+    pub modulus: Vec<u8>,
+    #[serde(with = "hex_bytes")]
+    pub public_exponent: Vec<u8>,
+    #[serde(with = "hex_bytes")]
+    pub private_exponent: Vec<u8>,
+}
+
+impl RsaKeyPair {
+    pub fn public_key(&self) -> RsaPublicKey {
+        RsaPublicKey {
+            modulus: self.modulus.clone(),
+            exponent: self.public_exponent.clone(),
+        }
+    }
+
+    fn private_key(&self) -> RsaPublicKey {
+        RsaPublicKey {
+            modulus: self.modulus.clone(),
+            exponent: self.private_exponent.clone(),
+        }
+    }
+
+    /// Sign `6A | body | SHA-1(body | hash_extra) | BC` (EMV Book 2 Annex A2.1)
+    fn sign(&self, body: &[u8], hash_extra: &[u8]) -> Result<Vec<u8>, String> {
+        let hash = sha1(&[body, hash_extra].concat());
+        let recovered = [&[0x6A][..], body, &hash, &[0xBC]].concat();
+        rsa_public(&recovered, &self.private_key())
+    }
+}
+
+/// Certification Authority test key and its index (tag 0x8F)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CaKeyPair {
+    pub index: u8,
+    #[serde(flatten)]
+    pub key: RsaKeyPair,
+}
+
+/// Keys of a simulated card
+///
+/// With CA, issuer and ICC keys the simulator issues its own certificate
+/// chain over the ODA records, so the card passes offline data
+/// authentication against the CA public key.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CardKeys {
+    /// ICC Master Key for Application Cryptograms (MK-AC), 16 bytes
+    #[serde(with = "hex_bytes")]
+    pub ac_master_key: Vec<u8>,
+    #[serde(default)]
+    pub ca: Option<CaKeyPair>,
+    #[serde(default)]
+    pub issuer: Option<RsaKeyPair>,
+    #[serde(default)]
+    pub icc: Option<RsaKeyPair>,
+}
+
+/// Record contents (inside template 0x70), numbered in order within an SFI
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecordProfile {
+    pub sfi: u8,
+    #[serde(with = "hex_bytes")]
+    pub data: Vec<u8>,
+    /// Part of the Static Data to be Authenticated
+    #[serde(default)]
+    pub oda: bool,
+}
+
+/// A card application described as JSON
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CardProfile {
+    #[serde(with = "hex_bytes")]
+    pub aid: Vec<u8>,
+    pub label: String,
+    #[serde(default, with = "hex_bytes")]
+    pub pdol: Vec<u8>,
+    /// Application Interchange Profile (tag 0x82)
+    #[serde(with = "hex_bytes")]
+    pub aip: Vec<u8>,
+    pub records: Vec<RecordProfile>,
+    /// Application Transaction Counter before the first transaction
+    #[serde(default)]
+    pub atc: u16,
+    /// Offline PIN, if the card has one
+    #[serde(default)]
+    pub pin: Option<String>,
+    #[serde(default = "default_pin_try_limit")]
+    pub pin_try_limit: u8,
+    /// Answer a TC request with a TC instead of going online
+    #[serde(default)]
+    pub offline_approval: bool,
+    #[serde(default = "default_issuer_application_data", with = "hex_bytes")]
+    pub issuer_application_data: Vec<u8>,
+    pub keys: CardKeys,
+}
+
+fn default_pin_try_limit() -> u8 {
+    3
+}
+
+fn default_issuer_application_data() -> Vec<u8> {
+    vec![0x06, 0x01, 0x0A, 0x03, 0xA0, 0x00, 0x00]
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Stage {
+    Idle,
+    Selected,
+    Initiated,
+    AwaitingSecondAc,
+    Completed,
+}
+
+/// In-process EMV card answering SELECT, GET PROCESSING OPTIONS, READ
+/// RECORD, GET DATA, GET CHALLENGE, VERIFY, INTERNAL AUTHENTICATE and
+/// GENERATE AC from a [`CardProfile`], for running kernels without hardware
+///
+/// Application cryptograms are a Retail MAC under the ATC session key over
+/// CDOL-related data | AIP | ATC.
+pub struct CardSimulator {
+    profile: CardProfile,
+    ac_master_key: [u8; 16],
+    /// SFI, record number and record template
+    records: Vec<(u8, u8, Vec<u8>)>,
+    afl: Vec<u8>,
+    atc: u16,
+    last_online_atc: u16,
+    pin_tries: u8,
+    challenge: Option<[u8; 8]>,
+    pdol_data: Vec<u8>,
+    cdol1_data: Vec<u8>,
+    stage: Stage,
+}
+
+impl CardSimulator {
+    pub fn new(profile: CardProfile) -> Result<Self, String> {
+        let ac_master_key = <[u8; 16]>::try_from(profile.keys.ac_master_key.as_slice())
+            .map_err(|_| "AC master key must be 16 bytes".to_string())?;
+        if profile.aip.len() != 2 {
+            return Err("AIP must be 2 bytes".to_string());
+        }
+
+        // Records of each SFI in profile order, ODA records first
+        let mut sfis: Vec<u8> = Vec::new();
+        for record in &profile.records {
+            if !(1..=30).contains(&record.sfi) {
+                return Err(format!("Invalid SFI: {}", record.sfi));
+            }
+            if !sfis.contains(&record.sfi) {
+                sfis.push(record.sfi);
+            }
+        }
+        let mut records = Vec::new();
+        let mut afl = Vec::new();
+        let mut static_data = Vec::new();
+        for &sfi in &sfis {
+            let in_sfi: Vec<_> = profile.records.iter().filter(|r| r.sfi == sfi).collect();
+            let oda = in_sfi.iter().take_while(|r| r.oda).count();
+            if in_sfi[oda..].iter().any(|r| r.oda) {
+                return Err(format!("ODA records must come first in SFI {}", sfi));
+            }
+            for (number, record) in in_sfi.iter().enumerate() {
+                if record.oda {
+                    static_data.extend_from_slice(&record.data);
+                }
+                records.push((
+                    sfi,
+                    number as u8 + 1,
+                    Tlv::new(&[0x70], record.data.clone()).to_bytes(),
+                ));
+            }
+            afl.extend_from_slice(&[sfi << 3, 1, in_sfi.len() as u8, oda as u8]);
+        }
+
+        let card_data: Vec<Tlv> = profile
+            .records
+            .iter()
+            .map(|record| Tlv::parse_flattened(&record.data))
+            .collect::<Result<Vec<_>, _>>()?
+            .concat();
+        if Tlv::find_by_tag(&card_data, &[0x9F, 0x4A]).is_some_and(|list| list.value == [0x82]) {
+            static_data.extend_from_slice(&profile.aip);
+        }
+
+        if let Some(certificates) = certificates(&profile.keys, &card_data, &static_data)? {
+            // Certificates go in a record of their own after the others
+            let sfi = *sfis.last().ok_or("Card has no records")?;
+            let position = afl.len() - 4;
+            afl[position + 2] += 1;
+            records.push((
+                sfi,
+                afl[position + 2],
+                Tlv::new(&[0x70], Tlv::encode(&certificates)).to_bytes(),
+            ));
+        }
+
+        Ok(Self {
+            ac_master_key,
+            records,
+            afl,
+            atc: profile.atc,
+            last_online_atc: profile.atc,
+            pin_tries: profile.pin_try_limit,
+            challenge: None,
+            pdol_data: Vec::new(),
+            cdol1_data: Vec::new(),
+            stage: Stage::Idle,
+            profile,
+        })
+    }
+
+    /// Card from a JSON [`CardProfile`]
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        let profile =
+            serde_json::from_str(json).map_err(|e| format!("Invalid card profile: {}", e))?;
+        Self::new(profile)
+    }
+
+    /// Application Transaction Counter
+    pub fn atc(&self) -> u16 {
+        self.atc
+    }
+
+    /// Remaining offline PIN tries
+    pub fn pin_try_counter(&self) -> u8 {
+        self.pin_tries
+    }
+
+    /// FCI returned when the application is selected
+    pub fn fci(&self) -> Vec<u8> {
+        let mut proprietary = vec![Tlv::new(&[0x50], self.profile.label.as_bytes().to_vec())];
+        if !self.profile.pdol.is_empty() {
+            proprietary.push(Tlv::new(&[0x9F, 0x38], self.profile.pdol.clone()));
+        }
+        Tlv::new(
+            &[0x6F],
+            Tlv::encode(&[
+                Tlv::new(&[0x84], self.profile.aid.clone()),
+                Tlv::new(&[0xA5], Tlv::encode(&proprietary)),
+            ]),
+        )
+        .to_bytes()
+    }
+
+    /// Process one command
+    pub fn transmit(&mut self, command: &ApduCommand) -> ApduResponse {
+        let data = command.data.as_deref().unwrap_or_default();
+        let result = match (command.cla, command.ins) {
+            (0x00, 0xA4) => self.select(data),
+            (0x80, 0xA8) => self.get_processing_options(data),
+            (0x00, 0xB2) => self.read_record(command.p1, command.p2),
+            (0x80, 0xCA) => self.get_data(u16::from_be_bytes([command.p1, command.p2])),
+            (0x00, 0x84) => self.get_challenge(),
+            (0x00, 0x20) => self.verify(command.p2, data),
+            (0x00, 0x88) => self.internal_authenticate(data),
+            (0x80, 0xAE) => self.generate_ac(command.p1, data),
+            (_, 0xA4 | 0xA8 | 0xB2 | 0xCA | 0x84 | 0x20 | 0x88 | 0xAE) => Err(0x6E00),
+            _ => Err(0x6D00),
+        };
+        let (data, sw) = match result {
+            Ok(data) => (data, 0x9000),
+            Err(sw) => (Vec::new(), sw),
+        };
+        ApduResponse {
+            data,
+            sw1: (sw >> 8) as u8,
+            sw2: sw as u8,
+        }
+    }
+
+    /// Select the application and run a kernel against the card until it
+    /// finishes
+    pub fn run(&mut self, kernel: &mut dyn ContactlessKernel) -> Result<KernelOutcome, String> {
+        let select = ApduCommand::new(0x00, 0xA4, 0x04, 0x00)
+            .with_data(self.profile.aid.clone())
+            .with_le(0x00);
+        let fci = self.transmit(&select);
+        if !fci.is_success() {
+            return Err(format!("SELECT failed: {:04X}", fci.status_word()));
+        }
+
+        let mut step = kernel.start(&fci.data)?;
+        loop {
+            match step {
+                KernelStep::Complete(outcome) => return Ok(*outcome),
+                KernelStep::Send(command) => {
+                    let response = self.transmit(&command);
+                    step = kernel.on_response(&response)?;
+                }
+            }
+        }
+    }
+
+    fn select(&mut self, name: &[u8]) -> Result<Vec<u8>, u16> {
+        if name == PPSE || name == PSE {
+            self.stage = Stage::Idle;
+            let entry = Tlv::encode(&[
+                Tlv::new(&[0x4F], self.profile.aid.clone()),
+                Tlv::new(&[0x50], self.profile.label.as_bytes().to_vec()),
+                Tlv::new(&[0x87], vec![0x01]),
+            ]);
+            let directory = Tlv::new(&[0xBF, 0x0C], Tlv::new(&[0x61], entry).to_bytes());
+            return Ok(Tlv::new(
+                &[0x6F],
+                Tlv::encode(&[
+                    Tlv::new(&[0x84], name.to_vec()),
+                    Tlv::new(&[0xA5], directory.to_bytes()),
+                ]),
+            )
+            .to_bytes());
+        }
+
+        // Partial selection by the start of the AID
+        if name.is_empty() || !self.profile.aid.starts_with(name) {
+            return Err(0x6A82);
+        }
+        self.stage = Stage::Selected;
+        self.challenge = None;
+        self.pdol_data.clear();
+        self.cdol1_data.clear();
+        Ok(self.fci())
+    }
+
+    fn get_processing_options(&mut self, data: &[u8]) -> Result<Vec<u8>, u16> {
+        if self.stage != Stage::Selected {
+            return Err(0x6985);
+        }
+        let pdol_data = match Tlv::parse(data).as_deref() {
+            Ok([command_template]) if command_template.tag == [0x83] => {
+                command_template.value.clone()
+            }
+            _ => return Err(0x6A80),
+        };
+        let expected = Dol::parse(&self.profile.pdol).map_or(0, |pdol| pdol.data_length());
+        if pdol_data.len() != expected {
+            return Err(0x6700);
+        }
+
+        self.atc = self.atc.checked_add(1).ok_or(0x6985_u16)?;
+        self.pdol_data = pdol_data;
+        self.stage = Stage::Initiated;
+        Ok(Tlv::new(
+            &[0x77],
+            Tlv::encode(&[
+                Tlv::new(&[0x82], self.profile.aip.clone()),
+                Tlv::new(&[0x94], self.afl.clone()),
+            ]),
+        )
+        .to_bytes())
+    }
+
+    fn read_record(&self, number: u8, p2: u8) -> Result<Vec<u8>, u16> {
+        if p2 & 0x07 != 0x04 {
+            return Err(0x6A86);
+        }
+        self.records
+            .iter()
+            .find(|(sfi, n, _)| *sfi == p2 >> 3 && *n == number)
+            .map(|(_, _, template)| template.clone())
+            .ok_or(0x6A83)
+    }
+
+    fn get_data(&self, tag: u16) -> Result<Vec<u8>, u16> {
+        let value = match tag {
+            0x9F36 => self.atc.to_be_bytes().to_vec(),
+            0x9F13 => self.last_online_atc.to_be_bytes().to_vec(),
+            0x9F17 => vec![self.pin_tries],
+            _ => return Err(0x6A88),
+        };
+        Ok(Tlv::new(&tag.to_be_bytes(), value).to_bytes())
+    }
+
+    fn get_challenge(&mut self) -> Result<Vec<u8>, u16> {
+        let mut challenge = [0u8; 8];
+        getrandom::getrandom(&mut challenge).map_err(|_| 0x6F00_u16)?;
+        self.challenge = Some(challenge);
+        Ok(challenge.to_vec())
+    }
+
+    fn verify(&mut self, p2: u8, data: &[u8]) -> Result<Vec<u8>, u16> {
+        let pin = self.profile.pin.as_deref().ok_or(0x6A88_u16)?;
+        if self.pin_tries == 0 {
+            return Err(0x6983);
+        }
+        let expected = build_pin_block(pin).map_err(|_| 0x6A88_u16)?;
+
+        let pin_block = match p2 {
+            0x80 => data.to_vec(),
+            0x88 => {
+                // 7F | PIN block | ICC challenge | padding
+                let icc = self.profile.keys.icc.as_ref().ok_or(0x6A86_u16)?;
+                let challenge = self.challenge.take().ok_or(0x6985_u16)?;
+                let recovered = rsa_public(data, &icc.private_key()).map_err(|_| 0x6984_u16)?;
+                if recovered.len() < 17 || recovered[0] != 0x7F || recovered[9..17] != challenge {
+                    return Err(0x6984);
+                }
+                recovered[1..9].to_vec()
+            }
+            _ => return Err(0x6A86),
+        };
+
+        if pin_block == expected.expose() {
+            self.pin_tries = self.profile.pin_try_limit;
+            Ok(Vec::new())
+        } else {
+            self.pin_tries -= 1;
+            Err(0x63C0 | u16::from(self.pin_tries))
+        }
+    }
+
+    /// Signed Dynamic Application Data (format 1): 80 | SDAD
+    fn internal_authenticate(&self, ddol_data: &[u8]) -> Result<Vec<u8>, u16> {
+        if self.stage == Stage::Idle {
+            return Err(0x6985);
+        }
+        let sdad = self.sign_dynamic_data(&self.atc.to_be_bytes(), &[], ddol_data)?;
+        Ok(Tlv::new(&[0x80], sdad).to_bytes())
+    }
+
+    fn generate_ac(&mut self, p1: u8, cdol_data: &[u8]) -> Result<Vec<u8>, u16> {
+        let requested = CryptogramType::from_bits(p1).map_err(|_| 0x6A86_u16)?;
+        let cryptogram_type = match (self.stage, requested) {
+            (Stage::Initiated, CryptogramType::Aac) => CryptogramType::Aac,
+            (Stage::Initiated, CryptogramType::Tc) if self.profile.offline_approval => {
+                CryptogramType::Tc
+            }
+            (Stage::Initiated, _) => CryptogramType::Arqc,
+            (Stage::AwaitingSecondAc, CryptogramType::Arqc) => return Err(0x6985),
+            (Stage::AwaitingSecondAc, requested) => requested,
+            _ => return Err(0x6985),
+        };
+        let second = self.stage == Stage::AwaitingSecondAc;
+        self.stage = match cryptogram_type {
+            CryptogramType::Arqc => Stage::AwaitingSecondAc,
+            _ => Stage::Completed,
+        };
+        if second && cryptogram_type == CryptogramType::Tc {
+            self.last_online_atc = self.atc;
+        }
+        if !second {
+            self.cdol1_data = cdol_data.to_vec();
+        }
+
+        let atc = self.atc.to_be_bytes();
+        let cryptogram = application_cryptogram(
+            &self.ac_master_key,
+            self.atc,
+            &[cdol_data, &self.profile.aip, &atc].concat(),
+        );
+        let cid = cryptogram_type.p1();
+        let mut response = vec![
+            Tlv::new(&[0x9F, 0x27], vec![cid]),
+            Tlv::new(&[0x9F, 0x36], atc.to_vec()),
+            Tlv::new(&[0x9F, 0x26], cryptogram.to_vec()),
+            Tlv::new(&[0x9F, 0x10], self.profile.issuer_application_data.clone()),
+        ];
+
+        // CDA (EMV Book 2 Section 6.6): the signature covers the cryptogram
+        // and a hash of the PDOL data, the CDOL1 data, at the second GENERATE
+        // AC the CDOL2 data, and this response
+        let cda = p1 & 0x10 != 0
+            && self.profile.aip[0] & 0x01 != 0
+            && cryptogram_type != CryptogramType::Aac;
+        if cda {
+            let cdol_tag: &[u8] = if second { &[0x8D] } else { &[0x8C] };
+            let unpredictable_number = self
+                .dol_value(cdol_tag, cdol_data, &[0x9F, 0x37])
+                .ok_or(0x6985_u16)?;
+            let cdol2_data: &[u8] = if second { cdol_data } else { &[] };
+            let hash = sha1(
+                &[
+                    &self.pdol_data,
+                    &self.cdol1_data,
+                    cdol2_data,
+                    &Tlv::encode(&response),
+                ]
+                .concat(),
+            );
+            let signed = [&[cid][..], &cryptogram, &hash].concat();
+            let sdad = self.sign_dynamic_data(&atc, &signed, &unpredictable_number)?;
+            response.push(Tlv::new(&[0x9F, 0x4B], sdad));
+        }
+
+        Ok(Tlv::new(&[0x77], Tlv::encode(&response)).to_bytes())
+    }
+
+    /// Format 05 signature over `Ldn | ICC Dynamic Number | extra` with the
+    /// terminal's dynamic data in the hash
+    fn sign_dynamic_data(
+        &self,
+        icc_dynamic_number: &[u8],
+        extra: &[u8],
+        terminal_dynamic_data: &[u8],
+    ) -> Result<Vec<u8>, u16> {
+        let icc = self.profile.keys.icc.as_ref().ok_or(0x6985_u16)?;
+        let icc_dynamic_data = [
+            &[icc_dynamic_number.len() as u8][..],
+            icc_dynamic_number,
+            extra,
+        ]
+        .concat();
+
+        let body_len = icc.public_key().modulus_len().saturating_sub(22);
+        let mut body = vec![0x05, 0x01, icc_dynamic_data.len() as u8];
+        body.extend_from_slice(&icc_dynamic_data);
+        if body.len() > body_len {
+            return Err(0x6985);
+        }
+        body.resize(body_len, 0xBB);
+        icc.sign(&body, terminal_dynamic_data)
+            .map_err(|_| 0x6985_u16)
+    }
+
+    /// Value of a data object within DOL-related data sent by the terminal
+    fn dol_value(&self, dol_tag: &[u8], dol_data: &[u8], tag: &[u8]) -> Option<Vec<u8>> {
+        let dol = self
+            .records
+            .iter()
+            .filter_map(|(_, _, template)| Tlv::parse_flattened(template).ok())
+            .find_map(|tlvs| Tlv::find_by_tag(&tlvs, dol_tag).cloned())?;
+        let mut offset = 0;
+        for entry in Dol::parse(&dol.value).ok()?.entries {
+            let value = dol_data.get(offset..offset + entry.length)?;
+            if entry.tag == tag {
+                return Some(value.to_vec());
+            }
+            offset += entry.length;
+        }
+        None
+    }
+}
+
+/// Issuer (0x90, 0x92, 0x9F32) and ICC (0x9F46-0x9F48) certificates with
+/// the CA Public Key Index (0x8F), when the profile has the keys for them
+fn certificates(
+    keys: &CardKeys,
+    card_data: &[Tlv],
+    static_data: &[u8],
+) -> Result<Option<Vec<Tlv>>, String> {
+    let (ca, issuer) = match (&keys.ca, &keys.issuer) {
+        (Some(ca), Some(issuer)) => (ca, issuer),
+        (None, None) if keys.icc.is_none() => return Ok(None),
+        _ => return Err("ICC key needs issuer and CA keys to certify it".to_string()),
+    };
+    let pan = Tlv::find_by_tag(card_data, &[0x5A])
+        .map(|tlv| tlv.value.as_slice())
+        .ok_or("Certificates need the PAN (5A) in a record")?;
+
+    // Issuer Identifier: leftmost 6 PAN digits
+    let mut issuer_id = pan.get(..3).ok_or("PAN too short")?.to_vec();
+    issuer_id.push(0xFF);
+    let header = [
+        &[0x02][..],
+        &issuer_id,
+        &CERTIFICATE_EXPIRY,
+        &[0x00, 0x00, 0x01, 0x01, 0x01],
+    ]
+    .concat();
+    let (issuer_certificate, issuer_remainder) = certify(&ca.key, &header, issuer, &[])?;
+
+    let mut tlvs = vec![
+        Tlv::new(&[0x8F], vec![ca.index]),
+        Tlv::new(&[0x90], issuer_certificate),
+        Tlv::new(&[0x92], issuer_remainder),
+        Tlv::new(&[0x9F, 0x32], issuer.public_exponent.clone()),
+    ];
+
+    if let Some(icc) = &keys.icc {
+        let mut padded_pan = pan.to_vec();
+        padded_pan.resize(10, 0xFF);
+        let header = [
+            &[0x04][..],
+            &padded_pan,
+            &CERTIFICATE_EXPIRY,
+            &[0x00, 0x00, 0x01, 0x01, 0x01],
+        ]
+        .concat();
+        let (icc_certificate, icc_remainder) = certify(issuer, &header, icc, static_data)?;
+        tlvs.extend([
+            Tlv::new(&[0x9F, 0x46], icc_certificate),
+            Tlv::new(&[0x9F, 0x47], icc.public_exponent.clone()),
+            Tlv::new(&[0x9F, 0x48], icc_remainder),
+        ]);
+    }
+
+    tlvs.retain(|tlv| !tlv.value.is_empty());
+    Ok(Some(tlvs))
+}
+
+/// Public key certificate of `subject` signed by `signer`: header, key
+/// lengths and as much of the modulus as fits, the rest as remainder
+fn certify(
+    signer: &RsaKeyPair,
+    header: &[u8],
+    subject: &RsaKeyPair,
+    static_data: &[u8],
+) -> Result<(Vec<u8>, Vec<u8>), String> {
+    let body_len = signer.public_key().modulus_len().saturating_sub(22);
+    let room = body_len.saturating_sub(header.len() + 2);
+    let modulus = &subject.modulus;
+    let split = room.min(modulus.len());
+
+    let mut body = header.to_vec();
+    body.extend_from_slice(&[modulus.len() as u8, subject.public_exponent.len() as u8]);
+    body.extend_from_slice(&modulus[..split]);
+    body.resize(body_len, 0xBB);
+    let remainder = modulus[split..].to_vec();
+
+    let hash_extra = [&remainder, &subject.public_exponent, static_data].concat();
+    Ok((signer.sign(&body, &hash_extra)?, remainder))
+}
+
+/// Binary values are written as hex in profiles
+mod hex_bytes {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&hex::encode_upper(value))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        hex::decode(&value).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::afl::AflEntry;
+    use crate::models::emv::TransactionType;
+    use crate::models::outcome::OutcomeStatus;
+    use crate::services::emv_processor::EmvProcessor;
+    use crate::services::kernels::{C4Config, KernelRegistry};
+    use crate::services::oda::tests::{
+        CA_MODULUS, CA_PRIVATE, ICC_MODULUS, ICC_PRIVATE, ISSUER_MODULUS, ISSUER_PRIVATE,
+    };
+    use crate::services::oda::{self, CaPublicKey};
+    use crate::services::terminal_context::{FixedClock, SeededRandom, TerminalContext};
+    use crate::utils::crypto::application_cryptogram;
+
+    const AC_MASTER_KEY: &str = "0123456789ABCDEFFEDCBA9876543210";
+
+    fn key(modulus: &str, private: &str) -> serde_json::Value {
+        serde_json::json!({
+            "modulus": modulus,
+            "public_exponent": "03",
+            "private_exponent": private
+        })
+    }
+
+    /// An American Express card with test keys, PIN 1234
+    fn card() -> CardSimulator {
+        let profile = serde_json::json!({
+            "aid": "A00000002501",
+            "label": "AMEX TEST",
+            "pdol": "9F02069F37045F2A02",
+            "aip": "1980",
+            "atc": 41,
+            "pin": "1234",
+            "records": [
+                {
+                    "sfi": 1,
+                    "oda": true,
+                    "data": "5A08374245455400126F5F24033012318C0E9F02069F1A0295059F37049F34038D0A8A0295059F02069F3704"
+                },
+                {
+                    "sfi": 1,
+                    "data": "8E0E000000000000000042031E031F039F0D0500000000009F0E0500000000009F0F050000000000"
+                }
+            ],
+            "keys": {
+                "ac_master_key": AC_MASTER_KEY,
+                "ca": {
+                    "index": 0x92,
+                    "modulus": CA_MODULUS,
+                    "public_exponent": "03",
+                    "private_exponent": CA_PRIVATE
+                },
+                "issuer": key(ISSUER_MODULUS, ISSUER_PRIVATE),
+                "icc": key(ICC_MODULUS, ICC_PRIVATE)
+            }
+        });
+        CardSimulator::from_json(&profile.to_string()).unwrap()
+    }
+
+    fn ca_public_key() -> CaPublicKey {
+        CaPublicKey {
+            rid: vec![0xA0, 0x00, 0x00, 0x00, 0x25],
+            index: 0x92,
+            key: crate::services::oda::tests::public_key(CA_MODULUS),
+        }
+    }
+
+    #[test]
+    fn test_full_kernel_transaction() {
+        let processor = EmvProcessor::new("840".to_string(), "USD".to_string());
+        let at = chrono::DateTime::parse_from_rfc3339("2026-10-18T09:05:42Z").unwrap();
+        let mut context = TerminalContext::new()
+            .with_random(SeededRandom::new(1))
+            .with_clock(FixedClock(at));
+        let terminal_data = processor
+            .terminal_data(&mut context, TransactionType::Purchase, 1_000, 0)
+            .unwrap();
+
+        // The kernel verifies CDA with the card's CA key
+        let registry = KernelRegistry::default().with_kernel(Box::new(C4Config {
+            ca_public_keys: vec![ca_public_key()],
+            ..C4Config::default()
+        }));
+        let mut card = card();
+        let mut kernel = processor
+            .contactless_kernel(
+                &registry,
+                &[0xA0, 0x00, 0x00, 0x00, 0x25, 0x01],
+                None,
+                TransactionType::Purchase,
+                terminal_data,
+            )
+            .unwrap();
+        let outcome = card.run(kernel.as_mut()).unwrap();
+        assert_eq!(card.atc(), 42);
+        assert_eq!(outcome.parameters.status, OutcomeStatus::OnlineRequest);
+
+        // The issuer recomputes the cryptogram from the Data Record
+        let record = &outcome.data_record;
+        let value = |tag: &[u8]| Tlv::find_by_tag(record, tag).unwrap().value.clone();
+        assert_eq!(value(&[0x9F, 0x36]), [0x00, 0x2A]);
+        let cdol1 = Dol::parse(&hex::decode("9F02069F1A0295059F37049F3403").unwrap()).unwrap();
+        let input = [cdol1.build(record), value(&[0x82]), value(&[0x9F, 0x36])].concat();
+        let key: [u8; 16] = hex::decode(AC_MASTER_KEY).unwrap().try_into().unwrap();
+        assert_eq!(
+            value(&[0x9F, 0x26]),
+            application_cryptogram(&key, 42, &input)
+        );
+        assert!(Tlv::find_by_tag(record, &[0x9F, 0x4B]).is_some());
+    }
+
+    #[test]
+    fn test_oda_pin_and_get_data() {
+        let processor = EmvProcessor::new("840".to_string(), "USD".to_string());
+        let mut card = card();
+        let aid = [0xA0, 0x00, 0x00, 0x00, 0x25, 0x01];
+        assert!(card
+            .transmit(&processor.select_application(&aid))
+            .is_success());
+        // Only the first GET PROCESSING OPTIONS is accepted
+        let gpo = processor.get_processing_options(&[0x00; 12]);
+        let response = card.transmit(&gpo);
+        assert_eq!(card.transmit(&gpo).status_word(), 0x6985);
+
+        // Read the records the AFL lists and recover the ICC Public Key
+        let tlvs = Tlv::parse_flattened(&response.data).unwrap();
+        let afl = AflEntry::parse_all(&Tlv::find_by_tag(&tlvs, &[0x94]).unwrap().value).unwrap();
+        let mut data = vec![Tlv::new(&[0x84], aid.to_vec())];
+        let mut static_data = Vec::new();
+        for entry in &afl {
+            for number in entry.first_record..=entry.last_record {
+                let record = card.transmit(&processor.read_record(entry.sfi, number));
+                assert!(record.is_success());
+                if number < entry.first_record + entry.oda_records {
+                    static_data.extend(Tlv::parse(&record.data).unwrap()[0].value.clone());
+                }
+                data.extend(Tlv::parse_flattened(&record.data).unwrap());
+            }
+        }
+        let icc_key = oda::icc_public_key(&data, &[ca_public_key()], &static_data).unwrap();
+
+        let sdad = card.transmit(&processor.internal_authenticate(&[0x11, 0x22, 0x33, 0x44]));
+        let sdad = &Tlv::parse(&sdad.data).unwrap()[0].value;
+        assert_eq!(
+            oda::verify_signed_dynamic_data(&icc_key, sdad, &[0x11, 0x22, 0x33, 0x44]).unwrap(),
+            [0x02, 0x00, 0x2A]
+        );
+
+        let wrong = processor.verify_plaintext_pin("9999").unwrap();
+        assert_eq!(card.transmit(&wrong).status_word(), 0x63C2);
+        let tries = card.transmit(&processor.get_data(0x9F17));
+        assert_eq!(tries.data.as_slice(), [0x9F, 0x17, 0x01, 0x02]);
+
+        let challenge = card.transmit(&processor.get_challenge());
+        let verify = processor
+            .verify_enciphered_pin("1234", &challenge.data, &icc_key)
+            .unwrap();
+        assert!(card.transmit(&verify).is_success());
+        assert_eq!(card.pin_try_counter(), 3);
+        // The challenge is used up
+        assert_eq!(card.transmit(&verify).status_word(), 0x6985);
+
+        // Both GENERATE ACs with CDA: the terminal recovers the signature and
+        // checks it against the PDOL, CDOL1 and CDOL2 data
+        let generate_ac = |p1: u8, data: &[u8]| {
+            ApduCommand::new(0x80, 0xAE, p1 | 0x10, 0x00)
+                .with_data(data.to_vec())
+                .with_le(0x00)
+        };
+        let cdol1_data = [vec![0x00; 13], vec![0xA1, 0xB2, 0xC3, 0xD4], vec![0x00; 3]].concat();
+        let first = card.transmit(&generate_ac(CryptogramType::Arqc.p1(), &cdol1_data));
+        assert!(first.is_success());
+        let dol_data = [vec![0x00; 12], cdol1_data.clone()].concat();
+        assert_eq!(
+            oda::verify_combined_signature(
+                &icc_key,
+                &[0xA1, 0xB2, 0xC3, 0xD4],
+                &dol_data,
+                &first.data
+            )
+            .unwrap(),
+            [0x00, 0x2A]
+        );
+        let cdol2_data = [
+            vec![0x30, 0x30],
+            vec![0x00; 11],
+            vec![0x0E, 0x0F, 0x10, 0x11],
+        ]
+        .concat();
+        let second = card.transmit(&generate_ac(CryptogramType::Tc.p1(), &cdol2_data));
+        let dol_data = [dol_data, cdol2_data].concat();
+        assert_eq!(
+            oda::verify_combined_signature(
+                &icc_key,
+                &[0x0E, 0x0F, 0x10, 0x11],
+                &dol_data,
+                &second.data
+            )
+            .unwrap(),
+            [0x00, 0x2A]
+        );
+        let tlvs = Tlv::parse_flattened(&second.data).unwrap();
+        assert_eq!(
+            Tlv::find_by_tag(&tlvs, &[0x9F, 0x27]).unwrap().value,
+            [0x40]
+        );
+    }
+}
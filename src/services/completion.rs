@@ -0,0 +1,465 @@
+use serde::{Deserialize, Serialize};
+
+use crate::models::cryptogram::GenerateAcResponse;
+use crate::models::dol::Dol;
+use crate::models::emv::{ApduCommand, ApduResponse, CryptogramType, EmvTransactionData, Tlv};
+use crate::models::issuer_script::{IssuerScript, IssuerScriptResults, ScriptTiming};
+use crate::models::transaction::AttestationResponse;
+use crate::models::tvr::{Tsi, Tvr};
+use crate::services::action_analysis::IssuerActionCodes;
+use crate::services::emv_processor::EmvProcessor;
+use crate::services::issuer_script::IssuerScriptRunner;
+
+/// Authorisation Response Code (tag 0x8A)
+pub const TAG_ARC: &[u8] = &[0x8A];
+/// Issuer Authentication Data (tag 0x91)
+pub const TAG_ISSUER_AUTHENTICATION_DATA: &[u8] = &[0x91];
+
+/// ARCs the issuer uses to approve a transaction
+const APPROVAL_CODES: &[[u8; 2]] = &[*b"00", *b"08", *b"10", *b"11"];
+/// Unable to go online, offline approved
+pub const ARC_UNABLE_TO_GO_ONLINE_APPROVED: [u8; 2] = *b"Y3";
+/// Unable to go online, offline declined
+pub const ARC_UNABLE_TO_GO_ONLINE_DECLINED: [u8; 2] = *b"Z3";
+
+/// Issuer response returned to the card
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IssuerResponse {
+    /// Authorisation Response Code (tag 0x8A), two ASCII characters
+    pub authorisation_response_code: [u8; 2],
+    /// Issuer Authentication Data (tag 0x91)
+    pub issuer_authentication_data: Option<Vec<u8>>,
+    /// Issuer scripts (tags 0x71 and 0x72)
+    #[serde(default)]
+    pub issuer_scripts: Vec<IssuerScript>,
+}
+
+impl IssuerResponse {
+    /// Take the ARC and Issuer Authentication Data from the backend response
+    pub fn from_attestation(response: &AttestationResponse) -> Result<Option<Self>, String> {
+        let arc = match &response.authorisation_response_code {
+            Some(arc) => <[u8; 2]>::try_from(arc.as_bytes())
+                .map_err(|_| format!("Invalid Authorisation Response Code: {}", arc))?,
+            None => return Ok(None),
+        };
+
+        let iad = response
+            .issuer_authentication_data
+            .as_deref()
+            .map(hex::decode)
+            .transpose()
+            .map_err(|e| format!("Invalid Issuer Authentication Data hex: {}", e))?;
+
+        let issuer_scripts = match response.issuer_scripts.as_deref() {
+            Some(scripts) => IssuerScript::parse_all(
+                &hex::decode(scripts).map_err(|e| format!("Invalid issuer script hex: {}", e))?,
+            )?,
+            None => Vec::new(),
+        };
+
+        Ok(Some(Self {
+            authorisation_response_code: arc,
+            issuer_authentication_data: iad,
+            issuer_scripts,
+        }))
+    }
+
+    /// Whether the issuer approved the transaction
+    pub fn is_approved(&self) -> bool {
+        APPROVAL_CODES.contains(&self.authorisation_response_code)
+    }
+}
+
+/// Result of sending the authorisation request
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OnlineResult {
+    /// The issuer (or backend on its behalf) responded
+    Authorised(IssuerResponse),
+    /// No response was received
+    UnableToGoOnline,
+}
+
+/// Whether AIP byte 1 b3 (issuer authentication is supported) is set
+pub fn issuer_authentication_supported(aip: &[u8]) -> bool {
+    aip.first().is_some_and(|b| b & 0x04 != 0)
+}
+
+/// Final result of online completion
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CompletionOutcome {
+    /// Whether the card returned a TC in the second GENERATE AC
+    pub approved: bool,
+    /// Authorisation Response Code sent to the card
+    pub authorisation_response_code: String,
+    /// The card's answer to the second GENERATE AC
+    pub cryptogram: GenerateAcResponse,
+    pub tvr: Tvr,
+    pub tsi: Tsi,
+    /// Issuer Script Results (tag 0x9F5B), empty when no script was received
+    pub issuer_script_results: IssuerScriptResults,
+}
+
+impl CompletionOutcome {
+    /// Record the final cryptogram, TVR, TSI and script results in the transaction data
+    pub fn apply_to(&self, transaction: &mut EmvTransactionData) {
+        self.cryptogram.apply_to(transaction);
+        transaction.tvr = Some(self.tvr);
+        transaction.tsi = Some(self.tsi);
+        transaction.issuer_script_results =
+            (!self.issuer_script_results.is_empty()).then(|| self.issuer_script_results.to_hex());
+    }
+}
+
+/// Next action requested by the online completion flow
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "step", content = "value")]
+pub enum CompletionStep {
+    /// Send this command to the card and pass the response to `on_response`
+    Send(ApduCommand),
+    /// Flow finished
+    Complete(Box<CompletionOutcome>),
+}
+
+#[derive(Debug, Clone)]
+enum State {
+    NotStarted,
+    AwaitingExternalAuthenticate,
+    AwaitingScript(ScriptTiming),
+    AwaitingSecondGenerateAc,
+    Done,
+}
+
+/// Online completion after an ARQC (EMV Book 3 Sections 10.9 and 10.10):
+/// issuer authentication, scripts before the final GENERATE AC, the second
+/// GENERATE AC, then scripts after it
+#[derive(Debug, Clone)]
+pub struct OnlineCompletion {
+    processor: EmvProcessor,
+    issuer_authentication_supported: bool,
+    cdol2: Dol,
+    data: Vec<Tlv>,
+    iac: IssuerActionCodes,
+    tvr: Tvr,
+    tsi: Tsi,
+    arc: [u8; 2],
+    requested: CryptogramType,
+    scripts_before: IssuerScriptRunner,
+    scripts_after: IssuerScriptRunner,
+    cryptogram: Option<GenerateAcResponse>,
+    state: State,
+}
+
+impl OnlineCompletion {
+    /// `aip` is the Application Interchange Profile (0x82), `cdol2` the card's
+    /// CDOL2 (0x8D) and `data` the terminal and card data objects used to fill it
+    pub fn new(
+        processor: EmvProcessor,
+        aip: &[u8],
+        cdol2: Dol,
+        data: Vec<Tlv>,
+        iac: IssuerActionCodes,
+        tvr: Tvr,
+        tsi: Tsi,
+    ) -> Self {
+        Self {
+            processor,
+            issuer_authentication_supported: issuer_authentication_supported(aip),
+            cdol2,
+            data,
+            iac,
+            tvr,
+            tsi,
+            arc: ARC_UNABLE_TO_GO_ONLINE_DECLINED,
+            requested: CryptogramType::Aac,
+            scripts_before: IssuerScriptRunner::default(),
+            scripts_after: IssuerScriptRunner::default(),
+            cryptogram: None,
+            state: State::NotStarted,
+        }
+    }
+
+    /// Begin completion with the result of the authorisation request
+    pub fn start(&mut self, result: OnlineResult) -> Result<CompletionStep, String> {
+        if !matches!(self.state, State::NotStarted) {
+            return Err("Online completion already started".to_string());
+        }
+
+        let response = match result {
+            OnlineResult::Authorised(response) => response,
+            OnlineResult::UnableToGoOnline => {
+                let decision = self
+                    .processor
+                    .terminal_action_analysis()
+                    .default_action(&self.tvr, &self.iac);
+                self.arc = match decision.cryptogram_type {
+                    CryptogramType::Tc => ARC_UNABLE_TO_GO_ONLINE_APPROVED,
+                    _ => ARC_UNABLE_TO_GO_ONLINE_DECLINED,
+                };
+                self.requested = decision.cryptogram_type;
+                return Ok(self.second_generate_ac());
+            }
+        };
+
+        self.arc = response.authorisation_response_code;
+        self.requested = if response.is_approved() {
+            CryptogramType::Tc
+        } else {
+            CryptogramType::Aac
+        };
+
+        let (before, after): (Vec<_>, Vec<_>) = response
+            .issuer_scripts
+            .into_iter()
+            .partition(|script| script.timing == ScriptTiming::BeforeFinalAc);
+        self.scripts_before = IssuerScriptRunner::new(before);
+        self.scripts_after = IssuerScriptRunner::new(after);
+
+        match response.issuer_authentication_data {
+            // Issuer authentication by EXTERNAL AUTHENTICATE
+            Some(iad) if self.issuer_authentication_supported => {
+                Tlv::upsert(&mut self.data, TAG_ISSUER_AUTHENTICATION_DATA, iad.clone());
+                self.state = State::AwaitingExternalAuthenticate;
+                Ok(CompletionStep::Send(
+                    self.processor.external_authenticate(&iad),
+                ))
+            }
+            // Otherwise the card authenticates the issuer from CDOL2, if it asks for it
+            Some(iad) => {
+                Tlv::upsert(&mut self.data, TAG_ISSUER_AUTHENTICATION_DATA, iad);
+                Ok(self.before_final_ac())
+            }
+            None => Ok(self.before_final_ac()),
+        }
+    }
+
+    /// Feed the card response to the last command sent
+    pub fn on_response(&mut self, response: &ApduResponse) -> Result<CompletionStep, String> {
+        match std::mem::replace(&mut self.state, State::Done) {
+            State::AwaitingExternalAuthenticate => {
+                self.tsi.set_issuer_authentication_performed(true);
+                if !response.is_success() {
+                    self.tvr.set_issuer_authentication_failed(true);
+                }
+                Ok(self.before_final_ac())
+            }
+            State::AwaitingScript(ScriptTiming::BeforeFinalAc) => {
+                self.scripts_before.on_response(response);
+                Ok(self.before_final_ac())
+            }
+            State::AwaitingSecondGenerateAc => {
+                let cryptogram = self
+                    .processor
+                    .parse_generate_ac_response(response, self.requested)?;
+                self.cryptogram = Some(cryptogram);
+                self.after_final_ac()
+            }
+            State::AwaitingScript(ScriptTiming::AfterFinalAc) => {
+                self.scripts_after.on_response(response);
+                self.after_final_ac()
+            }
+            state => {
+                self.state = state;
+                Err("No card response expected".to_string())
+            }
+        }
+    }
+
+    /// Deliver Issuer Script Template 1 commands, then the second GENERATE AC
+    fn before_final_ac(&mut self) -> CompletionStep {
+        if let Some(command) = self.scripts_before.next_command() {
+            self.state = State::AwaitingScript(ScriptTiming::BeforeFinalAc);
+            return CompletionStep::Send(command);
+        }
+
+        if self.scripts_before.performed() {
+            self.tsi.set_script_processing_performed(true);
+        }
+        if self.scripts_before.any_failed() {
+            self.tvr.set_script_failed_before_final_ac(true);
+        }
+        self.second_generate_ac()
+    }
+
+    /// Deliver Issuer Script Template 2 commands, then complete
+    fn after_final_ac(&mut self) -> Result<CompletionStep, String> {
+        if let Some(command) = self.scripts_after.next_command() {
+            self.state = State::AwaitingScript(ScriptTiming::AfterFinalAc);
+            return Ok(CompletionStep::Send(command));
+        }
+
+        if self.scripts_after.performed() {
+            self.tsi.set_script_processing_performed(true);
+        }
+        if self.scripts_after.any_failed() {
+            self.tvr.set_script_failed_after_final_ac(true);
+        }
+
+        let cryptogram = self
+            .cryptogram
+            .take()
+            .ok_or("Second GENERATE AC response missing")?;
+        let approved = cryptogram.cid.cryptogram_type()? == CryptogramType::Tc;
+        let results = self
+            .scripts_before
+            .results()
+            .iter()
+            .chain(self.scripts_after.results())
+            .copied()
+            .collect();
+
+        Ok(CompletionStep::Complete(Box::new(CompletionOutcome {
+            approved,
+            authorisation_response_code: String::from_utf8_lossy(&self.arc).into_owned(),
+            cryptogram,
+            tvr: self.tvr,
+            tsi: self.tsi,
+            issuer_script_results: IssuerScriptResults(results),
+        })))
+    }
+
+    /// Fill CDOL2 with the final ARC, TVR and TSI and build the second GENERATE AC
+    fn second_generate_ac(&mut self) -> CompletionStep {
+        Tlv::upsert(&mut self.data, TAG_ARC, self.arc.to_vec());
+        Tlv::upsert(&mut self.data, &[0x95], self.tvr.as_bytes().to_vec());
+        Tlv::upsert(&mut self.data, &[0x9B], self.tsi.as_bytes().to_vec());
+
+        let cdol2_data = self.cdol2.build(&self.data);
+        self.state = State::AwaitingSecondGenerateAc;
+        CompletionStep::Send(self.processor.generate_ac(self.requested, &cdol2_data))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn completion(aip: &[u8], iac: IssuerActionCodes) -> OnlineCompletion {
+        // ARC, TVR, Issuer Authentication Data
+        let cdol2 = Dol::parse(&hex::decode("8A0295059108").unwrap()).unwrap();
+        OnlineCompletion::new(
+            EmvProcessor::new("156".to_string(), "CNY".to_string()),
+            aip,
+            cdol2,
+            Vec::new(),
+            iac,
+            Tvr::default(),
+            Tsi::default(),
+        )
+    }
+
+    fn response(hex_str: &str) -> ApduResponse {
+        ApduResponse::from_bytes(&hex::decode(hex_str).unwrap()).unwrap()
+    }
+
+    fn sent(step: CompletionStep) -> ApduCommand {
+        match step {
+            CompletionStep::Send(cmd) => cmd,
+            other => panic!("expected a command, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_external_authenticate_failure_sets_tvr() {
+        let mut flow = completion(&[0x3C, 0x00], IssuerActionCodes::default());
+        let issuer = IssuerResponse {
+            authorisation_response_code: *b"00",
+            issuer_authentication_data: Some(vec![0x11; 8]),
+            issuer_scripts: Vec::new(),
+        };
+
+        let cmd = sent(flow.start(OnlineResult::Authorised(issuer)).unwrap());
+        assert_eq!((cmd.cla, cmd.ins), (0x00, 0x82));
+
+        let cmd = sent(flow.on_response(&response("6300")).unwrap());
+        assert_eq!(cmd.p1, CryptogramType::Tc.p1());
+        assert_eq!(
+            hex::encode_upper(cmd.data.as_ref().unwrap()),
+            "303000000000401111111111111111"
+        );
+
+        let step = flow
+            .on_response(&response("800B000012A1A2A3A4A5A6A7A89000"))
+            .unwrap();
+        match step {
+            CompletionStep::Complete(outcome) => {
+                assert!(!outcome.approved);
+                assert!(outcome.tvr.issuer_authentication_failed());
+                assert!(outcome.tsi.issuer_authentication_performed());
+            }
+            other => panic!("unexpected step {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_issuer_authentication_in_cdol2() {
+        // AIP without issuer authentication: data goes straight into CDOL2
+        let mut flow = completion(&[0x38, 0x00], IssuerActionCodes::default());
+        let issuer = IssuerResponse {
+            authorisation_response_code: *b"05",
+            issuer_authentication_data: Some(vec![0x22; 8]),
+            issuer_scripts: Vec::new(),
+        };
+
+        let cmd = sent(flow.start(OnlineResult::Authorised(issuer)).unwrap());
+        assert_eq!((cmd.ins, cmd.p1), (0xAE, CryptogramType::Aac.p1()));
+        assert!(hex::encode_upper(cmd.data.as_ref().unwrap()).starts_with("3035"));
+    }
+
+    #[test]
+    fn test_unable_to_go_online_uses_default_action() {
+        let iac = IssuerActionCodes {
+            default: Some([0x00; 5]),
+            ..Default::default()
+        };
+        let mut flow = completion(&[0x3C, 0x00], iac);
+
+        let cmd = sent(flow.start(OnlineResult::UnableToGoOnline).unwrap());
+        assert_eq!(cmd.p1, CryptogramType::Tc.p1());
+        assert!(hex::encode_upper(cmd.data.as_ref().unwrap()).starts_with("5933"));
+
+        let step = flow
+            .on_response(&response("800B400012A1A2A3A4A5A6A7A89000"))
+            .unwrap();
+        assert!(matches!(step, CompletionStep::Complete(outcome) if outcome.approved));
+    }
+
+    #[test]
+    fn test_scripts_around_second_generate_ac() {
+        let mut flow = completion(&[0x38, 0x00], IssuerActionCodes::default());
+        let scripts = hex::decode("71078605841E000000720C860A84DA9F58050011223344").unwrap();
+        let issuer = IssuerResponse {
+            authorisation_response_code: *b"00",
+            issuer_authentication_data: None,
+            issuer_scripts: IssuerScript::parse_all(&scripts).unwrap(),
+        };
+
+        // Template 1 fails before the final GENERATE AC
+        let cmd = sent(flow.start(OnlineResult::Authorised(issuer)).unwrap());
+        assert_eq!(cmd.ins, 0x1E);
+        let cmd = sent(flow.on_response(&response("6985")).unwrap());
+        assert_eq!(cmd.ins, 0xAE);
+        assert_eq!(
+            hex::encode_upper(&cmd.data.as_ref().unwrap()[2..7]),
+            "0000000020"
+        );
+
+        // Template 2 is delivered after it
+        let cmd = sent(
+            flow.on_response(&response("800B400012A1A2A3A4A5A6A7A89000"))
+                .unwrap(),
+        );
+        assert_eq!(cmd.ins, 0xDA);
+        match flow.on_response(&response("9000")).unwrap() {
+            CompletionStep::Complete(outcome) => {
+                assert!(outcome.approved);
+                assert!(outcome.tsi.script_processing_performed());
+                assert_eq!(
+                    outcome.issuer_script_results.to_hex(),
+                    "11000000002000000000"
+                );
+            }
+            other => panic!("unexpected step {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,416 @@
+use serde::{Deserialize, Serialize};
+
+use crate::models::cvm::{CvMethod, CvmCondition, CvmList, CvmResult, CvmResults, CvmRule};
+use crate::models::emv::TransactionType;
+use crate::models::terminal::TerminalConfig;
+use crate::models::tvr::{Tsi, Tvr};
+
+/// Transaction facts that CVM conditions are evaluated against
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CvmContext {
+    /// Authorised amount (minor units)
+    pub amount: u64,
+    /// Cashback amount (minor units), zero when no cashback
+    pub cashback_amount: u64,
+    /// Transaction currency code (ISO 4217 numeric)
+    pub transaction_currency_code: String,
+    /// Application Currency Code (tag 0x9F42), numeric
+    pub application_currency_code: Option<String>,
+    /// Transaction type
+    pub transaction_type: TransactionType,
+    /// Terminal configuration
+    pub terminal: TerminalConfig,
+    /// Cardholder already verified on the consumer device
+    pub cdcvm_performed: bool,
+}
+
+impl CvmContext {
+    fn is_cash(&self) -> bool {
+        self.transaction_type.is_cash()
+    }
+
+    fn is_unattended_cash(&self) -> bool {
+        self.is_cash() && self.terminal.is_unattended()
+    }
+
+    fn is_manual_cash(&self) -> bool {
+        self.transaction_type == TransactionType::CashAdvance && !self.terminal.is_unattended()
+    }
+
+    fn is_cashback(&self) -> bool {
+        self.cashback_amount > 0
+    }
+
+    /// Amount X/Y conditions only apply when transaction and application currency match
+    fn in_application_currency(&self) -> bool {
+        match self.application_currency_code {
+            Some(ref app) => {
+                app.trim_start_matches('0')
+                    == self.transaction_currency_code.trim_start_matches('0')
+            }
+            None => false,
+        }
+    }
+}
+
+/// Outcome of an attempt to perform a CVM, reported by the terminal
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CvmAttempt {
+    /// Method verified (e.g. offline PIN accepted by the card)
+    Successful,
+    /// Method performed but outcome unknown to the terminal (online PIN, signature)
+    Unknown,
+    /// Method failed
+    Failed,
+    /// Cardholder bypassed PIN entry
+    PinNotEntered,
+    /// Card reported the PIN try limit as exceeded
+    PinTryLimitExceeded,
+}
+
+/// Next action requested by CVM processing
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "step", content = "value")]
+pub enum CvmStep {
+    /// Terminal must perform the method and report the result
+    Perform(CvMethod),
+    /// CVM processing has finished
+    Complete(CvmOutcome),
+}
+
+/// Final result of CVM processing
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CvmOutcome {
+    /// Method that completed the processing, if any
+    pub method: Option<CvMethod>,
+    /// Cardholder verification did not fail
+    pub successful: bool,
+    /// CVM Results (tag 0x9F34)
+    pub cvm_results: CvmResults,
+    /// Signature line must be printed on the receipt
+    pub signature_required: bool,
+    /// Terminal Verification Results after CVM processing
+    pub tvr: Tvr,
+    /// Transaction Status Information after CVM processing
+    pub tsi: Tsi,
+}
+
+/// CVM List processing (EMV Book 3 Section 10.5)
+///
+/// Walks the CV Rules in order, asking the terminal to perform each
+/// selected method via [`CvmStep::Perform`] and continuing with the next
+/// rule when a method fails and the rule allows it.
+#[derive(Debug, Clone)]
+pub struct CvmProcessor {
+    list: Option<CvmList>,
+    cvm_supported: bool,
+    context: CvmContext,
+    next_rule: usize,
+    pending: Option<CvmRule>,
+    last_attempted: Option<CvmRule>,
+    tvr: Tvr,
+    tsi: Tsi,
+}
+
+impl CvmProcessor {
+    /// Create a processor; `cvm_supported` is AIP byte 1 b5
+    pub fn new(list: Option<CvmList>, cvm_supported: bool, context: CvmContext) -> Self {
+        Self {
+            list,
+            cvm_supported,
+            context,
+            next_rule: 0,
+            pending: None,
+            last_attempted: None,
+            tvr: Tvr::default(),
+            tsi: Tsi::default(),
+        }
+    }
+
+    /// Start processing with the TVR/TSI accumulated so far
+    pub fn start(&mut self, tvr: Tvr, tsi: Tsi) -> CvmStep {
+        self.tvr = tvr;
+        self.tsi = tsi;
+        self.next_rule = 0;
+        self.pending = None;
+        self.last_attempted = None;
+
+        if !self.cvm_supported {
+            return self.complete(None, CvmResults::not_performed(CvmResult::Unknown), true);
+        }
+
+        if self.context.cdcvm_performed {
+            self.tsi.set_cardholder_verification_performed(true);
+            let results = CvmResults {
+                method_code: CvMethod::Cdcvm.code(),
+                condition_code: CvmCondition::Always.code(),
+                result: CvmResult::Successful,
+            };
+            return self.complete(Some(CvMethod::Cdcvm), results, true);
+        }
+
+        let has_rules = self.list.as_ref().is_some_and(|l| !l.rules.is_empty());
+        if !has_rules {
+            self.tvr.set_icc_data_missing(true);
+            return self.complete(None, CvmResults::not_performed(CvmResult::Unknown), true);
+        }
+
+        self.tsi.set_cardholder_verification_performed(true);
+        self.advance()
+    }
+
+    /// Report the result of the method requested by the last [`CvmStep::Perform`]
+    pub fn report(&mut self, attempt: CvmAttempt) -> Result<CvmStep, String> {
+        let rule = self.pending.take().ok_or("No CVM is awaiting a result")?;
+
+        match attempt {
+            CvmAttempt::Successful | CvmAttempt::Unknown => {
+                if rule.method == CvMethod::OnlinePin {
+                    self.tvr.set_online_pin_entered(true);
+                }
+                let result = if attempt == CvmAttempt::Unknown
+                    || rule.method.requires_signature()
+                    || rule.method == CvMethod::OnlinePin
+                {
+                    CvmResult::Unknown
+                } else {
+                    CvmResult::Successful
+                };
+                Ok(self.complete(Some(rule.method), CvmResults::for_rule(&rule, result), true))
+            }
+            CvmAttempt::Failed => Ok(self.fail_rule(rule)),
+            CvmAttempt::PinNotEntered => {
+                self.tvr.set_pin_not_entered(true);
+                Ok(self.fail_rule(rule))
+            }
+            CvmAttempt::PinTryLimitExceeded => {
+                self.tvr.set_pin_try_limit_exceeded(true);
+                Ok(self.fail_rule(rule))
+            }
+        }
+    }
+
+    /// Evaluate rules from the current position until one needs the terminal
+    fn advance(&mut self) -> CvmStep {
+        let rules = self
+            .list
+            .as_ref()
+            .map(|l| l.rules.clone())
+            .unwrap_or_default();
+
+        while self.next_rule < rules.len() {
+            let rule = rules[self.next_rule];
+            self.next_rule += 1;
+
+            if !self.condition_satisfied(&rule) {
+                continue;
+            }
+
+            self.last_attempted = Some(rule);
+
+            let failed = match rule.method {
+                CvMethod::Unrecognised(_) => {
+                    self.tvr.set_unrecognised_cvm(true);
+                    true
+                }
+                CvMethod::Fail => true,
+                method if !self.terminal_supports(method) => true,
+                CvMethod::NoCvm => {
+                    let results = CvmResults::for_rule(&rule, CvmResult::Successful);
+                    return self.complete(Some(CvMethod::NoCvm), results, true);
+                }
+                method if method.requires_pin() && !self.context.terminal.pin_pad_available => {
+                    self.tvr.set_pin_pad_not_present(true);
+                    true
+                }
+                method => {
+                    self.pending = Some(rule);
+                    return CvmStep::Perform(method);
+                }
+            };
+
+            if failed && !rule.apply_next_if_unsuccessful {
+                return self.fail(rule);
+            }
+        }
+
+        match self.last_attempted {
+            Some(rule) => self.fail(rule),
+            None => {
+                self.tvr.set_cardholder_verification_not_successful(true);
+                self.complete(None, CvmResults::not_performed(CvmResult::Failed), false)
+            }
+        }
+    }
+
+    /// A performed method failed: continue if the rule allows it
+    fn fail_rule(&mut self, rule: CvmRule) -> CvmStep {
+        if rule.apply_next_if_unsuccessful {
+            self.advance()
+        } else {
+            self.fail(rule)
+        }
+    }
+
+    fn fail(&mut self, rule: CvmRule) -> CvmStep {
+        self.tvr.set_cardholder_verification_not_successful(true);
+        self.complete(None, CvmResults::for_rule(&rule, CvmResult::Failed), false)
+    }
+
+    fn complete(
+        &mut self,
+        method: Option<CvMethod>,
+        cvm_results: CvmResults,
+        successful: bool,
+    ) -> CvmStep {
+        CvmStep::Complete(CvmOutcome {
+            method,
+            successful,
+            cvm_results,
+            signature_required: method.is_some_and(|m| m.requires_signature()),
+            tvr: self.tvr,
+            tsi: self.tsi,
+        })
+    }
+
+    fn condition_satisfied(&self, rule: &CvmRule) -> bool {
+        let ctx = &self.context;
+        let (x, y) = self
+            .list
+            .as_ref()
+            .map_or((0, 0), |l| (l.amount_x as u64, l.amount_y as u64));
+
+        match rule.condition {
+            CvmCondition::Always => true,
+            CvmCondition::UnattendedCash => ctx.is_unattended_cash(),
+            CvmCondition::NotUnattendedCashNotManualCashNotCashback => {
+                !ctx.is_unattended_cash() && !ctx.is_manual_cash() && !ctx.is_cashback()
+            }
+            CvmCondition::TerminalSupportsCvm => self.terminal_supports(rule.method),
+            CvmCondition::ManualCash => ctx.is_manual_cash(),
+            CvmCondition::PurchaseWithCashback => ctx.is_cashback(),
+            CvmCondition::UnderX => ctx.in_application_currency() && ctx.amount < x,
+            CvmCondition::OverX => ctx.in_application_currency() && ctx.amount > x,
+            CvmCondition::UnderY => ctx.in_application_currency() && ctx.amount < y,
+            CvmCondition::OverY => ctx.in_application_currency() && ctx.amount > y,
+            CvmCondition::Unrecognised(_) => false,
+        }
+    }
+
+    fn terminal_supports(&self, method: CvMethod) -> bool {
+        let caps = &self.context.terminal.capabilities;
+        match method {
+            CvMethod::Fail => true,
+            CvMethod::OfflinePlaintextPin => caps.supports_plaintext_pin(),
+            CvMethod::OnlinePin => caps.supports_online_pin(),
+            CvMethod::OfflinePlaintextPinAndSignature => {
+                caps.supports_plaintext_pin() && caps.supports_signature()
+            }
+            CvMethod::OfflineEncipheredPin => caps.supports_enciphered_pin(),
+            CvMethod::OfflineEncipheredPinAndSignature => {
+                caps.supports_enciphered_pin() && caps.supports_signature()
+            }
+            CvMethod::Signature => caps.supports_signature(),
+            CvMethod::NoCvm => caps.supports_no_cvm(),
+            CvMethod::Cdcvm | CvMethod::Unrecognised(_) => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::terminal::TerminalCapabilities;
+
+    fn context(amount: u64) -> CvmContext {
+        CvmContext {
+            amount,
+            cashback_amount: 0,
+            transaction_currency_code: "156".to_string(),
+            application_currency_code: Some("0156".to_string()),
+            transaction_type: TransactionType::Purchase,
+            terminal: TerminalConfig::default(),
+            cdcvm_performed: false,
+        }
+    }
+
+    // X = 100.00; online PIN over X, otherwise No CVM
+    fn cvm_list() -> CvmList {
+        CvmList::parse(&hex::decode("000027100000000042071F00").unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_parse_cvm_list() {
+        let list = cvm_list();
+        assert_eq!(list.amount_x, 10000);
+        assert_eq!(list.rules.len(), 2);
+        assert_eq!(list.rules[0].method, CvMethod::OnlinePin);
+        assert!(list.rules[0].apply_next_if_unsuccessful);
+        assert_eq!(list.rules[0].condition, CvmCondition::OverX);
+        assert!(CvmList::parse(&[0x00; 9]).is_err());
+    }
+
+    #[test]
+    fn test_no_cvm_under_limit() {
+        let mut processor = CvmProcessor::new(Some(cvm_list()), true, context(5000));
+        match processor.start(Tvr::default(), Tsi::default()) {
+            CvmStep::Complete(outcome) => {
+                assert_eq!(outcome.method, Some(CvMethod::NoCvm));
+                assert_eq!(outcome.cvm_results.to_hex(), "1F0002");
+                assert!(outcome.tsi.cardholder_verification_performed());
+            }
+            step => panic!("unexpected step: {:?}", step),
+        }
+    }
+
+    #[test]
+    fn test_online_pin_over_limit() {
+        let mut processor = CvmProcessor::new(Some(cvm_list()), true, context(20000));
+        assert_eq!(
+            processor.start(Tvr::default(), Tsi::default()),
+            CvmStep::Perform(CvMethod::OnlinePin)
+        );
+
+        match processor.report(CvmAttempt::Unknown).unwrap() {
+            CvmStep::Complete(outcome) => {
+                assert_eq!(outcome.cvm_results.to_hex(), "420700");
+                assert!(outcome.tvr.online_pin_entered());
+            }
+            step => panic!("unexpected step: {:?}", step),
+        }
+    }
+
+    #[test]
+    fn test_pin_bypass_applies_next_rule() {
+        let mut processor = CvmProcessor::new(Some(cvm_list()), true, context(20000));
+        processor.start(Tvr::default(), Tsi::default());
+
+        match processor.report(CvmAttempt::PinNotEntered).unwrap() {
+            CvmStep::Complete(outcome) => {
+                assert!(outcome.successful);
+                assert_eq!(outcome.method, Some(CvMethod::NoCvm));
+                assert!(outcome.tvr.pin_not_entered());
+            }
+            step => panic!("unexpected step: {:?}", step),
+        }
+    }
+
+    #[test]
+    fn test_unsupported_method_fails() {
+        let mut ctx = context(20000);
+        ctx.terminal.capabilities = TerminalCapabilities([0xE0, 0x08, 0xC8]);
+        // Signature only, no fallback
+        let list = CvmList::parse(&hex::decode("00000000000000001E00").unwrap()).unwrap();
+        let mut processor = CvmProcessor::new(Some(list), true, ctx);
+
+        match processor.start(Tvr::default(), Tsi::default()) {
+            CvmStep::Complete(outcome) => {
+                assert!(!outcome.successful);
+                assert_eq!(outcome.cvm_results.to_hex(), "1E0001");
+                assert!(outcome.tvr.cardholder_verification_not_successful());
+            }
+            step => panic!("unexpected step: {:?}", step),
+        }
+    }
+}
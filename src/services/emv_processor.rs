@@ -1,23 +1,189 @@
 use crate::console_log;
-use crate::models::emv::{ApduCommand, ApduResponse, CardData, Tlv};
+use crate::models::card_date::CardDate;
+use crate::models::cryptogram::GenerateAcResponse;
+use crate::models::currency::{Amount, Country, Currency};
+use crate::models::cvm::CvmList;
+use crate::models::dol::Dol;
+use crate::models::emv::{
+    ApduCommand, ApduResponse, CardData, CryptogramType, EmvTransactionData, Tlv, TransactionType,
+};
+use crate::models::pan::Pan;
+use crate::models::secret::SecretString;
+use crate::models::terminal::TerminalConfig;
+use crate::models::track::Track2;
+use crate::models::tvr::{ApplicationUsageControl, Tsi, Tvr};
+use crate::services::action_analysis::{
+    ActionAnalysisOutcome, IssuerActionCodes, TerminalActionAnalysis,
+};
+use crate::services::completion::OnlineCompletion;
+use crate::services::cvm::{CvmContext, CvmProcessor};
+use crate::services::kernels::{ContactlessKernel, KernelRegistry};
+use crate::services::offline_pin;
+use crate::services::risk_management::TerminalRiskManager;
+use crate::services::terminal_context::TerminalContext;
+use crate::utils::crypto::RsaPublicKey;
 
 /// EMV Processor Service
 /// Handles EMV card interaction and APDU command processing
-#[derive(Clone)]
+#[derive(Debug, Clone)]
 pub struct EmvProcessor {
     // Terminal configuration
     terminal_country_code: String,
     terminal_currency_code: String,
+    terminal_config: TerminalConfig,
 }
 
 impl EmvProcessor {
+    /// Country and currency may be given as alpha or numeric ISO codes;
+    /// known codes are kept in their numeric form
     pub fn new(country_code: String, currency_code: String) -> Self {
         Self {
-            terminal_country_code: country_code,
-            terminal_currency_code: currency_code,
+            terminal_country_code: Country::find(&country_code)
+                .map(Country::numeric_code)
+                .unwrap_or(country_code),
+            terminal_currency_code: Currency::find(&currency_code)
+                .map(Currency::numeric_code)
+                .unwrap_or(currency_code),
+            terminal_config: TerminalConfig::default(),
         }
     }
 
+    /// Set terminal type, capabilities and PIN pad availability
+    pub fn with_terminal_config(mut self, config: TerminalConfig) -> Self {
+        self.terminal_config = config;
+        self
+    }
+
+    /// Terminal Country Code
+    pub fn terminal_country_code(&self) -> &str {
+        &self.terminal_country_code
+    }
+
+    /// Terminal Currency Code
+    pub fn terminal_currency_code(&self) -> &str {
+        &self.terminal_currency_code
+    }
+
+    /// Terminal configuration
+    pub fn terminal_config(&self) -> &TerminalConfig {
+        &self.terminal_config
+    }
+
+    /// Terminal country, if the configured code is known
+    pub fn country(&self) -> Option<&'static Country> {
+        Country::find(&self.terminal_country_code)
+    }
+
+    /// Terminal currency, if the configured code is known
+    pub fn currency(&self) -> Option<&'static Currency> {
+        Currency::find(&self.terminal_currency_code)
+    }
+
+    /// Transaction data objects for the card: Amount, Authorised (0x9F02),
+    /// Amount, Other (0x9F03), Transaction Currency Code (0x5F2A), Transaction
+    /// Currency Exponent (0x5F36), Terminal Country Code (0x9F1A) and
+    /// Transaction Type (0x9C)
+    ///
+    /// Only a purchase with cashback carries an other amount, and the
+    /// authorised amount includes it.
+    pub fn transaction_data(
+        &self,
+        transaction_type: TransactionType,
+        amount: u64,
+        amount_other: u64,
+    ) -> Result<Vec<Tlv>, String> {
+        let currency = self
+            .currency()
+            .ok_or_else(|| format!("Unknown currency: {}", self.terminal_currency_code))?;
+        let country = self
+            .country()
+            .ok_or_else(|| format!("Unknown country: {}", self.terminal_country_code))?;
+        match transaction_type {
+            TransactionType::PurchaseWithCashback if amount_other == 0 => {
+                return Err("Cashback amount is missing".to_string());
+            }
+            TransactionType::PurchaseWithCashback if amount_other > amount => {
+                return Err("Cashback amount exceeds the authorised amount".to_string());
+            }
+            TransactionType::PurchaseWithCashback => {}
+            _ if amount_other != 0 => {
+                return Err(format!(
+                    "Other amount is only allowed with cashback, not {:?}",
+                    transaction_type
+                ));
+            }
+            _ => {}
+        }
+        Ok(vec![
+            Tlv::new(
+                &[0x9F, 0x02],
+                Amount::new(amount, currency)?.to_n12().to_vec(),
+            ),
+            Tlv::new(
+                &[0x9F, 0x03],
+                Amount::new(amount_other, currency)?.to_n12().to_vec(),
+            ),
+            Tlv::new(&[0x5F, 0x2A], currency.to_bcd().to_vec()),
+            Tlv::new(&[0x5F, 0x36], vec![currency.exponent()]),
+            Tlv::new(&[0x9F, 0x1A], country.to_bcd().to_vec()),
+            Tlv::new(&[0x9C], vec![transaction_type.code()]),
+        ])
+    }
+
+    /// Transaction data plus the terminal's Unpredictable Number (0x9F37),
+    /// Transaction Date (0x9A), Transaction Time (0x9F21) and Transaction
+    /// Sequence Counter (0x9F41): the data objects DOLs ask the terminal for
+    pub fn terminal_data(
+        &self,
+        context: &mut TerminalContext,
+        transaction_type: TransactionType,
+        amount: u64,
+        amount_other: u64,
+    ) -> Result<Vec<Tlv>, String> {
+        let mut data = self.transaction_data(transaction_type, amount, amount_other)?;
+        data.extend(context.next_transaction()?.to_tlvs());
+        Ok(data)
+    }
+
+    /// Processing restrictions (EMV Book 3 Section 10.4) on the card's
+    /// Application Usage Control (0x9F07), Issuer Country Code (0x5F28) and
+    /// application dates, as TVR bits
+    pub fn processing_restrictions(
+        &self,
+        card_data: &[Tlv],
+        transaction_type: TransactionType,
+        transaction_date: &CardDate,
+    ) -> Tvr {
+        let mut tvr = Tvr::default();
+        let find = |tag: &[u8]| Tlv::find_by_tag(card_data, tag).map(|tlv| tlv.value.as_slice());
+
+        if let Some(auc) = find(&[0x9F, 0x07]).and_then(|auc| <[u8; 2]>::try_from(auc).ok()) {
+            let domestic = find(&[0x5F, 0x28]).map(|issuer| {
+                self.country()
+                    .is_some_and(|country| issuer == country.to_bcd())
+            });
+            let allowed = ApplicationUsageControl(auc).allows(
+                transaction_type,
+                domestic,
+                self.terminal_config.is_atm(),
+            );
+            tvr.set_service_not_allowed(!allowed);
+        }
+        // A date the card formats wrongly counts as failing the check
+        if let Some(expiry) = find(&[0x5F, 0x24]) {
+            tvr.set_expired_application(
+                !CardDate::from_bcd(expiry).is_ok_and(|date| !date.is_expired_on(transaction_date)),
+            );
+        }
+        if let Some(effective) = find(&[0x5F, 0x25]) {
+            tvr.set_application_not_yet_effective(
+                !CardDate::from_bcd(effective)
+                    .is_ok_and(|date| date.is_effective_on(transaction_date)),
+            );
+        }
+        tvr
+    }
+
     /// SELECT PPSE (Payment System Environment)
     pub fn select_ppse(&self) -> ApduCommand {
         // SELECT command: CLA=00, INS=A4, P1=04, P2=00
@@ -59,32 +225,195 @@ impl EmvProcessor {
     }
 
     /// GENERATE AC (Application Cryptogram)
-    pub fn generate_ac(&self, ac_type: u8, cdol_data: &[u8]) -> ApduCommand {
-        // P1: AC type (0x40=AAC, 0x80=TC, 0xC0=ARQC)
-        ApduCommand::new(0x80, 0xAE, ac_type, 0x00)
+    pub fn generate_ac(&self, ac_type: CryptogramType, cdol_data: &[u8]) -> ApduCommand {
+        // P1: AC type (0x00=AAC, 0x40=TC, 0x80=ARQC)
+        ApduCommand::new(0x80, 0xAE, ac_type.p1(), 0x00)
             .with_data(cdol_data.to_vec())
             .with_le(0x00)
     }
 
-    /// Parse card data from TLV response
+    /// Terminal Action Analysis followed by the first GENERATE AC
+    ///
+    /// The cryptogram type is decided from the TVR, the card's Issuer Action
+    /// Codes and this terminal's Terminal Action Codes.
+    pub fn first_generate_ac(
+        &self,
+        tvr: &Tvr,
+        iac: &IssuerActionCodes,
+        cdol1_data: &[u8],
+    ) -> (ActionAnalysisOutcome, ApduCommand) {
+        let decision = self.terminal_action_analysis().analyse(tvr, iac);
+        let command = self.generate_ac(decision.cryptogram_type, cdol1_data);
+        (decision, command)
+    }
+
+    /// Parse and validate the card's answer to GENERATE AC
+    pub fn parse_generate_ac_response(
+        &self,
+        response: &ApduResponse,
+        requested: CryptogramType,
+    ) -> Result<GenerateAcResponse, String> {
+        self.validate_response(response)?;
+        let parsed = GenerateAcResponse::parse(&response.data)?;
+        parsed.validate_against(requested)?;
+        Ok(parsed)
+    }
+
+    /// EXTERNAL AUTHENTICATE with the Issuer Authentication Data (tag 0x91)
+    pub fn external_authenticate(&self, issuer_authentication_data: &[u8]) -> ApduCommand {
+        ApduCommand::new(0x00, 0x82, 0x00, 0x00).with_data(issuer_authentication_data.to_vec())
+    }
+
+    /// INTERNAL AUTHENTICATE (DDA) with DDOL-related data
+    pub fn internal_authenticate(&self, ddol_data: &[u8]) -> ApduCommand {
+        ApduCommand::new(0x00, 0x88, 0x00, 0x00)
+            .with_data(ddol_data.to_vec())
+            .with_le(0x00)
+    }
+
+    /// EXCHANGE RELAY RESISTANCE DATA with the Terminal Relay Resistance Entropy
+    pub fn exchange_relay_resistance_data(&self, entropy: &[u8; 4]) -> ApduCommand {
+        ApduCommand::new(0x80, 0xEA, 0x00, 0x00)
+            .with_data(entropy.to_vec())
+            .with_le(0x00)
+    }
+
+    /// COMPUTE CRYPTOGRAPHIC CHECKSUM (mag-stripe mode) with UDOL-related data
+    pub fn compute_cryptographic_checksum(&self, udol_data: &[u8]) -> ApduCommand {
+        ApduCommand::new(0x80, 0x2A, 0x8E, 0x80)
+            .with_data(udol_data.to_vec())
+            .with_le(0x00)
+    }
+
+    /// RECOVER AC for a torn transaction with DRDOL-related data
+    pub fn recover_ac(&self, drdol_data: &[u8]) -> ApduCommand {
+        ApduCommand::new(0x80, 0xD0, 0x00, 0x00)
+            .with_data(drdol_data.to_vec())
+            .with_le(0x00)
+    }
+
+    /// Online completion: issuer authentication and the second GENERATE AC
+    ///
+    /// `data` holds the terminal and card data objects used to fill CDOL2 (0x8D).
+    pub fn online_completion(
+        &self,
+        aip: &[u8],
+        cdol2: Dol,
+        data: Vec<Tlv>,
+        iac: IssuerActionCodes,
+        tvr: Tvr,
+        tsi: Tsi,
+    ) -> OnlineCompletion {
+        OnlineCompletion::new(self.clone(), aip, cdol2, data, iac, tvr, tsi)
+    }
+
+    /// Contactless kernel for a card application selected from the PPSE
+    ///
+    /// The kernel comes from the Entry Point combination matching the ADF Name
+    /// and the directory entry's Kernel Identifier (0x9F2A), if present.
+    pub fn contactless_kernel(
+        &self,
+        registry: &KernelRegistry,
+        adf_name: &[u8],
+        kernel_identifier: Option<&[u8]>,
+        transaction_type: TransactionType,
+        terminal_data: Vec<Tlv>,
+    ) -> Result<Box<dyn ContactlessKernel>, String> {
+        registry.create(
+            self.clone(),
+            adf_name,
+            kernel_identifier,
+            transaction_type,
+            terminal_data,
+        )
+    }
+
+    /// Terminal Action Analysis using this terminal's action codes
+    pub fn terminal_action_analysis(&self) -> TerminalActionAnalysis {
+        TerminalActionAnalysis::new(self.terminal_config.clone())
+    }
+
+    /// GET DATA for a primitive data object (e.g. 0x9F17 PIN Try Counter)
+    pub fn get_data(&self, tag: u16) -> ApduCommand {
+        ApduCommand::new(0x80, 0xCA, (tag >> 8) as u8, tag as u8).with_le(0x00)
+    }
+
+    /// GET CHALLENGE (8-byte ICC unpredictable number)
+    pub fn get_challenge(&self) -> ApduCommand {
+        ApduCommand::new(0x00, 0x84, 0x00, 0x00).with_le(0x00)
+    }
+
+    /// VERIFY with a plaintext PIN block (P2=80)
+    pub fn verify_plaintext_pin(&self, pin: &str) -> Result<ApduCommand, String> {
+        let pin_block = offline_pin::build_pin_block(pin)?;
+        Ok(ApduCommand::new(0x00, 0x20, 0x00, 0x80).with_data(pin_block.expose().to_vec()))
+    }
+
+    /// VERIFY with an enciphered PIN (P2=88)
+    ///
+    /// `key` is the ICC PIN Encipherment Public Key, or the ICC Public Key
+    /// when the card has no dedicated PIN key.
+    pub fn verify_enciphered_pin(
+        &self,
+        pin: &str,
+        challenge: &[u8],
+        key: &RsaPublicKey,
+    ) -> Result<ApduCommand, String> {
+        let pin_block = offline_pin::build_pin_block(pin)?;
+        let enciphered = offline_pin::encipher_pin_block(&pin_block, challenge, key)?;
+        Ok(ApduCommand::new(0x00, 0x20, 0x00, 0x88).with_data(enciphered))
+    }
+
+    /// Parse card data from TLV response, rejecting cards that are expired or
+    /// not yet effective today
     pub fn parse_card_data(&self, tlv_data: &[u8], aid: String) -> Result<CardData, String> {
+        self.parse_card_data_on(tlv_data, aid, &CardDate::today())
+    }
+
+    /// Parse card data from TLV response for a transaction on the given date
+    pub fn parse_card_data_on(
+        &self,
+        tlv_data: &[u8],
+        aid: String,
+        transaction_date: &CardDate,
+    ) -> Result<CardData, String> {
         console_log!("parse_card_data is is calling");
         let tlvs = Tlv::parse(tlv_data)?;
 
         // Extract PAN (tag 0x5A)
         let pan_tlv = Tlv::find_by_tag(&tlvs, &[0x5A]).ok_or("PAN not found")?;
-        let pan = self.format_pan(&pan_tlv.value);
+        let pan = Pan::from_bcd(&pan_tlv.value)?;
 
         // Extract expiry date (tag 0x5F24)
         let expiry_tlv = Tlv::find_by_tag(&tlvs, &[0x5F, 0x24]).ok_or("Expiry date not found")?;
-        let expiry = self.format_expiry(&expiry_tlv.value);
+        let expiry = CardDate::from_bcd(&expiry_tlv.value)?;
+        if expiry.is_expired_on(transaction_date) {
+            return Err(format!("Card expired on {}", expiry));
+        }
+
+        // Extract effective date (tag 0x5F25) - optional
+        let effective_date = Tlv::find_by_tag(&tlvs, &[0x5F, 0x25])
+            .map(|tlv| CardDate::from_bcd(&tlv.value))
+            .transpose()?;
+        if let Some(effective) = effective_date {
+            if !effective.is_effective_on(transaction_date) {
+                return Err(format!("Card not effective until {}", effective));
+            }
+        }
 
         // Extract cardholder name (tag 0x5F20) - optional
         let cardholder_name = Tlv::find_by_tag(&tlvs, &[0x5F, 0x20])
-            .map(|tlv| String::from_utf8_lossy(&tlv.value).to_string());
+            .map(|tlv| SecretString::new(String::from_utf8_lossy(&tlv.value).to_string()));
 
-        // Extract Track 2 (tag 0x57) - optional
-        let track2 = Tlv::find_by_tag(&tlvs, &[0x57]).map(|tlv| hex::encode(&tlv.value));
+        // Extract Track 2 (tag 0x57) - optional, must agree with PAN and expiry
+        let track2 = match Tlv::find_by_tag(&tlvs, &[0x57]) {
+            Some(tlv) => {
+                Track2::parse(&tlv.value)?
+                    .validate(Some(&pan_tlv.value), Some(&expiry_tlv.value))?;
+                Some(SecretString::new(hex::encode(&tlv.value)))
+            }
+            None => None,
+        };
 
         // Extract application label (tag 0x50) - optional
         let app_label = Tlv::find_by_tag(&tlvs, &[0x50])
@@ -93,6 +422,7 @@ impl EmvProcessor {
         Ok(CardData {
             pan,
             expiry,
+            effective_date,
             cardholder_name,
             track2,
             aid,
@@ -100,24 +430,46 @@ impl EmvProcessor {
         })
     }
 
-    /// Format PAN from BCD encoding
-    fn format_pan(&self, bcd_data: &[u8]) -> String {
-        console_log!("format_pan is is calling");
-        let hex_str = hex::encode(bcd_data);
-        // Remove padding 'F'
-        hex_str
-            .trim_end_matches('f')
-            .trim_end_matches('F')
-            .to_string()
+    /// Build the CVM condition context for a transaction
+    ///
+    /// `application_currency` is the raw value of tag 0x9F42, if read from the card.
+    pub fn cvm_context(
+        &self,
+        transaction: &EmvTransactionData,
+        application_currency: Option<&[u8]>,
+    ) -> CvmContext {
+        CvmContext {
+            amount: transaction.amount.max(0) as u64,
+            cashback_amount: 0,
+            transaction_currency_code: Currency::find(&transaction.currency_code)
+                .map(Currency::numeric_code)
+                .unwrap_or_else(|| transaction.currency_code.clone()),
+            application_currency_code: application_currency.map(hex::encode),
+            transaction_type: transaction.transaction_type,
+            terminal: self.terminal_config.clone(),
+            cdcvm_performed: false,
+        }
     }
 
-    /// Format expiry date from BCD (YYMMDD -> YYMM)
-    fn format_expiry(&self, bcd_data: &[u8]) -> String {
-        if bcd_data.len() >= 2 {
-            format!("{:02x}{:02x}", bcd_data[0], bcd_data[1])
-        } else {
-            String::new()
-        }
+    /// Prepare CVM List processing from the CVM List (0x8E) and AIP (0x82) values
+    pub fn cvm_processor(
+        &self,
+        cvm_list: Option<&[u8]>,
+        aip: &[u8],
+        context: CvmContext,
+    ) -> Result<CvmProcessor, String> {
+        let list = cvm_list.map(CvmList::parse).transpose()?;
+        // AIP byte 1 b5: cardholder verification is supported
+        let cvm_supported = aip.first().is_some_and(|b| b & 0x10 != 0);
+        Ok(CvmProcessor::new(list, cvm_supported, context))
+    }
+
+    /// Terminal risk management using this terminal's floor limit and random selection
+    ///
+    /// Velocity checking needs ATC and Last Online ATC Register, read with
+    /// [`EmvProcessor::get_data`] for tags 0x9F36 and 0x9F13.
+    pub fn terminal_risk_manager(&self) -> TerminalRiskManager {
+        TerminalRiskManager::new(self.terminal_config.clone())
     }
 
     /// Validate APDU response
@@ -155,4 +507,93 @@ mod tests {
         assert_eq!(cmd.p1, 1);
         assert_eq!(cmd.p2, 0x0C); // (1 << 3) | 0x04
     }
+
+    #[test]
+    fn test_parse_generate_ac_format1() {
+        let processor = EmvProcessor::new("156".to_string(), "CNY".to_string());
+        let response = ApduResponse::from_bytes(
+            &hex::decode("801280001A1122334455667788060A0A03A000009000").unwrap(),
+        )
+        .unwrap();
+
+        let parsed = processor
+            .parse_generate_ac_response(&response, CryptogramType::Arqc)
+            .unwrap();
+        assert_eq!(parsed.cid.cryptogram_type(), Ok(CryptogramType::Arqc));
+        assert_eq!(parsed.atc, 0x001A);
+        assert_eq!(
+            parsed.issuer_application_data,
+            Some(vec![0x06, 0x0A, 0x0A, 0x03, 0xA0, 0x00, 0x00])
+        );
+    }
+
+    #[test]
+    fn test_parse_generate_ac_format2() {
+        let processor = EmvProcessor::new("156".to_string(), "CNY".to_string());
+        let response = ApduResponse::from_bytes(
+            &hex::decode("771A9F2701409F360200059F2608A1A2A3A4A5A6A7A89F10030102039000").unwrap(),
+        )
+        .unwrap();
+
+        let parsed = processor
+            .parse_generate_ac_response(&response, CryptogramType::Tc)
+            .unwrap();
+        assert_eq!(parsed.cid.cryptogram_type(), Ok(CryptogramType::Tc));
+        assert_eq!(hex::encode_upper(parsed.cryptogram), "A1A2A3A4A5A6A7A8");
+
+        // A TC is higher than a requested AAC
+        assert!(processor
+            .parse_generate_ac_response(&response, CryptogramType::Aac)
+            .is_err());
+    }
+
+    #[test]
+    fn test_transaction_data() {
+        let processor = EmvProcessor::new("CN".to_string(), "156".to_string());
+        assert_eq!(processor.terminal_country_code(), "156");
+        assert_eq!(processor.currency().unwrap().alpha, "CNY");
+
+        let data = processor
+            .transaction_data(TransactionType::PurchaseWithCashback, 12_345, 2_000)
+            .unwrap();
+        let value = |tag: &[u8]| Tlv::find_by_tag(&data, tag).unwrap().value.clone();
+        assert_eq!(value(&[0x9F, 0x02]), [0x00, 0x00, 0x00, 0x01, 0x23, 0x45]);
+        assert_eq!(value(&[0x9F, 0x03]), [0x00, 0x00, 0x00, 0x00, 0x20, 0x00]);
+        assert_eq!(value(&[0x5F, 0x2A]), [0x01, 0x56]);
+        assert_eq!(value(&[0x5F, 0x36]), [0x02]);
+        assert_eq!(value(&[0x9F, 0x1A]), [0x01, 0x56]);
+        assert_eq!(value(&[0x9C]), [0x09]);
+
+        assert!(processor
+            .transaction_data(TransactionType::Purchase, 12_345, 2_000)
+            .is_err());
+        assert!(processor
+            .transaction_data(TransactionType::PurchaseWithCashback, 1_000, 2_000)
+            .is_err());
+        let unknown = EmvProcessor::new("156".to_string(), "XYZ".to_string());
+        assert!(unknown
+            .transaction_data(TransactionType::Purchase, 100, 0)
+            .is_err());
+    }
+
+    #[test]
+    fn test_processing_restrictions() {
+        let processor = EmvProcessor::new("CN".to_string(), "CNY".to_string());
+        let today = CardDate::new(2026, 3, 1).unwrap();
+        // Domestic goods and services only, issued in the US
+        let card = vec![
+            Tlv::new(&[0x9F, 0x07], vec![0x29, 0x00]),
+            Tlv::new(&[0x5F, 0x28], vec![0x08, 0x40]),
+            Tlv::new(&[0x5F, 0x24], vec![0x26, 0x02, 0x28]),
+        ];
+        let tvr = processor.processing_restrictions(&card, TransactionType::Purchase, &today);
+        assert!(tvr.service_not_allowed() && tvr.expired_application());
+
+        let domestic = EmvProcessor::new("US".to_string(), "USD".to_string());
+        let tvr = domestic.processing_restrictions(&card[..2], TransactionType::Purchase, &today);
+        assert!(tvr.is_empty());
+        let tvr =
+            domestic.processing_restrictions(&card[..2], TransactionType::CashAdvance, &today);
+        assert!(tvr.service_not_allowed());
+    }
 }
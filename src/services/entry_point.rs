@@ -0,0 +1,262 @@
+use serde::{Deserialize, Serialize};
+
+use crate::models::emv::{Tlv, TransactionType};
+use crate::models::outcome::{OutcomeParameterSet, OutcomeStatus};
+use crate::services::emv_processor::EmvProcessor;
+use crate::services::kernels::{
+    Combination, ContactlessKernel, EntryPointConfig, KernelId, KernelOutcome, KernelRegistry,
+};
+
+/// Terminal Transaction Qualifiers (tag 0x9F66)
+const TAG_TTQ: &[u8] = &[0x9F, 0x66];
+
+/// Amount used for a status check: one unit of a currency with two decimals
+const STATUS_CHECK_AMOUNT: u64 = 100;
+
+/// Entry Point Pre-Processing Indicators for one combination
+/// (EMV Book B Section 3.1.1)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PreProcessingIndicators {
+    pub status_check_requested: bool,
+    pub contactless_application_not_allowed: bool,
+    pub zero_amount: bool,
+    pub cvm_required_limit_exceeded: bool,
+    pub floor_limit_exceeded: bool,
+    /// Copy of the configured TTQ with the amount dependent bits set
+    pub ttq: Option<[u8; 4]>,
+}
+
+impl PreProcessingIndicators {
+    pub fn new(config: &EntryPointConfig, amount: u64, online_capable: bool) -> Self {
+        let mut indicators = Self {
+            ttq: config.ttq.map(|mut ttq| {
+                // Online cryptogram and CVM required are set below
+                ttq[1] &= 0x3F;
+                ttq
+            }),
+            ..Self::default()
+        };
+
+        if config.status_check_supported && amount == STATUS_CHECK_AMOUNT {
+            indicators.status_check_requested = true;
+        }
+        if amount == 0 {
+            if config.zero_amount_allowed && online_capable {
+                indicators.zero_amount = true;
+            } else {
+                indicators.contactless_application_not_allowed = true;
+            }
+        }
+        if config
+            .transaction_limit
+            .is_some_and(|limit| amount >= limit)
+        {
+            indicators.contactless_application_not_allowed = true;
+        }
+        if config
+            .cvm_required_limit
+            .is_some_and(|limit| amount >= limit)
+        {
+            indicators.cvm_required_limit_exceeded = true;
+        }
+        if config.floor_limit.is_some_and(|limit| amount > limit) {
+            indicators.floor_limit_exceeded = true;
+        }
+
+        if let Some(ttq) = indicators.ttq.as_mut() {
+            if indicators.status_check_requested
+                || indicators.zero_amount
+                || indicators.floor_limit_exceeded
+            {
+                ttq[1] |= 0x80;
+            }
+            if indicators.cvm_required_limit_exceeded {
+                ttq[1] |= 0x40;
+            }
+        }
+        indicators
+    }
+}
+
+/// Combinations still allowed for a transaction after pre-processing
+#[derive(Debug, Clone)]
+pub struct PreProcessing {
+    pub amount: u64,
+    pub transaction_type: TransactionType,
+    combinations: Vec<(Combination, PreProcessingIndicators)>,
+}
+
+impl PreProcessing {
+    /// Allowed combination for a card application and its indicators
+    pub fn select(
+        &self,
+        adf_name: &[u8],
+        kernel_identifier: Option<&[u8]>,
+    ) -> Option<(&Combination, &PreProcessingIndicators)> {
+        let kernel_id = KernelId::resolve(adf_name, kernel_identifier)?;
+        self.combinations
+            .iter()
+            .find(|(combination, indicators)| {
+                !indicators.contactless_application_not_allowed
+                    && combination.matches(adf_name, kernel_id, self.transaction_type)
+            })
+            .map(|(combination, indicators)| (combination, indicators))
+    }
+}
+
+/// EMV contactless Entry Point (EMV Book B): pre-processing and kernel
+/// activation for the combinations in a kernel registry
+pub struct EntryPoint {
+    processor: EmvProcessor,
+    registry: KernelRegistry,
+}
+
+impl EntryPoint {
+    pub fn new(processor: EmvProcessor, registry: KernelRegistry) -> Self {
+        Self {
+            processor,
+            registry,
+        }
+    }
+
+    /// Pre-processing for a new transaction
+    ///
+    /// When no combination allows the amount the cardholder is asked to use
+    /// another interface and no card should be polled for.
+    pub fn pre_process(
+        &self,
+        amount: u64,
+        transaction_type: TransactionType,
+    ) -> Result<PreProcessing, Box<KernelOutcome>> {
+        let online_capable = self.processor.terminal_config().is_online_capable();
+        let combinations: Vec<_> = self
+            .registry
+            .combinations()
+            .iter()
+            .filter(|c| {
+                c.transaction_types.is_empty() || c.transaction_types.contains(&transaction_type)
+            })
+            .map(|c| {
+                let indicators = PreProcessingIndicators::new(&c.config, amount, online_capable);
+                (c.clone(), indicators)
+            })
+            .collect();
+
+        if combinations
+            .iter()
+            .all(|(_, indicators)| indicators.contactless_application_not_allowed)
+        {
+            let mut parameters = OutcomeParameterSet::new(OutcomeStatus::TryAnotherInterface);
+            parameters.ui_request_on_outcome = true;
+            // "Please Insert or Swipe Card"
+            return Err(Box::new(KernelOutcome::new(
+                parameters,
+                Vec::new(),
+                Vec::new(),
+            )));
+        }
+
+        Ok(PreProcessing {
+            amount,
+            transaction_type,
+            combinations,
+        })
+    }
+
+    /// Activate the kernel for a card application selected from the PPSE,
+    /// passing it the pre-set TTQ if the combination has one
+    pub fn activate(
+        &self,
+        pre_processing: &PreProcessing,
+        adf_name: &[u8],
+        kernel_identifier: Option<&[u8]>,
+        terminal_data: Vec<Tlv>,
+    ) -> Result<Box<dyn ContactlessKernel>, String> {
+        let (combination, indicators) = pre_processing
+            .select(adf_name, kernel_identifier)
+            .ok_or_else(|| {
+                format!(
+                    "No allowed combination for AID {}",
+                    hex::encode_upper(adf_name)
+                )
+            })?;
+
+        let mut terminal_data = terminal_data;
+        if let Some(ttq) = indicators.ttq {
+            Tlv::upsert(&mut terminal_data, TAG_TTQ, ttq.to_vec());
+        }
+        self.registry
+            .create_for(self.processor.clone(), combination, terminal_data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::outcome::UiMessage;
+
+    const VISA: [u8; 7] = [0xA0, 0x00, 0x00, 0x00, 0x03, 0x10, 0x10];
+
+    fn entry_point(config: EntryPointConfig) -> EntryPoint {
+        EntryPoint::new(
+            EmvProcessor::new("840".to_string(), "USD".to_string()),
+            KernelRegistry::empty()
+                .with_kernel(Box::new(crate::services::kernels::C3Config::default()))
+                .with_combination(Combination::new(&VISA, KernelId::Visa).with_config(config)),
+        )
+    }
+
+    #[test]
+    fn test_indicators_preset_ttq() {
+        let config = EntryPointConfig {
+            status_check_supported: true,
+            floor_limit: Some(2_000),
+            cvm_required_limit: Some(5_000),
+            ttq: Some([0x36, 0xC0, 0x40, 0x00]),
+            ..EntryPointConfig::default()
+        };
+
+        let indicators = PreProcessingIndicators::new(&config, 1_000, true);
+        assert!(!indicators.floor_limit_exceeded);
+        assert_eq!(indicators.ttq, Some([0x36, 0x00, 0x40, 0x00]));
+
+        let indicators = PreProcessingIndicators::new(&config, 6_000, true);
+        assert!(indicators.floor_limit_exceeded && indicators.cvm_required_limit_exceeded);
+        assert_eq!(indicators.ttq, Some([0x36, 0xC0, 0x40, 0x00]));
+
+        let indicators = PreProcessingIndicators::new(&config, 100, true);
+        assert!(indicators.status_check_requested);
+        assert_eq!(indicators.ttq, Some([0x36, 0x80, 0x40, 0x00]));
+
+        // Zero amount needs the combination to allow it
+        assert!(PreProcessingIndicators::new(&config, 0, true).contactless_application_not_allowed);
+    }
+
+    #[test]
+    fn test_all_combinations_disabled() {
+        let entry_point = entry_point(EntryPointConfig {
+            transaction_limit: Some(10_000),
+            ..EntryPointConfig::default()
+        });
+
+        let outcome = entry_point
+            .pre_process(10_000, TransactionType::Purchase)
+            .unwrap_err();
+        assert_eq!(
+            outcome.parameters.status,
+            OutcomeStatus::TryAnotherInterface
+        );
+        assert_eq!(
+            outcome.ui_request_on_outcome.unwrap().message,
+            UiMessage::PleaseInsertOrSwipeCard
+        );
+
+        let pre_processing = entry_point
+            .pre_process(9_999, TransactionType::Purchase)
+            .unwrap();
+        let kernel = entry_point
+            .activate(&pre_processing, &VISA, None, Vec::new())
+            .unwrap();
+        assert_eq!(kernel.kernel_id(), KernelId::Visa);
+    }
+}
@@ -0,0 +1,183 @@
+use serde::{Deserialize, Serialize};
+
+use crate::models::emv::Tlv;
+use crate::services::kernels::KernelId;
+
+/// A data object of field 55 and whether the issuer needs it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Field55Tag {
+    #[serde(with = "hex_tag")]
+    pub tag: Vec<u8>,
+    /// The request is not sent without it
+    pub mandatory: bool,
+}
+
+/// Data objects sent in ISO 8583 field 55 (ICC System Related Data), in order
+///
+/// The mandatory tags are the ones an issuer needs to validate the ARQC;
+/// optional tags are sent when the kernel has them.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Field55Spec {
+    pub tags: Vec<Field55Tag>,
+}
+
+/// Cryptogram input and result, EMV Book 4 Section 6.5.7
+const MANDATORY: &[&[u8]] = &[
+    &[0x9F, 0x26], // Application Cryptogram
+    &[0x9F, 0x27], // Cryptogram Information Data
+    &[0x9F, 0x10], // Issuer Application Data
+    &[0x9F, 0x37], // Unpredictable Number
+    &[0x9F, 0x36], // Application Transaction Counter
+    &[0x95],       // Terminal Verification Results
+    &[0x9A],       // Transaction Date
+    &[0x9C],       // Transaction Type
+    &[0x9F, 0x02], // Amount, Authorised
+    &[0x5F, 0x2A], // Transaction Currency Code
+    &[0x82],       // Application Interchange Profile
+    &[0x9F, 0x1A], // Terminal Country Code
+];
+
+const OPTIONAL: &[&[u8]] = &[
+    &[0x9F, 0x03], // Amount, Other
+    &[0x9F, 0x33], // Terminal Capabilities
+    &[0x9F, 0x34], // CVM Results
+    &[0x9F, 0x35], // Terminal Type
+    &[0x84],       // Dedicated File Name
+    &[0x9F, 0x09], // Application Version Number
+    &[0x9F, 0x1E], // Interface Device Serial Number
+    &[0x9F, 0x41], // Transaction Sequence Counter
+    &[0x5F, 0x34], // PAN Sequence Number
+];
+
+impl Default for Field55Spec {
+    /// The EMV minimum data set
+    fn default() -> Self {
+        let tags = MANDATORY
+            .iter()
+            .map(|tag| (tag, true))
+            .chain(OPTIONAL.iter().map(|tag| (tag, false)))
+            .map(|(tag, mandatory)| Field55Tag {
+                tag: tag.to_vec(),
+                mandatory,
+            })
+            .collect();
+        Self { tags }
+    }
+}
+
+impl Field55Spec {
+    /// Minimum data set plus the scheme's own data objects
+    pub fn for_kernel(kernel_id: KernelId) -> Self {
+        let spec = Self::default();
+        match kernel_id {
+            // Third Party Data
+            KernelId::Mastercard => spec.with_tag(&[0x9F, 0x6E], false),
+            // Form Factor Indicator, Customer Exclusive Data, TTQ; qVSDC
+            // produces no TVR
+            KernelId::Visa => spec
+                .with_tag(&[0x95], false)
+                .with_tag(&[0x9F, 0x6E], false)
+                .with_tag(&[0x9F, 0x7C], false)
+                .with_tag(&[0x9F, 0x66], false),
+            // Enhanced Contactless Reader Capabilities
+            KernelId::AmericanExpress => spec.with_tag(&[0x9F, 0x6E], false),
+            // Card Product Identification, Issuer Script Results; qPBOC
+            // produces no TVR
+            KernelId::UnionPay => spec
+                .with_tag(&[0x95], false)
+                .with_tag(&[0x9F, 0x63], false)
+                .with_tag(&[0xDF, 0x31], false),
+            _ => spec,
+        }
+    }
+
+    /// Data set for the scheme of an AID
+    pub fn for_aid(aid: &[u8]) -> Self {
+        KernelId::resolve(aid, None)
+            .map(Self::for_kernel)
+            .unwrap_or_default()
+    }
+
+    /// Add a tag, or change whether an existing one is mandatory
+    pub fn with_tag(mut self, tag: &[u8], mandatory: bool) -> Self {
+        match self.tags.iter_mut().find(|t| t.tag == tag) {
+            Some(existing) => existing.mandatory = mandatory,
+            None => self.tags.push(Field55Tag {
+                tag: tag.to_vec(),
+                mandatory,
+            }),
+        }
+        self
+    }
+
+    /// Select the data objects of this spec from the kernel data
+    pub fn select(&self, data: &[Tlv]) -> Result<Vec<Tlv>, String> {
+        let mut selected = Vec::new();
+        for field in &self.tags {
+            match Tlv::find_by_tag(data, &field.tag) {
+                Some(tlv) => selected.push(tlv.clone()),
+                None if field.mandatory => {
+                    return Err(format!(
+                        "Field 55 data object {} is missing",
+                        hex::encode_upper(&field.tag)
+                    ));
+                }
+                None => {}
+            }
+        }
+        Ok(selected)
+    }
+
+    /// Field 55 as BER-TLV
+    pub fn assemble(&self, data: &[Tlv]) -> Result<Vec<u8>, String> {
+        self.select(data).map(|tlvs| Tlv::encode(&tlvs))
+    }
+}
+
+/// Tags are configured as hex, e.g. `"9F26"`
+mod hex_tag {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(tag: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&hex::encode_upper(tag))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        hex::decode(&value).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_assemble_field55() {
+        let mut data: Vec<Tlv> = MANDATORY
+            .iter()
+            .map(|tag| Tlv::new(tag, vec![0x01]))
+            .collect();
+        data.push(Tlv::new(&[0x9F, 0x66], vec![0x36, 0x00, 0x40, 0x00]));
+        data.push(Tlv::new(&[0x5A], vec![0x47, 0x61]));
+
+        let visa = Field55Spec::for_aid(&[0xA0, 0x00, 0x00, 0x00, 0x03, 0x10, 0x10]);
+        let field55 = visa.assemble(&data).unwrap();
+        let tlvs = Tlv::parse(&field55).unwrap();
+        assert_eq!(tlvs.len(), MANDATORY.len() + 1);
+        assert_eq!(tlvs[0].tag, [0x9F, 0x26]);
+        assert!(Tlv::find_by_tag(&tlvs, &[0x5A]).is_none());
+        assert!(Field55Spec::default().assemble(&data).unwrap().len() < field55.len());
+
+        data.retain(|tlv| tlv.tag != [0x9F, 0x10]);
+        assert_eq!(
+            visa.assemble(&data),
+            Err("Field 55 data object 9F10 is missing".to_string())
+        );
+        let relaxed = visa.with_tag(&[0x9F, 0x10], false);
+        assert!(relaxed.assemble(&data).is_ok());
+
+        let json = serde_json::to_string(&relaxed.tags[2]).unwrap();
+        assert_eq!(json, r#"{"tag":"9F10","mandatory":false}"#);
+    }
+}
@@ -0,0 +1,398 @@
+use serde::{Deserialize, Serialize};
+
+/// One field of decoded Issuer Application Data
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IadField {
+    pub name: String,
+    /// Raw value, hex
+    pub value: String,
+}
+
+/// Issuer Application Data (tag 0x9F10) broken into scheme-specific fields
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DecodedIad {
+    pub scheme: String,
+    /// Layout name, e.g. "VIS format 0/1/3"
+    pub format: String,
+    /// Cryptogram Version Number
+    pub cvn: Option<u8>,
+    /// Derivation Key Index
+    pub derivation_key_index: Option<u8>,
+    /// Card Verification Results, hex
+    pub cvr: Option<String>,
+    pub fields: Vec<IadField>,
+    /// Descriptions of the CVR bits that are set
+    pub cvr_flags: Vec<String>,
+}
+
+impl DecodedIad {
+    fn new(scheme: &str, format: &str) -> Self {
+        Self {
+            scheme: scheme.to_string(),
+            format: format.to_string(),
+            cvn: None,
+            derivation_key_index: None,
+            cvr: None,
+            fields: Vec::new(),
+            cvr_flags: Vec::new(),
+        }
+    }
+
+    fn field(&mut self, name: &str, value: &[u8]) {
+        if !value.is_empty() {
+            self.fields.push(IadField {
+                name: name.to_string(),
+                value: hex::encode_upper(value),
+            });
+        }
+    }
+}
+
+/// Decoder for one scheme's Issuer Application Data layouts
+///
+/// Register additional layouts with [`IadDecoders::with_decoder`].
+pub trait IadDecoder: Send + Sync {
+    /// Scheme name shown in diagnostics
+    fn scheme(&self) -> &'static str;
+
+    /// Whether this decoder handles the application with this AID
+    fn supports(&self, aid: &[u8]) -> bool;
+
+    /// Break the IAD into fields
+    fn decode(&self, iad: &[u8]) -> Result<DecodedIad, String>;
+}
+
+/// Registered IAD decoders, tried in order
+pub struct IadDecoders {
+    decoders: Vec<Box<dyn IadDecoder>>,
+}
+
+impl Default for IadDecoders {
+    /// Visa, Mastercard and UnionPay decoders
+    fn default() -> Self {
+        Self {
+            decoders: vec![
+                Box::new(VisaIadDecoder),
+                Box::new(MastercardIadDecoder),
+                Box::new(UnionPayIadDecoder),
+            ],
+        }
+    }
+}
+
+impl IadDecoders {
+    /// Add a decoder, tried before the built-in ones
+    pub fn with_decoder(mut self, decoder: Box<dyn IadDecoder>) -> Self {
+        self.decoders.insert(0, decoder);
+        self
+    }
+
+    /// Decode the IAD of the application with this AID
+    pub fn decode(&self, aid: &[u8], iad: &[u8]) -> Result<DecodedIad, String> {
+        let decoder = self
+            .decoders
+            .iter()
+            .find(|decoder| decoder.supports(aid))
+            .ok_or_else(|| format!("No IAD decoder for AID {}", hex::encode_upper(aid)))?;
+        decoder.decode(iad)
+    }
+}
+
+/// (byte index within the CVR, mask, description)
+type CvrBits = &'static [(usize, u8, &'static str)];
+
+/// VIS CVR bits, indexes relative to the 3 CVR bytes after the length byte
+const VISA_CVR_BITS: CvrBits = &[
+    (0, 0x08, "Issuer authentication performed and failed"),
+    (0, 0x04, "Offline PIN verification performed"),
+    (0, 0x02, "Offline PIN verification failed"),
+    (0, 0x01, "Unable to go online"),
+    (1, 0x80, "Last online transaction not completed"),
+    (1, 0x40, "PIN Try Limit exceeded"),
+    (1, 0x20, "Exceeded velocity checking counters"),
+    (1, 0x10, "New card"),
+    (
+        1,
+        0x08,
+        "Issuer authentication failure on last online transaction",
+    ),
+    (
+        1,
+        0x04,
+        "Issuer authentication not performed after online authorisation",
+    ),
+    (
+        1,
+        0x02,
+        "Application blocked by card because PIN Try Limit exceeded",
+    ),
+    (
+        1,
+        0x01,
+        "Offline static data authentication failed on last transaction",
+    ),
+    (2, 0x08, "Issuer script processing failed"),
+    (
+        2,
+        0x04,
+        "Offline dynamic data authentication failed on last transaction",
+    ),
+    (2, 0x02, "Offline dynamic data authentication performed"),
+];
+
+/// M/Chip CVR bits (6-byte CVR)
+const MASTERCARD_CVR_BITS: CvrBits = &[
+    (0, 0x04, "Date check failed"),
+    (0, 0x02, "Offline PIN verification performed"),
+    (0, 0x01, "Offline encrypted PIN verification performed"),
+    (1, 0x80, "Offline PIN verification successful"),
+    (1, 0x40, "DDA returned"),
+    (
+        1,
+        0x20,
+        "Combined DDA/AC generation returned in first GENERATE AC",
+    ),
+    (
+        1,
+        0x10,
+        "Combined DDA/AC generation returned in second GENERATE AC",
+    ),
+    (1, 0x08, "Issuer authentication performed"),
+    (1, 0x04, "CIAC-Default skipped on CAT3"),
+    (3, 0x80, "Last online transaction not completed"),
+    (3, 0x40, "Unable to go online"),
+    (3, 0x20, "Offline PIN verification not performed"),
+    (3, 0x10, "Offline PIN verification failed"),
+    (3, 0x08, "PIN Try Limit exceeded"),
+    (3, 0x04, "International transaction"),
+    (3, 0x02, "Domestic transaction"),
+    (3, 0x01, "Terminal erroneously considers offline PIN OK"),
+    (4, 0x80, "Lower consecutive offline limit exceeded"),
+    (4, 0x40, "Upper consecutive offline limit exceeded"),
+    (4, 0x20, "Lower cumulative offline limit exceeded"),
+    (4, 0x10, "Upper cumulative offline limit exceeded"),
+    (4, 0x08, "Go online on next transaction was set"),
+    (4, 0x04, "Issuer authentication failed"),
+    (4, 0x02, "Script received"),
+    (4, 0x01, "Script failed"),
+];
+
+fn explain_cvr(cvr: &[u8], bits: CvrBits) -> Vec<String> {
+    bits.iter()
+        .filter(|(byte, mask, _)| cvr.get(*byte).is_some_and(|b| b & mask != 0))
+        .map(|(_, _, desc)| desc.to_string())
+        .collect()
+}
+
+/// Cryptogram types recorded in CVR byte 1 b8-b5 (second GAC, first GAC)
+fn explain_cvr_ac_types(first_byte: u8, flags: &mut Vec<String>) {
+    let second = match first_byte >> 6 {
+        0b00 => "AAC",
+        0b01 => "TC",
+        0b10 => "not requested",
+        _ => "RFU",
+    };
+    let first = match (first_byte >> 4) & 0x03 {
+        0b00 => "AAC",
+        0b01 => "TC",
+        0b10 => "ARQC",
+        _ => "RFU",
+    };
+    flags.push(format!("First GENERATE AC returned {}", first));
+    flags.push(format!("Second GENERATE AC {}", second));
+}
+
+fn has_rid(aid: &[u8], rid: &[u8; 5]) -> bool {
+    aid.starts_with(rid)
+}
+
+/// Visa Integrated Circuit Card Specification (VIS) layouts
+pub struct VisaIadDecoder;
+
+impl IadDecoder for VisaIadDecoder {
+    fn scheme(&self) -> &'static str {
+        "Visa"
+    }
+
+    fn supports(&self, aid: &[u8]) -> bool {
+        has_rid(aid, &[0xA0, 0x00, 0x00, 0x00, 0x03])
+    }
+
+    fn decode(&self, iad: &[u8]) -> Result<DecodedIad, String> {
+        match iad {
+            // Format 2: length 1F, CVN, DKI, 5-byte CVR, issuer discretionary data
+            [0x1F, cvn, dki, rest @ ..] if rest.len() >= 5 => {
+                let mut decoded = DecodedIad::new(self.scheme(), "VIS format 2");
+                decoded.cvn = Some(*cvn);
+                decoded.derivation_key_index = Some(*dki);
+                decoded.cvr = Some(hex::encode_upper(&rest[..5]));
+                explain_cvr_ac_types(rest[0], &mut decoded.cvr_flags);
+                decoded.field("Issuer Discretionary Data", &rest[5..]);
+                Ok(decoded)
+            }
+            // Format 0/1/3: length 06, DKI, CVN, CVR (length byte + 3 bytes)
+            [0x06, dki, cvn, 0x03, cvr @ ..] if cvr.len() >= 3 => {
+                let mut decoded = DecodedIad::new(self.scheme(), "VIS format 0/1/3");
+                decoded.cvn = Some(*cvn);
+                decoded.derivation_key_index = Some(*dki);
+                decoded.cvr = Some(hex::encode_upper(&cvr[..3]));
+                explain_cvr_ac_types(cvr[0], &mut decoded.cvr_flags);
+                decoded
+                    .cvr_flags
+                    .extend(explain_cvr(&cvr[..3], VISA_CVR_BITS));
+                decoded.field("Issuer Discretionary Data", &cvr[3..]);
+                Ok(decoded)
+            }
+            _ => Err("Unrecognised Visa IAD layout".to_string()),
+        }
+    }
+}
+
+/// M/Chip 4 and M/Chip Advance layouts
+pub struct MastercardIadDecoder;
+
+impl IadDecoder for MastercardIadDecoder {
+    fn scheme(&self) -> &'static str {
+        "Mastercard"
+    }
+
+    fn supports(&self, aid: &[u8]) -> bool {
+        has_rid(aid, &[0xA0, 0x00, 0x00, 0x00, 0x04])
+    }
+
+    /// DKI, CVN, 6-byte CVR, DAC/ICC Dynamic Number, then optional counters
+    fn decode(&self, iad: &[u8]) -> Result<DecodedIad, String> {
+        if iad.len() < 10 {
+            return Err(format!("Mastercard IAD too short: {}", iad.len()));
+        }
+
+        let mut decoded = DecodedIad::new(self.scheme(), "M/Chip");
+        decoded.derivation_key_index = Some(iad[0]);
+        decoded.cvn = Some(iad[1]);
+        decoded.cvr = Some(hex::encode_upper(&iad[2..8]));
+        explain_cvr_ac_types(iad[2], &mut decoded.cvr_flags);
+        decoded
+            .cvr_flags
+            .extend(explain_cvr(&iad[2..8], MASTERCARD_CVR_BITS));
+        decoded.cvr_flags.push(format!(
+            "Script counter {}, PIN try counter {}",
+            iad[4] >> 4,
+            iad[4] & 0x0F
+        ));
+        decoded.field("DAC/ICC Dynamic Number", &iad[8..10]);
+        decoded.field("Counters", &iad[10..iad.len().min(18)]);
+        if iad.len() > 18 {
+            decoded.field("Additional Data", &iad[18..]);
+        }
+        Ok(decoded)
+    }
+}
+
+/// UnionPay Integrated Circuit Card Specification (UICS) layout
+pub struct UnionPayIadDecoder;
+
+impl IadDecoder for UnionPayIadDecoder {
+    fn scheme(&self) -> &'static str {
+        "UnionPay"
+    }
+
+    fn supports(&self, aid: &[u8]) -> bool {
+        has_rid(aid, &[0xA0, 0x00, 0x00, 0x03, 0x33])
+    }
+
+    /// Length 07, DKI, CVN, CVR (length byte + 3 bytes), algorithm, then IDD
+    fn decode(&self, iad: &[u8]) -> Result<DecodedIad, String> {
+        match iad {
+            [0x07, dki, cvn, 0x03, cvr @ ..] if cvr.len() >= 4 => {
+                let mut decoded = DecodedIad::new(self.scheme(), "UICS");
+                decoded.cvn = Some(*cvn);
+                decoded.derivation_key_index = Some(*dki);
+                decoded.cvr = Some(hex::encode_upper(&cvr[..3]));
+                // The UICS CVR follows the VIS layout
+                explain_cvr_ac_types(cvr[0], &mut decoded.cvr_flags);
+                decoded
+                    .cvr_flags
+                    .extend(explain_cvr(&cvr[..3], VISA_CVR_BITS));
+                decoded.field("Algorithm Identifier", &cvr[3..4]);
+                decoded.cvr_flags.push(
+                    match cvr[3] {
+                        0x01 => "Algorithm: Triple DES",
+                        0x04 => "Algorithm: SM4",
+                        _ => "Algorithm: unknown",
+                    }
+                    .to_string(),
+                );
+                decoded.field("Issuer Discretionary Data", &cvr[4..]);
+                Ok(decoded)
+            }
+            _ => Err("Unrecognised UnionPay IAD layout".to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VISA_AID: &[u8] = &[0xA0, 0x00, 0x00, 0x00, 0x03, 0x10, 0x10];
+
+    #[test]
+    fn test_visa_format_0() {
+        let iad = hex::decode("06010A03A02000").unwrap();
+        let decoded = IadDecoders::default().decode(VISA_AID, &iad).unwrap();
+
+        assert_eq!(decoded.scheme, "Visa");
+        assert_eq!(decoded.cvn, Some(0x0A));
+        assert_eq!(decoded.derivation_key_index, Some(0x01));
+        assert_eq!(decoded.cvr.as_deref(), Some("A02000"));
+        assert!(decoded
+            .cvr_flags
+            .contains(&"First GENERATE AC returned ARQC".to_string()));
+        assert!(decoded
+            .cvr_flags
+            .contains(&"Exceeded velocity checking counters".to_string()));
+    }
+
+    #[test]
+    fn test_mastercard_and_unknown_aid() {
+        let iad = hex::decode("0110A0400340000000001234").unwrap();
+        let decoded = IadDecoders::default()
+            .decode(&[0xA0, 0x00, 0x00, 0x00, 0x04, 0x10, 0x10], &iad)
+            .unwrap();
+
+        assert_eq!(decoded.cvn, Some(0x10));
+        assert_eq!(decoded.cvr.as_deref(), Some("A04003400000"));
+        assert!(decoded
+            .cvr_flags
+            .contains(&"Unable to go online".to_string()));
+        assert_eq!(decoded.fields[0].value, "0000");
+        assert_eq!(decoded.fields[1].value, "1234");
+
+        assert!(IadDecoders::default()
+            .decode(&[0xA0, 0x00, 0x00, 0x00, 0x25], &iad)
+            .is_err());
+    }
+
+    #[test]
+    fn test_unionpay_uics() {
+        // 19-byte UICS IAD: DKI 01, CVN 01, CVR A02000, Triple DES, then the
+        // 10-byte IDD (length 0A, IDD format 01, MAC)
+        let iad = hex::decode("07010103A02000010A0100000000000D3EC6B4").unwrap();
+        let decoded = IadDecoders::default()
+            .decode(&[0xA0, 0x00, 0x00, 0x03, 0x33, 0x01, 0x01, 0x01], &iad)
+            .unwrap();
+
+        assert_eq!(decoded.scheme, "UnionPay");
+        assert_eq!(decoded.derivation_key_index, Some(0x01));
+        assert_eq!(decoded.cvn, Some(0x01));
+        assert_eq!(decoded.cvr.as_deref(), Some("A02000"));
+        assert_eq!(decoded.fields[0].value, "01");
+        assert_eq!(decoded.fields[1].value, "0A0100000000000D3EC6B4");
+        assert!(decoded
+            .cvr_flags
+            .contains(&"First GENERATE AC returned ARQC".to_string()));
+        assert!(decoded
+            .cvr_flags
+            .contains(&"Algorithm: Triple DES".to_string()));
+    }
+}
@@ -0,0 +1,532 @@
+use std::collections::HashMap;
+
+use base64::Engine;
+use quick_xml::escape::escape;
+use quick_xml::events::Event;
+use quick_xml::Reader;
+
+use crate::models::currency::{Amount, Currency};
+use crate::models::emv::{Tlv, TransactionType};
+use crate::models::outcome::OutcomeStatus;
+use crate::models::pan::Pan;
+use crate::models::transaction::{AttestationResponse, TransactionRequest};
+use crate::services::field55::Field55Spec;
+use crate::services::iso8583::MessageContext;
+use crate::services::kernels::{bcd_to_u64, KernelOutcome};
+
+const CAAA_001: &str = "urn:iso:std:iso:20022:tech:xsd:caaa.001.001.08";
+const CAAA_003: &str = "urn:iso:std:iso:20022:tech:xsd:caaa.003.001.08";
+const PROTOCOL_VERSION: &str = "8.0";
+
+/// Builds ISO 20022 acceptor to acquirer (CAPE) messages: `caaa.001`
+/// authorisation requests and `caaa.003` completion advices
+#[derive(Debug, Clone)]
+pub struct AcceptorMessageBuilder {
+    context: MessageContext,
+}
+
+impl AcceptorMessageBuilder {
+    /// The context's STAN is the exchange identification and its
+    /// transmission time the creation time
+    pub fn new(context: MessageContext) -> Self {
+        Self { context }
+    }
+
+    /// `AcceptorAuthorisationRequest` (caaa.001) for an online request
+    pub fn authorisation_request(
+        &self,
+        request: &TransactionRequest,
+        outcome: &KernelOutcome,
+    ) -> Result<String, String> {
+        let mut xml = Xml::new(CAAA_001);
+        xml.open("AccptrAuthstnReq");
+        self.header(&mut xml, "AUTQ");
+        xml.open("AuthstnReq");
+        self.environment(&mut xml, request, &outcome.data_record)?;
+        self.context(&mut xml);
+        self.transaction(&mut xml, request, &outcome.data_record, None, None)?;
+        xml.close("AuthstnReq");
+        xml.close("AccptrAuthstnReq");
+        Ok(xml.finish())
+    }
+
+    /// `AcceptorCompletionAdvice` (caaa.003) once the card has given its
+    /// final decision; `authorisation` is the host's answer to the request
+    /// if the transaction went online
+    pub fn completion_advice(
+        &self,
+        request: &TransactionRequest,
+        outcome: &KernelOutcome,
+        authorisation: Option<&AttestationResponse>,
+    ) -> Result<String, String> {
+        let success = match outcome.parameters.status {
+            OutcomeStatus::Approved => true,
+            OutcomeStatus::OnlineRequest => authorisation.is_some_and(|response| {
+                response.status == "approved" || response.status == "partially_approved"
+            }),
+            _ => false,
+        };
+
+        let mut xml = Xml::new(CAAA_003);
+        xml.open("AccptrCmpltnAdvc");
+        self.header(&mut xml, "CMPV");
+        xml.open("CmpltnAdvc");
+        self.environment(&mut xml, request, &outcome.data_record)?;
+        self.context(&mut xml);
+        let authorisation_code = authorisation.and_then(|response| response.auth_code.as_deref());
+        self.transaction(
+            &mut xml,
+            request,
+            &outcome.data_record,
+            Some(success),
+            authorisation_code,
+        )?;
+        xml.close("CmpltnAdvc");
+        xml.close("AccptrCmpltnAdvc");
+        Ok(xml.finish())
+    }
+
+    fn header(&self, xml: &mut Xml, function: &str) {
+        xml.open("Hdr");
+        xml.leaf("MsgFctn", function);
+        xml.leaf("PrtcolVrsn", PROTOCOL_VERSION);
+        xml.leaf("XchgId", &self.context.stan.to_string());
+        xml.leaf(
+            "CreDtTm",
+            &self
+                .context
+                .transmitted_at
+                .format("%Y-%m-%dT%H:%M:%SZ")
+                .to_string(),
+        );
+        xml.open("InitgPty");
+        xml.leaf("Id", &self.context.terminal_id);
+        xml.close("InitgPty");
+        xml.close("Hdr");
+    }
+
+    fn environment(
+        &self,
+        xml: &mut Xml,
+        request: &TransactionRequest,
+        data_record: &[Tlv],
+    ) -> Result<(), String> {
+        // Prefer the card's own PAN: the request may only carry a masked one
+        let pan = match Tlv::find_by_tag(data_record, &[0x5A]) {
+            Some(tlv) => Pan::from_bcd(&tlv.value)?,
+            None => request.card_data.pan.clone(),
+        };
+        let expiry = request.card_data.expiry;
+
+        xml.open("Envt");
+        if let Some(acquirer_id) = &self.context.acquirer_id {
+            xml.open("Acqrr");
+            xml.open("Id");
+            xml.leaf("Id", acquirer_id);
+            xml.close("Id");
+            xml.close("Acqrr");
+        }
+        xml.open("Mrchnt");
+        xml.open("Id");
+        xml.leaf("Id", &self.context.merchant_id);
+        xml.close("Id");
+        xml.close("Mrchnt");
+        xml.open("POI");
+        xml.open("Id");
+        xml.leaf("Id", &self.context.terminal_id);
+        xml.close("Id");
+        xml.close("POI");
+        xml.open("Card");
+        xml.open("PlainCardData");
+        xml.leaf("PAN", pan.expose()?);
+        if let Some(sequence) = Tlv::find_by_tag(data_record, &[0x5F, 0x34]) {
+            xml.leaf("CardSeqNb", &hex::encode(&sequence.value));
+        }
+        xml.leaf("XpryDt", &format!("{:04}-{:02}", expiry.year, expiry.month));
+        xml.close("PlainCardData");
+        if let Some(label) = &request.card_data.app_label {
+            xml.leaf("CardBrnd", label);
+        }
+        xml.close("Card");
+        xml.close("Envt");
+        Ok(())
+    }
+
+    fn context(&self, xml: &mut Xml) {
+        let entry_mode = match self.context.pos_entry_mode.get(..2) {
+            Some("07") => "CTLS",
+            Some("05") => "CICC",
+            Some("02" | "80" | "90") => "MGST",
+            _ => "PHYS",
+        };
+        xml.open("Cntxt");
+        xml.open("PmtCntxt");
+        xml.leaf("CardPres", "true");
+        xml.leaf("CardDataNtryMd", entry_mode);
+        xml.close("PmtCntxt");
+        xml.close("Cntxt");
+    }
+
+    fn transaction(
+        &self,
+        xml: &mut Xml,
+        request: &TransactionRequest,
+        data_record: &[Tlv],
+        success: Option<bool>,
+        authorisation_code: Option<&str>,
+    ) -> Result<(), String> {
+        let amount = request.validate()?;
+        let transaction_type = request.emv_data.transaction_type;
+        let service = match transaction_type {
+            TransactionType::Purchase
+            | TransactionType::PurchaseWithCashback
+            | TransactionType::Completion => "CRDP",
+            TransactionType::PreAuthorization => "RESA",
+            TransactionType::Withdrawal => "CSHW",
+            TransactionType::CashAdvance => "CSHD",
+            TransactionType::Refund => "RFND",
+            TransactionType::BalanceInquiry => "BALC",
+            TransactionType::Void => {
+                return Err("Voids are sent as cancellation requests (caaa.005)".to_string());
+            }
+        };
+        let aid = hex::decode(&request.card_data.aid).map_err(|e| e.to_string())?;
+        let icc_data = Field55Spec::for_aid(&aid).assemble(data_record)?;
+
+        xml.open("Tx");
+        if let Some(success) = success {
+            xml.leaf("TxSucss", if success { "true" } else { "false" });
+        }
+        xml.leaf("TxCaptr", "false");
+        xml.leaf("TxTp", service);
+        if let Some(mcc) = &self.context.merchant_category_code {
+            xml.leaf("MrchntCtgyCd", mcc);
+        }
+        xml.open("TxId");
+        xml.leaf(
+            "TxDtTm",
+            &self
+                .context
+                .transmitted_at
+                .format("%Y-%m-%dT%H:%M:%SZ")
+                .to_string(),
+        );
+        xml.leaf("TxRef", &format!("{:06}", self.context.stan % 1_000_000));
+        xml.close("TxId");
+        xml.open("TxDtls");
+        xml.leaf("Ccy", amount.currency.alpha);
+        xml.leaf("TtlAmt", &amount.to_decimal());
+        let cashback = Tlv::find_by_tag(data_record, &[0x9F, 0x03])
+            .map(|tlv| bcd_to_u64(&tlv.value))
+            .unwrap_or(0);
+        if cashback > 0 {
+            let cashback = Amount::new(cashback, amount.currency)?;
+            xml.open("DtldAmt");
+            xml.leaf("CshBck", &cashback.to_decimal());
+            xml.close("DtldAmt");
+        }
+        xml.leaf(
+            "ICCRltdData",
+            &base64::engine::general_purpose::STANDARD.encode(icc_data),
+        );
+        xml.close("TxDtls");
+        if let Some(code) = authorisation_code {
+            xml.open("AuthstnRslt");
+            xml.leaf("AuthstnCd", code);
+            xml.close("AuthstnRslt");
+        }
+        xml.close("Tx");
+        Ok(())
+    }
+}
+
+/// Parse an `AcceptorAuthorisationResponse` (caaa.002) or
+/// `AcceptorCompletionAdviceResponse` (caaa.004)
+///
+/// Issuer data objects returned in `ICCRltdData` (0x8A, 0x91, 0x71, 0x72)
+/// are passed on for online completion.
+pub fn parse_response(xml: &str) -> Result<AttestationResponse, String> {
+    let mut reader = Reader::from_str(xml);
+    reader.config_mut().trim_text(true);
+
+    let mut path: Vec<String> = Vec::new();
+    let mut values: HashMap<String, String> = HashMap::new();
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(element)) => {
+                path.push(String::from_utf8_lossy(element.local_name().as_ref()).into_owned());
+            }
+            Ok(Event::End(_)) => {
+                path.pop();
+            }
+            Ok(Event::Text(text)) => {
+                let text = text.unescape().map_err(|e| e.to_string())?;
+                values
+                    .entry(path.join("/"))
+                    .or_insert_with(|| text.into_owned());
+            }
+            Ok(Event::Eof) => break,
+            Ok(_) => {}
+            Err(e) => return Err(format!("Invalid ISO 20022 XML: {}", e)),
+        }
+    }
+
+    let root = values
+        .keys()
+        .filter_map(|key| key.split('/').nth(1))
+        .next()
+        .unwrap_or_default()
+        .to_string();
+    if root != "AccptrAuthstnRspn" && root != "AccptrCmpltnAdvcRspn" {
+        return Err(format!("Not an acceptor response: {}", root));
+    }
+    let find = |suffix: &str| {
+        values
+            .iter()
+            .find(|(key, _)| key.ends_with(suffix))
+            .map(|(_, value)| value.clone())
+    };
+
+    let response = find("RspnToAuthstn/Rspn")
+        .or_else(|| find("/Rspn"))
+        .ok_or("Response has no result")?;
+    let status = match response.as_str() {
+        "APPR" => "approved",
+        "PART" => "partially_approved",
+        "DECL" => "declined",
+        _ => "failed",
+    };
+    // A partial approval carries the amount the issuer approved
+    let approved_amount = match status {
+        "partially_approved" => {
+            let currency = find("TxDtls/Ccy")
+                .and_then(|code| Currency::find(&code))
+                .ok_or("Partial approval has no known currency")?;
+            let amount = find("TxDtls/TtlAmt").ok_or("Partial approval has no amount")?;
+            Some(Amount::parse(&amount, currency)?.value)
+        }
+        _ => None,
+    };
+
+    let issuer_data = match find("ICCRltdData") {
+        Some(data) => Tlv::parse(
+            &base64::engine::general_purpose::STANDARD
+                .decode(data)
+                .map_err(|e| format!("Invalid ICCRltdData: {}", e))?,
+        )?,
+        None => Vec::new(),
+    };
+    let issuer_value =
+        |tag: &[u8]| Tlv::find_by_tag(&issuer_data, tag).map(|tlv| tlv.value.clone());
+    let scripts: Vec<Tlv> = issuer_data
+        .iter()
+        .filter(|tlv| tlv.tag == [0x71] || tlv.tag == [0x72])
+        .cloned()
+        .collect();
+
+    let authorisation_response_code = match issuer_value(&[0x8A]) {
+        Some(arc) => Some(String::from_utf8_lossy(&arc).into_owned()),
+        None => match status {
+            "approved" => Some("00".to_string()),
+            "partially_approved" => Some("10".to_string()),
+            "declined" => Some("05".to_string()),
+            _ => None,
+        },
+    };
+
+    Ok(AttestationResponse {
+        transaction_id: find("AcqrrTxId/TxRef")
+            .or_else(|| find("TxId/TxRef"))
+            .ok_or("Response has no transaction reference")?,
+        status: status.to_string(),
+        auth_code: find("AuthstnCd"),
+        message: find("RspnRsn").or_else(|| find("AddtlRspnInf")),
+        authorisation_response_code,
+        issuer_authentication_data: issuer_value(&[0x91]).map(hex::encode_upper),
+        issuer_scripts: (!scripts.is_empty()).then(|| hex::encode_upper(Tlv::encode(&scripts))),
+        approved_amount,
+    })
+}
+
+/// Minimal XML writer for the fixed message layouts above
+struct Xml(String);
+
+impl Xml {
+    fn new(namespace: &str) -> Self {
+        Self(format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?><Document xmlns=\"{}\">",
+            namespace
+        ))
+    }
+
+    fn open(&mut self, name: &str) {
+        self.0.push_str(&format!("<{}>", name));
+    }
+
+    fn close(&mut self, name: &str) {
+        self.0.push_str(&format!("</{}>", name));
+    }
+
+    fn leaf(&mut self, name: &str, text: &str) {
+        self.0
+            .push_str(&format!("<{0}>{1}</{0}>", name, escape(text)));
+    }
+
+    fn finish(mut self) -> String {
+        self.0.push_str("</Document>");
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::outcome::OutcomeParameterSet;
+    use chrono::{DateTime, Utc};
+
+    fn request() -> TransactionRequest {
+        serde_json::from_value(serde_json::json!({
+            "device_id": "device-123",
+            "amount": 12345,
+            "currency": "CNY",
+            "card_data": {
+                "pan": "476173******0010",
+                "expiry": "3012",
+                "aid": "A0000000031010",
+                "app_label": "VISA & CO"
+            },
+            "emv_data": {
+                "amount": 12345,
+                "currency_code": "156",
+                "transaction_type": "purchase"
+            }
+        }))
+        .unwrap()
+    }
+
+    fn outcome(status: OutcomeStatus) -> KernelOutcome {
+        let data = [
+            ("5A", "4761739001010010"),
+            ("9F26", "1122334455667788"),
+            ("9F27", "80"),
+            ("9F10", "06010A03A00000"),
+            ("9F37", "12345678"),
+            ("9F36", "001A"),
+            ("95", "0000008000"),
+            ("9A", "260921"),
+            ("9C", "00"),
+            ("9F02", "000000012345"),
+            ("5F2A", "0156"),
+            ("82", "2000"),
+            ("9F1A", "0156"),
+        ]
+        .iter()
+        .map(|(tag, value)| Tlv::new(&hex::decode(tag).unwrap(), hex::decode(value).unwrap()))
+        .collect();
+        KernelOutcome::new(OutcomeParameterSet::new(status), data, Vec::new())
+    }
+
+    fn builder() -> AcceptorMessageBuilder {
+        let at = DateTime::parse_from_rfc3339("2026-09-21T08:30:05Z").unwrap();
+        AcceptorMessageBuilder::new(MessageContext::new(
+            42,
+            at.with_timezone(&Utc),
+            "TERM0001",
+            "MERCHANT0000001",
+        ))
+    }
+
+    #[test]
+    fn test_authorisation_request_and_completion_advice() {
+        let xml = builder()
+            .authorisation_request(&request(), &outcome(OutcomeStatus::OnlineRequest))
+            .unwrap();
+        assert!(xml.contains(CAAA_001));
+        assert!(xml.contains("<MsgFctn>AUTQ</MsgFctn>"));
+        assert!(xml.contains("<PAN>4761739001010010</PAN><XpryDt>2030-12</XpryDt>"));
+        assert!(xml.contains("<CardBrnd>VISA &amp; CO</CardBrnd>"));
+        assert!(xml.contains("<CardDataNtryMd>CTLS</CardDataNtryMd>"));
+        assert!(xml.contains("<TxTp>CRDP</TxTp>"));
+        assert!(xml.contains("<Ccy>CNY</Ccy><TtlAmt>123.45</TtlAmt><ICCRltdData>nyYI"));
+
+        let approval = AttestationResponse {
+            transaction_id: "ACQ-1".to_string(),
+            status: "approved".to_string(),
+            auth_code: Some("A1B2C3".to_string()),
+            message: None,
+            authorisation_response_code: Some("00".to_string()),
+            issuer_authentication_data: None,
+            issuer_scripts: None,
+            approved_amount: None,
+        };
+        let advice = builder()
+            .completion_advice(
+                &request(),
+                &outcome(OutcomeStatus::OnlineRequest),
+                Some(&approval),
+            )
+            .unwrap();
+        assert!(advice.contains(CAAA_003));
+        assert!(advice.contains("<Tx><TxSucss>true</TxSucss>"));
+        assert!(advice.contains("<AuthstnRslt><AuthstnCd>A1B2C3</AuthstnCd></AuthstnRslt></Tx>"));
+
+        let declined = builder()
+            .completion_advice(&request(), &outcome(OutcomeStatus::Declined), None)
+            .unwrap();
+        assert!(declined.contains("<TxSucss>false</TxSucss>"));
+    }
+
+    #[test]
+    fn test_parse_authorisation_response() {
+        // ARC "00", Issuer Authentication Data and one issuer script
+        let icc = base64::engine::general_purpose::STANDARD
+            .encode(hex::decode("8A023030910A1122334455667788303071079F180411223344").unwrap());
+        let xml = format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+            <Document xmlns="urn:iso:std:iso:20022:tech:xsd:caaa.002.001.08">
+              <AccptrAuthstnRspn>
+                <Hdr><MsgFctn>AUTP</MsgFctn></Hdr>
+                <AuthstnRspn>
+                  <Tx><AcqrrTxId><TxRef>ACQ-789</TxRef></AcqrrTxId></Tx>
+                  <TxRspn>
+                    <AuthstnRslt>
+                      <RspnToAuthstn><Rspn>APPR</Rspn></RspnToAuthstn>
+                      <AuthstnCd>654321</AuthstnCd>
+                    </AuthstnRslt>
+                    <ICCRltdData>{}</ICCRltdData>
+                  </TxRspn>
+                </AuthstnRspn>
+              </AccptrAuthstnRspn>
+            </Document>"#,
+            icc
+        );
+        let response = parse_response(&xml).unwrap();
+        assert_eq!(response.transaction_id, "ACQ-789");
+        assert_eq!(response.status, "approved");
+        assert_eq!(response.auth_code.as_deref(), Some("654321"));
+        assert_eq!(response.authorisation_response_code.as_deref(), Some("00"));
+        assert_eq!(
+            response.issuer_authentication_data.as_deref(),
+            Some("11223344556677883030")
+        );
+        assert_eq!(
+            response.issuer_scripts.as_deref(),
+            Some("71079F180411223344")
+        );
+
+        let partial = xml.replace("APPR", "PART").replace(
+            "<Tx><AcqrrTxId>",
+            "<Tx><TxDtls><Ccy>EUR</Ccy><TtlAmt>80.50</TtlAmt></TxDtls><AcqrrTxId>",
+        );
+        let response = parse_response(&partial).unwrap();
+        assert_eq!(response.status, "partially_approved");
+        assert_eq!(response.approved_amount, Some(8050));
+        assert!(parse_response(&xml.replace("APPR", "PART")).is_err());
+
+        let declined = xml.replace("APPR", "DECL").replace(&icc, "");
+        let response = parse_response(&declined).unwrap();
+        assert_eq!(response.status, "declined");
+        assert_eq!(response.authorisation_response_code.as_deref(), Some("05"));
+        assert!(parse_response("<Document><Other/></Document>").is_err());
+    }
+}
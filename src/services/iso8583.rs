@@ -0,0 +1,903 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroize;
+
+use crate::models::emv::{Tlv, TransactionType};
+use crate::models::pan::Pan;
+use crate::services::field55::Field55Spec;
+
+/// ISO 8583 version, the first digit of the MTI
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Iso8583Version {
+    V1987,
+    V1993,
+}
+
+impl Iso8583Version {
+    fn mti_digit(&self) -> char {
+        match self {
+            Iso8583Version::V1987 => '0',
+            Iso8583Version::V1993 => '1',
+        }
+    }
+}
+
+/// Content of a field
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FieldType {
+    /// Digits (`n`)
+    Numeric,
+    /// Track 2 data (`z`): digits and the `=` separator
+    Track2,
+    /// Printable characters (`an`, `ans`)
+    AlphaNumeric,
+    /// Bytes (`b`)
+    Binary,
+}
+
+/// Length of a field, in digits, characters or bytes by field type
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FieldLength {
+    Fixed(usize),
+    /// Up to 99, with a two digit length prefix
+    LlVar(usize),
+    /// Up to 999, with a three digit length prefix
+    LllVar(usize),
+}
+
+/// How MTI, bitmap, numeric fields and length prefixes are packed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Packing {
+    /// Numeric data as BCD, bitmap and binary fields as bytes
+    Bcd,
+    /// Everything as ASCII, bitmap and binary fields hex encoded
+    Ascii,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FieldSpec {
+    pub field_type: FieldType,
+    pub length: FieldLength,
+}
+
+impl FieldSpec {
+    pub const fn fixed(field_type: FieldType, length: usize) -> Self {
+        Self {
+            field_type,
+            length: FieldLength::Fixed(length),
+        }
+    }
+
+    pub const fn llvar(field_type: FieldType, max: usize) -> Self {
+        Self {
+            field_type,
+            length: FieldLength::LlVar(max),
+        }
+    }
+
+    pub const fn lllvar(field_type: FieldType, max: usize) -> Self {
+        Self {
+            field_type,
+            length: FieldLength::LllVar(max),
+        }
+    }
+}
+
+/// Message layout: version, packing and the specification of each field
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MessageSpec {
+    pub version: Iso8583Version,
+    pub packing: Packing,
+    pub fields: BTreeMap<u8, FieldSpec>,
+}
+
+/// Fields of ISO 8583:1987 used for card acceptance
+const FIELDS_1987: &[(u8, FieldSpec)] = {
+    use FieldType::*;
+    &[
+        (2, FieldSpec::llvar(Numeric, 19)),
+        (3, FieldSpec::fixed(Numeric, 6)),
+        (4, FieldSpec::fixed(Numeric, 12)),
+        (7, FieldSpec::fixed(Numeric, 10)),
+        (11, FieldSpec::fixed(Numeric, 6)),
+        (12, FieldSpec::fixed(Numeric, 6)),
+        (13, FieldSpec::fixed(Numeric, 4)),
+        (14, FieldSpec::fixed(Numeric, 4)),
+        (18, FieldSpec::fixed(Numeric, 4)),
+        (22, FieldSpec::fixed(Numeric, 3)),
+        (23, FieldSpec::fixed(Numeric, 3)),
+        (25, FieldSpec::fixed(Numeric, 2)),
+        (32, FieldSpec::llvar(Numeric, 11)),
+        (35, FieldSpec::llvar(Track2, 37)),
+        (37, FieldSpec::fixed(AlphaNumeric, 12)),
+        (38, FieldSpec::fixed(AlphaNumeric, 6)),
+        (39, FieldSpec::fixed(AlphaNumeric, 2)),
+        (41, FieldSpec::fixed(AlphaNumeric, 8)),
+        (42, FieldSpec::fixed(AlphaNumeric, 15)),
+        (43, FieldSpec::fixed(AlphaNumeric, 40)),
+        (49, FieldSpec::fixed(Numeric, 3)),
+        (52, FieldSpec::fixed(Binary, 8)),
+        (53, FieldSpec::fixed(Numeric, 16)),
+        (54, FieldSpec::lllvar(AlphaNumeric, 120)),
+        (55, FieldSpec::lllvar(Binary, 255)),
+        (60, FieldSpec::lllvar(AlphaNumeric, 999)),
+        (61, FieldSpec::lllvar(AlphaNumeric, 999)),
+        (62, FieldSpec::lllvar(AlphaNumeric, 999)),
+        (63, FieldSpec::lllvar(AlphaNumeric, 999)),
+        (64, FieldSpec::fixed(Binary, 8)),
+        (90, FieldSpec::fixed(Numeric, 42)),
+        (128, FieldSpec::fixed(Binary, 8)),
+    ]
+};
+
+/// Fields of ISO 8583:1993 used for card acceptance
+const FIELDS_1993: &[(u8, FieldSpec)] = {
+    use FieldType::*;
+    &[
+        (2, FieldSpec::llvar(Numeric, 19)),
+        (3, FieldSpec::fixed(Numeric, 6)),
+        (4, FieldSpec::fixed(Numeric, 12)),
+        (7, FieldSpec::fixed(Numeric, 10)),
+        (11, FieldSpec::fixed(Numeric, 6)),
+        (12, FieldSpec::fixed(Numeric, 12)),
+        (14, FieldSpec::fixed(Numeric, 4)),
+        (18, FieldSpec::fixed(Numeric, 4)),
+        (22, FieldSpec::fixed(AlphaNumeric, 12)),
+        (23, FieldSpec::fixed(Numeric, 3)),
+        (24, FieldSpec::fixed(Numeric, 3)),
+        (25, FieldSpec::fixed(Numeric, 4)),
+        (32, FieldSpec::llvar(Numeric, 11)),
+        (35, FieldSpec::llvar(Track2, 37)),
+        (37, FieldSpec::fixed(AlphaNumeric, 12)),
+        (38, FieldSpec::fixed(AlphaNumeric, 6)),
+        (39, FieldSpec::fixed(Numeric, 3)),
+        (41, FieldSpec::fixed(AlphaNumeric, 8)),
+        (42, FieldSpec::fixed(AlphaNumeric, 15)),
+        (43, FieldSpec::llvar(AlphaNumeric, 99)),
+        (49, FieldSpec::fixed(Numeric, 3)),
+        (52, FieldSpec::fixed(Binary, 8)),
+        (53, FieldSpec::llvar(Binary, 48)),
+        (54, FieldSpec::lllvar(AlphaNumeric, 120)),
+        (55, FieldSpec::lllvar(Binary, 255)),
+        (56, FieldSpec::llvar(Numeric, 35)),
+        (64, FieldSpec::fixed(Binary, 8)),
+        (128, FieldSpec::fixed(Binary, 8)),
+    ]
+};
+
+impl MessageSpec {
+    /// ISO 8583:1987 fields with the given packing
+    pub fn iso1987(packing: Packing) -> Self {
+        Self {
+            version: Iso8583Version::V1987,
+            packing,
+            fields: FIELDS_1987.iter().copied().collect(),
+        }
+    }
+
+    /// ISO 8583:1993 fields with the given packing
+    pub fn iso1993(packing: Packing) -> Self {
+        Self {
+            version: Iso8583Version::V1993,
+            packing,
+            fields: FIELDS_1993.iter().copied().collect(),
+        }
+    }
+
+    /// Add or replace a field specification, e.g. a private use field of
+    /// the acquirer
+    pub fn with_field(mut self, number: u8, spec: FieldSpec) -> Self {
+        self.fields.insert(number, spec);
+        self
+    }
+
+    /// Pack a message: MTI, bitmaps, then the present fields in order
+    pub fn pack(&self, message: &Iso8583Message) -> Result<Vec<u8>, String> {
+        if message.mti.len() != 4 || !message.mti.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(format!("Invalid MTI: {}", message.mti));
+        }
+        let mut out = self.pack_digits(message.mti.as_bytes(), false);
+
+        let secondary = message.fields.keys().any(|n| *n > 64);
+        let mut bitmap = [0u8; 16];
+        if secondary {
+            bitmap[0] |= 0x80;
+        }
+        for number in message.fields.keys() {
+            if !(2..=128).contains(number) || *number == 65 {
+                return Err(format!("Field {} cannot be set", number));
+            }
+            let bit = usize::from(*number - 1);
+            bitmap[bit / 8] |= 0x80 >> (bit % 8);
+        }
+        out.extend(self.pack_binary(&bitmap[..if secondary { 16 } else { 8 }]));
+
+        for (number, value) in &message.fields {
+            let spec = self
+                .fields
+                .get(number)
+                .ok_or_else(|| format!("Field {} has no specification", number))?;
+            out.extend(
+                self.pack_field(spec, value)
+                    .map_err(|e| format!("Field {}: {}", number, e))?,
+            );
+        }
+        Ok(out)
+    }
+
+    /// Parse a packed message
+    pub fn unpack(&self, data: &[u8]) -> Result<Iso8583Message, String> {
+        let mut reader = Reader { data, pos: 0 };
+        let mti = String::from_utf8(self.read_digits(&mut reader, 4, false)?)
+            .map_err(|e| e.to_string())?;
+
+        let mut bitmap = self.read_binary(&mut reader, 8)?;
+        if bitmap[0] & 0x80 != 0 {
+            bitmap.extend(self.read_binary(&mut reader, 8)?);
+        }
+
+        let mut message = Iso8583Message::new(&mti);
+        for bit in 1..bitmap.len() * 8 {
+            if bitmap[bit / 8] & (0x80 >> (bit % 8)) == 0 {
+                continue;
+            }
+            let number = (bit + 1) as u8;
+            if number == 65 {
+                return Err("Tertiary bitmaps are not supported".to_string());
+            }
+            let spec = self
+                .fields
+                .get(&number)
+                .ok_or_else(|| format!("Field {} has no specification", number))?;
+            let value = self
+                .read_field(&mut reader, spec)
+                .map_err(|e| format!("Field {}: {}", number, e))?;
+            message.fields.insert(number, value);
+        }
+        if reader.pos != data.len() {
+            return Err(format!(
+                "{} bytes left after the last field",
+                data.len() - reader.pos
+            ));
+        }
+        Ok(message)
+    }
+
+    fn pack_field(&self, spec: &FieldSpec, value: &[u8]) -> Result<Vec<u8>, String> {
+        match spec.field_type {
+            FieldType::Numeric if !value.iter().all(u8::is_ascii_digit) => {
+                return Err("must be numeric".to_string());
+            }
+            FieldType::Track2
+                if !value
+                    .iter()
+                    .all(|b| b.is_ascii_digit() || *b == b'=' || *b == b'D') =>
+            {
+                return Err("must be track 2 data".to_string());
+            }
+            FieldType::AlphaNumeric if !value.iter().all(|b| (0x20..0x7F).contains(b)) => {
+                return Err("must be printable ASCII".to_string());
+            }
+            _ => {}
+        }
+
+        let mut value = value.to_vec();
+        let (prefix_digits, max) = match spec.length {
+            FieldLength::Fixed(length) => {
+                match spec.field_type {
+                    FieldType::Numeric if value.len() < length => {
+                        value.splice(0..0, std::iter::repeat_n(b'0', length - value.len()));
+                    }
+                    FieldType::AlphaNumeric if value.len() < length => {
+                        value.resize(length, b' ');
+                    }
+                    _ => {}
+                }
+                if value.len() != length {
+                    return Err(format!("must be {} long, not {}", length, value.len()));
+                }
+                (0, length)
+            }
+            FieldLength::LlVar(max) => (2, max),
+            FieldLength::LllVar(max) => (3, max),
+        };
+        if value.len() > max {
+            return Err(format!("longer than {}", max));
+        }
+
+        let mut out = Vec::new();
+        if prefix_digits > 0 {
+            let prefix = format!("{:0width$}", value.len(), width = prefix_digits);
+            out.extend(self.pack_digits(prefix.as_bytes(), false));
+        }
+        out.extend(match spec.field_type {
+            FieldType::Numeric => self.pack_digits(&value, prefix_digits > 0),
+            FieldType::Track2 => self.pack_digits(&value, true),
+            FieldType::AlphaNumeric => value,
+            FieldType::Binary => self.pack_binary(&value),
+        });
+        Ok(out)
+    }
+
+    fn read_field(&self, reader: &mut Reader, spec: &FieldSpec) -> Result<Vec<u8>, String> {
+        let (length, variable) = match spec.length {
+            FieldLength::Fixed(length) => (length, false),
+            FieldLength::LlVar(max) => (self.read_length(reader, 2, max)?, true),
+            FieldLength::LllVar(max) => (self.read_length(reader, 3, max)?, true),
+        };
+        match spec.field_type {
+            FieldType::Numeric => self.read_digits(reader, length, variable),
+            FieldType::Track2 => self.read_digits(reader, length, true),
+            FieldType::AlphaNumeric => reader.take(length).map(<[u8]>::to_vec),
+            FieldType::Binary => self.read_binary(reader, length),
+        }
+    }
+
+    fn read_length(&self, reader: &mut Reader, digits: usize, max: usize) -> Result<usize, String> {
+        let prefix = self.read_digits(reader, digits, false)?;
+        let length: usize = String::from_utf8_lossy(&prefix)
+            .parse()
+            .map_err(|_| "invalid length prefix".to_string())?;
+        if length > max {
+            return Err(format!("longer than {}", max));
+        }
+        Ok(length)
+    }
+
+    /// Digits as BCD or ASCII; an odd number of BCD digits is padded on the
+    /// left, or on the right for variable length and track data
+    fn pack_digits(&self, digits: &[u8], left_aligned: bool) -> Vec<u8> {
+        if self.packing == Packing::Ascii {
+            return digits.to_vec();
+        }
+        let mut nibbles: Vec<u8> = digits
+            .iter()
+            .map(|b| {
+                if *b == b'=' || *b == b'D' {
+                    0x0D
+                } else {
+                    b - b'0'
+                }
+            })
+            .collect();
+        if nibbles.len() % 2 == 1 {
+            if left_aligned {
+                nibbles.push(if digits.contains(&b'=') || digits.contains(&b'D') {
+                    0x0F
+                } else {
+                    0x00
+                });
+            } else {
+                nibbles.insert(0, 0x00);
+            }
+        }
+        nibbles
+            .chunks(2)
+            .map(|pair| (pair[0] << 4) | pair[1])
+            .collect()
+    }
+
+    fn read_digits(
+        &self,
+        reader: &mut Reader,
+        count: usize,
+        left_aligned: bool,
+    ) -> Result<Vec<u8>, String> {
+        if self.packing == Packing::Ascii {
+            return reader.take(count).map(<[u8]>::to_vec);
+        }
+        let bytes = reader.take(count.div_ceil(2))?;
+        let nibbles: Vec<u8> = bytes.iter().flat_map(|b| [b >> 4, b & 0x0F]).collect();
+        let skip = if left_aligned {
+            0
+        } else {
+            nibbles.len() - count
+        };
+        nibbles[skip..skip + count]
+            .iter()
+            .map(|nibble| match nibble {
+                0..=9 => Ok(b'0' + nibble),
+                0x0D => Ok(b'='),
+                _ => Err(format!("invalid BCD digit {:X}", nibble)),
+            })
+            .collect()
+    }
+
+    fn pack_binary(&self, bytes: &[u8]) -> Vec<u8> {
+        match self.packing {
+            Packing::Bcd => bytes.to_vec(),
+            Packing::Ascii => hex::encode_upper(bytes).into_bytes(),
+        }
+    }
+
+    fn read_binary(&self, reader: &mut Reader, count: usize) -> Result<Vec<u8>, String> {
+        match self.packing {
+            Packing::Bcd => reader.take(count).map(<[u8]>::to_vec),
+            Packing::Ascii => hex::decode(reader.take(count * 2)?).map_err(|e| e.to_string()),
+        }
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], String> {
+        let bytes = self
+            .data
+            .get(self.pos..self.pos + count)
+            .ok_or("message is truncated")?;
+        self.pos += count;
+        Ok(bytes)
+    }
+}
+
+/// Fields holding the PAN, track data or PIN block
+const SENSITIVE_FIELDS: &[u8] = &[2, 35, 36, 45, 52];
+
+/// ISO 8583 message
+///
+/// Numeric and text fields hold their ASCII characters, binary fields their
+/// bytes. `Debug` output leaves out cardholder data, and values are zeroized
+/// on drop.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Iso8583Message {
+    /// Message Type Indicator, e.g. `"0200"`
+    pub mti: String,
+    fields: BTreeMap<u8, Vec<u8>>,
+}
+
+impl Iso8583Message {
+    pub fn new(mti: &str) -> Self {
+        Self {
+            mti: mti.to_string(),
+            fields: BTreeMap::new(),
+        }
+    }
+
+    pub fn set(&mut self, number: u8, value: impl Into<Vec<u8>>) {
+        if let Some(mut old) = self.fields.insert(number, value.into()) {
+            old.zeroize();
+        }
+    }
+
+    pub fn with_field(mut self, number: u8, value: impl Into<Vec<u8>>) -> Self {
+        self.set(number, value);
+        self
+    }
+
+    pub fn get(&self, number: u8) -> Option<&[u8]> {
+        self.fields.get(&number).map(Vec::as_slice)
+    }
+
+    /// Value of a numeric or text field
+    pub fn get_str(&self, number: u8) -> Option<&str> {
+        self.get(number)
+            .and_then(|value| std::str::from_utf8(value).ok())
+    }
+
+    pub fn remove(&mut self, number: u8) {
+        if let Some(mut old) = self.fields.remove(&number) {
+            old.zeroize();
+        }
+    }
+
+    /// Numbers of the fields present, in order
+    pub fn field_numbers(&self) -> impl Iterator<Item = u8> + '_ {
+        self.fields.keys().copied()
+    }
+}
+
+impl Drop for Iso8583Message {
+    fn drop(&mut self) {
+        self.fields.values_mut().for_each(Zeroize::zeroize);
+    }
+}
+
+impl std::fmt::Debug for Iso8583Message {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut message = f.debug_struct("Iso8583Message");
+        message.field("mti", &self.mti);
+        for (number, value) in &self.fields {
+            let name = format!("{}", number);
+            if SENSITIVE_FIELDS.contains(number) {
+                message.field(&name, &format_args!("[REDACTED; {}]", value.len()));
+            } else {
+                match std::str::from_utf8(value) {
+                    Ok(text) if text.bytes().all(|b| (0x20..0x7F).contains(&b)) => {
+                        message.field(&name, &text)
+                    }
+                    _ => message.field(&name, &hex::encode_upper(value)),
+                };
+            }
+        }
+        message.finish()
+    }
+}
+
+/// Acquirer and card acceptor data for outgoing messages
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MessageContext {
+    /// Systems Trace Audit Number (field 11)
+    pub stan: u32,
+    /// Transmission date and time (field 7)
+    pub transmitted_at: DateTime<Utc>,
+    /// Card Acceptor Terminal Identification (field 41)
+    pub terminal_id: String,
+    /// Card Acceptor Identification Code (field 42)
+    pub merchant_id: String,
+    /// Merchant Type (field 18)
+    pub merchant_category_code: Option<String>,
+    /// Acquiring Institution Identification Code (field 32)
+    pub acquirer_id: Option<String>,
+    /// POS Entry Mode (field 22); the POS data code in 1993 messages
+    pub pos_entry_mode: String,
+}
+
+impl MessageContext {
+    /// Context for a contactless chip read (POS entry mode `071`)
+    pub fn new(
+        stan: u32,
+        transmitted_at: DateTime<Utc>,
+        terminal_id: &str,
+        merchant_id: &str,
+    ) -> Self {
+        Self {
+            stan,
+            transmitted_at,
+            terminal_id: terminal_id.to_string(),
+            merchant_id: merchant_id.to_string(),
+            merchant_category_code: None,
+            acquirer_id: None,
+            pos_entry_mode: "071".to_string(),
+        }
+    }
+
+    pub fn with_merchant_category_code(mut self, mcc: &str) -> Self {
+        self.merchant_category_code = Some(mcc.to_string());
+        self
+    }
+
+    pub fn with_acquirer_id(mut self, acquirer_id: &str) -> Self {
+        self.acquirer_id = Some(acquirer_id.to_string());
+        self
+    }
+
+    pub fn with_pos_entry_mode(mut self, pos_entry_mode: &str) -> Self {
+        self.pos_entry_mode = pos_entry_mode.to_string();
+        self
+    }
+}
+
+/// Builds authorisation, financial, advice and reversal messages from a
+/// kernel's data record
+#[derive(Debug, Clone)]
+pub struct MessageBuilder {
+    version: Iso8583Version,
+    context: MessageContext,
+    field55: Option<Field55Spec>,
+}
+
+impl MessageBuilder {
+    /// Field 55 follows the scheme of the Dedicated File Name (0x84) unless
+    /// set with [`MessageBuilder::with_field55`]
+    pub fn new(version: Iso8583Version, context: MessageContext) -> Self {
+        Self {
+            version,
+            context,
+            field55: None,
+        }
+    }
+
+    pub fn with_field55(mut self, spec: Field55Spec) -> Self {
+        self.field55 = Some(spec);
+        self
+    }
+
+    /// Authorisation request (0100)
+    pub fn authorization(&self, data_record: &[Tlv]) -> Result<Iso8583Message, String> {
+        self.card_message("100", data_record)
+    }
+
+    /// Financial transaction request (0200)
+    pub fn financial(&self, data_record: &[Tlv]) -> Result<Iso8583Message, String> {
+        self.card_message("200", data_record)
+    }
+
+    /// Financial advice (0220) of a transaction approved offline or
+    /// completed after a pre-authorisation
+    pub fn advice(
+        &self,
+        data_record: &[Tlv],
+        authorization_code: Option<&str>,
+    ) -> Result<Iso8583Message, String> {
+        let mut message = self.card_message("220", data_record)?;
+        if let Some(code) = authorization_code {
+            message.set(38, code);
+        }
+        Ok(message)
+    }
+
+    /// Reversal request (0400) of an earlier request, e.g. after a timeout
+    /// or a card decline of an online approval
+    pub fn reversal(&self, original: &Iso8583Message) -> Result<Iso8583Message, String> {
+        let mut message = Iso8583Message::new(&self.mti("400"));
+        for number in [2, 3, 4, 14, 18, 22, 23, 32, 35, 41, 42, 49, 55] {
+            if let Some(value) = original.get(number) {
+                message.set(number, value);
+            }
+        }
+        self.set_trace(&mut message);
+
+        let field = |number| original.get_str(number).unwrap_or_default();
+        let acquirer_id = self.context.acquirer_id.as_deref().unwrap_or_default();
+        match self.version {
+            Iso8583Version::V1987 => {
+                // Original MTI, STAN, transmission date and time, acquirer
+                // and forwarding institution
+                message.set(
+                    90,
+                    format!(
+                        "{}{}{}{:0>11}{:0>11}",
+                        original.mti,
+                        field(11),
+                        field(7),
+                        acquirer_id,
+                        ""
+                    ),
+                );
+            }
+            Iso8583Version::V1993 => {
+                message.set(24, "400");
+                message.set(
+                    56,
+                    format!(
+                        "{}{}{}{:02}{}",
+                        original.mti,
+                        field(11),
+                        field(12),
+                        acquirer_id.len(),
+                        acquirer_id
+                    ),
+                );
+            }
+        }
+        Ok(message)
+    }
+
+    fn mti(&self, class: &str) -> String {
+        format!("{}{}", self.version.mti_digit(), class)
+    }
+
+    fn card_message(&self, class: &str, data_record: &[Tlv]) -> Result<Iso8583Message, String> {
+        let find = |tag: &[u8]| Tlv::find_by_tag(data_record, tag).map(|tlv| tlv.value.as_slice());
+        let digits = |value: &[u8]| hex::encode_upper(value);
+        // Numeric data object of a fixed length, so slicing its digits is safe
+        let numeric = |tag: &[u8], len: usize| match find(tag) {
+            Some(value) if value.len() == len => Ok(Some(digits(value))),
+            Some(value) => Err(format!(
+                "Data object {} has length {}, expected {}",
+                hex::encode_upper(tag),
+                value.len(),
+                len
+            )),
+            None => Ok(None),
+        };
+        let mut message = Iso8583Message::new(&self.mti(class));
+
+        let track2 =
+            find(&[0x57]).map(|track| digits(track).trim_end_matches('F').replace('D', "="));
+        let pan = match find(&[0x5A]) {
+            Some(pan) => Pan::from_bcd(pan)?,
+            None => {
+                let track2 = track2.as_deref().ok_or("Data record has no PAN")?;
+                Pan::parse(track2.split('=').next().unwrap_or_default())?
+            }
+        };
+        message.set(2, pan.expose()?);
+
+        let transaction_type = find(&[0x9C])
+            .and_then(|value| value.first())
+            .and_then(|code| TransactionType::from_code(*code))
+            .unwrap_or(TransactionType::Purchase);
+        message.set(3, transaction_type.processing_code());
+        message.set(
+            4,
+            digits(find(&[0x9F, 0x02]).ok_or("Data record has no amount")?),
+        );
+        self.set_trace(&mut message);
+
+        let transmitted = self.context.transmitted_at;
+        let date = numeric(&[0x9A], 3)?.unwrap_or_else(|| transmitted.format("%y%m%d").to_string());
+        let time =
+            numeric(&[0x9F, 0x21], 3)?.unwrap_or_else(|| transmitted.format("%H%M%S").to_string());
+        match self.version {
+            Iso8583Version::V1987 => {
+                message.set(12, time);
+                message.set(13, &date[2..]);
+            }
+            Iso8583Version::V1993 => {
+                message.set(12, format!("{}{}", date, time));
+                message.set(24, if class == "100" { "100" } else { "200" });
+            }
+        }
+        if let Some(expiry) = numeric(&[0x5F, 0x24], 3)? {
+            message.set(14, &expiry[..4]);
+        }
+        if let Some(mcc) = &self.context.merchant_category_code {
+            message.set(18, mcc.as_str());
+        }
+        message.set(22, self.context.pos_entry_mode.as_str());
+        match find(&[0x5F, 0x34]) {
+            // n2 in BCD, so the hex digits are the number
+            Some(&[sequence]) => message.set(23, format!("{:03X}", sequence)),
+            Some(_) => return Err("PAN Sequence Number must be one byte".to_string()),
+            None => {}
+        }
+        if let Some(acquirer_id) = &self.context.acquirer_id {
+            message.set(32, acquirer_id.as_str());
+        }
+        if let Some(track2) = track2 {
+            message.set(35, track2);
+        }
+        message.set(41, self.context.terminal_id.as_str());
+        message.set(42, self.context.merchant_id.as_str());
+        if let Some(currency) = numeric(&[0x5F, 0x2A], 2)? {
+            message.set(49, &currency[1..]);
+        }
+
+        let field55 = match &self.field55 {
+            Some(spec) => spec.assemble(data_record)?,
+            None => {
+                Field55Spec::for_aid(find(&[0x84]).unwrap_or_default()).assemble(data_record)?
+            }
+        };
+        message.set(55, field55);
+        Ok(message)
+    }
+
+    fn set_trace(&self, message: &mut Iso8583Message) {
+        message.set(
+            7,
+            self.context.transmitted_at.format("%m%d%H%M%S").to_string(),
+        );
+        message.set(11, format!("{:06}", self.context.stan % 1_000_000));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data_record() -> Vec<Tlv> {
+        [
+            ("5A", "4761739001010010"),
+            ("57", "4761739001010010D3012201114387808F"),
+            ("5F24", "301231"),
+            ("5F34", "01"),
+            ("9F02", "000000012345"),
+            ("5F2A", "0156"),
+            ("9C", "00"),
+            ("9A", "260921"),
+            ("9F26", "1122334455667788"),
+            ("9F27", "80"),
+            ("9F10", "06010A03A00000"),
+            ("9F37", "12345678"),
+            ("9F36", "001A"),
+            ("95", "0000008000"),
+            ("82", "2000"),
+            ("9F1A", "0156"),
+            ("84", "A0000000031010"),
+        ]
+        .iter()
+        .map(|(tag, value)| Tlv::new(&hex::decode(tag).unwrap(), hex::decode(value).unwrap()))
+        .collect()
+    }
+
+    fn context() -> MessageContext {
+        let at = DateTime::parse_from_rfc3339("2026-09-21T08:30:05Z").unwrap();
+        MessageContext::new(42, at.with_timezone(&Utc), "TERM0001", "MERCHANT0000001")
+            .with_acquirer_id("12345")
+    }
+
+    #[test]
+    fn test_pack_and_unpack_bcd() {
+        let spec = MessageSpec::iso1987(Packing::Bcd);
+        let message = Iso8583Message::new("0800")
+            .with_field(3, "990000")
+            .with_field(11, "42")
+            .with_field(35, "4761739001010010=3012201")
+            .with_field(41, "TERM1")
+            .with_field(128, [0xAA; 8]);
+
+        let packed = spec.pack(&message).unwrap();
+        assert_eq!(
+            hex::encode_upper(&packed[..24]),
+            "0800A0200000208000000000000000000001990000000042"
+        );
+        let unpacked = spec.unpack(&packed).unwrap();
+        assert_eq!(unpacked.get_str(11), Some("000042"));
+        assert_eq!(unpacked.get_str(35), Some("4761739001010010=3012201"));
+        assert_eq!(unpacked.get_str(41), Some("TERM1   "));
+        assert_eq!(unpacked.get(128), Some(&[0xAA; 8][..]));
+        assert!(!format!("{:?}", unpacked).contains("4761739001010010"));
+
+        assert!(spec
+            .pack(&Iso8583Message::new("0800").with_field(3, "99A"))
+            .is_err());
+        assert!(spec.unpack(&packed[..packed.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_financial_request_and_reversal() {
+        let builder = MessageBuilder::new(Iso8583Version::V1987, context());
+        let request = builder.financial(&data_record()).unwrap();
+        assert_eq!(request.mti, "0200");
+        assert_eq!(request.get_str(2), Some("4761739001010010"));
+        assert_eq!(request.get_str(3), Some("000000"));
+        assert_eq!(request.get_str(4), Some("000000012345"));
+        assert_eq!(request.get_str(7), Some("0921083005"));
+        assert_eq!(request.get_str(13), Some("0921"));
+        assert_eq!(request.get_str(14), Some("3012"));
+        assert_eq!(request.get_str(23), Some("001"));
+        assert_eq!(
+            request.get_str(35),
+            Some("4761739001010010=3012201114387808")
+        );
+        assert_eq!(request.get_str(49), Some("156"));
+        let icc_data = Tlv::parse(request.get(55).unwrap()).unwrap();
+        assert_eq!(icc_data.len(), 14);
+        assert_eq!(icc_data[0].tag, [0x9F, 0x26]);
+        assert_eq!(icc_data[13].tag, [0x5F, 0x34]);
+
+        let spec = MessageSpec::iso1987(Packing::Ascii);
+        let packed = spec.pack(&request).unwrap();
+        assert!(packed.starts_with(b"0200"));
+        assert_eq!(spec.unpack(&packed).unwrap(), request);
+
+        let reversal = builder.reversal(&request).unwrap();
+        assert_eq!(reversal.mti, "0400");
+        assert_eq!(
+            reversal.get_str(90),
+            Some("020000004209210830050000001234500000000000")
+        );
+        assert!(MessageSpec::iso1987(Packing::Bcd).pack(&reversal).is_ok());
+
+        let advice = MessageBuilder::new(Iso8583Version::V1993, context())
+            .advice(&data_record(), Some("A1B2C3"))
+            .unwrap();
+        assert_eq!(advice.mti, "1220");
+        assert_eq!(advice.get_str(12), Some("260921083005"));
+        assert_eq!(advice.get_str(38), Some("A1B2C3"));
+        assert!(MessageSpec::iso1993(Packing::Bcd).pack(&advice).is_ok());
+    }
+
+    #[test]
+    fn test_sequence_number_and_short_data_objects() {
+        let builder = MessageBuilder::new(Iso8583Version::V1987, context());
+        let with = |tag: &[u8], value: &[u8]| {
+            let mut record = data_record();
+            Tlv::upsert(&mut record, tag, value.to_vec());
+            builder.financial(&record)
+        };
+        assert_eq!(
+            with(&[0x5F, 0x34], &[0x12]).unwrap().get_str(23),
+            Some("012")
+        );
+
+        assert!(with(&[0x9A], &[0x26, 0x09]).is_err());
+        assert!(with(&[0x5F, 0x24], &[0x30]).is_err());
+        assert!(with(&[0x5F, 0x2A], &[]).is_err());
+
+        // The processing code starts with the Transaction Type (0x9C)
+        let cash = with(&[0x9C], &[TransactionType::CashAdvance.code()]).unwrap();
+        assert_eq!(cash.get_str(3), Some("170000"));
+    }
+}
@@ -0,0 +1,117 @@
+use crate::models::emv::{ApduCommand, ApduResponse};
+use crate::models::issuer_script::{IssuerScript, ScriptResult, ScriptStatus};
+
+/// Delivers issuer scripts to the card one command at a time
+/// (EMV Book 3 Section 10.10)
+///
+/// A script stops at the first command whose SW1 is not 90, 62 or 63;
+/// processing continues with the next script.
+#[derive(Debug, Clone, Default)]
+pub struct IssuerScriptRunner {
+    scripts: Vec<IssuerScript>,
+    script: usize,
+    command: usize,
+    results: Vec<ScriptResult>,
+    sent: bool,
+}
+
+impl IssuerScriptRunner {
+    pub fn new(scripts: Vec<IssuerScript>) -> Self {
+        Self {
+            scripts,
+            ..Default::default()
+        }
+    }
+
+    /// Next command to send, or `None` once every script has been processed
+    pub fn next_command(&mut self) -> Option<ApduCommand> {
+        while let Some(script) = self.scripts.get(self.script) {
+            if let Some(command) = script.commands.get(self.command) {
+                self.sent = true;
+                return Some(command.clone());
+            }
+
+            let status = if script.commands.is_empty() {
+                ScriptStatus::NotPerformed
+            } else {
+                ScriptStatus::Successful
+            };
+            self.finish_script(status);
+        }
+        None
+    }
+
+    /// Record the card response to the command returned by `next_command`
+    pub fn on_response(&mut self, response: &ApduResponse) {
+        if matches!(response.sw1, 0x90 | 0x62 | 0x63) {
+            self.command += 1;
+        } else {
+            let sequence = u8::try_from(self.command + 1).unwrap_or(u8::MAX);
+            self.finish_script(ScriptStatus::Failed(sequence));
+        }
+    }
+
+    /// Whether any command was sent to the card
+    pub fn performed(&self) -> bool {
+        self.sent
+    }
+
+    /// Whether any script was terminated by an error
+    pub fn any_failed(&self) -> bool {
+        self.results
+            .iter()
+            .any(|result| matches!(result.status, ScriptStatus::Failed(_)))
+    }
+
+    pub fn results(&self) -> &[ScriptResult] {
+        &self.results
+    }
+
+    fn finish_script(&mut self, status: ScriptStatus) {
+        self.results.push(ScriptResult {
+            status,
+            script_id: self.scripts[self.script].script_id,
+        });
+        self.script += 1;
+        self.command = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_failed_command_stops_script_only() {
+        // Script 1: two commands, the second fails; script 2: empty; script 3: one command
+        let data = hex::decode(
+            "711A9F1804000000018605841E000000860A84DA9F5805001122334471079F180400000002710786058418000000",
+        )
+        .unwrap();
+        let scripts = IssuerScript::parse_all(&data).unwrap();
+        let mut runner = IssuerScriptRunner::new(scripts);
+
+        let ok = ApduResponse::from_bytes(&[0x90, 0x00]).unwrap();
+        let error = ApduResponse::from_bytes(&[0x6A, 0x81]).unwrap();
+
+        assert_eq!(runner.next_command().unwrap().ins, 0x1E);
+        runner.on_response(&ok);
+        assert_eq!(runner.next_command().unwrap().ins, 0xDA);
+        runner.on_response(&error);
+        assert_eq!(runner.next_command().unwrap().ins, 0x18);
+        runner.on_response(&ok);
+        assert!(runner.next_command().is_none());
+
+        assert!(runner.performed());
+        assert!(runner.any_failed());
+        let statuses: Vec<_> = runner.results().iter().map(|r| r.status).collect();
+        assert_eq!(
+            statuses,
+            vec![
+                ScriptStatus::Failed(2),
+                ScriptStatus::NotPerformed,
+                ScriptStatus::Successful
+            ]
+        );
+    }
+}
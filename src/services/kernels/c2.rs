@@ -0,0 +1,1344 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    bcd_to_u64, collect_tags, error_indication, u64_to_bcd, Combination, ContactlessKernel,
+    ContactlessLimits, KernelFactory, KernelId, KernelOutcome, KernelStep, L2Error,
+};
+use crate::models::afl::AflEntry;
+use crate::models::cryptogram::GenerateAcResponse;
+use crate::models::cvm::{CvMethod, CvmCondition, CvmList, CvmResult, CvmResults, CvmRule};
+use crate::models::dol::Dol;
+use crate::models::emv::{ApduResponse, CryptogramType, Tlv};
+use crate::models::outcome::{OutcomeCvm, OutcomeParameterSet, OutcomeStart, OutcomeStatus};
+use crate::models::secret::expose;
+use crate::models::terminal::TerminalConfig;
+use crate::models::tvr::{RelayResistance, Tvr};
+use crate::services::action_analysis::{IssuerActionCodes, TerminalActionAnalysis};
+use crate::services::emv_processor::EmvProcessor;
+use crate::services::oda::{self, CaPublicKey};
+
+const TAG_AMOUNT: &[u8] = &[0x9F, 0x02];
+const TAG_AIP: &[u8] = &[0x82];
+const TAG_AFL: &[u8] = &[0x94];
+const TAG_PAN: &[u8] = &[0x5A];
+const TAG_PAN_SEQUENCE: &[u8] = &[0x5F, 0x34];
+const TAG_CDOL1: &[u8] = &[0x8C];
+const TAG_CVM_LIST: &[u8] = &[0x8E];
+const TAG_CVM_RESULTS: &[u8] = &[0x9F, 0x34];
+const TAG_PDOL: &[u8] = &[0x9F, 0x38];
+const TAG_SDA_TAG_LIST: &[u8] = &[0x9F, 0x4A];
+const TAG_UDOL: &[u8] = &[0x9F, 0x69];
+const TAG_DRDOL: &[u8] = &[0x9F, 0x51];
+const TAG_UNPREDICTABLE_NUMBER: &[u8] = &[0x9F, 0x37];
+const TAG_UNPREDICTABLE_NUMBER_NUMERIC: &[u8] = &[0x9F, 0x6A];
+const TAG_TRANSACTION_CURRENCY: &[u8] = &[0x5F, 0x2A];
+const TAG_APPLICATION_CURRENCY: &[u8] = &[0x9F, 0x42];
+const TAG_OUTCOME_PARAMETER_SET: &[u8] = &[0xDF, 0x81, 0x29];
+const TAG_ERROR_INDICATION: &[u8] = &[0xDF, 0x81, 0x15];
+
+/// UDOL used when the card has none: Unpredictable Number (Numeric)
+const DEFAULT_UDOL: &[u8] = &[0x9F, 0x6A, 0x04];
+
+/// Data Record contents in EMV mode
+const EMV_MODE_DATA_RECORD: &[&[u8]] = &[
+    &[0x9F, 0x02],
+    &[0x9F, 0x03],
+    &[0x9F, 0x26],
+    &[0x5F, 0x24],
+    &[0x82],
+    &[0x50],
+    &[0x5A],
+    &[0x5F, 0x34],
+    &[0x57],
+    &[0x9F, 0x12],
+    &[0x9F, 0x36],
+    &[0x9F, 0x27],
+    &[0x9F, 0x34],
+    &[0x84],
+    &[0x9F, 0x10],
+    &[0x9F, 0x33],
+    &[0x9F, 0x1A],
+    &[0x9F, 0x35],
+    &[0x95],
+    &[0x5F, 0x2A],
+    &[0x9A],
+    &[0x9C],
+    &[0x9F, 0x37],
+    &[0x9F, 0x4B],
+    &[0x9F, 0x6E],
+];
+
+/// Data Record contents in mag-stripe mode; the host builds the dynamic
+/// track data from the CVC3s, ATC and bitmaps
+const MAG_STRIPE_DATA_RECORD: &[&[u8]] = &[
+    &[0x9F, 0x02],
+    &[0x50],
+    &[0x84],
+    &[0x56],
+    &[0x9F, 0x6B],
+    &[0x9F, 0x60],
+    &[0x9F, 0x61],
+    &[0x9F, 0x36],
+    &[0x9F, 0x6A],
+    &[0x9F, 0x62],
+    &[0x9F, 0x63],
+    &[0x9F, 0x64],
+    &[0x9F, 0x65],
+    &[0x9F, 0x66],
+    &[0x9F, 0x67],
+];
+
+/// CVM Capability bits (tags 0xDF8118 / 0xDF8119, byte 1)
+const CVM_CAPABILITY_ONLINE_PIN: u8 = 0x40;
+const CVM_CAPABILITY_SIGNATURE: u8 = 0x20;
+const CVM_CAPABILITY_NO_CVM: u8 = 0x08;
+
+/// Maximum number of EXCHANGE RELAY RESISTANCE DATA retries
+const MAX_RELAY_RESISTANCE_RETRIES: u8 = 2;
+
+/// Mastercard contactless kernel (C-2) configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct C2Config {
+    /// Terminal type, capabilities and Terminal Action Codes
+    pub terminal: TerminalConfig,
+    pub emv_mode_supported: bool,
+    pub mag_stripe_mode_supported: bool,
+    /// On-device cardholder verification supported by the kernel
+    pub on_device_cvm_supported: bool,
+    pub relay_resistance_supported: bool,
+    /// Reader Contactless Transaction Limit, no on-device CVM (0xDF8124)
+    pub transaction_limit_no_on_device_cvm: u64,
+    /// Reader Contactless Transaction Limit, on-device CVM (0xDF8125)
+    pub transaction_limit_on_device_cvm: u64,
+    /// Reader Contactless Floor Limit (0xDF8123)
+    pub floor_limit: u64,
+    /// Reader CVM Required Limit (0xDF8126)
+    pub cvm_required_limit: u64,
+    /// CVM Capability - CVM Required (0xDF8118)
+    pub cvm_capability_cvm_required: u8,
+    /// CVM Capability - No CVM Required (0xDF8119)
+    pub cvm_capability_no_cvm_required: u8,
+    /// Minimum Relay Resistance Grace Period (0xDF8132), units of 100 µs
+    pub min_relay_resistance_grace_period: u16,
+    /// Maximum Relay Resistance Grace Period (0xDF8133), units of 100 µs
+    pub max_relay_resistance_grace_period: u16,
+    /// Terminal Expected Transmission Time For Relay Resistance C-APDU (0xDF8134)
+    pub expected_capdu_transmission_time: u16,
+    /// Terminal Expected Transmission Time For Relay Resistance R-APDU (0xDF8135)
+    pub expected_rapdu_transmission_time: u16,
+    /// Maximum number of records kept in the torn transaction log (0xDF811D)
+    pub max_torn_records: usize,
+    /// Certification Authority Public Keys for CDA
+    #[serde(default)]
+    pub ca_public_keys: Vec<CaPublicKey>,
+}
+
+impl Default for C2Config {
+    fn default() -> Self {
+        Self {
+            terminal: TerminalConfig::default(),
+            emv_mode_supported: true,
+            mag_stripe_mode_supported: true,
+            on_device_cvm_supported: true,
+            relay_resistance_supported: true,
+            transaction_limit_no_on_device_cvm: 10_000,
+            transaction_limit_on_device_cvm: 999_999_999,
+            floor_limit: 0,
+            cvm_required_limit: 5_000,
+            cvm_capability_cvm_required: CVM_CAPABILITY_ONLINE_PIN | CVM_CAPABILITY_SIGNATURE,
+            cvm_capability_no_cvm_required: CVM_CAPABILITY_NO_CVM,
+            min_relay_resistance_grace_period: 20,
+            max_relay_resistance_grace_period: 50,
+            expected_capdu_transmission_time: 18,
+            expected_rapdu_transmission_time: 18,
+            max_torn_records: 10,
+            ca_public_keys: Vec::new(),
+        }
+    }
+}
+
+/// A transaction whose GENERATE AC got no answer, kept to recover the AC
+/// when the same card is presented again
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TornRecord {
+    pub pan: Vec<u8>,
+    pub pan_sequence: Option<Vec<u8>>,
+    /// DRDOL-related data sent with RECOVER AC
+    pub drdol_data: Vec<u8>,
+    /// Cryptogram type requested by the torn GENERATE AC
+    pub requested: CryptogramType,
+    /// PDOL and CDOL1 related data of the torn transaction, for the CDA hash
+    pub pdol_data: Vec<u8>,
+    pub cdol1_data: Vec<u8>,
+    /// CVM and Data Record of the torn transaction, which a recovered AC
+    /// completes instead of the current one
+    pub cvm: OutcomeCvm,
+    #[serde(serialize_with = "expose::serialize")]
+    pub data_record: Vec<Tlv>,
+}
+
+/// Torn transaction log shared across transactions
+pub trait TornTransactionLog: Send + Sync {
+    fn store(&self, record: TornRecord);
+
+    /// Remove and return the record for this card, if any
+    fn take(&self, pan: &[u8], pan_sequence: Option<&[u8]>) -> Option<TornRecord>;
+}
+
+/// In-memory torn transaction log; the oldest record is dropped when full
+#[derive(Debug, Default)]
+pub struct InMemoryTornLog {
+    records: Mutex<Vec<TornRecord>>,
+    capacity: usize,
+}
+
+impl InMemoryTornLog {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            records: Mutex::new(Vec::new()),
+            capacity,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.records
+            .lock()
+            .map(|records| records.len())
+            .unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl TornTransactionLog for InMemoryTornLog {
+    fn store(&self, record: TornRecord) {
+        if let Ok(mut records) = self.records.lock() {
+            if records.len() >= self.capacity.max(1) {
+                records.remove(0);
+            }
+            records.push(record);
+        }
+    }
+
+    fn take(&self, pan: &[u8], pan_sequence: Option<&[u8]>) -> Option<TornRecord> {
+        let mut records = self.records.lock().ok()?;
+        let index = records
+            .iter()
+            .position(|r| r.pan == pan && r.pan_sequence.as_deref() == pan_sequence)?;
+        Some(records.remove(index))
+    }
+}
+
+#[derive(Debug, Clone)]
+enum State {
+    NotStarted,
+    AwaitingGpo,
+    ExchangingRelayResistance,
+    ReadingRecords,
+    AwaitingRecoverAc(Box<TornRecord>),
+    AwaitingGenerateAc(CryptogramType),
+    AwaitingChecksum,
+    Done,
+}
+
+/// Mastercard contactless kernel (EMV Contactless Book C-2)
+///
+/// Drives one card session: GET PROCESSING OPTIONS, relay resistance,
+/// READ RECORD, then GENERATE AC (EMV mode) or COMPUTE CRYPTOGRAPHIC
+/// CHECKSUM (mag-stripe mode). A CDA signature is verified before the
+/// outcome is decided; a failed check declines the transaction.
+#[derive(Clone)]
+pub struct C2Kernel {
+    processor: EmvProcessor,
+    config: C2Config,
+    torn_log: Arc<dyn TornTransactionLog>,
+    data: Vec<Tlv>,
+    tvr: Tvr,
+    aip: [u8; 2],
+    emv_mode: bool,
+    afl: Vec<AflEntry>,
+    records: Vec<(u8, u8)>,
+    next_record: usize,
+    /// Static Data to be Authenticated, from the records flagged in the AFL
+    static_data: Vec<u8>,
+    /// PDOL and CDOL1 related data, covered by the CDA hash
+    pdol_data: Vec<u8>,
+    cdol1_data: Vec<u8>,
+    relay_resistance_retries: u8,
+    cvm: OutcomeCvm,
+    state: State,
+}
+
+impl C2Kernel {
+    /// `terminal_data` holds transaction data objects such as Amount (0x9F02),
+    /// currency, date, type and Unpredictable Number (0x9F37)
+    pub fn new(processor: EmvProcessor, config: C2Config, terminal_data: Vec<Tlv>) -> Self {
+        let torn_log = Arc::new(InMemoryTornLog::new(config.max_torn_records));
+        Self {
+            processor,
+            config,
+            torn_log,
+            data: terminal_data,
+            tvr: Tvr::default(),
+            aip: [0x00; 2],
+            emv_mode: false,
+            afl: Vec::new(),
+            records: Vec::new(),
+            next_record: 0,
+            static_data: Vec::new(),
+            pdol_data: Vec::new(),
+            cdol1_data: Vec::new(),
+            relay_resistance_retries: 0,
+            cvm: OutcomeCvm::NotApplicable,
+            state: State::NotStarted,
+        }
+    }
+
+    /// Share a torn transaction log across kernel instances
+    pub fn with_torn_log(mut self, torn_log: Arc<dyn TornTransactionLog>) -> Self {
+        self.torn_log = torn_log;
+        self
+    }
+
+    /// All data objects known to the kernel so far
+    pub fn data(&self) -> &[Tlv] {
+        &self.data
+    }
+
+    /// Begin with the FCI returned by SELECT and send GET PROCESSING OPTIONS
+    ///
+    /// The PDOL is filled from the terminal data only; unlike C-3 no TTQ
+    /// (0x9F66) is supplied.
+    pub fn start(&mut self, fci: &[u8]) -> Result<KernelStep, String> {
+        if !matches!(self.state, State::NotStarted) {
+            return Err("Kernel already started".to_string());
+        }
+
+        for tlv in Tlv::parse_flattened(fci)? {
+            Tlv::upsert(&mut self.data, &tlv.tag, tlv.value);
+        }
+
+        self.pdol_data = match Tlv::find_by_tag(&self.data, TAG_PDOL) {
+            Some(pdol) => Dol::parse(&pdol.value)?.build(&self.data),
+            None => Vec::new(),
+        };
+
+        self.state = State::AwaitingGpo;
+        Ok(KernelStep::Send(
+            self.processor.get_processing_options(&self.pdol_data),
+        ))
+    }
+
+    /// Feed the card response to the last command sent
+    pub fn on_response(&mut self, response: &ApduResponse) -> Result<KernelStep, String> {
+        match std::mem::replace(&mut self.state, State::Done) {
+            State::AwaitingGpo => Ok(self.on_gpo(response)),
+            State::ExchangingRelayResistance => {
+                self.state = State::ExchangingRelayResistance;
+                Err("EXCHANGE RELAY RESISTANCE DATA needs on_timed_response".to_string())
+            }
+            State::ReadingRecords => Ok(self.on_record(response)),
+            State::AwaitingRecoverAc(torn) if response.is_success() => {
+                Ok(self.on_recover_ac(response, *torn))
+            }
+            // Recovery failed: the card did not keep the torn transaction
+            State::AwaitingRecoverAc(_) => Ok(self.generate_ac()),
+            State::AwaitingGenerateAc(requested) => Ok(self.on_generate_ac(response, requested)),
+            State::AwaitingChecksum => Ok(self.on_checksum(response)),
+            state => {
+                self.state = state;
+                Err("No card response expected".to_string())
+            }
+        }
+    }
+
+    /// Feed a card response together with the time measured between sending
+    /// the command and receiving the response (needed for relay resistance)
+    pub fn on_timed_response(
+        &mut self,
+        response: &ApduResponse,
+        elapsed: Duration,
+    ) -> Result<KernelStep, String> {
+        if !matches!(self.state, State::ExchangingRelayResistance) {
+            return self.on_response(response);
+        }
+        self.state = State::Done;
+        Ok(self.on_relay_resistance(response, elapsed))
+    }
+
+    /// The card did not answer (Level 1 error); a torn GENERATE AC is logged
+    /// for recovery on the next presentation
+    pub fn on_communication_error(&mut self) -> KernelStep {
+        if let State::AwaitingGenerateAc(requested) = self.state {
+            if let Some(drdol) = Tlv::find_by_tag(&self.data, TAG_DRDOL) {
+                if let (Ok(drdol), Some(pan)) = (
+                    Dol::parse(&drdol.value),
+                    Tlv::find_by_tag(&self.data, TAG_PAN),
+                ) {
+                    self.torn_log.store(TornRecord {
+                        pan: pan.value.clone(),
+                        pan_sequence: Tlv::find_by_tag(&self.data, TAG_PAN_SEQUENCE)
+                            .map(|tlv| tlv.value.clone()),
+                        drdol_data: drdol.build(&self.data),
+                        requested,
+                        pdol_data: self.pdol_data.clone(),
+                        cdol1_data: self.cdol1_data.clone(),
+                        cvm: self.cvm,
+                        data_record: collect_tags(&self.data, EMV_MODE_DATA_RECORD),
+                    });
+                }
+            }
+        }
+
+        self.state = State::Done;
+        let mut parameters = OutcomeParameterSet::new(OutcomeStatus::EndApplication);
+        parameters.start = OutcomeStart::B;
+        parameters.ui_request_on_restart = true;
+        parameters.discretionary_data_present = true;
+        self.finish(
+            parameters,
+            Vec::new(),
+            error_indication(0x02, L2Error::Ok, 0),
+        )
+    }
+
+    fn on_gpo(&mut self, response: &ApduResponse) -> KernelStep {
+        if !response.is_success() {
+            return self.end_application(L2Error::StatusBytes, response.status_word());
+        }
+
+        let tlvs = match Tlv::parse_flattened(&response.data) {
+            Ok(tlvs) => tlvs,
+            Err(_) => return self.end_application(L2Error::ParsingError, 0),
+        };
+
+        let (aip, afl) = match Tlv::find_by_tag(&tlvs, &[0x80]) {
+            // Format 1: AIP followed by AFL
+            Some(format1) if format1.value.len() >= 2 => {
+                (format1.value[..2].to_vec(), format1.value[2..].to_vec())
+            }
+            _ => match (
+                Tlv::find_by_tag(&tlvs, TAG_AIP),
+                Tlv::find_by_tag(&tlvs, TAG_AFL),
+            ) {
+                (Some(aip), Some(afl)) if aip.value.len() == 2 => {
+                    (aip.value.clone(), afl.value.clone())
+                }
+                _ => return self.end_application(L2Error::CardDataMissing, 0),
+            },
+        };
+
+        self.aip = [aip[0], aip[1]];
+        Tlv::upsert(&mut self.data, TAG_AIP, aip);
+        Tlv::upsert(&mut self.data, TAG_AFL, afl.clone());
+
+        let limit = if self.on_device_cvm() {
+            self.config.transaction_limit_on_device_cvm
+        } else {
+            self.config.transaction_limit_no_on_device_cvm
+        };
+        if self.amount() > limit {
+            let mut parameters = OutcomeParameterSet::new(OutcomeStatus::SelectNext);
+            parameters.start = OutcomeStart::C;
+            parameters.discretionary_data_present = true;
+            return self.finish(
+                parameters,
+                Vec::new(),
+                error_indication(0x00, L2Error::MaxLimitExceeded, 0),
+            );
+        }
+
+        // AIP byte 2 b8: EMV mode is supported
+        self.emv_mode = self.aip[1] & 0x80 != 0 && self.config.emv_mode_supported;
+        if !self.emv_mode && !self.config.mag_stripe_mode_supported {
+            let mut parameters = OutcomeParameterSet::new(OutcomeStatus::TryAnotherInterface);
+            parameters.discretionary_data_present = true;
+            return self.finish(
+                parameters,
+                Vec::new(),
+                error_indication(0x00, L2Error::MagStripeNotSupported, 0),
+            );
+        }
+
+        self.afl = match AflEntry::parse_all(&afl) {
+            Ok(entries) => entries,
+            Err(_) => return self.end_application(L2Error::CardDataError, 0),
+        };
+        self.records = AflEntry::records(&self.afl);
+
+        // AIP byte 2 b1: relay resistance protocol is supported
+        if self.emv_mode && self.aip[1] & 0x01 != 0 {
+            if self.config.relay_resistance_supported {
+                return self.exchange_relay_resistance_data();
+            }
+            self.tvr.set_relay_resistance(RelayResistance::NotPerformed);
+        }
+
+        self.read_next_record()
+    }
+
+    fn exchange_relay_resistance_data(&mut self) -> KernelStep {
+        // The Terminal Relay Resistance Entropy is a fresh Unpredictable Number
+        let mut entropy = [0u8; 4];
+        if getrandom::getrandom(&mut entropy).is_err() {
+            return self.end_application(L2Error::Ok, 0);
+        }
+        Tlv::upsert(&mut self.data, TAG_UNPREDICTABLE_NUMBER, entropy.to_vec());
+        Tlv::upsert(&mut self.data, &[0xDF, 0x83, 0x01], entropy.to_vec());
+
+        self.state = State::ExchangingRelayResistance;
+        KernelStep::Send(self.processor.exchange_relay_resistance_data(&entropy))
+    }
+
+    fn on_relay_resistance(&mut self, response: &ApduResponse, elapsed: Duration) -> KernelStep {
+        if !response.is_success() {
+            return self.end_application(L2Error::StatusBytes, response.status_word());
+        }
+
+        let value = match Tlv::parse(&response.data) {
+            Ok(tlvs) => match Tlv::find_by_tag(&tlvs, &[0x80]) {
+                Some(tlv) if tlv.value.len() == 10 => tlv.value.clone(),
+                _ => return self.end_application(L2Error::ParsingError, 0),
+            },
+            Err(_) => return self.end_application(L2Error::ParsingError, 0),
+        };
+
+        let min_time = u64::from(u16::from_be_bytes([value[4], value[5]]));
+        let max_time = u64::from(u16::from_be_bytes([value[6], value[7]]));
+        let device_transmission = u64::from(u16::from_be_bytes([value[8], value[9]]));
+        let measured = (elapsed.as_micros() / 100) as u64;
+
+        let processing = measured.saturating_sub(
+            u64::from(self.config.expected_capdu_transmission_time)
+                + device_transmission.min(u64::from(self.config.expected_rapdu_transmission_time)),
+        );
+
+        let too_fast = processing
+            < min_time.saturating_sub(u64::from(self.config.min_relay_resistance_grace_period));
+        let too_slow =
+            processing > max_time + u64::from(self.config.max_relay_resistance_grace_period);
+
+        if too_slow && self.relay_resistance_retries < MAX_RELAY_RESISTANCE_RETRIES {
+            self.relay_resistance_retries += 1;
+            return self.exchange_relay_resistance_data();
+        }
+
+        if too_fast || too_slow {
+            self.tvr.set_relay_resistance_time_limits_exceeded(true);
+        }
+        self.tvr.set_relay_resistance(RelayResistance::Performed);
+
+        Tlv::upsert(&mut self.data, &[0xDF, 0x83, 0x02], value[..4].to_vec());
+        Tlv::upsert(&mut self.data, &[0xDF, 0x83, 0x03], value[4..6].to_vec());
+        Tlv::upsert(&mut self.data, &[0xDF, 0x83, 0x04], value[6..8].to_vec());
+        Tlv::upsert(&mut self.data, &[0xDF, 0x83, 0x05], value[8..10].to_vec());
+        Tlv::upsert(
+            &mut self.data,
+            &[0xDF, 0x83, 0x06],
+            (measured.min(u64::from(u16::MAX)) as u16)
+                .to_be_bytes()
+                .to_vec(),
+        );
+
+        self.read_next_record()
+    }
+
+    fn read_next_record(&mut self) -> KernelStep {
+        match self.records.get(self.next_record) {
+            Some(&(sfi, record)) => {
+                self.state = State::ReadingRecords;
+                KernelStep::Send(self.processor.read_record(sfi, record))
+            }
+            None => self.after_records(),
+        }
+    }
+
+    fn on_record(&mut self, response: &ApduResponse) -> KernelStep {
+        if !response.is_success() {
+            return self.end_application(L2Error::StatusBytes, response.status_word());
+        }
+
+        let template = match Tlv::parse(&response.data) {
+            Ok(tlvs) => match Tlv::find_by_tag(&tlvs, &[0x70]) {
+                Some(template) => template.clone(),
+                None => return self.end_application(L2Error::ParsingError, 0),
+            },
+            Err(_) => return self.end_application(L2Error::ParsingError, 0),
+        };
+        match Tlv::parse_flattened(&template.value) {
+            Ok(tlvs) => {
+                for tlv in tlvs {
+                    Tlv::upsert(&mut self.data, &tlv.tag, tlv.value);
+                }
+            }
+            Err(_) => return self.end_application(L2Error::ParsingError, 0),
+        }
+
+        // Static Data to be Authenticated: the template value for SFI 1-10,
+        // the whole record otherwise
+        let (sfi, record) = self.records[self.next_record];
+        if AflEntry::is_oda_record(&self.afl, sfi, record) {
+            if sfi <= 10 {
+                self.static_data.extend_from_slice(&template.value);
+            } else {
+                self.static_data.extend_from_slice(&response.data);
+            }
+        }
+
+        self.next_record += 1;
+        self.read_next_record()
+    }
+
+    fn after_records(&mut self) -> KernelStep {
+        if Tlv::find_by_tag(&self.data, TAG_PAN).is_none() {
+            return self.end_application(L2Error::CardDataMissing, 0);
+        }
+
+        let (cvm, cvm_results) = self.select_cvm();
+        self.cvm = cvm;
+        Tlv::upsert(
+            &mut self.data,
+            TAG_CVM_RESULTS,
+            cvm_results.to_bytes().to_vec(),
+        );
+
+        if !self.emv_mode {
+            return self.compute_cryptographic_checksum();
+        }
+
+        if Tlv::find_by_tag(&self.data, TAG_CDOL1).is_none() {
+            return self.end_application(L2Error::CardDataMissing, 0);
+        }
+
+        if self.amount() > self.config.floor_limit {
+            self.tvr.set_floor_limit_exceeded(true);
+        }
+        // AIP byte 1 b1: CDA supported
+        if self.aip[0] & 0x01 == 0 {
+            self.tvr.set_offline_data_authentication_not_performed(true);
+        }
+
+        if Tlv::find_by_tag(&self.data, TAG_DRDOL).is_some() {
+            let pan = Tlv::find_by_tag(&self.data, TAG_PAN).map(|tlv| tlv.value.clone());
+            let psn = Tlv::find_by_tag(&self.data, TAG_PAN_SEQUENCE).map(|tlv| tlv.value.clone());
+            if let Some(torn) = pan.and_then(|pan| self.torn_log.take(&pan, psn.as_deref())) {
+                let command = self.processor.recover_ac(&torn.drdol_data);
+                self.state = State::AwaitingRecoverAc(Box::new(torn));
+                return KernelStep::Send(command);
+            }
+        }
+
+        self.generate_ac()
+    }
+
+    /// CVM selection: on-device CVM above the CVM Required Limit, otherwise
+    /// the card's CVM List against the applicable CVM Capability
+    fn select_cvm(&mut self) -> (OutcomeCvm, CvmResults) {
+        let above_limit = self.amount() > self.config.cvm_required_limit;
+        let capability = if above_limit {
+            self.config.cvm_capability_cvm_required
+        } else {
+            self.config.cvm_capability_no_cvm_required
+        };
+
+        if above_limit && self.on_device_cvm() {
+            return (
+                OutcomeCvm::ConfirmationCodeVerified,
+                CvmResults {
+                    method_code: 0x01,
+                    condition_code: 0x00,
+                    result: CvmResult::Successful,
+                },
+            );
+        }
+
+        if !self.emv_mode {
+            let cvm = if !above_limit {
+                OutcomeCvm::NoCvm
+            } else if capability & CVM_CAPABILITY_ONLINE_PIN != 0 {
+                OutcomeCvm::OnlinePin
+            } else if capability & CVM_CAPABILITY_SIGNATURE != 0 {
+                OutcomeCvm::ObtainSignature
+            } else {
+                OutcomeCvm::NoCvm
+            };
+            return (cvm, CvmResults::not_performed(CvmResult::Unknown));
+        }
+
+        // AIP byte 1 b5: cardholder verification is supported
+        let list = Tlv::find_by_tag(&self.data, TAG_CVM_LIST)
+            .filter(|_| self.aip[0] & 0x10 != 0)
+            .and_then(|tlv| CvmList::parse(&tlv.value).ok());
+        let list = match list {
+            Some(list) => list,
+            None => {
+                return (
+                    OutcomeCvm::NoCvm,
+                    CvmResults::not_performed(CvmResult::Unknown),
+                )
+            }
+        };
+
+        for rule in &list.rules {
+            if !self.condition_satisfied(rule, &list, capability) {
+                continue;
+            }
+
+            let selected = match rule.method {
+                CvMethod::OnlinePin if capability & CVM_CAPABILITY_ONLINE_PIN != 0 => {
+                    Some((OutcomeCvm::OnlinePin, CvmResult::Unknown))
+                }
+                CvMethod::Signature if capability & CVM_CAPABILITY_SIGNATURE != 0 => {
+                    Some((OutcomeCvm::ObtainSignature, CvmResult::Unknown))
+                }
+                CvMethod::NoCvm if capability & CVM_CAPABILITY_NO_CVM != 0 => {
+                    Some((OutcomeCvm::NoCvm, CvmResult::Successful))
+                }
+                _ => None,
+            };
+
+            match selected {
+                Some((cvm, result)) => return (cvm, CvmResults::for_rule(rule, result)),
+                None if rule.apply_next_if_unsuccessful && rule.method != CvMethod::Fail => {
+                    continue
+                }
+                None => break,
+            }
+        }
+
+        self.tvr.set_cardholder_verification_not_successful(true);
+        (
+            OutcomeCvm::NoCvm,
+            CvmResults::not_performed(CvmResult::Failed),
+        )
+    }
+
+    fn condition_satisfied(&self, rule: &CvmRule, list: &CvmList, capability: u8) -> bool {
+        let same_currency = Tlv::find_by_tag(&self.data, TAG_APPLICATION_CURRENCY)
+            .zip(Tlv::find_by_tag(&self.data, TAG_TRANSACTION_CURRENCY))
+            .is_some_and(|(application, transaction)| application.value == transaction.value);
+        let amount = self.amount();
+
+        match rule.condition {
+            CvmCondition::Always | CvmCondition::NotUnattendedCashNotManualCashNotCashback => true,
+            CvmCondition::TerminalSupportsCvm => match rule.method {
+                CvMethod::OnlinePin => capability & CVM_CAPABILITY_ONLINE_PIN != 0,
+                CvMethod::Signature => capability & CVM_CAPABILITY_SIGNATURE != 0,
+                CvMethod::NoCvm => capability & CVM_CAPABILITY_NO_CVM != 0,
+                _ => false,
+            },
+            CvmCondition::UnderX => same_currency && amount < u64::from(list.amount_x),
+            CvmCondition::OverX => same_currency && amount > u64::from(list.amount_x),
+            CvmCondition::UnderY => same_currency && amount < u64::from(list.amount_y),
+            CvmCondition::OverY => same_currency && amount > u64::from(list.amount_y),
+            _ => false,
+        }
+    }
+
+    fn generate_ac(&mut self) -> KernelStep {
+        Tlv::upsert(&mut self.data, &[0x95], self.tvr.as_bytes().to_vec());
+
+        let iac = IssuerActionCodes::from_tlvs(&self.data);
+        let decision =
+            TerminalActionAnalysis::new(self.config.terminal.clone()).analyse(&self.tvr, &iac);
+
+        self.cdol1_data =
+            match Tlv::find_by_tag(&self.data, TAG_CDOL1).map(|cdol1| Dol::parse(&cdol1.value)) {
+                Some(Ok(cdol1)) => cdol1.build(&self.data),
+                _ => return self.end_application(L2Error::CardDataError, 0),
+            };
+
+        let mut command = self
+            .processor
+            .generate_ac(decision.cryptogram_type, &self.cdol1_data);
+        // P1 b5: CDA signature requested
+        if self.aip[0] & 0x01 != 0 && decision.cryptogram_type != CryptogramType::Aac {
+            command.p1 |= 0x10;
+        }
+
+        self.state = State::AwaitingGenerateAc(decision.cryptogram_type);
+        KernelStep::Send(command)
+    }
+
+    /// The card kept the torn transaction: complete that transaction from
+    /// its logged data rather than the current transaction's
+    fn on_recover_ac(&mut self, response: &ApduResponse, torn: TornRecord) -> KernelStep {
+        self.data
+            .retain(|tlv| !EMV_MODE_DATA_RECORD.contains(&tlv.tag.as_slice()));
+        for tlv in torn.data_record {
+            Tlv::upsert(&mut self.data, &tlv.tag, tlv.value);
+        }
+        if let Some(tvr) = Tlv::find_by_tag(&self.data, &[0x95])
+            .and_then(|tlv| <[u8; 5]>::try_from(tlv.value.as_slice()).ok())
+        {
+            self.tvr = Tvr::from_bytes(tvr);
+        }
+        self.pdol_data = torn.pdol_data;
+        self.cdol1_data = torn.cdol1_data;
+        self.cvm = torn.cvm;
+        self.on_generate_ac(response, torn.requested)
+    }
+
+    fn on_generate_ac(&mut self, response: &ApduResponse, requested: CryptogramType) -> KernelStep {
+        if !response.is_success() {
+            return self.end_application(L2Error::StatusBytes, response.status_word());
+        }
+
+        let parsed = match GenerateAcResponse::parse(&response.data) {
+            Ok(parsed) => parsed,
+            Err(_) => return self.end_application(L2Error::ParsingError, 0),
+        };
+        let mut returned = match parsed.validate_against(requested) {
+            Ok(returned) => returned,
+            Err(_) => return self.end_application(L2Error::CardDataError, 0),
+        };
+
+        // CDA was requested for a TC or ARQC: without a valid signature the
+        // cryptogram cannot be trusted and the transaction is declined
+        if self.aip[0] & 0x01 != 0
+            && requested != CryptogramType::Aac
+            && returned != CryptogramType::Aac
+            && self.verify_cda(&response.data).is_err()
+        {
+            self.tvr.set_cda_failed(true);
+            Tlv::upsert(&mut self.data, &[0x95], self.tvr.as_bytes().to_vec());
+            returned = CryptogramType::Aac;
+        }
+
+        Tlv::upsert(&mut self.data, &[0x9F, 0x27], vec![parsed.cid.0]);
+        Tlv::upsert(
+            &mut self.data,
+            &[0x9F, 0x36],
+            parsed.atc.to_be_bytes().to_vec(),
+        );
+        Tlv::upsert(&mut self.data, &[0x9F, 0x26], parsed.cryptogram.to_vec());
+        if let Some(iad) = parsed.issuer_application_data {
+            Tlv::upsert(&mut self.data, &[0x9F, 0x10], iad);
+        }
+        if let Some(sdad) = parsed.signed_dynamic_data {
+            Tlv::upsert(&mut self.data, &[0x9F, 0x4B], sdad);
+        }
+
+        let status = match returned {
+            CryptogramType::Tc => OutcomeStatus::Approved,
+            CryptogramType::Arqc => OutcomeStatus::OnlineRequest,
+            CryptogramType::Aac => OutcomeStatus::Declined,
+        };
+        self.complete(status, EMV_MODE_DATA_RECORD)
+    }
+
+    /// Recover the ICC Public Key and check the CDA signature of a GENERATE
+    /// AC response
+    fn verify_cda(&mut self, response: &[u8]) -> Result<(), String> {
+        let mut static_data = self.static_data.clone();
+        if let Some(tag_list) = Tlv::find_by_tag(&self.data, TAG_SDA_TAG_LIST) {
+            // Only the AIP may be listed
+            if tag_list.value != TAG_AIP {
+                return Err("Invalid SDA Tag List".to_string());
+            }
+            static_data.extend_from_slice(&self.aip);
+        }
+
+        let icc_key = oda::icc_public_key(&self.data, &self.config.ca_public_keys, &static_data)?;
+        let unpredictable_number = Tlv::find_by_tag(&self.data, TAG_UNPREDICTABLE_NUMBER)
+            .map(|tlv| tlv.value.clone())
+            .ok_or("Unpredictable Number not found")?;
+        let icc_dynamic_number = oda::verify_combined_signature(
+            &icc_key,
+            &unpredictable_number,
+            &[self.pdol_data.as_slice(), &self.cdol1_data].concat(),
+            response,
+        )?;
+        Tlv::upsert(&mut self.data, &[0x9F, 0x4C], icc_dynamic_number);
+        Ok(())
+    }
+
+    fn compute_cryptographic_checksum(&mut self) -> KernelStep {
+        if Tlv::find_by_tag(&self.data, TAG_UNPREDICTABLE_NUMBER_NUMERIC).is_none() {
+            let numeric = Tlv::find_by_tag(&self.data, TAG_UNPREDICTABLE_NUMBER)
+                .map(|tlv| {
+                    tlv.value
+                        .iter()
+                        .map(|&b| u64_to_bcd(u64::from(b % 100), 1)[0])
+                        .collect()
+                })
+                .unwrap_or_else(|| vec![0x00; 4]);
+            Tlv::upsert(&mut self.data, TAG_UNPREDICTABLE_NUMBER_NUMERIC, numeric);
+        }
+
+        let udol = Tlv::find_by_tag(&self.data, TAG_UDOL)
+            .map(|tlv| tlv.value.clone())
+            .unwrap_or_else(|| DEFAULT_UDOL.to_vec());
+        let udol_data = match Dol::parse(&udol) {
+            Ok(udol) => udol.build(&self.data),
+            Err(_) => return self.end_application(L2Error::CardDataError, 0),
+        };
+
+        self.state = State::AwaitingChecksum;
+        KernelStep::Send(self.processor.compute_cryptographic_checksum(&udol_data))
+    }
+
+    fn on_checksum(&mut self, response: &ApduResponse) -> KernelStep {
+        if !response.is_success() {
+            return self.end_application(L2Error::StatusBytes, response.status_word());
+        }
+
+        let tlvs = match Tlv::parse_flattened(&response.data) {
+            Ok(tlvs) => tlvs,
+            Err(_) => return self.end_application(L2Error::ParsingError, 0),
+        };
+        // CVC3 (Track2) and ATC are mandatory
+        if Tlv::find_by_tag(&tlvs, &[0x9F, 0x61]).is_none()
+            || Tlv::find_by_tag(&tlvs, &[0x9F, 0x36]).is_none()
+        {
+            return self.end_application(L2Error::CardDataMissing, 0);
+        }
+
+        for tlv in tlvs {
+            Tlv::upsert(&mut self.data, &tlv.tag, tlv.value);
+        }
+        self.complete(OutcomeStatus::OnlineRequest, MAG_STRIPE_DATA_RECORD)
+    }
+
+    fn complete(&mut self, status: OutcomeStatus, record_tags: &[&[u8]]) -> KernelStep {
+        let mut parameters = OutcomeParameterSet::new(status);
+        parameters.cvm = self.cvm;
+        parameters.receipt = self.cvm == OutcomeCvm::ObtainSignature;
+        parameters.data_record_present = true;
+        parameters.discretionary_data_present = true;
+
+        let data_record = collect_tags(&self.data, record_tags);
+        self.finish(
+            parameters,
+            data_record,
+            error_indication(0x00, L2Error::Ok, 0),
+        )
+    }
+
+    fn end_application(&mut self, error: L2Error, status_word: u16) -> KernelStep {
+        let mut parameters = OutcomeParameterSet::new(OutcomeStatus::EndApplication);
+        parameters.discretionary_data_present = true;
+        self.finish(
+            parameters,
+            Vec::new(),
+            error_indication(0x00, error, status_word),
+        )
+    }
+
+    fn finish(
+        &mut self,
+        parameters: OutcomeParameterSet,
+        data_record: Vec<Tlv>,
+        error_indication: Vec<u8>,
+    ) -> KernelStep {
+        self.state = State::Done;
+        Tlv::upsert(
+            &mut self.data,
+            TAG_OUTCOME_PARAMETER_SET,
+            parameters.to_bytes().to_vec(),
+        );
+
+        let mut discretionary_data = collect_tags(&self.data, &[TAG_APPLICATION_CURRENCY]);
+        discretionary_data.push(Tlv::new(TAG_ERROR_INDICATION, error_indication));
+
+        KernelStep::Complete(Box::new(KernelOutcome::new(
+            parameters,
+            data_record,
+            discretionary_data,
+        )))
+    }
+
+    fn amount(&self) -> u64 {
+        Tlv::find_by_tag(&self.data, TAG_AMOUNT)
+            .map(|tlv| bcd_to_u64(&tlv.value))
+            .unwrap_or(0)
+    }
+
+    /// AIP byte 1 b2: on-device cardholder verification is supported
+    fn on_device_cvm(&self) -> bool {
+        self.aip[0] & 0x02 != 0 && self.config.on_device_cvm_supported
+    }
+}
+
+impl ContactlessKernel for C2Kernel {
+    fn kernel_id(&self) -> KernelId {
+        KernelId::Mastercard
+    }
+
+    fn start(&mut self, fci: &[u8]) -> Result<KernelStep, String> {
+        C2Kernel::start(self, fci)
+    }
+
+    fn on_response(&mut self, response: &ApduResponse) -> Result<KernelStep, String> {
+        C2Kernel::on_response(self, response)
+    }
+
+    fn on_timed_response(
+        &mut self,
+        response: &ApduResponse,
+        elapsed: Duration,
+    ) -> Result<KernelStep, String> {
+        C2Kernel::on_timed_response(self, response, elapsed)
+    }
+
+    fn on_communication_error(&mut self) -> KernelStep {
+        C2Kernel::on_communication_error(self)
+    }
+}
+
+/// Creates C-2 sessions sharing one torn transaction log, so a transaction
+/// torn in one session is recovered in the next
+#[derive(Clone)]
+pub struct C2Factory {
+    pub config: C2Config,
+    pub torn_log: Arc<dyn TornTransactionLog>,
+}
+
+impl Default for C2Factory {
+    fn default() -> Self {
+        Self::new(C2Config::default())
+    }
+}
+
+impl C2Factory {
+    pub fn new(config: C2Config) -> Self {
+        let torn_log = Arc::new(InMemoryTornLog::new(config.max_torn_records));
+        Self { config, torn_log }
+    }
+}
+
+impl KernelFactory for C2Factory {
+    fn kernel_id(&self) -> KernelId {
+        KernelId::Mastercard
+    }
+
+    fn create(
+        &self,
+        processor: EmvProcessor,
+        combination: &Combination,
+        terminal_data: Vec<Tlv>,
+    ) -> Box<dyn ContactlessKernel> {
+        let limits = ContactlessLimits {
+            transaction_limit: self.config.transaction_limit_no_on_device_cvm,
+            floor_limit: self.config.floor_limit,
+            cvm_required_limit: self.config.cvm_required_limit,
+        }
+        .with_entry_point(&combination.config);
+        let config = C2Config {
+            transaction_limit_no_on_device_cvm: limits.transaction_limit,
+            floor_limit: limits.floor_limit,
+            cvm_required_limit: limits.cvm_required_limit,
+            ..self.config.clone()
+        };
+        Box::new(
+            C2Kernel::new(processor, config, terminal_data).with_torn_log(self.torn_log.clone()),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::emv::ApduCommand;
+    use crate::services::kernels::KernelOutcome;
+    use crate::services::oda::tests::{
+        icc_certificate, issuer_certificate, public_key, sign_combined, CA_MODULUS,
+    };
+
+    const PAN: [u8; 8] = [0x47, 0x61, 0x73, 0x90, 0x01, 0x01, 0x01, 0x19];
+    /// PDOL data the kernel sends: Terminal Country Code (0x9F1A)
+    const PDOL_DATA: [u8; 2] = [0x08, 0x40];
+
+    fn tlv(tag: &[u8], value: &[u8]) -> Vec<u8> {
+        let length = if value.len() > 127 {
+            vec![0x81, value.len() as u8]
+        } else {
+            vec![value.len() as u8]
+        };
+        [tag, &length, value].concat()
+    }
+
+    /// A Mastercard card answering each command from fixed data
+    struct SimulatedCard {
+        aip: [u8; 2],
+        record: Vec<u8>,
+        tear_generate_ac: bool,
+        /// Sign CDA over different data, as a cloned card would
+        tampered: bool,
+    }
+
+    impl SimulatedCard {
+        fn new(aip: [u8; 2]) -> Self {
+            let record = [
+                tlv(&[0x5A], &PAN),
+                tlv(&[0x5F, 0x24], &[0x30, 0x12, 0x31]),
+                tlv(&[0x5F, 0x34], &[0x01]),
+                tlv(
+                    &[0x8C],
+                    &hex::decode("9F02069F1A0295059F37049F3403").unwrap(),
+                ),
+                tlv(
+                    &[0x8E],
+                    &hex::decode("00000000000000004203410342031F03").unwrap(),
+                ),
+                tlv(&[0x9F, 0x0D], &[0x00; 5]),
+                tlv(&[0x9F, 0x0E], &[0x00; 5]),
+                tlv(&[0x9F, 0x0F], &[0x00; 5]),
+                tlv(&[0x9F, 0x51], &hex::decode("9F3704").unwrap()),
+            ]
+            .concat();
+            Self {
+                aip,
+                record: tlv(&[0x70], &record),
+                tear_generate_ac: false,
+                tampered: false,
+            }
+        }
+
+        fn certificates(&self) -> Vec<u8> {
+            let (issuer_cert, issuer_remainder) = issuer_certificate();
+            let (icc_cert, icc_remainder) = icc_certificate(&PAN, &self.record[2..]);
+            tlv(
+                &[0x70],
+                &[
+                    tlv(&[0x8F], &[0x92]),
+                    tlv(&[0x90], &issuer_cert),
+                    tlv(&[0x92], &issuer_remainder),
+                    tlv(&[0x9F, 0x32], &[0x03]),
+                    tlv(&[0x9F, 0x46], &icc_cert),
+                    tlv(&[0x9F, 0x47], &[0x03]),
+                    tlv(&[0x9F, 0x48], &icc_remainder),
+                ]
+                .concat(),
+            )
+        }
+
+        fn respond(&self, command: &ApduCommand) -> Option<Vec<u8>> {
+            let data = match command.ins {
+                0xA8 => tlv(
+                    &[0x77],
+                    &[
+                        tlv(&[0x82], &self.aip),
+                        tlv(&[0x94], &[0x08, 0x01, 0x02, 0x01]),
+                    ]
+                    .concat(),
+                ),
+                0xEA => tlv(&[0x80], &hex::decode("A1B2C3D4001000200012").unwrap()),
+                0xB2 if command.p1 == 1 => self.record.clone(),
+                0xB2 => self.certificates(),
+                0xAE if self.tear_generate_ac => return None,
+                0xAE | 0xD0 => {
+                    // Echo the requested cryptogram type in the CID
+                    let cid = if command.ins == 0xD0 {
+                        0x80
+                    } else {
+                        command.p1 & 0xC0
+                    };
+                    let objects = [
+                        tlv(&[0x9F, 0x27], &[cid]),
+                        tlv(&[0x9F, 0x36], &[0x00, 0x2A]),
+                        tlv(&[0x9F, 0x26], &[0xAC; 8]),
+                    ]
+                    .concat();
+                    if command.p1 & 0x10 == 0 {
+                        return Some([tlv(&[0x77], &objects), vec![0x90, 0x00]].concat());
+                    }
+                    // CDOL1 ends with 9F37 (4) and 9F34 (3)
+                    let cdol1_data = command.data.clone().unwrap();
+                    let un = &cdol1_data[cdol1_data.len() - 7..cdol1_data.len() - 3];
+                    let mut dol_data = [&PDOL_DATA[..], &cdol1_data].concat();
+                    if self.tampered {
+                        dol_data[0] ^= 0xFF;
+                    }
+                    let sdad = sign_combined(&[0x00, 0x2A], un, &dol_data, &tlv(&[0x77], &objects));
+                    tlv(&[0x77], &[objects, tlv(&[0x9F, 0x4B], &sdad)].concat())
+                }
+                0x2A => tlv(
+                    &[0x77],
+                    &[
+                        tlv(&[0x9F, 0x61], &[0x12, 0x34]),
+                        tlv(&[0x9F, 0x36], &[0x00, 0x2B]),
+                    ]
+                    .concat(),
+                ),
+                _ => return Some(vec![0x6D, 0x00]),
+            };
+            Some([data, vec![0x90, 0x00]].concat())
+        }
+    }
+
+    fn terminal_data(amount: &[u8; 6]) -> Vec<Tlv> {
+        vec![
+            Tlv::new(&[0x9F, 0x02], amount.to_vec()),
+            Tlv::new(&[0x9F, 0x1A], vec![0x08, 0x40]),
+            Tlv::new(&[0x5F, 0x2A], vec![0x08, 0x40]),
+            Tlv::new(&[0x9F, 0x37], vec![0x11, 0x22, 0x33, 0x44]),
+        ]
+    }
+
+    fn fci() -> Vec<u8> {
+        tlv(
+            &[0x6F],
+            &[
+                tlv(&[0x84], &[0xA0, 0x00, 0x00, 0x00, 0x04, 0x10, 0x10]),
+                tlv(&[0xA5], &tlv(&[0x9F, 0x38], &[0x9F, 0x1A, 0x02])),
+            ]
+            .concat(),
+        )
+    }
+
+    fn kernel(amount: &[u8; 6]) -> C2Kernel {
+        let config = C2Config {
+            floor_limit: 5_000,
+            ca_public_keys: vec![CaPublicKey {
+                rid: vec![0xA0, 0x00, 0x00, 0x00, 0x04],
+                index: 0x92,
+                key: public_key(CA_MODULUS),
+            }],
+            ..C2Config::default()
+        };
+        C2Kernel::new(
+            EmvProcessor::new("840".to_string(), "USD".to_string()),
+            config,
+            terminal_data(amount),
+        )
+    }
+
+    fn run(kernel: &mut C2Kernel, card: &SimulatedCard) -> (KernelOutcome, Vec<u8>) {
+        let mut sent = Vec::new();
+        let mut step = kernel.start(&fci()).unwrap();
+        loop {
+            let command = match step {
+                KernelStep::Complete(outcome) => return (*outcome, sent),
+                KernelStep::Send(command) => command,
+            };
+            sent.push(command.ins);
+            step = match card.respond(&command) {
+                Some(bytes) => {
+                    let response = ApduResponse::from_bytes(&bytes).unwrap();
+                    kernel
+                        .on_timed_response(&response, Duration::from_millis(5))
+                        .unwrap()
+                }
+                None => kernel.on_communication_error(),
+            };
+        }
+    }
+
+    #[test]
+    fn test_emv_mode_with_relay_resistance() {
+        let mut kernel = kernel(&[0x00, 0x00, 0x00, 0x00, 0x10, 0x00]);
+        let (outcome, sent) = run(&mut kernel, &SimulatedCard::new([0x19, 0x81]));
+
+        assert_eq!(sent, vec![0xA8, 0xEA, 0xB2, 0xB2, 0xAE]);
+        assert_eq!(outcome.parameters.status, OutcomeStatus::Approved);
+        assert_eq!(outcome.parameters.cvm, OutcomeCvm::NoCvm);
+        assert_eq!(
+            Tlv::find_by_tag(&outcome.data_record, &[0x95])
+                .unwrap()
+                .value,
+            vec![0x00, 0x00, 0x00, 0x00, 0x02]
+        );
+        // Online PIN is not in the No CVM Required capability, so No CVM applies
+        assert_eq!(
+            Tlv::find_by_tag(&outcome.data_record, &[0x9F, 0x34])
+                .unwrap()
+                .value,
+            vec![0x1F, 0x03, 0x02]
+        );
+        assert_eq!(
+            Tlv::find_by_tag(kernel.data(), &[0x9F, 0x4C])
+                .unwrap()
+                .value,
+            vec![0x00, 0x2A]
+        );
+    }
+
+    #[test]
+    fn test_cda_failure_declines() {
+        let mut card = SimulatedCard::new([0x19, 0x81]);
+        card.tampered = true;
+        let mut kernel = kernel(&[0x00, 0x00, 0x00, 0x00, 0x10, 0x00]);
+        let (outcome, _) = run(&mut kernel, &card);
+
+        assert_eq!(outcome.parameters.status, OutcomeStatus::Declined);
+        assert_eq!(
+            Tlv::find_by_tag(&outcome.data_record, &[0x95])
+                .unwrap()
+                .value,
+            vec![0x04, 0x00, 0x00, 0x00, 0x02]
+        );
+    }
+
+    #[test]
+    fn test_mag_stripe_mode_above_cvm_limit() {
+        let mut kernel = kernel(&[0x00, 0x00, 0x00, 0x00, 0x60, 0x00]);
+        let (outcome, sent) = run(&mut kernel, &SimulatedCard::new([0x18, 0x00]));
+
+        assert_eq!(sent, vec![0xA8, 0xB2, 0xB2, 0x2A]);
+        assert_eq!(outcome.parameters.status, OutcomeStatus::OnlineRequest);
+        assert_eq!(outcome.parameters.cvm, OutcomeCvm::OnlinePin);
+        assert!(Tlv::find_by_tag(&outcome.data_record, &[0x9F, 0x61]).is_some());
+    }
+
+    #[test]
+    fn test_torn_transaction_is_recovered() {
+        let log = Arc::new(InMemoryTornLog::new(5));
+        let mut card = SimulatedCard::new([0x18, 0x80]);
+        card.tear_generate_ac = true;
+
+        let amount = [0x00, 0x00, 0x00, 0x00, 0x10, 0x00];
+        let mut first = kernel(&amount).with_torn_log(log.clone());
+        let (outcome, _) = run(&mut first, &card);
+        assert_eq!(outcome.parameters.status, OutcomeStatus::EndApplication);
+        assert_eq!(outcome.parameters.start, OutcomeStart::B);
+        assert_eq!(log.len(), 1);
+
+        // A persisted record keeps the cardholder data it completes with
+        let record = log.take(&PAN, Some(&[0x01])).unwrap();
+        let json = serde_json::to_string(&record).unwrap();
+        let restored: TornRecord = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored, record);
+        log.store(restored);
+
+        // The next presentation is for another amount; the recovered AC
+        // completes the torn transaction with its own data
+        card.tear_generate_ac = false;
+        let mut second = kernel(&[0x00, 0x00, 0x00, 0x00, 0x20, 0x00]).with_torn_log(log.clone());
+        let (outcome, sent) = run(&mut second, &card);
+        assert_eq!(sent, vec![0xA8, 0xB2, 0xB2, 0xD0]);
+        assert_eq!(outcome.parameters.status, OutcomeStatus::OnlineRequest);
+        assert_eq!(
+            Tlv::find_by_tag(&outcome.data_record, &[0x9F, 0x02])
+                .unwrap()
+                .value,
+            amount.to_vec()
+        );
+        assert!(log.is_empty());
+
+        // A serialized outcome leaves the PAN out
+        let json = serde_json::to_value(&outcome).unwrap();
+        let pan = json["data_record"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|tlv| tlv["tag"] == serde_json::json!([0x5A]))
+            .unwrap();
+        assert!(pan.get("value").is_none());
+    }
+
+    #[test]
+    fn test_amount_over_contactless_limit() {
+        let mut kernel = kernel(&[0x00, 0x00, 0x00, 0x02, 0x00, 0x00]);
+        let (outcome, sent) = run(&mut kernel, &SimulatedCard::new([0x18, 0x80]));
+
+        assert_eq!(sent, vec![0xA8]);
+        assert_eq!(outcome.parameters.status, OutcomeStatus::SelectNext);
+        assert_eq!(outcome.parameters.start, OutcomeStart::C);
+    }
+}
@@ -0,0 +1,945 @@
+use serde::{Deserialize, Serialize};
+
+use super::{
+    bcd_to_u64, collect_tags, error_indication, Combination, ContactlessKernel, ContactlessLimits,
+    EntryPointConfig, KernelFactory, KernelId, KernelOutcome, KernelStep, L2Error,
+};
+use crate::models::afl::AflEntry;
+use crate::models::card_date::CardDate;
+use crate::models::dol::Dol;
+use crate::models::emv::{ApduResponse, CryptogramType, Tlv};
+use crate::models::outcome::{OutcomeCvm, OutcomeParameterSet, OutcomeStart, OutcomeStatus};
+use crate::models::terminal::TerminalConfig;
+use crate::services::emv_processor::EmvProcessor;
+use crate::services::oda::{self, CaPublicKey};
+
+const TAG_AMOUNT: &[u8] = &[0x9F, 0x02];
+const TAG_AFL: &[u8] = &[0x94];
+const TAG_PAN: &[u8] = &[0x5A];
+const TAG_TRACK2: &[u8] = &[0x57];
+const TAG_EXPIRATION_DATE: &[u8] = &[0x5F, 0x24];
+const TAG_TRANSACTION_DATE: &[u8] = &[0x9A];
+const TAG_TRANSACTION_CURRENCY: &[u8] = &[0x5F, 0x2A];
+const TAG_PDOL: &[u8] = &[0x9F, 0x38];
+const TAG_UNPREDICTABLE_NUMBER: &[u8] = &[0x9F, 0x37];
+const TAG_CID: &[u8] = &[0x9F, 0x27];
+const TAG_APPLICATION_CRYPTOGRAM: &[u8] = &[0x9F, 0x26];
+const TAG_ATC: &[u8] = &[0x9F, 0x36];
+const TAG_IAD: &[u8] = &[0x9F, 0x10];
+const TAG_SDAD: &[u8] = &[0x9F, 0x4B];
+const TAG_ICC_DYNAMIC_NUMBER: &[u8] = &[0x9F, 0x4C];
+const TAG_SDA_TAG_LIST: &[u8] = &[0x9F, 0x4A];
+const TAG_TTQ: &[u8] = &[0x9F, 0x66];
+const TAG_CTQ: &[u8] = &[0x9F, 0x6C];
+const TAG_CARD_AUTHENTICATION_DATA: &[u8] = &[0x9F, 0x69];
+const TAG_OUTCOME_PARAMETER_SET: &[u8] = &[0xDF, 0x81, 0x29];
+const TAG_ERROR_INDICATION: &[u8] = &[0xDF, 0x81, 0x15];
+
+/// Data Record contents for qVSDC
+const DATA_RECORD: &[&[u8]] = &[
+    &[0x9F, 0x02],
+    &[0x9F, 0x03],
+    &[0x9F, 0x26],
+    &[0x9F, 0x27],
+    &[0x9F, 0x10],
+    &[0x9F, 0x36],
+    &[0x9F, 0x37],
+    &[0x9F, 0x66],
+    &[0x9F, 0x6C],
+    &[0x9F, 0x6E],
+    &[0x9F, 0x69],
+    &[0x9F, 0x4C],
+    &[0x82],
+    &[0x84],
+    &[0x57],
+    &[0x5A],
+    &[0x5F, 0x24],
+    &[0x5F, 0x34],
+    &[0x5F, 0x2A],
+    &[0x9F, 0x1A],
+    &[0x9A],
+    &[0x9C],
+    &[0x95],
+];
+
+/// Card Transaction Qualifiers (tag 0x9F6C) bits
+const CTQ_ONLINE_PIN_REQUIRED: u8 = 0x80;
+const CTQ_SIGNATURE_REQUIRED: u8 = 0x40;
+const CTQ_GO_ONLINE_IF_ODA_FAILS: u8 = 0x20;
+const CTQ_SWITCH_INTERFACE_IF_ODA_FAILS: u8 = 0x10;
+const CTQ_GO_ONLINE_IF_EXPIRED: u8 = 0x08;
+/// CTQ byte 2
+const CTQ_CDCVM_PERFORMED: u8 = 0x80;
+
+/// Visa contactless kernel (C-3) configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct C3Config {
+    /// Terminal type, capabilities and floor limit
+    pub terminal: TerminalConfig,
+    /// Contact chip interface available (TTQ byte 1 b5)
+    pub contact_chip_supported: bool,
+    pub online_pin_supported: bool,
+    pub signature_supported: bool,
+    /// Consumer Device CVM supported (TTQ byte 3 b7)
+    pub cdcvm_supported: bool,
+    /// Issuer Update Processing supported (TTQ byte 3 b8)
+    pub issuer_update_supported: bool,
+    /// Reader Contactless Transaction Limit
+    pub transaction_limit: u64,
+    /// Reader Contactless Floor Limit
+    pub floor_limit: u64,
+    /// Reader CVM Required Limit
+    pub cvm_required_limit: u64,
+    /// CA Public Keys for fDDA
+    #[serde(default)]
+    pub ca_public_keys: Vec<CaPublicKey>,
+}
+
+impl Default for C3Config {
+    fn default() -> Self {
+        Self {
+            terminal: TerminalConfig::default(),
+            contact_chip_supported: true,
+            online_pin_supported: true,
+            signature_supported: true,
+            cdcvm_supported: true,
+            issuer_update_supported: false,
+            transaction_limit: 10_000,
+            floor_limit: 0,
+            cvm_required_limit: 5_000,
+            ca_public_keys: Vec::new(),
+        }
+    }
+}
+
+impl C3Config {
+    /// Copy with an Entry Point combination's limits applied
+    pub fn with_entry_point(&self, config: &EntryPointConfig) -> Self {
+        let limits = ContactlessLimits {
+            transaction_limit: self.transaction_limit,
+            floor_limit: self.floor_limit,
+            cvm_required_limit: self.cvm_required_limit,
+        }
+        .with_entry_point(config);
+        Self {
+            transaction_limit: limits.transaction_limit,
+            floor_limit: limits.floor_limit,
+            cvm_required_limit: limits.cvm_required_limit,
+            ..self.clone()
+        }
+    }
+
+    /// Terminal Transaction Qualifiers (tag 0x9F66) for an amount
+    pub fn ttq(&self, amount: u64) -> [u8; 4] {
+        let online_capable = self.terminal.is_online_capable();
+        let mut ttq = [0x20, 0x00, 0x00, 0x00];
+
+        if self.contact_chip_supported {
+            ttq[0] |= 0x10;
+        }
+        if !online_capable {
+            ttq[0] |= 0x08;
+        }
+        if self.online_pin_supported && online_capable {
+            ttq[0] |= 0x04;
+        }
+        if self.signature_supported {
+            ttq[0] |= 0x02;
+        }
+
+        // Online cryptogram required
+        if online_capable
+            && (amount == 0 || amount > self.floor_limit || self.terminal.is_online_only())
+        {
+            ttq[1] |= 0x80;
+        }
+        // CVM required
+        if amount >= self.cvm_required_limit {
+            ttq[1] |= 0x40;
+        }
+
+        if self.issuer_update_supported {
+            ttq[2] |= 0x80;
+        }
+        if self.cdcvm_supported {
+            ttq[2] |= 0x40;
+        }
+        ttq
+    }
+}
+
+#[derive(Debug, Clone)]
+enum State {
+    NotStarted,
+    AwaitingGpo,
+    ReadingRecords,
+    Done,
+}
+
+/// Visa contactless kernel (EMV Contactless Book C-3, qVSDC)
+///
+/// The card returns its cryptogram in the GET PROCESSING OPTIONS response;
+/// records are then read for the PAN and, for offline approvals, for fast
+/// DDA (fDDA) over the Card Authentication Related Data (0x9F69).
+#[derive(Debug, Clone)]
+pub struct C3Kernel {
+    processor: EmvProcessor,
+    config: C3Config,
+    data: Vec<Tlv>,
+    ttq: [u8; 4],
+    cryptogram: CryptogramType,
+    afl: Vec<AflEntry>,
+    records: Vec<(u8, u8)>,
+    next_record: usize,
+    static_data: Vec<u8>,
+    state: State,
+}
+
+impl C3Kernel {
+    /// `terminal_data` holds transaction data objects such as Amount (0x9F02),
+    /// currency, date, type and Unpredictable Number (0x9F37)
+    pub fn new(processor: EmvProcessor, config: C3Config, terminal_data: Vec<Tlv>) -> Self {
+        Self {
+            processor,
+            config,
+            data: terminal_data,
+            ttq: [0x00; 4],
+            cryptogram: CryptogramType::Aac,
+            afl: Vec::new(),
+            records: Vec::new(),
+            next_record: 0,
+            static_data: Vec::new(),
+            state: State::NotStarted,
+        }
+    }
+
+    /// All data objects known to the kernel so far
+    pub fn data(&self) -> &[Tlv] {
+        &self.data
+    }
+
+    /// Begin with the FCI returned by SELECT and send GET PROCESSING OPTIONS
+    /// with the Terminal Transaction Qualifiers in the PDOL data
+    pub fn start(&mut self, fci: &[u8]) -> Result<KernelStep, String> {
+        if !matches!(self.state, State::NotStarted) {
+            return Err("Kernel already started".to_string());
+        }
+
+        for tlv in Tlv::parse_flattened(fci)? {
+            Tlv::upsert(&mut self.data, &tlv.tag, tlv.value);
+        }
+
+        let amount = self.amount();
+        if amount > self.config.transaction_limit {
+            return Ok(self.try_another_interface(L2Error::MaxLimitExceeded));
+        }
+
+        if Tlv::find_by_tag(&self.data, TAG_UNPREDICTABLE_NUMBER).is_none() {
+            let mut unpredictable_number = [0u8; 4];
+            getrandom::getrandom(&mut unpredictable_number)
+                .map_err(|e| format!("RNG failure: {}", e))?;
+            Tlv::upsert(
+                &mut self.data,
+                TAG_UNPREDICTABLE_NUMBER,
+                unpredictable_number.to_vec(),
+            );
+        }
+
+        // Entry Point may have pre-set the qualifiers for this combination
+        self.ttq = Tlv::find_by_tag(&self.data, TAG_TTQ)
+            .and_then(|tlv| <[u8; 4]>::try_from(tlv.value.as_slice()).ok())
+            .unwrap_or_else(|| self.config.ttq(amount));
+        Tlv::upsert(&mut self.data, TAG_TTQ, self.ttq.to_vec());
+
+        // qVSDC cards ask for the TTQ in their PDOL
+        let pdol = match Tlv::find_by_tag(&self.data, TAG_PDOL) {
+            Some(pdol) => Dol::parse(&pdol.value)?,
+            None => return Ok(self.try_another_interface(L2Error::CardDataMissing)),
+        };
+        if !pdol.contains(TAG_TTQ) {
+            return Ok(self.try_another_interface(L2Error::CardDataMissing));
+        }
+
+        self.state = State::AwaitingGpo;
+        Ok(KernelStep::Send(
+            self.processor
+                .get_processing_options(&pdol.build(&self.data)),
+        ))
+    }
+
+    /// Feed the card response to the last command sent
+    pub fn on_response(&mut self, response: &ApduResponse) -> Result<KernelStep, String> {
+        match std::mem::replace(&mut self.state, State::Done) {
+            State::AwaitingGpo => Ok(self.on_gpo(response)),
+            State::ReadingRecords => Ok(self.on_record(response)),
+            state => {
+                self.state = state;
+                Err("No card response expected".to_string())
+            }
+        }
+    }
+
+    /// The card did not answer; the cardholder is asked to present it again
+    pub fn on_communication_error(&mut self) -> KernelStep {
+        let mut parameters = OutcomeParameterSet::new(OutcomeStatus::TryAgain);
+        parameters.start = OutcomeStart::B;
+        parameters.ui_request_on_restart = true;
+        parameters.discretionary_data_present = true;
+        self.finish(
+            parameters,
+            Vec::new(),
+            error_indication(0x02, L2Error::Ok, 0),
+        )
+    }
+
+    fn on_gpo(&mut self, response: &ApduResponse) -> KernelStep {
+        match response.status_word() {
+            0x9000 => {}
+            // Consumer device asks the cardholder to look at it ("See Phone")
+            0x6986 => {
+                let mut parameters = OutcomeParameterSet::new(OutcomeStatus::TryAgain);
+                parameters.start = OutcomeStart::B;
+                parameters.ui_request_on_outcome = true;
+                parameters.ui_request_on_restart = true;
+                parameters.removal_timeout = 0;
+                parameters.discretionary_data_present = true;
+                return self.finish(
+                    parameters,
+                    Vec::new(),
+                    error_indication(0x00, L2Error::StatusBytes, 0x6986),
+                );
+            }
+            0x6984 => return self.try_another_interface(L2Error::StatusBytes),
+            sw => return self.end_application(L2Error::StatusBytes, sw),
+        }
+
+        // qVSDC responds in format 2 only
+        let template = Tlv::parse(&response.data)
+            .ok()
+            .and_then(|tlvs| Tlv::find_by_tag(&tlvs, &[0x77]).cloned());
+        let tlvs = match template.map(|template| Tlv::parse_flattened(&template.value)) {
+            Some(Ok(tlvs)) => tlvs,
+            _ => return self.end_application(L2Error::ParsingError, 0),
+        };
+        for tlv in tlvs {
+            Tlv::upsert(&mut self.data, &tlv.tag, tlv.value);
+        }
+
+        if Tlv::find_by_tag(&self.data, TAG_APPLICATION_CRYPTOGRAM).is_none()
+            || Tlv::find_by_tag(&self.data, TAG_ATC).is_none()
+        {
+            return self.end_application(L2Error::CardDataMissing, 0);
+        }
+
+        self.cryptogram = match self.cryptogram_type() {
+            Some(cryptogram) => cryptogram,
+            None => return self.end_application(L2Error::CardDataError, 0),
+        };
+        if self.cryptogram == CryptogramType::Aac {
+            return self.complete(OutcomeStatus::Declined, OutcomeCvm::NoCvm);
+        }
+
+        if let Some(afl) = Tlv::find_by_tag(&self.data, TAG_AFL) {
+            self.afl = match AflEntry::parse_all(&afl.value) {
+                Ok(afl) => afl,
+                Err(_) => return self.end_application(L2Error::CardDataError, 0),
+            };
+            self.records = AflEntry::records(&self.afl);
+        }
+        self.read_next_record()
+    }
+
+    /// Cryptogram type from the CID, or from the CVR in the Issuer
+    /// Application Data when the card omits the CID (CVR byte 2 b6-b5)
+    fn cryptogram_type(&self) -> Option<CryptogramType> {
+        if let Some(cid) = Tlv::find_by_tag(&self.data, TAG_CID) {
+            return CryptogramType::from_bits(*cid.value.first()? & 0xC0).ok();
+        }
+
+        // VIS format 0/1/3 (06) and UICS (07): length, DKI, CVN, then the
+        // CVR with its own length byte
+        let iad = Tlv::find_by_tag(&self.data, TAG_IAD)?;
+        let byte2 = match iad.value.as_slice() {
+            [0x06 | 0x07, _, _, 0x03, byte2, ..] => *byte2,
+            _ => return None,
+        };
+        match byte2 & 0x30 {
+            0x00 => Some(CryptogramType::Aac),
+            0x10 => Some(CryptogramType::Tc),
+            0x20 => Some(CryptogramType::Arqc),
+            _ => None,
+        }
+    }
+
+    fn read_next_record(&mut self) -> KernelStep {
+        match self.records.get(self.next_record) {
+            Some(&(sfi, record)) => {
+                self.state = State::ReadingRecords;
+                KernelStep::Send(self.processor.read_record(sfi, record))
+            }
+            None => self.after_records(),
+        }
+    }
+
+    fn on_record(&mut self, response: &ApduResponse) -> KernelStep {
+        if !response.is_success() {
+            return self.end_application(L2Error::StatusBytes, response.status_word());
+        }
+
+        let template = match Tlv::parse(&response.data) {
+            Ok(tlvs) => match Tlv::find_by_tag(&tlvs, &[0x70]) {
+                Some(template) => template.clone(),
+                None => return self.end_application(L2Error::ParsingError, 0),
+            },
+            Err(_) => return self.end_application(L2Error::ParsingError, 0),
+        };
+        match Tlv::parse_flattened(&template.value) {
+            Ok(tlvs) => {
+                for tlv in tlvs {
+                    Tlv::upsert(&mut self.data, &tlv.tag, tlv.value);
+                }
+            }
+            Err(_) => return self.end_application(L2Error::ParsingError, 0),
+        }
+
+        // Static Data to be Authenticated: the template value for SFI 1-10,
+        // the whole record otherwise
+        let (sfi, record) = self.records[self.next_record];
+        if AflEntry::is_oda_record(&self.afl, sfi, record) {
+            if sfi <= 10 {
+                self.static_data.extend_from_slice(&template.value);
+            } else {
+                self.static_data.extend_from_slice(&response.data);
+            }
+        }
+
+        self.next_record += 1;
+        self.read_next_record()
+    }
+
+    fn after_records(&mut self) -> KernelStep {
+        if Tlv::find_by_tag(&self.data, TAG_PAN).is_none()
+            && Tlv::find_by_tag(&self.data, TAG_TRACK2).is_none()
+        {
+            return self.end_application(L2Error::CardDataMissing, 0);
+        }
+
+        let ctq = Tlv::find_by_tag(&self.data, TAG_CTQ)
+            .filter(|tlv| tlv.value.len() == 2)
+            .map(|tlv| [tlv.value[0], tlv.value[1]]);
+
+        let cvm = match self.select_cvm(ctq) {
+            Ok(cvm) => cvm,
+            Err(step) => return step,
+        };
+
+        if self.cryptogram == CryptogramType::Arqc {
+            return self.complete(OutcomeStatus::OnlineRequest, cvm);
+        }
+
+        // Offline approval: the card must not be expired and fDDA must pass
+        let ctq1 = ctq.map(|ctq| ctq[0]).unwrap_or(0);
+        if self.expired() {
+            if ctq1 & CTQ_GO_ONLINE_IF_EXPIRED != 0 && self.terminal_online_capable() {
+                return self.complete(OutcomeStatus::OnlineRequest, cvm);
+            }
+            return self.complete(OutcomeStatus::Declined, cvm);
+        }
+
+        if self.fdda(ctq).is_err() {
+            if ctq1 & CTQ_GO_ONLINE_IF_ODA_FAILS != 0 && self.terminal_online_capable() {
+                return self.complete(OutcomeStatus::OnlineRequest, cvm);
+            }
+            if ctq1 & CTQ_SWITCH_INTERFACE_IF_ODA_FAILS != 0 && self.config.contact_chip_supported {
+                return self.try_another_interface(L2Error::Ok);
+            }
+            return self.complete(OutcomeStatus::Declined, cvm);
+        }
+
+        self.complete(OutcomeStatus::Approved, cvm)
+    }
+
+    /// CVM from the Card Transaction Qualifiers
+    ///
+    /// A card may ask for online PIN even when the reader did not require a
+    /// CVM (single tap and PIN request): the PIN is collected after this tap
+    /// and sent online. A reader without online PIN switches to contact.
+    fn select_cvm(&mut self, ctq: Option<[u8; 2]>) -> Result<OutcomeCvm, KernelStep> {
+        let cvm_required = self.ttq[1] & 0x40 != 0;
+        let online_pin_supported = self.ttq[0] & 0x04 != 0;
+        let signature_supported = self.ttq[0] & 0x02 != 0;
+
+        let [ctq1, ctq2] = match ctq {
+            Some(ctq) => ctq,
+            None if !cvm_required => return Ok(OutcomeCvm::NoCvm),
+            None if signature_supported => return Ok(OutcomeCvm::ObtainSignature),
+            None if online_pin_supported && self.cryptogram == CryptogramType::Arqc => {
+                return Ok(OutcomeCvm::OnlinePin)
+            }
+            None => return Err(self.complete(OutcomeStatus::Declined, OutcomeCvm::NoCvm)),
+        };
+
+        if ctq1 & CTQ_ONLINE_PIN_REQUIRED != 0 {
+            if online_pin_supported && self.cryptogram == CryptogramType::Arqc {
+                return Ok(OutcomeCvm::OnlinePin);
+            }
+            if self.config.contact_chip_supported {
+                return Err(self.try_another_interface(L2Error::Ok));
+            }
+            return Err(self.complete(OutcomeStatus::Declined, OutcomeCvm::NoCvm));
+        }
+
+        if ctq2 & CTQ_CDCVM_PERFORMED != 0 {
+            return Ok(OutcomeCvm::ConfirmationCodeVerified);
+        }
+
+        if !cvm_required {
+            return Ok(OutcomeCvm::NoCvm);
+        }
+        if ctq1 & CTQ_SIGNATURE_REQUIRED != 0 && signature_supported {
+            return Ok(OutcomeCvm::ObtainSignature);
+        }
+        Err(self.complete(OutcomeStatus::Declined, OutcomeCvm::NoCvm))
+    }
+
+    /// fast DDA: verify the Signed Dynamic Application Data over
+    /// 9F37 | 9F02 | 5F2A | 9F69 and check the signed CTQ
+    fn fdda(&mut self, ctq: Option<[u8; 2]>) -> Result<(), String> {
+        let find = |tag: &[u8]| {
+            Tlv::find_by_tag(&self.data, tag)
+                .map(|tlv| tlv.value.clone())
+                .ok_or_else(|| format!("Missing data object {}", hex::encode_upper(tag)))
+        };
+        let card_authentication_data = find(TAG_CARD_AUTHENTICATION_DATA)?;
+        if card_authentication_data.first() != Some(&0x01) {
+            return Err("Unsupported fDDA version".to_string());
+        }
+        let sdad = find(TAG_SDAD)?;
+
+        let mut static_data = self.static_data.clone();
+        if let Some(tag_list) = Tlv::find_by_tag(&self.data, TAG_SDA_TAG_LIST) {
+            // Only the AIP may be listed
+            if tag_list.value != [0x82] {
+                return Err("Invalid SDA Tag List".to_string());
+            }
+            static_data.extend_from_slice(&find(&[0x82])?);
+        }
+
+        let icc_key = oda::icc_public_key(&self.data, &self.config.ca_public_keys, &static_data)?;
+
+        let dynamic_data_input = [
+            find(TAG_UNPREDICTABLE_NUMBER)?,
+            find(TAG_AMOUNT)?,
+            find(TAG_TRANSACTION_CURRENCY)?,
+            card_authentication_data.clone(),
+        ]
+        .concat();
+        let icc_dynamic_data =
+            oda::verify_signed_dynamic_data(&icc_key, &sdad, &dynamic_data_input)?;
+
+        // The CTQ signed in 9F69 must match the one returned in clear
+        if let (Some(ctq), Some(signed)) = (ctq, card_authentication_data.get(5..7)) {
+            if signed != ctq {
+                return Err("Card Transaction Qualifiers do not match".to_string());
+            }
+        }
+
+        if let Some((&len, number)) = icc_dynamic_data.split_first() {
+            let number = number.get(..usize::from(len)).unwrap_or(number).to_vec();
+            Tlv::upsert(&mut self.data, TAG_ICC_DYNAMIC_NUMBER, number);
+        }
+        Ok(())
+    }
+
+    /// Application Expiration Date (0x5F24) before the transaction date
+    fn expired(&self) -> bool {
+        match (
+            Tlv::find_by_tag(&self.data, TAG_EXPIRATION_DATE),
+            Tlv::find_by_tag(&self.data, TAG_TRANSACTION_DATE),
+        ) {
+            (Some(expiry), Some(date)) => {
+                match (
+                    CardDate::from_bcd(&expiry.value),
+                    CardDate::from_bcd(&date.value),
+                ) {
+                    (Ok(expiry), Ok(date)) => expiry.is_expired_on(&date),
+                    // An invalid expiration date cannot be trusted offline
+                    (Err(_), _) => true,
+                    (Ok(_), Err(_)) => false,
+                }
+            }
+            _ => false,
+        }
+    }
+
+    fn terminal_online_capable(&self) -> bool {
+        self.ttq[0] & 0x08 == 0
+    }
+
+    fn complete(&mut self, status: OutcomeStatus, cvm: OutcomeCvm) -> KernelStep {
+        let mut parameters = OutcomeParameterSet::new(status);
+        parameters.cvm = cvm;
+        parameters.receipt = cvm == OutcomeCvm::ObtainSignature;
+        parameters.data_record_present = true;
+        parameters.discretionary_data_present = true;
+        parameters.ui_request_on_outcome = true;
+
+        let data_record = collect_tags(&self.data, DATA_RECORD);
+        self.finish(
+            parameters,
+            data_record,
+            error_indication(0x00, L2Error::Ok, 0),
+        )
+    }
+
+    fn try_another_interface(&mut self, error: L2Error) -> KernelStep {
+        let mut parameters = OutcomeParameterSet::new(OutcomeStatus::TryAnotherInterface);
+        parameters.ui_request_on_outcome = true;
+        parameters.discretionary_data_present = true;
+        self.finish(parameters, Vec::new(), error_indication(0x00, error, 0))
+    }
+
+    fn end_application(&mut self, error: L2Error, status_word: u16) -> KernelStep {
+        let mut parameters = OutcomeParameterSet::new(OutcomeStatus::EndApplication);
+        parameters.discretionary_data_present = true;
+        self.finish(
+            parameters,
+            Vec::new(),
+            error_indication(0x00, error, status_word),
+        )
+    }
+
+    fn finish(
+        &mut self,
+        parameters: OutcomeParameterSet,
+        data_record: Vec<Tlv>,
+        error_indication: Vec<u8>,
+    ) -> KernelStep {
+        self.state = State::Done;
+        Tlv::upsert(
+            &mut self.data,
+            TAG_OUTCOME_PARAMETER_SET,
+            parameters.to_bytes().to_vec(),
+        );
+
+        // Available Offline Spending Amount, when the card returns it
+        let mut discretionary_data = collect_tags(&self.data, &[&[0x9F, 0x5D]]);
+        discretionary_data.push(Tlv::new(TAG_ERROR_INDICATION, error_indication));
+
+        KernelStep::Complete(Box::new(KernelOutcome::new(
+            parameters,
+            data_record,
+            discretionary_data,
+        )))
+    }
+
+    fn amount(&self) -> u64 {
+        Tlv::find_by_tag(&self.data, TAG_AMOUNT)
+            .map(|tlv| bcd_to_u64(&tlv.value))
+            .unwrap_or(0)
+    }
+}
+
+impl ContactlessKernel for C3Kernel {
+    fn kernel_id(&self) -> KernelId {
+        KernelId::Visa
+    }
+
+    fn start(&mut self, fci: &[u8]) -> Result<KernelStep, String> {
+        C3Kernel::start(self, fci)
+    }
+
+    fn on_response(&mut self, response: &ApduResponse) -> Result<KernelStep, String> {
+        C3Kernel::on_response(self, response)
+    }
+
+    fn on_communication_error(&mut self) -> KernelStep {
+        C3Kernel::on_communication_error(self)
+    }
+}
+
+impl KernelFactory for C3Config {
+    fn kernel_id(&self) -> KernelId {
+        KernelId::Visa
+    }
+
+    fn create(
+        &self,
+        processor: EmvProcessor,
+        combination: &Combination,
+        terminal_data: Vec<Tlv>,
+    ) -> Box<dyn ContactlessKernel> {
+        Box::new(C3Kernel::new(
+            processor,
+            self.with_entry_point(&combination.config),
+            terminal_data,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::emv::ApduCommand;
+    use crate::models::outcome::UiMessage;
+    use crate::services::field55::Field55Spec;
+    use crate::services::oda::tests::{
+        icc_certificate, issuer_certificate, public_key, sign_dynamic_data, CA_MODULUS,
+    };
+
+    const PAN: [u8; 8] = [0x47, 0x61, 0x73, 0x90, 0x01, 0x01, 0x01, 0x19];
+
+    fn tlv(tag: &[u8], value: &[u8]) -> Vec<u8> {
+        let length = if value.len() > 127 {
+            vec![0x81, value.len() as u8]
+        } else {
+            vec![value.len() as u8]
+        };
+        [tag, &length, value].concat()
+    }
+
+    /// A Visa card answering GPO with a cryptogram and fDDA signature
+    struct VisaCard {
+        cid: u8,
+        ctq: [u8; 2],
+        status: Option<u16>,
+        tampered: bool,
+        omit_cid: bool,
+    }
+
+    impl VisaCard {
+        fn new(cid: u8, ctq: [u8; 2]) -> Self {
+            Self {
+                cid,
+                ctq,
+                status: None,
+                tampered: false,
+                omit_cid: false,
+            }
+        }
+
+        fn oda_record() -> Vec<u8> {
+            [tlv(&[0x5A], &PAN), tlv(&[0x5F, 0x24], &[0x30, 0x12, 0x31])].concat()
+        }
+
+        fn respond(&self, command: &ApduCommand) -> Vec<u8> {
+            if let Some(sw) = self.status {
+                return sw.to_be_bytes().to_vec();
+            }
+
+            let data = match (command.ins, command.p1, command.p2 >> 3) {
+                (0xA8, _, _) => {
+                    // PDOL data: TTQ(4) | amount(6) | UN(4) | currency(2)
+                    let pdol_data = &command.data.as_ref().unwrap()[2..];
+                    let card_authentication_data =
+                        [&[0x01, 0xCA, 0xFE, 0xBA, 0xBE][..], &self.ctq].concat();
+                    let mut signed = [
+                        &pdol_data[10..14],
+                        &pdol_data[4..10],
+                        &pdol_data[14..16],
+                        &card_authentication_data[..],
+                    ]
+                    .concat();
+                    if self.tampered {
+                        signed[0] ^= 0xFF;
+                    }
+                    let cid = if self.omit_cid {
+                        Vec::new()
+                    } else {
+                        tlv(&[0x9F, 0x27], &[self.cid])
+                    };
+                    tlv(
+                        &[0x77],
+                        &[
+                            tlv(&[0x82], &[0x20, 0x00]),
+                            tlv(&[0x94], &[0x08, 0x01, 0x01, 0x01, 0x10, 0x01, 0x01, 0x00]),
+                            cid,
+                            tlv(&[0x9F, 0x36], &[0x00, 0x07]),
+                            tlv(&[0x9F, 0x26], &[0x5A; 8]),
+                            tlv(&[0x9F, 0x10], &hex::decode("06010A03A00000").unwrap()),
+                            tlv(&[0x9F, 0x6C], &self.ctq),
+                            tlv(&[0x9F, 0x69], &card_authentication_data),
+                            tlv(&[0x9F, 0x4B], &sign_dynamic_data(&[0x12, 0x34], &signed)),
+                        ]
+                        .concat(),
+                    )
+                }
+                (0xB2, 1, 1) => tlv(&[0x70], &Self::oda_record()),
+                (0xB2, 1, 2) => {
+                    let (issuer_cert, issuer_remainder) = issuer_certificate();
+                    let (icc_cert, icc_remainder) = icc_certificate(&PAN, &Self::oda_record());
+                    tlv(
+                        &[0x70],
+                        &[
+                            tlv(&[0x8F], &[0x92]),
+                            tlv(&[0x90], &issuer_cert),
+                            tlv(&[0x92], &issuer_remainder),
+                            tlv(&[0x9F, 0x32], &[0x03]),
+                            tlv(&[0x9F, 0x46], &icc_cert),
+                            tlv(&[0x9F, 0x47], &[0x03]),
+                            tlv(&[0x9F, 0x48], &icc_remainder),
+                        ]
+                        .concat(),
+                    )
+                }
+                _ => return vec![0x6A, 0x83],
+            };
+            [data, vec![0x90, 0x00]].concat()
+        }
+    }
+
+    fn kernel(amount: &[u8; 6], config: C3Config) -> C3Kernel {
+        let config = C3Config {
+            floor_limit: 5_000,
+            ca_public_keys: vec![CaPublicKey {
+                rid: vec![0xA0, 0x00, 0x00, 0x00, 0x03],
+                index: 0x92,
+                key: public_key(CA_MODULUS),
+            }],
+            ..config
+        };
+        C3Kernel::new(
+            EmvProcessor::new("840".to_string(), "USD".to_string()),
+            config,
+            vec![
+                Tlv::new(&[0x9F, 0x02], amount.to_vec()),
+                Tlv::new(&[0x5F, 0x2A], vec![0x08, 0x40]),
+                Tlv::new(&[0x9A], vec![0x26, 0x10, 0x18]),
+                Tlv::new(&[0x9C], vec![0x00]),
+                Tlv::new(&[0x9F, 0x1A], vec![0x08, 0x40]),
+                Tlv::new(&[0x9F, 0x37], vec![0x11, 0x22, 0x33, 0x44]),
+            ],
+        )
+    }
+
+    fn run(kernel: &mut C3Kernel, card: &VisaCard) -> KernelOutcome {
+        let fci = tlv(
+            &[0x6F],
+            &[
+                tlv(&[0x84], &[0xA0, 0x00, 0x00, 0x00, 0x03, 0x10, 0x10]),
+                tlv(
+                    &[0xA5],
+                    &tlv(
+                        &[0x9F, 0x38],
+                        &hex::decode("9F66049F02069F37045F2A02").unwrap(),
+                    ),
+                ),
+            ]
+            .concat(),
+        );
+
+        let mut step = kernel.start(&fci).unwrap();
+        loop {
+            match step {
+                KernelStep::Complete(outcome) => return *outcome,
+                KernelStep::Send(command) => {
+                    let response = ApduResponse::from_bytes(&card.respond(&command)).unwrap();
+                    step = kernel.on_response(&response).unwrap();
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_offline_approval_with_fdda() {
+        let mut kernel = kernel(&[0x00, 0x00, 0x00, 0x00, 0x10, 0x00], C3Config::default());
+        let outcome = run(&mut kernel, &VisaCard::new(0x40, [0x00, 0x00]));
+
+        assert_eq!(outcome.parameters.status, OutcomeStatus::Approved);
+        assert_eq!(outcome.parameters.cvm, OutcomeCvm::NoCvm);
+        assert_eq!(
+            Tlv::find_by_tag(&outcome.data_record, &[0x9F, 0x4C])
+                .unwrap()
+                .value,
+            vec![0x12, 0x34]
+        );
+        // No online cryptogram and no CVM required below the limits
+        assert_eq!(
+            Tlv::find_by_tag(kernel.data(), &[0x9F, 0x66])
+                .unwrap()
+                .value,
+            vec![0x36, 0x00, 0x40, 0x00]
+        );
+    }
+
+    #[test]
+    fn test_fdda_failure_declines_or_goes_online() {
+        let amount = [0x00, 0x00, 0x00, 0x00, 0x10, 0x00];
+        let mut card = VisaCard::new(0x40, [0x00, 0x00]);
+        card.tampered = true;
+        let outcome = run(&mut kernel(&amount, C3Config::default()), &card);
+        assert_eq!(outcome.parameters.status, OutcomeStatus::Declined);
+
+        card.ctq = [CTQ_GO_ONLINE_IF_ODA_FAILS, 0x00];
+        let outcome = run(&mut kernel(&amount, C3Config::default()), &card);
+        assert_eq!(outcome.parameters.status, OutcomeStatus::OnlineRequest);
+    }
+
+    #[test]
+    fn test_online_pin_above_cvm_limit() {
+        let mut kernel = kernel(&[0x00, 0x00, 0x00, 0x00, 0x60, 0x00], C3Config::default());
+        let outcome = run(
+            &mut kernel,
+            &VisaCard::new(0x80, [CTQ_ONLINE_PIN_REQUIRED, 0x00]),
+        );
+
+        assert_eq!(outcome.parameters.status, OutcomeStatus::OnlineRequest);
+        assert_eq!(outcome.parameters.cvm, OutcomeCvm::OnlinePin);
+        assert_eq!(
+            Tlv::find_by_tag(&outcome.data_record, &[0x9F, 0x66])
+                .unwrap()
+                .value,
+            vec![0x36, 0xC0, 0x40, 0x00]
+        );
+        // The online request carries the data record in field 55
+        assert!(Field55Spec::for_kernel(KernelId::Visa)
+            .assemble(&outcome.data_record)
+            .is_ok());
+    }
+
+    #[test]
+    fn test_cryptogram_type_from_iad_without_cid() {
+        // The card's IAD carries CVR byte 2 = A0, an ARQC
+        let mut card = VisaCard::new(0x80, [0x00, 0x00]);
+        card.omit_cid = true;
+        let outcome = run(
+            &mut kernel(&[0x00, 0x00, 0x00, 0x00, 0x10, 0x00], C3Config::default()),
+            &card,
+        );
+        assert_eq!(outcome.parameters.status, OutcomeStatus::OnlineRequest);
+    }
+
+    #[test]
+    fn test_pin_request_without_online_pin_switches_interface() {
+        let config = C3Config {
+            online_pin_supported: false,
+            ..C3Config::default()
+        };
+        let mut kernel = kernel(&[0x00, 0x00, 0x00, 0x00, 0x10, 0x00], config);
+        let outcome = run(
+            &mut kernel,
+            &VisaCard::new(0x80, [CTQ_ONLINE_PIN_REQUIRED, 0x00]),
+        );
+        assert_eq!(
+            outcome.parameters.status,
+            OutcomeStatus::TryAnotherInterface
+        );
+    }
+
+    #[test]
+    fn test_see_phone_and_decline() {
+        let amount = [0x00, 0x00, 0x00, 0x00, 0x10, 0x00];
+        let mut card = VisaCard::new(0x00, [0x00, 0x00]);
+        let outcome = run(&mut kernel(&amount, C3Config::default()), &card);
+        assert_eq!(outcome.parameters.status, OutcomeStatus::Declined);
+
+        card.status = Some(0x6986);
+        let outcome = run(&mut kernel(&amount, C3Config::default()), &card);
+        assert_eq!(outcome.parameters.status, OutcomeStatus::TryAgain);
+        assert_eq!(outcome.parameters.start, OutcomeStart::B);
+        assert_eq!(
+            outcome.ui_request_on_outcome.unwrap().message,
+            UiMessage::SeePhoneForInstructions
+        );
+    }
+}
@@ -0,0 +1,162 @@
+use serde::{Deserialize, Serialize};
+
+use super::emv_mode::{EmvModeKernel, EmvModeProfile};
+use super::{
+    bcd_to_u64, Combination, ContactlessKernel, ContactlessLimits, KernelFactory, KernelId,
+};
+use crate::models::emv::Tlv;
+use crate::models::terminal::TerminalConfig;
+use crate::services::emv_processor::EmvProcessor;
+use crate::services::oda::CaPublicKey;
+
+/// Enhanced Contactless Reader Capabilities (tag 0x9F6E)
+const TAG_ENHANCED_READER_CAPABILITIES: &[u8] = &[0x9F, 0x6E];
+
+/// Data Record additions: reader capabilities and Mobile CVM Results
+const DATA_RECORD: &[&[u8]] = &[&[0x9F, 0x6E], &[0x9F, 0x71]];
+
+/// American Express contactless kernel (C-4, Expresspay) configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct C4Config {
+    /// Terminal type, capabilities and Terminal Action Codes
+    pub terminal: TerminalConfig,
+    pub limits: ContactlessLimits,
+    pub contact_supported: bool,
+    /// Cardholder verification on a mobile device supported
+    pub mobile_cvm_supported: bool,
+    /// Ask for the contact interface when the card declines
+    pub try_another_interface_after_decline: bool,
+    /// Certification Authority Public Keys for CDA
+    #[serde(default)]
+    pub ca_public_keys: Vec<CaPublicKey>,
+}
+
+impl Default for C4Config {
+    fn default() -> Self {
+        Self {
+            terminal: TerminalConfig::default(),
+            limits: ContactlessLimits::default(),
+            contact_supported: true,
+            mobile_cvm_supported: true,
+            try_another_interface_after_decline: false,
+            ca_public_keys: Vec::new(),
+        }
+    }
+}
+
+impl C4Config {
+    /// Enhanced Contactless Reader Capabilities (tag 0x9F6E)
+    pub fn enhanced_reader_capabilities(&self, cvm_required: bool) -> [u8; 4] {
+        let capabilities = &self.terminal.capabilities;
+        // EMV full online mode supported
+        let mut value = [0x20, 0x00, 0x00, 0x03];
+
+        if self.contact_supported {
+            value[0] |= 0x80;
+        }
+        if self.mobile_cvm_supported {
+            value[0] |= 0x08;
+            value[1] |= 0x80;
+        }
+        if self.try_another_interface_after_decline {
+            value[0] |= 0x04;
+        }
+        if capabilities.supports_online_pin() {
+            value[1] |= 0x40;
+        }
+        if capabilities.supports_signature() {
+            value[1] |= 0x20;
+        }
+        if !self.terminal.is_online_capable() {
+            value[2] |= 0x80;
+        }
+        if cvm_required {
+            value[2] |= 0x40;
+        }
+        value
+    }
+}
+
+impl KernelFactory for C4Config {
+    fn kernel_id(&self) -> KernelId {
+        KernelId::AmericanExpress
+    }
+
+    fn create(
+        &self,
+        processor: EmvProcessor,
+        combination: &Combination,
+        terminal_data: Vec<Tlv>,
+    ) -> Box<dyn ContactlessKernel> {
+        let limits = self.limits.with_entry_point(&combination.config);
+        let amount = Tlv::find_by_tag(&terminal_data, &[0x9F, 0x02])
+            .map(|tlv| bcd_to_u64(&tlv.value))
+            .unwrap_or(0);
+        let capabilities = self.enhanced_reader_capabilities(amount >= limits.cvm_required_limit);
+
+        let profile = EmvModeProfile {
+            kernel_id: KernelId::AmericanExpress,
+            terminal: self.terminal.clone(),
+            limits,
+            reader_data: vec![Tlv::new(
+                TAG_ENHANCED_READER_CAPABILITIES,
+                capabilities.to_vec(),
+            )],
+            data_record: DATA_RECORD,
+            try_another_interface_on_decline: self.try_another_interface_after_decline,
+            ca_public_keys: self.ca_public_keys.clone(),
+        };
+        Box::new(EmvModeKernel::new(processor, profile, terminal_data))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::outcome::{OutcomeCvm, OutcomeStatus};
+    use crate::services::kernels::emv_mode::tests::{
+        ca_public_keys, run, terminal_data, EmvModeCard,
+    };
+
+    const AMEX: [u8; 6] = [0xA0, 0x00, 0x00, 0x00, 0x25, 0x01];
+
+    #[test]
+    fn test_online_pin_above_cvm_limit() {
+        let config = C4Config {
+            ca_public_keys: ca_public_keys(&AMEX),
+            ..C4Config::default()
+        };
+        let mut kernel = config.create(
+            EmvProcessor::new("156".to_string(), "CNY".to_string()),
+            &Combination::new(&AMEX, KernelId::AmericanExpress),
+            terminal_data(&[0x00, 0x00, 0x00, 0x00, 0x60, 0x00]),
+        );
+        let card = EmvModeCard::new(&AMEX, &[0x9F, 0x6E, 0x04, 0x9F, 0x02, 0x06]);
+        let outcome = run(kernel.as_mut(), &card);
+
+        assert_eq!(outcome.parameters.status, OutcomeStatus::OnlineRequest);
+        assert_eq!(outcome.parameters.cvm, OutcomeCvm::OnlinePin);
+        // Contact, EMV full online, mobile; mobile CVM and online PIN; CVM required
+        assert_eq!(card.gpo_data.borrow()[2..6], [0xA8, 0xC0, 0x40, 0x03]);
+    }
+
+    #[test]
+    fn test_decline_switches_interface_when_configured() {
+        let config = C4Config {
+            try_another_interface_after_decline: true,
+            ..C4Config::default()
+        };
+        let mut kernel = config.create(
+            EmvProcessor::new("156".to_string(), "CNY".to_string()),
+            &Combination::new(&AMEX, KernelId::AmericanExpress),
+            terminal_data(&[0x00, 0x00, 0x00, 0x00, 0x10, 0x00]),
+        );
+        let mut card = EmvModeCard::new(&AMEX, &[0x9F, 0x6E, 0x04]);
+        card.cid = Some(0x00);
+        let outcome = run(kernel.as_mut(), &card);
+        assert_eq!(
+            outcome.parameters.status,
+            OutcomeStatus::TryAnotherInterface
+        );
+    }
+}
@@ -0,0 +1,150 @@
+use serde::{Deserialize, Serialize};
+
+use super::emv_mode::{EmvModeKernel, EmvModeProfile};
+use super::{
+    bcd_to_u64, Combination, ContactlessKernel, ContactlessLimits, KernelFactory, KernelId,
+};
+use crate::models::emv::Tlv;
+use crate::models::terminal::TerminalConfig;
+use crate::services::emv_processor::EmvProcessor;
+use crate::services::oda::CaPublicKey;
+
+const TAG_TERMINAL_COMPATIBILITY_INDICATOR: &[u8] = &[0x9F, 0x52];
+const TAG_TERMINAL_INTERCHANGE_PROFILE: &[u8] = &[0x9F, 0x53];
+
+/// Data Record additions: the reader data sent to the card
+const DATA_RECORD: &[&[u8]] = &[&[0x9F, 0x52], &[0x9F, 0x53]];
+
+/// JCB contactless kernel (C-5, J/Speedy) configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct C5Config {
+    /// Terminal type, capabilities and Terminal Action Codes
+    pub terminal: TerminalConfig,
+    pub limits: ContactlessLimits,
+    /// On-device cardholder verification supported
+    pub cdcvm_supported: bool,
+    pub issuer_update_supported: bool,
+    /// Certification Authority Public Keys for CDA
+    #[serde(default)]
+    pub ca_public_keys: Vec<CaPublicKey>,
+}
+
+impl Default for C5Config {
+    fn default() -> Self {
+        Self {
+            terminal: TerminalConfig::default(),
+            limits: ContactlessLimits::default(),
+            cdcvm_supported: true,
+            issuer_update_supported: false,
+            ca_public_keys: Vec::new(),
+        }
+    }
+}
+
+impl C5Config {
+    /// Terminal Interchange Profile (tag 0x9F53)
+    pub fn terminal_interchange_profile(&self, cvm_required: bool) -> [u8; 3] {
+        let capabilities = &self.terminal.capabilities;
+        let mut value = [0x00; 3];
+
+        if cvm_required {
+            value[0] |= 0x80;
+        }
+        if capabilities.supports_signature() {
+            value[0] |= 0x40;
+        }
+        if capabilities.supports_online_pin() {
+            value[0] |= 0x20;
+        }
+        if self.cdcvm_supported {
+            value[0] |= 0x10;
+        }
+        if self.issuer_update_supported {
+            value[1] |= 0x80;
+        }
+        value
+    }
+}
+
+impl KernelFactory for C5Config {
+    fn kernel_id(&self) -> KernelId {
+        KernelId::Jcb
+    }
+
+    fn create(
+        &self,
+        processor: EmvProcessor,
+        combination: &Combination,
+        terminal_data: Vec<Tlv>,
+    ) -> Box<dyn ContactlessKernel> {
+        let limits = self.limits.with_entry_point(&combination.config);
+        let amount = Tlv::find_by_tag(&terminal_data, &[0x9F, 0x02])
+            .map(|tlv| bcd_to_u64(&tlv.value))
+            .unwrap_or(0);
+        let profile_value = self.terminal_interchange_profile(amount >= limits.cvm_required_limit);
+
+        let profile = EmvModeProfile {
+            kernel_id: KernelId::Jcb,
+            terminal: self.terminal.clone(),
+            limits,
+            reader_data: vec![
+                // EMV mode only; legacy mode is not supported
+                Tlv::new(TAG_TERMINAL_COMPATIBILITY_INDICATOR, vec![0x01]),
+                Tlv::new(TAG_TERMINAL_INTERCHANGE_PROFILE, profile_value.to_vec()),
+            ],
+            data_record: DATA_RECORD,
+            try_another_interface_on_decline: false,
+            ca_public_keys: self.ca_public_keys.clone(),
+        };
+        Box::new(EmvModeKernel::new(processor, profile, terminal_data))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::outcome::{OutcomeCvm, OutcomeStatus};
+    use crate::services::kernels::emv_mode::tests::{
+        ca_public_keys, run, terminal_data, EmvModeCard,
+    };
+
+    const JCB: [u8; 7] = [0xA0, 0x00, 0x00, 0x00, 0x65, 0x10, 0x10];
+
+    #[test]
+    fn test_offline_approval_needs_cda() {
+        let config = C5Config {
+            limits: ContactlessLimits {
+                floor_limit: 5_000,
+                ..ContactlessLimits::default()
+            },
+            ca_public_keys: ca_public_keys(&JCB),
+            ..C5Config::default()
+        };
+        let kernel = || {
+            config.create(
+                EmvProcessor::new("156".to_string(), "CNY".to_string()),
+                &Combination::new(&JCB, KernelId::Jcb),
+                terminal_data(&[0x00, 0x00, 0x00, 0x00, 0x10, 0x00]),
+            )
+        };
+        let mut card = EmvModeCard::new(&JCB, &[0x9F, 0x52, 0x01, 0x9F, 0x53, 0x03]);
+        let outcome = run(kernel().as_mut(), &card);
+
+        assert_eq!(outcome.parameters.status, OutcomeStatus::Approved);
+        assert_eq!(outcome.parameters.cvm, OutcomeCvm::NoCvm);
+        assert_eq!(card.gpo_data.borrow()[2..], [0x01, 0x30, 0x00, 0x00]);
+        assert!(Tlv::find_by_tag(&outcome.data_record, &[0x9F, 0x53]).is_some());
+
+        // A TC without the requested CDA signature is not approved
+        card.signs_cda = false;
+        let outcome = run(kernel().as_mut(), &card);
+        assert_eq!(outcome.parameters.status, OutcomeStatus::Declined);
+        assert_eq!(
+            Tlv::find_by_tag(&outcome.data_record, &[0x95])
+                .unwrap()
+                .value[0]
+                & 0x04,
+            0x04
+        );
+    }
+}
@@ -0,0 +1,154 @@
+use serde::{Deserialize, Serialize};
+
+use super::emv_mode::{EmvModeKernel, EmvModeProfile};
+use super::{
+    bcd_to_u64, Combination, ContactlessKernel, ContactlessLimits, KernelFactory, KernelId,
+};
+use crate::models::emv::Tlv;
+use crate::models::terminal::TerminalConfig;
+use crate::services::emv_processor::EmvProcessor;
+use crate::services::oda::CaPublicKey;
+
+const TAG_TTQ: &[u8] = &[0x9F, 0x66];
+
+/// Data Record additions: the qualifiers sent to the card
+const DATA_RECORD: &[&[u8]] = &[&[0x9F, 0x66]];
+
+/// Discover contactless kernel (C-6, D-PAS) configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct C6Config {
+    /// Terminal type, capabilities and Terminal Action Codes
+    pub terminal: TerminalConfig,
+    pub limits: ContactlessLimits,
+    pub contact_supported: bool,
+    /// Consumer Device CVM supported
+    pub cdcvm_supported: bool,
+    /// Certification Authority Public Keys for CDA
+    #[serde(default)]
+    pub ca_public_keys: Vec<CaPublicKey>,
+}
+
+impl Default for C6Config {
+    fn default() -> Self {
+        Self {
+            terminal: TerminalConfig::default(),
+            limits: ContactlessLimits::default(),
+            contact_supported: true,
+            cdcvm_supported: true,
+            ca_public_keys: Vec::new(),
+        }
+    }
+}
+
+impl C6Config {
+    /// D-PAS Terminal Transaction Qualifiers (tag 0x9F66) for an amount
+    pub fn ttq(&self, amount: u64, limits: &ContactlessLimits) -> [u8; 4] {
+        let capabilities = &self.terminal.capabilities;
+        let online_capable = self.terminal.is_online_capable();
+        // D-PAS EMV mode supported
+        let mut ttq = [0x20, 0x00, 0x00, 0x00];
+
+        if self.contact_supported {
+            ttq[0] |= 0x10;
+        }
+        if !online_capable {
+            ttq[0] |= 0x08;
+        }
+        if capabilities.supports_online_pin() && online_capable {
+            ttq[0] |= 0x04;
+        }
+        if capabilities.supports_signature() {
+            ttq[0] |= 0x02;
+        }
+        if online_capable && (amount == 0 || amount > limits.floor_limit) {
+            ttq[1] |= 0x80;
+        }
+        if amount >= limits.cvm_required_limit {
+            ttq[1] |= 0x40;
+        }
+        if self.cdcvm_supported {
+            ttq[2] |= 0x40;
+        }
+        ttq
+    }
+}
+
+impl KernelFactory for C6Config {
+    fn kernel_id(&self) -> KernelId {
+        KernelId::Discover
+    }
+
+    fn create(
+        &self,
+        processor: EmvProcessor,
+        combination: &Combination,
+        terminal_data: Vec<Tlv>,
+    ) -> Box<dyn ContactlessKernel> {
+        let limits = self.limits.with_entry_point(&combination.config);
+        let amount = Tlv::find_by_tag(&terminal_data, &[0x9F, 0x02])
+            .map(|tlv| bcd_to_u64(&tlv.value))
+            .unwrap_or(0);
+        // Entry Point may have pre-set the qualifiers for this combination
+        let ttq = match Tlv::find_by_tag(&terminal_data, TAG_TTQ) {
+            Some(tlv) => tlv.value.clone(),
+            None => self.ttq(amount, &limits).to_vec(),
+        };
+
+        let profile = EmvModeProfile {
+            kernel_id: KernelId::Discover,
+            terminal: self.terminal.clone(),
+            limits,
+            reader_data: vec![Tlv::new(TAG_TTQ, ttq)],
+            data_record: DATA_RECORD,
+            try_another_interface_on_decline: false,
+            ca_public_keys: self.ca_public_keys.clone(),
+        };
+        Box::new(EmvModeKernel::new(processor, profile, terminal_data))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::outcome::OutcomeStatus;
+    use crate::services::kernels::emv_mode::tests::{
+        ca_public_keys, run, terminal_data, EmvModeCard,
+    };
+    use crate::services::kernels::EntryPointConfig;
+
+    const DISCOVER: [u8; 7] = [0xA0, 0x00, 0x00, 0x01, 0x52, 0x30, 0x10];
+
+    #[test]
+    fn test_combination_limit_applies() {
+        let combination =
+            Combination::new(&DISCOVER, KernelId::Discover).with_config(EntryPointConfig {
+                transaction_limit: Some(500),
+                ..EntryPointConfig::default()
+            });
+        let card = EmvModeCard::new(&DISCOVER, &[0x9F, 0x66, 0x04]);
+        let config = C6Config {
+            ca_public_keys: ca_public_keys(&DISCOVER),
+            ..C6Config::default()
+        };
+
+        let mut kernel = config.create(
+            EmvProcessor::new("156".to_string(), "CNY".to_string()),
+            &combination,
+            terminal_data(&[0x00, 0x00, 0x00, 0x00, 0x10, 0x00]),
+        );
+        let outcome = run(kernel.as_mut(), &card);
+        assert_eq!(
+            outcome.parameters.status,
+            OutcomeStatus::TryAnotherInterface
+        );
+
+        let mut kernel = config.create(
+            EmvProcessor::new("156".to_string(), "CNY".to_string()),
+            &Combination::new(&DISCOVER, KernelId::Discover),
+            terminal_data(&[0x00, 0x00, 0x00, 0x00, 0x10, 0x00]),
+        );
+        let outcome = run(kernel.as_mut(), &card);
+        assert_eq!(outcome.parameters.status, OutcomeStatus::OnlineRequest);
+        assert_eq!(card.gpo_data.borrow()[2..], [0x34, 0x80, 0x40, 0x00]);
+    }
+}
@@ -0,0 +1,154 @@
+use serde::{Deserialize, Serialize};
+
+use super::{
+    u64_to_bcd, C3Config, C3Kernel, Combination, ContactlessKernel, KernelFactory, KernelId,
+    KernelStep,
+};
+use crate::models::emv::{ApduResponse, Tlv};
+use crate::services::emv_processor::EmvProcessor;
+
+/// Electronic Cash Terminal Support Indicator (tag 0x9F7A)
+const TAG_EC_SUPPORT_INDICATOR: &[u8] = &[0x9F, 0x7A];
+/// Electronic Cash Terminal Transaction Limit (tag 0x9F7B)
+const TAG_EC_TRANSACTION_LIMIT: &[u8] = &[0x9F, 0x7B];
+
+/// UnionPay QuickPass kernel (C-7, qPBOC) configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct C7Config {
+    /// TTQ, limits and CA keys, shared with the qVSDC flow
+    pub reader: C3Config,
+    /// Offline Electronic Cash transactions supported
+    pub ec_supported: bool,
+    /// Electronic Cash Terminal Transaction Limit
+    pub ec_transaction_limit: u64,
+}
+
+impl Default for C7Config {
+    /// QuickPass without CVM up to 1000.00 CNY
+    fn default() -> Self {
+        Self {
+            reader: C3Config {
+                transaction_limit: 999_999_999,
+                cvm_required_limit: 100_000,
+                ..C3Config::default()
+            },
+            ec_supported: false,
+            ec_transaction_limit: 100_000,
+        }
+    }
+}
+
+/// UnionPay QuickPass kernel (EMV Contactless Book C-7)
+///
+/// qPBOC follows the qVSDC flow (TTQ/CTQ, cryptogram in the GET PROCESSING
+/// OPTIONS response, fDDA over 0x9F69) and adds the Electronic Cash reader
+/// data objects to the PDOL.
+#[derive(Debug, Clone)]
+pub struct C7Kernel {
+    inner: C3Kernel,
+}
+
+impl C7Kernel {
+    pub fn new(processor: EmvProcessor, config: C7Config, terminal_data: Vec<Tlv>) -> Self {
+        let mut data = terminal_data;
+        Tlv::upsert(
+            &mut data,
+            TAG_EC_SUPPORT_INDICATOR,
+            vec![u8::from(config.ec_supported)],
+        );
+        Tlv::upsert(
+            &mut data,
+            TAG_EC_TRANSACTION_LIMIT,
+            u64_to_bcd(config.ec_transaction_limit, 6),
+        );
+        Self {
+            inner: C3Kernel::new(processor, config.reader, data),
+        }
+    }
+
+    /// All data objects known to the kernel so far
+    pub fn data(&self) -> &[Tlv] {
+        self.inner.data()
+    }
+}
+
+impl ContactlessKernel for C7Kernel {
+    fn kernel_id(&self) -> KernelId {
+        KernelId::UnionPay
+    }
+
+    fn start(&mut self, fci: &[u8]) -> Result<KernelStep, String> {
+        self.inner.start(fci)
+    }
+
+    fn on_response(&mut self, response: &ApduResponse) -> Result<KernelStep, String> {
+        self.inner.on_response(response)
+    }
+
+    fn on_communication_error(&mut self) -> KernelStep {
+        self.inner.on_communication_error()
+    }
+}
+
+impl KernelFactory for C7Config {
+    fn kernel_id(&self) -> KernelId {
+        KernelId::UnionPay
+    }
+
+    fn create(
+        &self,
+        processor: EmvProcessor,
+        combination: &Combination,
+        terminal_data: Vec<Tlv>,
+    ) -> Box<dyn ContactlessKernel> {
+        let config = C7Config {
+            reader: self.reader.with_entry_point(&combination.config),
+            ..self.clone()
+        };
+        Box::new(C7Kernel::new(processor, config, terminal_data))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::outcome::OutcomeStatus;
+
+    #[test]
+    fn test_ec_data_objects_in_pdol() {
+        let config = C7Config {
+            ec_supported: true,
+            ..C7Config::default()
+        };
+        let mut kernel = C7Kernel::new(
+            EmvProcessor::new("156".to_string(), "CNY".to_string()),
+            config,
+            vec![Tlv::new(
+                &[0x9F, 0x02],
+                vec![0x00, 0x00, 0x00, 0x00, 0x10, 0x00],
+            )],
+        );
+        // 6F { 84 A000000333010101, A5 { 9F38 { 9F66 04 9F7A 01 9F7B 06 } } }
+        let fci = hex::decode("6F188408A000000333010101A50C9F38099F66049F7A019F7B06").unwrap();
+
+        let command = match kernel.start(&fci).unwrap() {
+            KernelStep::Send(command) => command,
+            KernelStep::Complete(_) => panic!("expected GET PROCESSING OPTIONS"),
+        };
+        assert_eq!(
+            hex::encode_upper(command.data.as_ref().unwrap()),
+            "830B3680400001000000100000"
+        );
+
+        let response = ApduResponse::from_bytes(&[0x69, 0x84]).unwrap();
+        match kernel.on_response(&response).unwrap() {
+            KernelStep::Complete(outcome) => {
+                assert_eq!(
+                    outcome.parameters.status,
+                    OutcomeStatus::TryAnotherInterface
+                )
+            }
+            KernelStep::Send(_) => panic!("expected an outcome"),
+        }
+    }
+}
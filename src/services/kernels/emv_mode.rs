@@ -0,0 +1,725 @@
+use super::{
+    bcd_to_u64, collect_tags, error_indication, ContactlessKernel, ContactlessLimits, KernelId,
+    KernelOutcome, KernelStep, L2Error,
+};
+use crate::models::afl::AflEntry;
+use crate::models::card_date::CardDate;
+use crate::models::cryptogram::GenerateAcResponse;
+use crate::models::cvm::{CvMethod, CvmList, CvmResult, CvmResults};
+use crate::models::dol::Dol;
+use crate::models::emv::{ApduResponse, CryptogramType, Tlv, TransactionType};
+use crate::models::outcome::{OutcomeCvm, OutcomeParameterSet, OutcomeStart, OutcomeStatus};
+use crate::models::terminal::TerminalConfig;
+use crate::models::tvr::{Tsi, Tvr};
+use crate::services::action_analysis::{IssuerActionCodes, TerminalActionAnalysis};
+use crate::services::cvm::{CvmAttempt, CvmContext, CvmProcessor, CvmStep};
+use crate::services::emv_processor::EmvProcessor;
+use crate::services::oda::{self, CaPublicKey};
+
+const TAG_AMOUNT: &[u8] = &[0x9F, 0x02];
+const TAG_AIP: &[u8] = &[0x82];
+const TAG_AFL: &[u8] = &[0x94];
+const TAG_PAN: &[u8] = &[0x5A];
+const TAG_TRACK2: &[u8] = &[0x57];
+const TAG_CDOL1: &[u8] = &[0x8C];
+const TAG_CVM_LIST: &[u8] = &[0x8E];
+const TAG_PDOL: &[u8] = &[0x9F, 0x38];
+const TAG_SDA_TAG_LIST: &[u8] = &[0x9F, 0x4A];
+const TAG_UNPREDICTABLE_NUMBER: &[u8] = &[0x9F, 0x37];
+const TAG_TRANSACTION_TYPE: &[u8] = &[0x9C];
+const TAG_TRANSACTION_DATE: &[u8] = &[0x9A];
+const TAG_TRANSACTION_CURRENCY: &[u8] = &[0x5F, 0x2A];
+const TAG_APPLICATION_CURRENCY: &[u8] = &[0x9F, 0x42];
+const TAG_OUTCOME_PARAMETER_SET: &[u8] = &[0xDF, 0x81, 0x29];
+const TAG_ERROR_INDICATION: &[u8] = &[0xDF, 0x81, 0x15];
+
+/// Data Record contents common to EMV mode kernels
+const DATA_RECORD: &[&[u8]] = &[
+    &[0x9F, 0x02],
+    &[0x9F, 0x03],
+    &[0x9F, 0x26],
+    &[0x9F, 0x27],
+    &[0x9F, 0x10],
+    &[0x9F, 0x36],
+    &[0x9F, 0x37],
+    &[0x9F, 0x34],
+    &[0x9F, 0x33],
+    &[0x9F, 0x35],
+    &[0x9F, 0x1A],
+    &[0x9F, 0x4B],
+    &[0x82],
+    &[0x84],
+    &[0x57],
+    &[0x5A],
+    &[0x5F, 0x24],
+    &[0x5F, 0x34],
+    &[0x5F, 0x2A],
+    &[0x95],
+    &[0x9A],
+    &[0x9C],
+];
+
+/// What distinguishes one EMV mode kernel from another
+#[derive(Debug, Clone)]
+pub(crate) struct EmvModeProfile {
+    pub kernel_id: KernelId,
+    pub terminal: TerminalConfig,
+    pub limits: ContactlessLimits,
+    /// Kernel-specific reader data objects offered to the PDOL
+    /// (e.g. 0x9F6E for C-4, 0x9F53 for C-5, 0x9F66 for C-6)
+    pub reader_data: Vec<Tlv>,
+    /// Data objects added to the Data Record
+    pub data_record: &'static [&'static [u8]],
+    /// Ask for another interface instead of declining on an AAC
+    pub try_another_interface_on_decline: bool,
+    /// Certification Authority Public Keys for CDA
+    pub ca_public_keys: Vec<CaPublicKey>,
+}
+
+#[derive(Debug, Clone)]
+enum State {
+    NotStarted,
+    AwaitingGpo,
+    ReadingRecords,
+    AwaitingGenerateAc(CryptogramType),
+    Done,
+}
+
+/// EMV mode contactless flow shared by C-4, C-5 and C-6: GET PROCESSING
+/// OPTIONS, READ RECORD, CVM List processing, Terminal Action Analysis and
+/// a single GENERATE AC with CDA requested when the card supports it
+///
+/// A TC or ARQC whose CDA signature does not verify is declined.
+#[derive(Debug, Clone)]
+pub(crate) struct EmvModeKernel {
+    profile: EmvModeProfile,
+    processor: EmvProcessor,
+    data: Vec<Tlv>,
+    tvr: Tvr,
+    tsi: Tsi,
+    aip: [u8; 2],
+    afl: Vec<AflEntry>,
+    records: Vec<(u8, u8)>,
+    next_record: usize,
+    /// Static Data to be Authenticated, from the records flagged in the AFL
+    static_data: Vec<u8>,
+    /// PDOL and CDOL1 related data, covered by the CDA hash
+    pdol_data: Vec<u8>,
+    cdol1_data: Vec<u8>,
+    cvm: OutcomeCvm,
+    state: State,
+}
+
+impl EmvModeKernel {
+    pub fn new(processor: EmvProcessor, profile: EmvModeProfile, terminal_data: Vec<Tlv>) -> Self {
+        let mut data = terminal_data;
+        for tlv in &profile.reader_data {
+            Tlv::upsert(&mut data, &tlv.tag, tlv.value.clone());
+        }
+
+        Self {
+            processor: processor.with_terminal_config(profile.terminal.clone()),
+            profile,
+            data,
+            tvr: Tvr::default(),
+            tsi: Tsi::default(),
+            aip: [0x00; 2],
+            afl: Vec::new(),
+            records: Vec::new(),
+            next_record: 0,
+            static_data: Vec::new(),
+            pdol_data: Vec::new(),
+            cdol1_data: Vec::new(),
+            cvm: OutcomeCvm::NoCvm,
+            state: State::NotStarted,
+        }
+    }
+
+    fn begin(&mut self, fci: &[u8]) -> Result<KernelStep, String> {
+        if !matches!(self.state, State::NotStarted) {
+            return Err("Kernel already started".to_string());
+        }
+
+        for tlv in Tlv::parse_flattened(fci)? {
+            Tlv::upsert(&mut self.data, &tlv.tag, tlv.value);
+        }
+
+        if self.amount() > self.profile.limits.transaction_limit {
+            return Ok(self.try_another_interface(L2Error::MaxLimitExceeded));
+        }
+
+        self.pdol_data = match Tlv::find_by_tag(&self.data, TAG_PDOL) {
+            Some(pdol) => Dol::parse(&pdol.value)?.build(&self.data),
+            None => Vec::new(),
+        };
+
+        self.state = State::AwaitingGpo;
+        Ok(KernelStep::Send(
+            self.processor.get_processing_options(&self.pdol_data),
+        ))
+    }
+
+    fn respond(&mut self, response: &ApduResponse) -> Result<KernelStep, String> {
+        match std::mem::replace(&mut self.state, State::Done) {
+            State::AwaitingGpo => Ok(self.on_gpo(response)),
+            State::ReadingRecords => Ok(self.on_record(response)),
+            State::AwaitingGenerateAc(requested) => Ok(self.on_generate_ac(response, requested)),
+            state => {
+                self.state = state;
+                Err("No card response expected".to_string())
+            }
+        }
+    }
+
+    fn communication_error(&mut self) -> KernelStep {
+        let mut parameters = OutcomeParameterSet::new(OutcomeStatus::TryAgain);
+        parameters.start = OutcomeStart::B;
+        parameters.ui_request_on_restart = true;
+        parameters.discretionary_data_present = true;
+        self.finish(
+            parameters,
+            Vec::new(),
+            error_indication(0x02, L2Error::Ok, 0),
+        )
+    }
+
+    fn on_gpo(&mut self, response: &ApduResponse) -> KernelStep {
+        match response.status_word() {
+            0x9000 => {}
+            0x6984 | 0x6985 => return self.try_another_interface(L2Error::StatusBytes),
+            sw => return self.end_application(L2Error::StatusBytes, sw),
+        }
+
+        let tlvs = match Tlv::parse_flattened(&response.data) {
+            Ok(tlvs) => tlvs,
+            Err(_) => return self.end_application(L2Error::ParsingError, 0),
+        };
+        let (aip, afl) = match Tlv::find_by_tag(&tlvs, &[0x80]) {
+            Some(format1) if format1.value.len() >= 2 => {
+                (format1.value[..2].to_vec(), format1.value[2..].to_vec())
+            }
+            _ => match (
+                Tlv::find_by_tag(&tlvs, TAG_AIP),
+                Tlv::find_by_tag(&tlvs, TAG_AFL),
+            ) {
+                (Some(aip), Some(afl)) if aip.value.len() == 2 => {
+                    (aip.value.clone(), afl.value.clone())
+                }
+                _ => return self.end_application(L2Error::CardDataMissing, 0),
+            },
+        };
+
+        self.aip = [aip[0], aip[1]];
+        Tlv::upsert(&mut self.data, TAG_AIP, aip);
+        self.afl = match AflEntry::parse_all(&afl) {
+            Ok(entries) => entries,
+            Err(_) => return self.end_application(L2Error::CardDataError, 0),
+        };
+        self.records = AflEntry::records(&self.afl);
+        Tlv::upsert(&mut self.data, TAG_AFL, afl);
+
+        self.read_next_record()
+    }
+
+    fn read_next_record(&mut self) -> KernelStep {
+        match self.records.get(self.next_record) {
+            Some(&(sfi, record)) => {
+                self.state = State::ReadingRecords;
+                KernelStep::Send(self.processor.read_record(sfi, record))
+            }
+            None => self.after_records(),
+        }
+    }
+
+    fn on_record(&mut self, response: &ApduResponse) -> KernelStep {
+        if !response.is_success() {
+            return self.end_application(L2Error::StatusBytes, response.status_word());
+        }
+
+        let template = match Tlv::parse(&response.data) {
+            Ok(tlvs) => match Tlv::find_by_tag(&tlvs, &[0x70]) {
+                Some(template) => template.clone(),
+                None => return self.end_application(L2Error::ParsingError, 0),
+            },
+            Err(_) => return self.end_application(L2Error::ParsingError, 0),
+        };
+        match Tlv::parse_flattened(&template.value) {
+            Ok(tlvs) => {
+                for tlv in tlvs {
+                    Tlv::upsert(&mut self.data, &tlv.tag, tlv.value);
+                }
+            }
+            Err(_) => return self.end_application(L2Error::ParsingError, 0),
+        }
+
+        // Static Data to be Authenticated: the template value for SFI 1-10,
+        // the whole record otherwise
+        let (sfi, record) = self.records[self.next_record];
+        if AflEntry::is_oda_record(&self.afl, sfi, record) {
+            if sfi <= 10 {
+                self.static_data.extend_from_slice(&template.value);
+            } else {
+                self.static_data.extend_from_slice(&response.data);
+            }
+        }
+
+        self.next_record += 1;
+        self.read_next_record()
+    }
+
+    fn after_records(&mut self) -> KernelStep {
+        if (Tlv::find_by_tag(&self.data, TAG_PAN).is_none()
+            && Tlv::find_by_tag(&self.data, TAG_TRACK2).is_none())
+            || Tlv::find_by_tag(&self.data, TAG_CDOL1).is_none()
+        {
+            return self.end_application(L2Error::CardDataMissing, 0);
+        }
+
+        self.processing_restrictions();
+        self.cardholder_verification();
+
+        if self.amount() > self.profile.limits.floor_limit {
+            self.tvr.set_floor_limit_exceeded(true);
+        }
+        // AIP byte 1 b1: CDA supported
+        if self.aip[0] & 0x01 == 0 {
+            self.tvr.set_offline_data_authentication_not_performed(true);
+        }
+
+        self.generate_ac()
+    }
+
+    /// Processing restrictions against the transaction date, on the same
+    /// checks the contact flow runs
+    fn processing_restrictions(&mut self) {
+        let Some(Ok(date)) = Tlv::find_by_tag(&self.data, TAG_TRANSACTION_DATE)
+            .map(|tlv| CardDate::from_bcd(&tlv.value))
+        else {
+            return;
+        };
+        let tvr =
+            self.processor
+                .processing_restrictions(&self.data, self.transaction_type(), &date);
+        self.tvr.set_service_not_allowed(tvr.service_not_allowed());
+        self.tvr.set_expired_application(tvr.expired_application());
+        self.tvr
+            .set_application_not_yet_effective(tvr.application_not_yet_effective());
+    }
+
+    /// No CVM below the CVM Required Limit, otherwise CVM List processing.
+    /// Online PIN and signature are deferred to after the card is removed;
+    /// offline PIN is not available over the contactless interface.
+    fn cardholder_verification(&mut self) {
+        let amount = self.amount();
+        if amount < self.profile.limits.cvm_required_limit {
+            self.cvm = OutcomeCvm::NoCvm;
+            let results = CvmResults {
+                method_code: CvMethod::NoCvm.code(),
+                condition_code: 0x00,
+                result: CvmResult::Successful,
+            };
+            Tlv::upsert(&mut self.data, &[0x9F, 0x34], results.to_bytes().to_vec());
+            return;
+        }
+
+        let find = |tag: &[u8]| Tlv::find_by_tag(&self.data, tag).map(|tlv| tlv.value.clone());
+        let context = CvmContext {
+            amount,
+            cashback_amount: find(&[0x9F, 0x03])
+                .map(|value| bcd_to_u64(&value))
+                .unwrap_or(0),
+            transaction_currency_code: find(TAG_TRANSACTION_CURRENCY)
+                .map(hex::encode)
+                .unwrap_or_default(),
+            application_currency_code: find(TAG_APPLICATION_CURRENCY).map(hex::encode),
+            transaction_type: self.transaction_type(),
+            terminal: self.profile.terminal.clone(),
+            cdcvm_performed: false,
+        };
+        let list = find(TAG_CVM_LIST).and_then(|list| CvmList::parse(&list).ok());
+        // AIP byte 1 b5: cardholder verification is supported
+        let mut processor = CvmProcessor::new(list, self.aip[0] & 0x10 != 0, context);
+
+        let mut step = processor.start(self.tvr, self.tsi);
+        let outcome = loop {
+            let attempt = match step {
+                CvmStep::Complete(outcome) => break outcome,
+                CvmStep::Perform(CvMethod::OnlinePin | CvMethod::Signature) => CvmAttempt::Unknown,
+                CvmStep::Perform(CvMethod::Cdcvm) => CvmAttempt::Successful,
+                CvmStep::Perform(_) => CvmAttempt::Failed,
+            };
+            step = match processor.report(attempt) {
+                Ok(step) => step,
+                Err(_) => return,
+            };
+        };
+
+        self.tvr = outcome.tvr;
+        self.tsi = outcome.tsi;
+        self.cvm = match outcome.method {
+            Some(CvMethod::OnlinePin) => OutcomeCvm::OnlinePin,
+            Some(CvMethod::Cdcvm) => OutcomeCvm::ConfirmationCodeVerified,
+            _ if outcome.signature_required => OutcomeCvm::ObtainSignature,
+            _ => OutcomeCvm::NoCvm,
+        };
+        Tlv::upsert(
+            &mut self.data,
+            &[0x9F, 0x34],
+            outcome.cvm_results.to_bytes().to_vec(),
+        );
+    }
+
+    fn generate_ac(&mut self) -> KernelStep {
+        Tlv::upsert(&mut self.data, &[0x95], self.tvr.as_bytes().to_vec());
+
+        let iac = IssuerActionCodes::from_tlvs(&self.data);
+        let decision =
+            TerminalActionAnalysis::new(self.profile.terminal.clone()).analyse(&self.tvr, &iac);
+
+        self.cdol1_data =
+            match Tlv::find_by_tag(&self.data, TAG_CDOL1).map(|cdol1| Dol::parse(&cdol1.value)) {
+                Some(Ok(cdol1)) => cdol1.build(&self.data),
+                _ => return self.end_application(L2Error::CardDataError, 0),
+            };
+
+        let mut command = self
+            .processor
+            .generate_ac(decision.cryptogram_type, &self.cdol1_data);
+        // P1 b5: CDA signature requested
+        if self.aip[0] & 0x01 != 0 && decision.cryptogram_type != CryptogramType::Aac {
+            command.p1 |= 0x10;
+        }
+
+        self.state = State::AwaitingGenerateAc(decision.cryptogram_type);
+        KernelStep::Send(command)
+    }
+
+    fn on_generate_ac(&mut self, response: &ApduResponse, requested: CryptogramType) -> KernelStep {
+        if !response.is_success() {
+            return self.end_application(L2Error::StatusBytes, response.status_word());
+        }
+
+        let parsed = match GenerateAcResponse::parse(&response.data) {
+            Ok(parsed) => parsed,
+            Err(_) => return self.end_application(L2Error::ParsingError, 0),
+        };
+        let mut returned = match parsed.validate_against(requested) {
+            Ok(returned) => returned,
+            Err(_) => return self.end_application(L2Error::CardDataError, 0),
+        };
+
+        // CDA was requested for a TC or ARQC: without a valid signature the
+        // cryptogram cannot be trusted and the transaction is declined
+        if self.aip[0] & 0x01 != 0
+            && requested != CryptogramType::Aac
+            && returned != CryptogramType::Aac
+            && self.verify_cda(&response.data).is_err()
+        {
+            self.tvr.set_cda_failed(true);
+            Tlv::upsert(&mut self.data, &[0x95], self.tvr.as_bytes().to_vec());
+            returned = CryptogramType::Aac;
+        }
+
+        Tlv::upsert(&mut self.data, &[0x9F, 0x27], vec![parsed.cid.0]);
+        Tlv::upsert(
+            &mut self.data,
+            &[0x9F, 0x36],
+            parsed.atc.to_be_bytes().to_vec(),
+        );
+        Tlv::upsert(&mut self.data, &[0x9F, 0x26], parsed.cryptogram.to_vec());
+        if let Some(iad) = parsed.issuer_application_data {
+            Tlv::upsert(&mut self.data, &[0x9F, 0x10], iad);
+        }
+        if let Some(sdad) = parsed.signed_dynamic_data {
+            Tlv::upsert(&mut self.data, &[0x9F, 0x4B], sdad);
+        }
+
+        match returned {
+            CryptogramType::Tc => self.complete(OutcomeStatus::Approved),
+            CryptogramType::Arqc => self.complete(OutcomeStatus::OnlineRequest),
+            CryptogramType::Aac if self.profile.try_another_interface_on_decline => {
+                self.try_another_interface(L2Error::Ok)
+            }
+            CryptogramType::Aac => self.complete(OutcomeStatus::Declined),
+        }
+    }
+
+    /// Recover the ICC Public Key and check the CDA signature of a GENERATE
+    /// AC response
+    fn verify_cda(&mut self, response: &[u8]) -> Result<(), String> {
+        let mut static_data = self.static_data.clone();
+        if let Some(tag_list) = Tlv::find_by_tag(&self.data, TAG_SDA_TAG_LIST) {
+            // Only the AIP may be listed
+            if tag_list.value != TAG_AIP {
+                return Err("Invalid SDA Tag List".to_string());
+            }
+            static_data.extend_from_slice(&self.aip);
+        }
+
+        let icc_key = oda::icc_public_key(&self.data, &self.profile.ca_public_keys, &static_data)?;
+        let unpredictable_number = Tlv::find_by_tag(&self.data, TAG_UNPREDICTABLE_NUMBER)
+            .map(|tlv| tlv.value.clone())
+            .ok_or("Unpredictable Number not found")?;
+        let icc_dynamic_number = oda::verify_combined_signature(
+            &icc_key,
+            &unpredictable_number,
+            &[self.pdol_data.as_slice(), &self.cdol1_data].concat(),
+            response,
+        )?;
+        Tlv::upsert(&mut self.data, &[0x9F, 0x4C], icc_dynamic_number);
+        Ok(())
+    }
+
+    fn complete(&mut self, status: OutcomeStatus) -> KernelStep {
+        let mut parameters = OutcomeParameterSet::new(status);
+        parameters.cvm = self.cvm;
+        parameters.receipt = self.cvm == OutcomeCvm::ObtainSignature;
+        parameters.data_record_present = true;
+        parameters.discretionary_data_present = true;
+        parameters.ui_request_on_outcome = true;
+
+        let mut data_record = collect_tags(&self.data, DATA_RECORD);
+        data_record.extend(collect_tags(&self.data, self.profile.data_record));
+        self.finish(
+            parameters,
+            data_record,
+            error_indication(0x00, L2Error::Ok, 0),
+        )
+    }
+
+    fn try_another_interface(&mut self, error: L2Error) -> KernelStep {
+        let mut parameters = OutcomeParameterSet::new(OutcomeStatus::TryAnotherInterface);
+        parameters.ui_request_on_outcome = true;
+        parameters.discretionary_data_present = true;
+        self.finish(parameters, Vec::new(), error_indication(0x00, error, 0))
+    }
+
+    fn end_application(&mut self, error: L2Error, status_word: u16) -> KernelStep {
+        let mut parameters = OutcomeParameterSet::new(OutcomeStatus::EndApplication);
+        parameters.discretionary_data_present = true;
+        self.finish(
+            parameters,
+            Vec::new(),
+            error_indication(0x00, error, status_word),
+        )
+    }
+
+    fn finish(
+        &mut self,
+        parameters: OutcomeParameterSet,
+        data_record: Vec<Tlv>,
+        error_indication: Vec<u8>,
+    ) -> KernelStep {
+        self.state = State::Done;
+        Tlv::upsert(
+            &mut self.data,
+            TAG_OUTCOME_PARAMETER_SET,
+            parameters.to_bytes().to_vec(),
+        );
+
+        KernelStep::Complete(Box::new(KernelOutcome::new(
+            parameters,
+            data_record,
+            vec![Tlv::new(TAG_ERROR_INDICATION, error_indication)],
+        )))
+    }
+
+    fn amount(&self) -> u64 {
+        Tlv::find_by_tag(&self.data, TAG_AMOUNT)
+            .map(|tlv| bcd_to_u64(&tlv.value))
+            .unwrap_or(0)
+    }
+
+    /// Transaction Type (0x9C) as far as CVM conditions are concerned
+    fn transaction_type(&self) -> TransactionType {
+        Tlv::find_by_tag(&self.data, TAG_TRANSACTION_TYPE)
+            .and_then(|tlv| tlv.value.first())
+            .and_then(|code| TransactionType::from_code(*code))
+            .unwrap_or(TransactionType::Purchase)
+    }
+}
+
+impl ContactlessKernel for EmvModeKernel {
+    fn kernel_id(&self) -> KernelId {
+        self.profile.kernel_id
+    }
+
+    fn start(&mut self, fci: &[u8]) -> Result<KernelStep, String> {
+        self.begin(fci)
+    }
+
+    fn on_response(&mut self, response: &ApduResponse) -> Result<KernelStep, String> {
+        self.respond(response)
+    }
+
+    fn on_communication_error(&mut self) -> KernelStep {
+        self.communication_error()
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::models::emv::ApduCommand;
+    use crate::services::kernels::ContactlessKernel;
+    use crate::services::oda::tests::{
+        icc_certificate, issuer_certificate, public_key, sign_combined, CA_MODULUS,
+    };
+
+    const PAN: [u8; 8] = [0x47, 0x61, 0x73, 0x90, 0x01, 0x01, 0x01, 0x19];
+
+    pub(crate) fn tlv(tag: &[u8], value: &[u8]) -> Vec<u8> {
+        let length = if value.len() > 127 {
+            vec![0x81, value.len() as u8]
+        } else {
+            vec![value.len() as u8]
+        };
+        [tag, &length, value].concat()
+    }
+
+    /// The CA key the test cards are certified under, for a kernel config
+    pub(crate) fn ca_public_keys(aid: &[u8]) -> Vec<CaPublicKey> {
+        vec![CaPublicKey {
+            rid: aid[..5].to_vec(),
+            index: 0x92,
+            key: public_key(CA_MODULUS),
+        }]
+    }
+
+    /// A card answering an EMV mode contactless flow; `pdol` is requested in
+    /// the FCI and the GPO command data is recorded for inspection
+    pub(crate) struct EmvModeCard {
+        pub aid: Vec<u8>,
+        pub pdol: Vec<u8>,
+        pub aip: [u8; 2],
+        /// CID returned by GENERATE AC, `None` to echo the request
+        pub cid: Option<u8>,
+        /// Add a CDA signature when the terminal asks for one
+        pub signs_cda: bool,
+        pub gpo_data: std::cell::RefCell<Vec<u8>>,
+    }
+
+    impl EmvModeCard {
+        pub fn new(aid: &[u8], pdol: &[u8]) -> Self {
+            Self {
+                aid: aid.to_vec(),
+                pdol: pdol.to_vec(),
+                aip: [0x19, 0x80],
+                cid: None,
+                signs_cda: true,
+                gpo_data: std::cell::RefCell::new(Vec::new()),
+            }
+        }
+
+        pub fn fci(&self) -> Vec<u8> {
+            tlv(
+                &[0x6F],
+                &[
+                    tlv(&[0x84], &self.aid),
+                    tlv(&[0xA5], &tlv(&[0x9F, 0x38], &self.pdol)),
+                ]
+                .concat(),
+            )
+        }
+
+        fn record() -> Vec<u8> {
+            [
+                tlv(&[0x5A], &PAN),
+                tlv(&[0x5F, 0x24], &[0x30, 0x12, 0x31]),
+                tlv(
+                    &[0x8C],
+                    &hex::decode("9F02069F1A0295059F37049F3403").unwrap(),
+                ),
+                tlv(
+                    &[0x8E],
+                    &hex::decode("000000000000000042031E031F03").unwrap(),
+                ),
+                tlv(&[0x9F, 0x0D], &[0x00; 5]),
+                tlv(&[0x9F, 0x0E], &[0x00; 5]),
+                tlv(&[0x9F, 0x0F], &[0x00; 5]),
+            ]
+            .concat()
+        }
+
+        fn certificates() -> Vec<u8> {
+            let (issuer_cert, issuer_remainder) = issuer_certificate();
+            let (icc_cert, icc_remainder) = icc_certificate(&PAN, &Self::record());
+            [
+                tlv(&[0x8F], &[0x92]),
+                tlv(&[0x90], &issuer_cert),
+                tlv(&[0x92], &issuer_remainder),
+                tlv(&[0x9F, 0x32], &[0x03]),
+                tlv(&[0x9F, 0x46], &icc_cert),
+                tlv(&[0x9F, 0x47], &[0x03]),
+                tlv(&[0x9F, 0x48], &icc_remainder),
+            ]
+            .concat()
+        }
+
+        fn generate_ac(&self, command: &ApduCommand) -> Vec<u8> {
+            let cid = self.cid.unwrap_or(command.p1 & 0xC0);
+            let objects = [
+                tlv(&[0x9F, 0x27], &[cid]),
+                tlv(&[0x9F, 0x36], &[0x00, 0x01]),
+                tlv(&[0x9F, 0x26], &[0x11; 8]),
+            ]
+            .concat();
+            if command.p1 & 0x10 == 0 || !self.signs_cda || cid & 0xC0 == 0x00 {
+                return tlv(&[0x77], &objects);
+            }
+
+            // CDOL1 ends with 9F37 (4) and 9F34 (3)
+            let cdol1_data = command.data.clone().unwrap();
+            let un = &cdol1_data[cdol1_data.len() - 7..cdol1_data.len() - 3];
+            let dol_data = [&self.gpo_data.borrow()[2..], &cdol1_data[..]].concat();
+            let sdad = sign_combined(&[0x00, 0x01], un, &dol_data, &tlv(&[0x77], &objects));
+            tlv(&[0x77], &[objects, tlv(&[0x9F, 0x4B], &sdad)].concat())
+        }
+
+        fn respond(&self, command: &ApduCommand) -> Vec<u8> {
+            let data = match command.ins {
+                0xA8 => {
+                    *self.gpo_data.borrow_mut() = command.data.clone().unwrap_or_default();
+                    tlv(
+                        &[0x77],
+                        &[
+                            tlv(&[0x82], &self.aip),
+                            tlv(&[0x94], &[0x08, 0x01, 0x02, 0x01]),
+                        ]
+                        .concat(),
+                    )
+                }
+                0xB2 if command.p1 == 1 => tlv(&[0x70], &Self::record()),
+                0xB2 => tlv(&[0x70], &Self::certificates()),
+                0xAE => self.generate_ac(command),
+                _ => return vec![0x6D, 0x00],
+            };
+            [data, vec![0x90, 0x00]].concat()
+        }
+    }
+
+    /// Terminal data for an amount in minor units, with transaction date
+    pub(crate) fn terminal_data(amount: &[u8; 6]) -> Vec<Tlv> {
+        vec![
+            Tlv::new(&[0x9F, 0x02], amount.to_vec()),
+            Tlv::new(&[0x9F, 0x1A], vec![0x01, 0x56]),
+            Tlv::new(&[0x5F, 0x2A], vec![0x01, 0x56]),
+            Tlv::new(&[0x9A], vec![0x26, 0x10, 0x18]),
+            Tlv::new(&[0x9C], vec![0x00]),
+            Tlv::new(&[0x9F, 0x37], vec![0x11, 0x22, 0x33, 0x44]),
+        ]
+    }
+
+    pub(crate) fn run(kernel: &mut dyn ContactlessKernel, card: &EmvModeCard) -> KernelOutcome {
+        let mut step = kernel.start(&card.fci()).unwrap();
+        loop {
+            match step {
+                KernelStep::Complete(outcome) => return *outcome,
+                KernelStep::Send(command) => {
+                    let response = ApduResponse::from_bytes(&card.respond(&command)).unwrap();
+                    step = kernel.on_response(&response).unwrap();
+                }
+            }
+        }
+    }
+}
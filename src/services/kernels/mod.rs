@@ -0,0 +1,235 @@
+pub mod c2;
+pub mod c3;
+pub mod c4;
+pub mod c5;
+pub mod c6;
+pub mod c7;
+mod emv_mode;
+pub mod registry;
+
+pub use c2::{C2Config, C2Factory, C2Kernel, InMemoryTornLog, TornRecord, TornTransactionLog};
+pub use c3::{C3Config, C3Kernel};
+pub use c4::C4Config;
+pub use c5::C5Config;
+pub use c6::C6Config;
+pub use c7::{C7Config, C7Kernel};
+pub use registry::{Combination, EntryPointConfig, KernelFactory, KernelRegistry};
+
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+pub(crate) use crate::models::currency::u64_to_bcd;
+use crate::models::emv::{ApduCommand, ApduResponse, Tlv};
+use crate::models::outcome::{OutcomeParameterSet, UiRequest};
+
+/// Contactless kernel, identified by the Kernel Identifier (tag 0x9F2A)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KernelId {
+    /// C-2, Mastercard
+    Mastercard,
+    /// C-3, Visa
+    Visa,
+    /// C-4, American Express
+    AmericanExpress,
+    /// C-5, JCB
+    Jcb,
+    /// C-6, Discover
+    Discover,
+    /// C-7, UnionPay
+    UnionPay,
+    /// Any other international or domestic kernel
+    Other(u8),
+}
+
+impl KernelId {
+    pub fn from_code(code: u8) -> Self {
+        match code {
+            0x02 => KernelId::Mastercard,
+            0x03 => KernelId::Visa,
+            0x04 => KernelId::AmericanExpress,
+            0x05 => KernelId::Jcb,
+            0x06 => KernelId::Discover,
+            0x07 => KernelId::UnionPay,
+            other => KernelId::Other(other),
+        }
+    }
+
+    pub fn code(&self) -> u8 {
+        match self {
+            KernelId::Mastercard => 0x02,
+            KernelId::Visa => 0x03,
+            KernelId::AmericanExpress => 0x04,
+            KernelId::Jcb => 0x05,
+            KernelId::Discover => 0x06,
+            KernelId::UnionPay => 0x07,
+            KernelId::Other(code) => *code,
+        }
+    }
+
+    /// Requested kernel for a directory entry (EMV Book B Section 3.3.2.5)
+    ///
+    /// Without a Kernel Identifier the kernel follows from the RID.
+    pub fn resolve(aid: &[u8], kernel_identifier: Option<&[u8]>) -> Option<Self> {
+        match kernel_identifier.and_then(|id| id.first()) {
+            // Short kernel ID for international kernels (b8-b7 = 00)
+            Some(&id) if id & 0xC0 == 0x00 && id != 0x00 => Some(Self::from_code(id)),
+            Some(&id) if id != 0x00 => Some(KernelId::Other(id)),
+            _ => Self::default_for_rid(aid),
+        }
+    }
+
+    fn default_for_rid(aid: &[u8]) -> Option<Self> {
+        match aid.get(..5)? {
+            [0xA0, 0x00, 0x00, 0x00, 0x04] => Some(KernelId::Mastercard),
+            [0xA0, 0x00, 0x00, 0x00, 0x03] => Some(KernelId::Visa),
+            [0xA0, 0x00, 0x00, 0x00, 0x25] => Some(KernelId::AmericanExpress),
+            [0xA0, 0x00, 0x00, 0x00, 0x65] => Some(KernelId::Jcb),
+            [0xA0, 0x00, 0x00, 0x01, 0x52] | [0xA0, 0x00, 0x00, 0x03, 0x24] => {
+                Some(KernelId::Discover)
+            }
+            [0xA0, 0x00, 0x00, 0x03, 0x33] => Some(KernelId::UnionPay),
+            _ => None,
+        }
+    }
+}
+
+/// A contactless kernel session driven by Entry Point
+pub trait ContactlessKernel: Send {
+    fn kernel_id(&self) -> KernelId;
+
+    /// Begin with the FCI returned by SELECT
+    fn start(&mut self, fci: &[u8]) -> Result<KernelStep, String>;
+
+    /// Feed the card response to the last command sent
+    fn on_response(&mut self, response: &ApduResponse) -> Result<KernelStep, String>;
+
+    /// Feed a response with the measured command round trip, for kernels
+    /// that time card responses (relay resistance)
+    fn on_timed_response(
+        &mut self,
+        response: &ApduResponse,
+        _elapsed: Duration,
+    ) -> Result<KernelStep, String> {
+        self.on_response(response)
+    }
+
+    /// The card did not answer
+    fn on_communication_error(&mut self) -> KernelStep;
+}
+
+/// Reader limits applied by a kernel, in minor units
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContactlessLimits {
+    /// Reader Contactless Transaction Limit
+    pub transaction_limit: u64,
+    /// Reader Contactless Floor Limit
+    pub floor_limit: u64,
+    /// Reader CVM Required Limit
+    pub cvm_required_limit: u64,
+}
+
+impl Default for ContactlessLimits {
+    fn default() -> Self {
+        Self {
+            transaction_limit: 10_000,
+            floor_limit: 0,
+            cvm_required_limit: 5_000,
+        }
+    }
+}
+
+impl ContactlessLimits {
+    /// Limits overridden by an Entry Point combination
+    pub fn with_entry_point(mut self, config: &EntryPointConfig) -> Self {
+        if let Some(limit) = config.transaction_limit {
+            self.transaction_limit = limit;
+        }
+        if let Some(limit) = config.floor_limit {
+            self.floor_limit = limit;
+        }
+        if let Some(limit) = config.cvm_required_limit {
+            self.cvm_required_limit = limit;
+        }
+        self
+    }
+}
+
+/// Result a contactless kernel hands back to Entry Point
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KernelOutcome {
+    /// Outcome Parameter Set (tag 0xDF8129)
+    pub parameters: OutcomeParameterSet,
+    /// Data Record (tag 0xFF8105), sent to the acquirer; serialized without
+    /// the values of cardholder data objects
+    pub data_record: Vec<Tlv>,
+    /// Discretionary Data (tag 0xFF8106)
+    pub discretionary_data: Vec<Tlv>,
+    /// Message to show with the outcome
+    #[serde(default)]
+    pub ui_request_on_outcome: Option<UiRequest>,
+    /// Message to show when Entry Point restarts
+    #[serde(default)]
+    pub ui_request_on_restart: Option<UiRequest>,
+}
+
+impl KernelOutcome {
+    /// Outcome with the standard UI requests for its parameters
+    pub fn new(
+        parameters: OutcomeParameterSet,
+        data_record: Vec<Tlv>,
+        discretionary_data: Vec<Tlv>,
+    ) -> Self {
+        Self {
+            ui_request_on_outcome: UiRequest::on_outcome(&parameters),
+            ui_request_on_restart: UiRequest::on_restart(&parameters),
+            parameters,
+            data_record,
+            discretionary_data,
+        }
+    }
+}
+
+/// Next action requested by a contactless kernel
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "step", content = "value")]
+pub enum KernelStep {
+    /// Send this command to the card and pass the response back to the kernel
+    Send(ApduCommand),
+    /// Kernel finished
+    Complete(Box<KernelOutcome>),
+}
+
+/// Level 2 error reported in Error Indication (0xDF8115) byte 2
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum L2Error {
+    Ok = 0x00,
+    CardDataMissing = 0x01,
+    StatusBytes = 0x03,
+    ParsingError = 0x04,
+    MaxLimitExceeded = 0x05,
+    CardDataError = 0x06,
+    MagStripeNotSupported = 0x07,
+}
+
+/// Value of a numeric (n, BCD) data object such as Amount, Authorised (0x9F02)
+pub(crate) fn bcd_to_u64(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0u64, |acc, b| {
+        acc.saturating_mul(100)
+            .saturating_add(u64::from(b >> 4) * 10 + u64::from(b & 0x0F))
+    })
+}
+
+/// Copy the listed data objects that are present, in list order
+pub(crate) fn collect_tags(data: &[Tlv], tags: &[&[u8]]) -> Vec<Tlv> {
+    tags.iter()
+        .filter_map(|tag| Tlv::find_by_tag(data, tag).cloned())
+        .collect()
+}
+
+/// Error Indication (0xDF8115): L1, L2 and L3 errors, SW12, message on error
+pub(crate) fn error_indication(l1: u8, l2: L2Error, status_word: u16) -> Vec<u8> {
+    let sw = status_word.to_be_bytes();
+    vec![l1, l2 as u8, 0x00, sw[0], sw[1], 0xFF]
+}
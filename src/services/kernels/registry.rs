@@ -0,0 +1,279 @@
+use serde::{Deserialize, Serialize};
+
+use super::{
+    C2Factory, C3Config, C4Config, C5Config, C6Config, C7Config, ContactlessKernel, KernelId,
+};
+use crate::models::emv::{Tlv, TransactionType};
+use crate::services::emv_processor::EmvProcessor;
+
+/// Entry Point Configuration Data for one combination (EMV Book B Table 5-2)
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EntryPointConfig {
+    pub status_check_supported: bool,
+    pub zero_amount_allowed: bool,
+    pub extended_selection_supported: bool,
+    /// Reader Contactless Transaction Limit, overriding the kernel default
+    pub transaction_limit: Option<u64>,
+    /// Reader Contactless Floor Limit, overriding the kernel default
+    pub floor_limit: Option<u64>,
+    /// Reader CVM Required Limit, overriding the kernel default
+    pub cvm_required_limit: Option<u64>,
+    /// Terminal Transaction Qualifiers (tag 0x9F66) pre-set by Entry Point
+    /// for C-3, C-6 and C-7 combinations, overriding the kernel's own
+    pub ttq: Option<[u8; 4]>,
+}
+
+/// A supported {AID, Kernel ID, transaction types} combination
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Combination {
+    /// AID, matched against the start of the card's ADF Name
+    pub aid: Vec<u8>,
+    pub kernel_id: KernelId,
+    /// Transaction types this combination applies to; empty means all
+    #[serde(default)]
+    pub transaction_types: Vec<TransactionType>,
+    #[serde(default)]
+    pub config: EntryPointConfig,
+}
+
+impl Combination {
+    pub fn new(aid: &[u8], kernel_id: KernelId) -> Self {
+        Self {
+            aid: aid.to_vec(),
+            kernel_id,
+            transaction_types: Vec::new(),
+            config: EntryPointConfig::default(),
+        }
+    }
+
+    pub fn with_transaction_types(mut self, transaction_types: Vec<TransactionType>) -> Self {
+        self.transaction_types = transaction_types;
+        self
+    }
+
+    pub fn with_config(mut self, config: EntryPointConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub(crate) fn matches(
+        &self,
+        adf_name: &[u8],
+        kernel_id: KernelId,
+        transaction_type: TransactionType,
+    ) -> bool {
+        adf_name.starts_with(&self.aid)
+            && self.kernel_id == kernel_id
+            && (self.transaction_types.is_empty()
+                || self.transaction_types.contains(&transaction_type))
+    }
+}
+
+/// Creates kernel sessions for one Kernel ID
+pub trait KernelFactory: Send + Sync {
+    fn kernel_id(&self) -> KernelId;
+
+    /// New kernel session with the combination's limits applied
+    fn create(
+        &self,
+        processor: EmvProcessor,
+        combination: &Combination,
+        terminal_data: Vec<Tlv>,
+    ) -> Box<dyn ContactlessKernel>;
+}
+
+/// Kernels and Entry Point combinations supported by this reader
+pub struct KernelRegistry {
+    kernels: Vec<Box<dyn KernelFactory>>,
+    combinations: Vec<Combination>,
+}
+
+impl Default for KernelRegistry {
+    /// Kernels C-2 to C-7 and the international brands' main AIDs
+    fn default() -> Self {
+        let combinations = vec![
+            // Mastercard, Maestro
+            Combination::new(
+                &[0xA0, 0x00, 0x00, 0x00, 0x04, 0x10, 0x10],
+                KernelId::Mastercard,
+            ),
+            Combination::new(
+                &[0xA0, 0x00, 0x00, 0x00, 0x04, 0x30, 0x60],
+                KernelId::Mastercard,
+            ),
+            // Visa credit/debit, Visa Electron
+            Combination::new(&[0xA0, 0x00, 0x00, 0x00, 0x03, 0x10, 0x10], KernelId::Visa),
+            Combination::new(&[0xA0, 0x00, 0x00, 0x00, 0x03, 0x20, 0x10], KernelId::Visa),
+            Combination::new(
+                &[0xA0, 0x00, 0x00, 0x00, 0x25, 0x01],
+                KernelId::AmericanExpress,
+            ),
+            Combination::new(&[0xA0, 0x00, 0x00, 0x00, 0x65, 0x10, 0x10], KernelId::Jcb),
+            Combination::new(
+                &[0xA0, 0x00, 0x00, 0x01, 0x52, 0x30, 0x10],
+                KernelId::Discover,
+            ),
+            // UnionPay debit (..01), credit (..02) and quasi-credit (..03)
+            Combination::new(
+                &[0xA0, 0x00, 0x00, 0x03, 0x33, 0x01, 0x01],
+                KernelId::UnionPay,
+            ),
+        ];
+
+        Self {
+            kernels: vec![
+                Box::new(C2Factory::default()),
+                Box::new(C3Config::default()),
+                Box::new(C4Config::default()),
+                Box::new(C5Config::default()),
+                Box::new(C6Config::default()),
+                Box::new(C7Config::default()),
+            ],
+            combinations,
+        }
+    }
+}
+
+impl KernelRegistry {
+    /// Registry without kernels or combinations
+    pub fn empty() -> Self {
+        Self {
+            kernels: Vec::new(),
+            combinations: Vec::new(),
+        }
+    }
+
+    /// Register a kernel; it replaces any kernel with the same Kernel ID
+    pub fn with_kernel(mut self, kernel: Box<dyn KernelFactory>) -> Self {
+        self.kernels.retain(|k| k.kernel_id() != kernel.kernel_id());
+        self.kernels.push(kernel);
+        self
+    }
+
+    /// Add a combination; it takes precedence over existing ones
+    pub fn with_combination(mut self, combination: Combination) -> Self {
+        self.combinations.insert(0, combination);
+        self
+    }
+
+    pub fn combinations(&self) -> &[Combination] {
+        &self.combinations
+    }
+
+    /// Combination for a card application, from its ADF Name and the
+    /// Kernel Identifier (0x9F2A) of its directory entry
+    pub fn select(
+        &self,
+        adf_name: &[u8],
+        kernel_identifier: Option<&[u8]>,
+        transaction_type: TransactionType,
+    ) -> Option<&Combination> {
+        let kernel_id = KernelId::resolve(adf_name, kernel_identifier)?;
+        self.combinations
+            .iter()
+            .find(|c| c.matches(adf_name, kernel_id, transaction_type))
+    }
+
+    /// New kernel session for a card application
+    pub fn create(
+        &self,
+        processor: EmvProcessor,
+        adf_name: &[u8],
+        kernel_identifier: Option<&[u8]>,
+        transaction_type: TransactionType,
+        terminal_data: Vec<Tlv>,
+    ) -> Result<Box<dyn ContactlessKernel>, String> {
+        let combination = self
+            .select(adf_name, kernel_identifier, transaction_type)
+            .ok_or_else(|| {
+                format!(
+                    "No supported combination for AID {}",
+                    hex::encode_upper(adf_name)
+                )
+            })?;
+        self.create_for(processor, combination, terminal_data)
+    }
+
+    /// New kernel session for an already selected combination
+    pub fn create_for(
+        &self,
+        processor: EmvProcessor,
+        combination: &Combination,
+        terminal_data: Vec<Tlv>,
+    ) -> Result<Box<dyn ContactlessKernel>, String> {
+        let kernel = self
+            .kernels
+            .iter()
+            .find(|k| k.kernel_id() == combination.kernel_id)
+            .ok_or_else(|| format!("Kernel {:?} is not registered", combination.kernel_id))?;
+        Ok(kernel.create(processor, combination, terminal_data))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UNIONPAY_DEBIT: [u8; 8] = [0xA0, 0x00, 0x00, 0x03, 0x33, 0x01, 0x01, 0x01];
+
+    #[test]
+    fn test_kernel_id_resolution() {
+        assert_eq!(
+            KernelId::resolve(&UNIONPAY_DEBIT, None),
+            Some(KernelId::UnionPay)
+        );
+        // The directory entry's Kernel Identifier wins over the RID
+        assert_eq!(
+            KernelId::resolve(&UNIONPAY_DEBIT, Some(&[0x03])),
+            Some(KernelId::Visa)
+        );
+        assert_eq!(
+            KernelId::resolve(&UNIONPAY_DEBIT, Some(&[0x81, 0x56, 0x01])),
+            Some(KernelId::Other(0x81))
+        );
+        assert_eq!(
+            KernelId::resolve(&[0xA0, 0x00, 0x00, 0x09, 0x99], None),
+            None
+        );
+    }
+
+    #[test]
+    fn test_select_and_create() {
+        let processor = EmvProcessor::new("156".to_string(), "CNY".to_string());
+        let registry = KernelRegistry::default().with_combination(
+            Combination::new(&UNIONPAY_DEBIT[..7], KernelId::UnionPay)
+                .with_transaction_types(vec![TransactionType::Refund]),
+        );
+
+        let refund = registry
+            .select(&UNIONPAY_DEBIT, Some(&[0x07]), TransactionType::Refund)
+            .unwrap();
+        assert_eq!(refund.transaction_types, vec![TransactionType::Refund]);
+        let purchase = registry
+            .select(&UNIONPAY_DEBIT, None, TransactionType::Purchase)
+            .unwrap();
+        assert!(purchase.transaction_types.is_empty());
+
+        let kernel = registry
+            .create(
+                processor.clone(),
+                &UNIONPAY_DEBIT,
+                None,
+                TransactionType::Purchase,
+                Vec::new(),
+            )
+            .unwrap();
+        assert_eq!(kernel.kernel_id(), KernelId::UnionPay);
+
+        // Without the UnionPay combination the card is not supported
+        assert!(KernelRegistry::empty()
+            .create(
+                processor,
+                &UNIONPAY_DEBIT,
+                None,
+                TransactionType::Purchase,
+                Vec::new()
+            )
+            .is_err());
+    }
+}
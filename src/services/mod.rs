@@ -1,9 +1,34 @@
+pub mod action_analysis;
+pub mod card_simulator;
+pub mod completion;
+pub mod cvm;
 pub mod emv_processor;
+pub mod entry_point;
+pub mod field55;
+pub mod iad;
+pub mod iso20022;
+pub mod iso8583;
+pub mod kernels;
+pub mod oda;
+pub mod issuer_script;
+pub mod offline_pin;
+pub mod risk_management;
+pub mod terminal_context;
 
 #[cfg(feature = "server")]
 pub mod backend_client;
 
+pub use action_analysis::TerminalActionAnalysis;
+pub use card_simulator::CardSimulator;
+pub use completion::OnlineCompletion;
+pub use cvm::CvmProcessor;
 pub use emv_processor::EmvProcessor;
+pub use entry_point::EntryPoint;
+pub use field55::Field55Spec;
+pub use iad::{IadDecoder, IadDecoders};
+pub use kernels::{C2Kernel, C3Kernel, C7Kernel, KernelRegistry};
+pub use risk_management::TerminalRiskManager;
+pub use terminal_context::TerminalContext;
 
 #[cfg(feature = "server")]
 pub use backend_client::BackendClient;
@@ -0,0 +1,468 @@
+use serde::{Deserialize, Serialize};
+
+use crate::models::card_date::CardDate;
+use crate::models::emv::Tlv;
+use crate::utils::crypto::{rsa_public, sha1, RsaPublicKey};
+
+/// Certification Authority Public Key, selected by RID and index (tag 0x8F)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CaPublicKey {
+    /// Registered Application Provider Identifier (first 5 bytes of the AID)
+    pub rid: Vec<u8>,
+    pub index: u8,
+    pub key: RsaPublicKey,
+}
+
+/// Recover and check the Issuer Public Key (EMV Book 2 Section 5.3 / 6.3)
+pub fn recover_issuer_public_key(
+    ca_key: &RsaPublicKey,
+    certificate: &[u8],
+    remainder: &[u8],
+    exponent: &[u8],
+    pan: &[u8],
+    transaction_date: Option<&[u8]>,
+) -> Result<RsaPublicKey, String> {
+    let recovered = recover(ca_key, certificate, 0x02)?;
+    if recovered.len() < 36 {
+        return Err("Issuer Public Key Certificate too short".to_string());
+    }
+
+    // Issuer Identifier: leftmost 3-8 PAN digits, padded with 'F'
+    let issuer_id = hex::encode_upper(&recovered[2..6]);
+    if !hex::encode_upper(pan).starts_with(issuer_id.trim_end_matches('F')) {
+        return Err("Issuer Identifier does not match the PAN".to_string());
+    }
+    check_expiry(
+        &recovered[6..8],
+        transaction_date,
+        "Issuer Public Key Certificate",
+    )?;
+
+    let modulus = key_modulus(&recovered, 15, 36, remainder)?;
+    let mut hashed = recovered[1..recovered.len() - 21].to_vec();
+    hashed.extend_from_slice(remainder);
+    hashed.extend_from_slice(exponent);
+    check_hash(&recovered, &hashed, "Issuer Public Key Certificate")?;
+
+    Ok(RsaPublicKey {
+        modulus,
+        exponent: exponent.to_vec(),
+    })
+}
+
+/// Recover and check the ICC Public Key (EMV Book 2 Section 6.4)
+///
+/// `static_data` is the Static Data to be Authenticated: the records flagged
+/// in the AFL followed by the data listed in the SDA Tag List (0x9F4A).
+pub fn recover_icc_public_key(
+    issuer_key: &RsaPublicKey,
+    certificate: &[u8],
+    remainder: &[u8],
+    exponent: &[u8],
+    pan: &[u8],
+    static_data: &[u8],
+    transaction_date: Option<&[u8]>,
+) -> Result<RsaPublicKey, String> {
+    let recovered = recover(issuer_key, certificate, 0x04)?;
+    if recovered.len() < 42 {
+        return Err("ICC Public Key Certificate too short".to_string());
+    }
+
+    let certified_pan = hex::encode_upper(&recovered[2..12]);
+    if certified_pan.trim_end_matches('F') != hex::encode_upper(pan).trim_end_matches('F') {
+        return Err("ICC Public Key Certificate PAN does not match".to_string());
+    }
+    check_expiry(
+        &recovered[12..14],
+        transaction_date,
+        "ICC Public Key Certificate",
+    )?;
+
+    let modulus = key_modulus(&recovered, 21, 42, remainder)?;
+    let mut hashed = recovered[1..recovered.len() - 21].to_vec();
+    hashed.extend_from_slice(remainder);
+    hashed.extend_from_slice(exponent);
+    hashed.extend_from_slice(static_data);
+    check_hash(&recovered, &hashed, "ICC Public Key Certificate")?;
+
+    Ok(RsaPublicKey {
+        modulus,
+        exponent: exponent.to_vec(),
+    })
+}
+
+/// Verify Signed Dynamic Application Data (tag 0x9F4B) and return the ICC
+/// Dynamic Data (EMV Book 2 Section 6.5.2)
+///
+/// `dynamic_data_input` is the DDOL-related data the card signed, e.g. for
+/// Visa fDDA: 9F37 | 9F02 | 5F2A | 9F69.
+pub fn verify_signed_dynamic_data(
+    icc_key: &RsaPublicKey,
+    sdad: &[u8],
+    dynamic_data_input: &[u8],
+) -> Result<Vec<u8>, String> {
+    let recovered = recover(icc_key, sdad, 0x05)?;
+    if recovered.len() < 25 || recovered[2] != 0x01 {
+        return Err("Invalid Signed Dynamic Application Data".to_string());
+    }
+
+    let dynamic_len = usize::from(recovered[3]);
+    if 4 + dynamic_len > recovered.len() - 21 {
+        return Err("ICC Dynamic Data length exceeds signature".to_string());
+    }
+
+    let mut hashed = recovered[1..recovered.len() - 21].to_vec();
+    hashed.extend_from_slice(dynamic_data_input);
+    check_hash(&recovered, &hashed, "Signed Dynamic Application Data")?;
+
+    Ok(recovered[4..4 + dynamic_len].to_vec())
+}
+
+/// Verify the CDA signature in a format 2 GENERATE AC response and return
+/// the ICC Dynamic Number (EMV Book 2 Section 6.6.2)
+///
+/// `dol_data` is the PDOL data followed by the CDOL1 data, and by the CDOL2
+/// data for a second GENERATE AC. The signed CID and cryptogram must match
+/// the response, and the Transaction Data Hash must cover `dol_data` and the
+/// response data objects other than 0x9F4B.
+pub fn verify_combined_signature(
+    icc_key: &RsaPublicKey,
+    unpredictable_number: &[u8],
+    dol_data: &[u8],
+    response: &[u8],
+) -> Result<Vec<u8>, String> {
+    let template = Tlv::parse(response)?
+        .into_iter()
+        .find(|tlv| tlv.tag == [0x77])
+        .ok_or("CDA needs a format 2 GENERATE AC response")?;
+    let objects = Tlv::parse(&template.value)?;
+    let find = |tag: &[u8]| Tlv::find_by_tag(&objects, tag).map(|tlv| tlv.value.as_slice());
+
+    let sdad = find(&[0x9F, 0x4B]).ok_or("Signed Dynamic Application Data not found")?;
+    let unsigned: Vec<Tlv> = objects
+        .iter()
+        .filter(|tlv| tlv.tag != [0x9F, 0x4B])
+        .cloned()
+        .collect();
+    let hash = sha1(&[dol_data, &Tlv::encode(&unsigned)].concat());
+
+    let icc_dynamic_data = verify_signed_dynamic_data(icc_key, sdad, unpredictable_number)?;
+    let (&number_len, rest) = icc_dynamic_data
+        .split_first()
+        .ok_or("Empty ICC Dynamic Data")?;
+    let number_len = usize::from(number_len);
+    // ICC Dynamic Number | CID | Application Cryptogram | Transaction Data Hash
+    let signed = rest
+        .get(number_len..number_len + 29)
+        .ok_or("ICC Dynamic Data too short for CDA")?;
+    if find(&[0x9F, 0x27]) != Some(&signed[..1]) {
+        return Err("Cryptogram Information Data does not match the signature".to_string());
+    }
+    if find(&[0x9F, 0x26]).is_some_and(|cryptogram| cryptogram != &signed[1..9]) {
+        return Err("Application Cryptogram does not match the signature".to_string());
+    }
+    if signed[9..] != hash {
+        return Err("Transaction Data Hash mismatch".to_string());
+    }
+    Ok(rest[..number_len].to_vec())
+}
+
+/// Recover the ICC Public Key from card data: CA key index (0x8F), issuer
+/// certificate (0x90, 0x92, 0x9F32) and ICC certificate (0x9F46-0x9F48)
+pub fn icc_public_key(
+    data: &[Tlv],
+    ca_keys: &[CaPublicKey],
+    static_data: &[u8],
+) -> Result<RsaPublicKey, String> {
+    let find = |tag: &[u8]| Tlv::find_by_tag(data, tag).map(|tlv| tlv.value.as_slice());
+    let required = |tag: &[u8]| {
+        find(tag).ok_or_else(|| format!("Missing data object {}", hex::encode_upper(tag)))
+    };
+
+    let aid = find(&[0x4F])
+        .or_else(|| find(&[0x84]))
+        .ok_or("Missing Application Identifier")?;
+    let index = required(&[0x8F])?
+        .first()
+        .copied()
+        .ok_or("Empty CA Public Key Index")?;
+    let ca_key = ca_keys
+        .iter()
+        .find(|key| aid.starts_with(&key.rid) && key.index == index)
+        .ok_or_else(|| format!("CA Public Key {:02X} not found", index))?;
+
+    let pan = required(&[0x5A])?;
+    let date = find(&[0x9A]);
+    let issuer_key = recover_issuer_public_key(
+        &ca_key.key,
+        required(&[0x90])?,
+        find(&[0x92]).unwrap_or_default(),
+        required(&[0x9F, 0x32])?,
+        pan,
+        date,
+    )?;
+
+    recover_icc_public_key(
+        &issuer_key,
+        required(&[0x9F, 0x46])?,
+        find(&[0x9F, 0x48]).unwrap_or_default(),
+        required(&[0x9F, 0x47])?,
+        pan,
+        static_data,
+        date,
+    )
+}
+
+/// RSA recovery with header (0x6A), format and trailer (0xBC) checks
+fn recover(key: &RsaPublicKey, data: &[u8], format: u8) -> Result<Vec<u8>, String> {
+    if data.len() != key.modulus_len() {
+        return Err(format!(
+            "Signed data length {} does not match key length {}",
+            data.len(),
+            key.modulus_len()
+        ));
+    }
+
+    let recovered = rsa_public(data, key)?;
+    if recovered.first() != Some(&0x6A)
+        || recovered.last() != Some(&0xBC)
+        || recovered.get(1) != Some(&format)
+    {
+        return Err(format!("Invalid recovered data format {:02X}", format));
+    }
+    Ok(recovered)
+}
+
+/// Public key modulus from a recovered certificate: the leftmost digits
+/// follow the fixed header, the rest is in the remainder
+fn key_modulus(
+    recovered: &[u8],
+    header_len: usize,
+    overhead: usize,
+    remainder: &[u8],
+) -> Result<Vec<u8>, String> {
+    // Hash algorithm and public key algorithm must both be 01
+    if recovered[header_len - 4] != 0x01 || recovered[header_len - 3] != 0x01 {
+        return Err("Unsupported certificate algorithm".to_string());
+    }
+
+    let key_len = usize::from(recovered[header_len - 2]);
+    let available = recovered.len() - overhead;
+    let mut modulus = recovered[header_len..header_len + available.min(key_len)].to_vec();
+    if key_len > available {
+        if remainder.len() != key_len - available {
+            return Err("Public key remainder length mismatch".to_string());
+        }
+        modulus.extend_from_slice(remainder);
+    }
+    Ok(modulus)
+}
+
+fn check_hash(recovered: &[u8], hashed: &[u8], what: &str) -> Result<(), String> {
+    let end = recovered.len() - 1;
+    if recovered[end - 20..end] != sha1(hashed) {
+        return Err(format!("{} hash mismatch", what));
+    }
+    Ok(())
+}
+
+/// Certificate expiration date (MMYY) must not precede the transaction date (YYMMDD)
+fn check_expiry(expiry: &[u8], transaction_date: Option<&[u8]>, what: &str) -> Result<(), String> {
+    let date = match transaction_date {
+        Some(date) => CardDate::from_bcd(date)?,
+        None => return Ok(()),
+    };
+    let expiry = CardDate::from_digits(&hex::encode([expiry[1], expiry[0]]))
+        .map_err(|_| format!("{} has an invalid expiration date", what))?;
+    if expiry.is_expired_on(&date) {
+        return Err(format!("{} expired", what));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Test keys (e = 3) with their private exponents, for simulated cards
+    pub(crate) const CA_MODULUS: &str = "D13DAA1EF5571672217980B6BC92E4247029D435AE5E0B19352F3B386B92C38188C755583535477396744857A0A77C7CC1C40B1DBB9A4C145A0B204B8CB7FBB8964ABA86FB6D3A5F3542915969013E357637EEF5019D516BBB1C518D08258E53";
+    pub(crate) const CA_PRIVATE: &str = "8B7E7169F8E4B9A16BA655CF2861ED6DA01BE2CE743EB210CE1F7CD047B72D0105DA38E578CE2FA2644D858FC06FA851F73A84678C91D35EDC8BC6B5DEC2186C76BFB3F4DF1FDB14826ACAB403D664E0E18C9BE3ACD37679F35632B1A74F5ACB";
+    pub(crate) const ISSUER_MODULUS: &str = "9706A91D126DFA1099AB8CB862EBD2DD3C5BA5F4D8D26DA2D8008EE40C5D35E864B9925E9A17EDD2D319EA27BF3B593CCC8C120CDD090E1FD5DEB913546584A80BE8330192656115F03D0232A9BC4619";
+    pub(crate) const ISSUER_PRIVATE: &str = "64AF1B68B6F3FC0B111D087AEC9D373E283D194DE5E19E6C90005F42B2E8CE9AEDD10C3F116549363139F3E53B0784D6C41BE2E33D02BC1EDEDC99C031311920D3C29FD687631841658A4720391BB20B";
+    pub(crate) const ICC_MODULUS: &str = "BAA48F46637262ACE805EBB8A9AB9D1F37D92EF5D7B61C3E96202D08B150D1644CA97AEF904A0C2870E266A6EB93500077B90F6F0CD283E8D487688013AF7B21";
+    pub(crate) const ICC_PRIVATE: &str = "7C6DB4D997A1971DF003F27B1BC7BE14CFE61F4E8FCEBD7F0EC01E05CB8B3641B8141BE1573E87A0DDE30D3B4A47E666031E978F8C68615C9D89148A2FB9DA63";
+
+    pub(crate) fn public_key(modulus: &str) -> RsaPublicKey {
+        RsaPublicKey {
+            modulus: hex::decode(modulus).unwrap(),
+            exponent: vec![0x03],
+        }
+    }
+
+    /// Sign `body` (everything between header and hash) with a private key
+    pub(crate) fn sign(modulus: &str, private: &str, body: &[u8], hash_extra: &[u8]) -> Vec<u8> {
+        let hash = sha1(&[body, hash_extra].concat());
+        let plain = [&[0x6A][..], body, &hash, &[0xBC]].concat();
+        let key = RsaPublicKey {
+            modulus: hex::decode(modulus).unwrap(),
+            exponent: hex::decode(private).unwrap(),
+        };
+        rsa_public(&plain, &key).unwrap()
+    }
+
+    /// Issuer certificate (0x90) and remainder (0x92) for a PAN starting 4761
+    pub(crate) fn issuer_certificate() -> (Vec<u8>, Vec<u8>) {
+        let modulus = hex::decode(ISSUER_MODULUS).unwrap();
+        let split = 96 - 36;
+        let mut body = vec![0x02, 0x47, 0x61, 0xFF, 0xFF, 0x12, 0x30, 0x00, 0x00, 0x01];
+        body.extend_from_slice(&[0x01, 0x01, modulus.len() as u8, 0x01]);
+        body.extend_from_slice(&modulus[..split]);
+        let remainder = modulus[split..].to_vec();
+        let certificate = sign(
+            CA_MODULUS,
+            CA_PRIVATE,
+            &body,
+            &[&remainder[..], &[0x03]].concat(),
+        );
+        (certificate, remainder)
+    }
+
+    /// ICC certificate (0x9F46) and remainder (0x9F48) over `static_data`
+    pub(crate) fn icc_certificate(pan: &[u8], static_data: &[u8]) -> (Vec<u8>, Vec<u8>) {
+        let modulus = hex::decode(ICC_MODULUS).unwrap();
+        let split = 80 - 42;
+        let mut padded_pan = pan.to_vec();
+        padded_pan.resize(10, 0xFF);
+        let mut body = vec![0x04];
+        body.extend_from_slice(&padded_pan);
+        body.extend_from_slice(&[0x12, 0x30, 0x00, 0x00, 0x01, 0x01, 0x01]);
+        body.extend_from_slice(&[modulus.len() as u8, 0x01]);
+        body.extend_from_slice(&modulus[..split]);
+        let remainder = modulus[split..].to_vec();
+        let hashed = [&remainder[..], &[0x03], static_data].concat();
+        let certificate = sign(ISSUER_MODULUS, ISSUER_PRIVATE, &body, &hashed);
+        (certificate, remainder)
+    }
+
+    /// Signed Dynamic Application Data over `dynamic_data_input`
+    pub(crate) fn sign_dynamic_data(
+        icc_dynamic_number: &[u8],
+        dynamic_data_input: &[u8],
+    ) -> Vec<u8> {
+        sign_icc_dynamic_data(icc_dynamic_number, &[], dynamic_data_input)
+    }
+
+    /// CDA signature for a format 2 GENERATE AC response without 0x9F4B
+    pub(crate) fn sign_combined(
+        icc_dynamic_number: &[u8],
+        unpredictable_number: &[u8],
+        dol_data: &[u8],
+        response: &[u8],
+    ) -> Vec<u8> {
+        let objects = Tlv::parse(&Tlv::parse(response).unwrap()[0].value).unwrap();
+        let find = |tag: &[u8]| Tlv::find_by_tag(&objects, tag).unwrap().value.clone();
+        let hash = sha1(&[dol_data, &Tlv::encode(&objects)].concat());
+        let signed = [find(&[0x9F, 0x27]), find(&[0x9F, 0x26]), hash.to_vec()].concat();
+        sign_icc_dynamic_data(icc_dynamic_number, &signed, unpredictable_number)
+    }
+
+    fn sign_icc_dynamic_data(
+        icc_dynamic_number: &[u8],
+        extra: &[u8],
+        dynamic_data_input: &[u8],
+    ) -> Vec<u8> {
+        let mut icc_dynamic_data = vec![icc_dynamic_number.len() as u8];
+        icc_dynamic_data.extend_from_slice(icc_dynamic_number);
+        icc_dynamic_data.extend_from_slice(extra);
+        let mut body = vec![0x05, 0x01, icc_dynamic_data.len() as u8];
+        body.extend_from_slice(&icc_dynamic_data);
+        body.resize(64 - 22, 0xBB);
+        sign(ICC_MODULUS, ICC_PRIVATE, &body, dynamic_data_input)
+    }
+
+    #[test]
+    fn test_certificate_chain_and_sdad() {
+        let pan = [0x47, 0x61, 0x73, 0x90, 0x01, 0x01, 0x01, 0x19];
+        let (issuer_cert, issuer_remainder) = issuer_certificate();
+        let issuer_key = recover_issuer_public_key(
+            &public_key(CA_MODULUS),
+            &issuer_cert,
+            &issuer_remainder,
+            &[0x03],
+            &pan,
+            Some(&[0x26, 0x10, 0x18]),
+        )
+        .unwrap();
+        assert_eq!(issuer_key, public_key(ISSUER_MODULUS));
+
+        let static_data = [0x5A, 0x08, 0x47, 0x61];
+        let (icc_cert, icc_remainder) = icc_certificate(&pan, &static_data);
+        let icc_key = recover_icc_public_key(
+            &issuer_key,
+            &icc_cert,
+            &icc_remainder,
+            &[0x03],
+            &pan,
+            &static_data,
+            None,
+        )
+        .unwrap();
+        assert_eq!(icc_key, public_key(ICC_MODULUS));
+
+        // Tampered static data breaks the ICC certificate hash
+        assert!(recover_icc_public_key(
+            &issuer_key,
+            &icc_cert,
+            &icc_remainder,
+            &[0x03],
+            &pan,
+            &[0x00],
+            None,
+        )
+        .is_err());
+
+        let sdad = sign_dynamic_data(&[0x01, 0x02, 0x03, 0x04], b"input");
+        assert_eq!(
+            verify_signed_dynamic_data(&icc_key, &sdad, b"input").unwrap(),
+            vec![0x04, 0x01, 0x02, 0x03, 0x04]
+        );
+        assert!(verify_signed_dynamic_data(&icc_key, &sdad, b"other").is_err());
+
+        // CDA: the signature covers the CID, cryptogram and response hash
+        let response = hex::decode("77149F2701809F360200079F26081122334455667788").unwrap();
+        let un = [0xCA, 0xFE, 0xBA, 0xBE];
+        let sdad = sign_combined(&[0x00, 0x07], &un, b"dol", &response);
+        let signed = [
+            &response[..1],
+            &[response[1] + 3 + sdad.len() as u8][..],
+            &response[2..],
+            &[0x9F, 0x4B, sdad.len() as u8],
+            &sdad,
+        ]
+        .concat();
+        assert_eq!(
+            verify_combined_signature(&icc_key, &un, b"dol", &signed).unwrap(),
+            vec![0x00, 0x07]
+        );
+        assert!(verify_combined_signature(&icc_key, &un, b"other", &signed)
+            .unwrap_err()
+            .contains("Hash"));
+        let mut wrong_cid = signed.clone();
+        wrong_cid[5] = 0x40;
+        assert!(verify_combined_signature(&icc_key, &un, b"dol", &wrong_cid).is_err());
+    }
+
+    #[test]
+    fn test_expired_issuer_certificate() {
+        let (issuer_cert, issuer_remainder) = issuer_certificate();
+        let result = recover_issuer_public_key(
+            &public_key(CA_MODULUS),
+            &issuer_cert,
+            &issuer_remainder,
+            &[0x03],
+            &[0x47, 0x61, 0x73, 0x90],
+            Some(&[0x31, 0x01, 0x01]),
+        );
+        assert!(result.unwrap_err().contains("expired"));
+    }
+}
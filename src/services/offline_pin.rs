@@ -0,0 +1,315 @@
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
+
+use crate::models::emv::{ApduCommand, ApduResponse, Tlv};
+use crate::models::secret::SecretBytes;
+use crate::services::cvm::CvmAttempt;
+use crate::services::emv_processor::EmvProcessor;
+use crate::utils::crypto::{rsa_public, RsaPublicKey};
+
+/// PIN Try Counter data object
+pub const TAG_PIN_TRY_COUNTER: u16 = 0x9F17;
+
+/// Build an ISO 9564 format 2 PIN block (`2N PPPP...F`) for offline verification
+pub fn build_pin_block(pin: &str) -> Result<SecretBytes, String> {
+    if !(4..=12).contains(&pin.len()) || !pin.bytes().all(|b| b.is_ascii_digit()) {
+        return Err("PIN must be 4 to 12 digits".to_string());
+    }
+
+    let mut nibbles = Zeroizing::new(vec![0x2, pin.len() as u8]);
+    nibbles.extend(pin.bytes().map(|b| b - b'0'));
+    nibbles.resize(16, 0xF);
+
+    let block = nibbles
+        .chunks(2)
+        .map(|pair| (pair[0] << 4) | pair[1])
+        .collect();
+    Ok(SecretBytes::new(block))
+}
+
+/// Encipher a PIN block for VERIFY P2=88 (EMV Book 2 Section 7.2)
+///
+/// Data enciphered: `7F` | PIN block | ICC challenge | random padding,
+/// sized to the length of the key modulus.
+pub fn encipher_pin_block(
+    pin_block: &SecretBytes,
+    challenge: &[u8],
+    key: &RsaPublicKey,
+) -> Result<Vec<u8>, String> {
+    if challenge.len() != 8 {
+        return Err(format!("Invalid ICC challenge length: {}", challenge.len()));
+    }
+
+    let modulus_len = key.modulus_len();
+    if modulus_len < 17 {
+        return Err("PIN encipherment key is too short".to_string());
+    }
+
+    let mut padding = vec![0u8; modulus_len - 17];
+    getrandom::getrandom(&mut padding).map_err(|e| format!("RNG failure: {}", e))?;
+
+    let mut data = Zeroizing::new(Vec::with_capacity(modulus_len));
+    data.push(0x7F);
+    data.extend_from_slice(pin_block.expose());
+    data.extend_from_slice(challenge);
+    data.extend_from_slice(&padding);
+
+    rsa_public(&data, key)
+}
+
+/// Parse the PIN Try Counter from a GET DATA response (`9F17 01 xx` or bare value)
+pub fn parse_pin_try_counter(data: &[u8]) -> Result<u8, String> {
+    let tlvs = Tlv::parse(data)?;
+    match Tlv::find_by_tag(&tlvs, &[0x9F, 0x17]) {
+        Some(tlv) => tlv
+            .value
+            .first()
+            .copied()
+            .ok_or_else(|| "Empty PIN Try Counter".to_string()),
+        None if data.len() == 1 => Ok(data[0]),
+        None => Err("PIN Try Counter not found".to_string()),
+    }
+}
+
+/// Card answer to VERIFY
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PinVerifyResult {
+    /// 9000: PIN correct
+    Verified,
+    /// 63Cx: PIN wrong, x tries remaining
+    Incorrect { tries_remaining: u8 },
+    /// 6983: authentication method blocked
+    Blocked,
+    /// 6984: reference data invalidated
+    ReferenceDataInvalidated,
+    /// Any other status word
+    Error(u16),
+}
+
+impl PinVerifyResult {
+    pub fn from_response(response: &ApduResponse) -> Self {
+        match (response.sw1, response.sw2) {
+            (0x90, 0x00) => PinVerifyResult::Verified,
+            (0x63, sw2) if sw2 & 0xF0 == 0xC0 => PinVerifyResult::Incorrect {
+                tries_remaining: sw2 & 0x0F,
+            },
+            (0x69, 0x83) => PinVerifyResult::Blocked,
+            (0x69, 0x84) => PinVerifyResult::ReferenceDataInvalidated,
+            _ => PinVerifyResult::Error(response.status_word()),
+        }
+    }
+
+    /// Whether no further PIN attempts are possible
+    pub fn is_blocked(&self) -> bool {
+        matches!(
+            self,
+            PinVerifyResult::Blocked
+                | PinVerifyResult::ReferenceDataInvalidated
+                | PinVerifyResult::Incorrect { tries_remaining: 0 }
+        )
+    }
+}
+
+/// Next action requested by the offline PIN flow
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "step", content = "value")]
+pub enum OfflinePinStep {
+    /// Send this command to the card and pass the response to `on_response`
+    Send(ApduCommand),
+    /// Prompt the cardholder for the PIN (tries remaining, if known)
+    RequestPin(Option<u8>),
+    /// Flow finished; report to CVM processing
+    Complete(CvmAttempt),
+}
+
+#[derive(Debug, Clone)]
+enum State {
+    AwaitingTryCounter,
+    AwaitingPin,
+    AwaitingChallenge(SecretBytes),
+    AwaitingVerify,
+    Done,
+}
+
+/// Offline PIN verification: PIN Try Counter check, PIN entry, optional
+/// GET CHALLENGE and VERIFY, with retries while the card allows them
+#[derive(Debug, Clone)]
+pub struct OfflinePinFlow {
+    processor: EmvProcessor,
+    encipherment_key: Option<RsaPublicKey>,
+    state: State,
+}
+
+impl OfflinePinFlow {
+    /// Plaintext PIN flow (CVM 01/03)
+    pub fn plaintext(processor: EmvProcessor) -> Self {
+        Self {
+            processor,
+            encipherment_key: None,
+            state: State::AwaitingTryCounter,
+        }
+    }
+
+    /// Enciphered PIN flow (CVM 04/05) using the ICC PIN encipherment or ICC public key
+    pub fn enciphered(processor: EmvProcessor, key: RsaPublicKey) -> Self {
+        Self {
+            processor,
+            encipherment_key: Some(key),
+            state: State::AwaitingTryCounter,
+        }
+    }
+
+    /// Begin by reading the PIN Try Counter
+    pub fn start(&mut self) -> OfflinePinStep {
+        self.state = State::AwaitingTryCounter;
+        OfflinePinStep::Send(self.processor.get_data(TAG_PIN_TRY_COUNTER))
+    }
+
+    /// Feed the card response to the last command sent
+    pub fn on_response(&mut self, response: &ApduResponse) -> Result<OfflinePinStep, String> {
+        match std::mem::replace(&mut self.state, State::Done) {
+            State::AwaitingTryCounter => {
+                // The counter is optional; without it the cardholder is simply prompted
+                let tries = if response.is_success() {
+                    Some(parse_pin_try_counter(&response.data)?)
+                } else {
+                    None
+                };
+
+                if tries == Some(0) {
+                    return Ok(OfflinePinStep::Complete(CvmAttempt::PinTryLimitExceeded));
+                }
+
+                self.state = State::AwaitingPin;
+                Ok(OfflinePinStep::RequestPin(tries))
+            }
+            State::AwaitingChallenge(pin_block) => {
+                if !response.is_success() || response.data.len() != 8 {
+                    return Ok(OfflinePinStep::Complete(CvmAttempt::Failed));
+                }
+
+                let key = self
+                    .encipherment_key
+                    .as_ref()
+                    .ok_or("No PIN encipherment key")?;
+                let data = encipher_pin_block(&pin_block, &response.data, key)?;
+
+                self.state = State::AwaitingVerify;
+                Ok(OfflinePinStep::Send(
+                    ApduCommand::new(0x00, 0x20, 0x00, 0x88).with_data(data),
+                ))
+            }
+            State::AwaitingVerify => {
+                let result = PinVerifyResult::from_response(response);
+                Ok(match result {
+                    PinVerifyResult::Verified => OfflinePinStep::Complete(CvmAttempt::Successful),
+                    _ if result.is_blocked() => {
+                        OfflinePinStep::Complete(CvmAttempt::PinTryLimitExceeded)
+                    }
+                    PinVerifyResult::Incorrect { tries_remaining } => {
+                        self.state = State::AwaitingPin;
+                        OfflinePinStep::RequestPin(Some(tries_remaining))
+                    }
+                    _ => OfflinePinStep::Complete(CvmAttempt::Failed),
+                })
+            }
+            state => {
+                self.state = state;
+                Err("No card response expected".to_string())
+            }
+        }
+    }
+
+    /// Submit the PIN entered by the cardholder
+    pub fn submit_pin(&mut self, pin: &str) -> Result<OfflinePinStep, String> {
+        if !matches!(self.state, State::AwaitingPin) {
+            return Err("PIN entry not expected".to_string());
+        }
+
+        if self.encipherment_key.is_some() {
+            self.state = State::AwaitingChallenge(build_pin_block(pin)?);
+            Ok(OfflinePinStep::Send(self.processor.get_challenge()))
+        } else {
+            let command = self.processor.verify_plaintext_pin(pin)?;
+            self.state = State::AwaitingVerify;
+            Ok(OfflinePinStep::Send(command))
+        }
+    }
+
+    /// Cardholder declined to enter the PIN
+    pub fn bypass(&mut self) -> OfflinePinStep {
+        self.state = State::Done;
+        OfflinePinStep::Complete(CvmAttempt::PinNotEntered)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn processor() -> EmvProcessor {
+        EmvProcessor::new("156".to_string(), "CNY".to_string())
+    }
+
+    fn response(bytes: &[u8]) -> ApduResponse {
+        ApduResponse::from_bytes(bytes).unwrap()
+    }
+
+    #[test]
+    fn test_build_pin_block() {
+        assert_eq!(
+            hex::encode(build_pin_block("1234").unwrap().expose()),
+            "241234ffffffffff"
+        );
+        assert!(build_pin_block("12a4").is_err());
+        assert!(build_pin_block("123").is_err());
+    }
+
+    #[test]
+    fn test_verify_result_decoding() {
+        assert_eq!(
+            PinVerifyResult::from_response(&response(&[0x63, 0xC2])),
+            PinVerifyResult::Incorrect { tries_remaining: 2 }
+        );
+        assert!(PinVerifyResult::from_response(&response(&[0x69, 0x83])).is_blocked());
+        assert!(PinVerifyResult::from_response(&response(&[0x63, 0xC0])).is_blocked());
+    }
+
+    #[test]
+    fn test_plaintext_flow_with_retry() {
+        let mut flow = OfflinePinFlow::plaintext(processor());
+        assert!(matches!(flow.start(), OfflinePinStep::Send(ref c) if c.ins == 0xCA));
+
+        let step = flow
+            .on_response(&response(&[0x9F, 0x17, 0x01, 0x03, 0x90, 0x00]))
+            .unwrap();
+        assert!(matches!(step, OfflinePinStep::RequestPin(Some(3))));
+
+        let step = flow.submit_pin("1111").unwrap();
+        assert!(matches!(step, OfflinePinStep::Send(ref c) if c.p2 == 0x80));
+
+        let step = flow.on_response(&response(&[0x63, 0xC2])).unwrap();
+        assert!(matches!(step, OfflinePinStep::RequestPin(Some(2))));
+
+        flow.submit_pin("1234").unwrap();
+        let step = flow.on_response(&response(&[0x90, 0x00])).unwrap();
+        assert!(matches!(
+            step,
+            OfflinePinStep::Complete(CvmAttempt::Successful)
+        ));
+    }
+
+    #[test]
+    fn test_try_counter_exhausted() {
+        let mut flow = OfflinePinFlow::plaintext(processor());
+        flow.start();
+        let step = flow
+            .on_response(&response(&[0x9F, 0x17, 0x01, 0x00, 0x90, 0x00]))
+            .unwrap();
+        assert!(matches!(
+            step,
+            OfflinePinStep::Complete(CvmAttempt::PinTryLimitExceeded)
+        ));
+    }
+}
@@ -0,0 +1,321 @@
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::models::emv::{ApduResponse, Tlv};
+use crate::models::pan::Pan;
+use crate::models::terminal::{RandomSelection, TerminalConfig};
+use crate::models::tvr::{Tsi, Tvr};
+
+/// Application Transaction Counter
+pub const TAG_ATC: u16 = 0x9F36;
+/// Last Online ATC Register
+pub const TAG_LAST_ONLINE_ATC: u16 = 0x9F13;
+
+/// Lookup into the terminal's log of approved transactions, used to
+/// detect split sales against the floor limit
+pub trait SplitSalesLog: Send + Sync {
+    /// Total of recent approved amounts (minor units) for this PAN
+    fn recent_amount(&self, pan: &Pan) -> u64;
+}
+
+/// Terminal without a transaction log
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoSplitSalesLog;
+
+impl SplitSalesLog for NoSplitSalesLog {
+    fn recent_amount(&self, _pan: &Pan) -> u64 {
+        0
+    }
+}
+
+/// Card and transaction data needed by terminal risk management
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RiskManagementData {
+    /// Authorised amount (minor units)
+    pub amount: u64,
+    /// Application PAN, `None` if the card did not return one
+    pub pan: Option<Pan>,
+    /// Lower Consecutive Offline Limit (tag 0x9F14)
+    pub lower_consecutive_offline_limit: Option<u8>,
+    /// Upper Consecutive Offline Limit (tag 0x9F23)
+    pub upper_consecutive_offline_limit: Option<u8>,
+    /// ATC from GET DATA 0x9F36, `None` if it could not be read
+    pub atc: Option<u16>,
+    /// Last Online ATC Register from GET DATA 0x9F13, `None` if it could not be read
+    pub last_online_atc: Option<u16>,
+}
+
+impl RiskManagementData {
+    /// Whether velocity checking applies (both limits present on the card)
+    pub fn velocity_checking_required(&self) -> bool {
+        self.lower_consecutive_offline_limit.is_some()
+            && self.upper_consecutive_offline_limit.is_some()
+    }
+}
+
+/// Result of terminal risk management
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RiskManagementOutcome {
+    pub floor_limit_exceeded: bool,
+    pub randomly_selected: bool,
+    pub lower_limit_exceeded: bool,
+    pub upper_limit_exceeded: bool,
+    pub new_card: bool,
+    /// Terminal Verification Results after risk management
+    pub tvr: Tvr,
+    /// Transaction Status Information after risk management
+    pub tsi: Tsi,
+}
+
+/// Terminal Risk Management (EMV Book 3 Section 10.6)
+#[derive(Clone)]
+pub struct TerminalRiskManager {
+    config: TerminalConfig,
+    log: Arc<dyn SplitSalesLog>,
+}
+
+impl TerminalRiskManager {
+    pub fn new(config: TerminalConfig) -> Self {
+        Self {
+            config,
+            log: Arc::new(NoSplitSalesLog),
+        }
+    }
+
+    /// Use a transaction log for split-sales detection
+    pub fn with_split_sales_log(mut self, log: Arc<dyn SplitSalesLog>) -> Self {
+        self.log = log;
+        self
+    }
+
+    /// Perform risk management, drawing the random selection number from the system RNG
+    pub fn perform(
+        &self,
+        data: &RiskManagementData,
+        tvr: Tvr,
+        tsi: Tsi,
+    ) -> Result<RiskManagementOutcome, String> {
+        let mut byte = [0u8; 1];
+        getrandom::getrandom(&mut byte).map_err(|e| format!("RNG failure: {}", e))?;
+        // Uniform 1..=99 from the low bits; the slight bias is irrelevant here
+        let random = (byte[0] % 99) + 1;
+        Ok(self.perform_with_random(data, random, tvr, tsi))
+    }
+
+    /// Perform risk management with a given random number in 1..=99
+    pub fn perform_with_random(
+        &self,
+        data: &RiskManagementData,
+        random: u8,
+        mut tvr: Tvr,
+        mut tsi: Tsi,
+    ) -> RiskManagementOutcome {
+        let floor_limit_exceeded = self.floor_limit_exceeded(data);
+        if floor_limit_exceeded {
+            tvr.set_floor_limit_exceeded(true);
+        }
+
+        let randomly_selected = !floor_limit_exceeded
+            && random_selection(
+                data.amount,
+                self.config.floor_limit,
+                &self.config.random_selection,
+                random,
+            );
+        if randomly_selected {
+            tvr.set_randomly_selected_online(true);
+        }
+
+        let velocity = if data.velocity_checking_required() {
+            velocity_check(data)
+        } else {
+            VelocityResult::default()
+        };
+        if velocity.lower_limit_exceeded {
+            tvr.set_lower_consecutive_offline_limit_exceeded(true);
+        }
+        if velocity.upper_limit_exceeded {
+            tvr.set_upper_consecutive_offline_limit_exceeded(true);
+        }
+        if velocity.new_card {
+            tvr.set_new_card(true);
+        }
+
+        tsi.set_terminal_risk_management_performed(true);
+
+        RiskManagementOutcome {
+            floor_limit_exceeded,
+            randomly_selected,
+            lower_limit_exceeded: velocity.lower_limit_exceeded,
+            upper_limit_exceeded: velocity.upper_limit_exceeded,
+            new_card: velocity.new_card,
+            tvr,
+            tsi,
+        }
+    }
+
+    /// Floor limit check, including recent approved amounts for the same PAN
+    fn floor_limit_exceeded(&self, data: &RiskManagementData) -> bool {
+        let recent = data
+            .pan
+            .as_ref()
+            .map_or(0, |pan| self.log.recent_amount(pan));
+        let total = data.amount.saturating_add(recent);
+        total >= self.config.floor_limit
+    }
+}
+
+/// Random transaction selection with biased selection above the threshold
+///
+/// `random` is a number in 1..=99. Amounts at or above the floor limit
+/// are never selected here since they already go online.
+pub fn random_selection(
+    amount: u64,
+    floor_limit: u64,
+    params: &RandomSelection,
+    random: u8,
+) -> bool {
+    if amount >= floor_limit {
+        return false;
+    }
+
+    let target = params.target_percentage as u64;
+    if amount < params.threshold {
+        return (random as u64) <= target;
+    }
+
+    let max_target = (params.max_target_percentage as u64).max(target);
+    let span = floor_limit - params.threshold;
+    let interpolated = target + (max_target - target) * (amount - params.threshold) / span;
+    (random as u64) <= interpolated
+}
+
+/// Velocity checking outcome
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct VelocityResult {
+    pub lower_limit_exceeded: bool,
+    pub upper_limit_exceeded: bool,
+    pub new_card: bool,
+}
+
+/// Velocity checking against the consecutive offline limits
+pub fn velocity_check(data: &RiskManagementData) -> VelocityResult {
+    let (atc, last_online) = match (data.atc, data.last_online_atc) {
+        (Some(atc), Some(last_online)) if atc > last_online => (atc, last_online),
+        // Counters unavailable or inconsistent: both limits are treated as exceeded
+        _ => {
+            return VelocityResult {
+                lower_limit_exceeded: true,
+                upper_limit_exceeded: true,
+                new_card: data.last_online_atc == Some(0),
+            }
+        }
+    };
+
+    let offline_count = (atc - last_online) as u64;
+    VelocityResult {
+        lower_limit_exceeded: data
+            .lower_consecutive_offline_limit
+            .is_some_and(|limit| offline_count > limit as u64),
+        upper_limit_exceeded: data
+            .upper_consecutive_offline_limit
+            .is_some_and(|limit| offline_count > limit as u64),
+        new_card: last_online == 0,
+    }
+}
+
+/// Extract a two-byte counter from a GET DATA response
+pub fn parse_counter(response: &ApduResponse, tag: u16) -> Option<u16> {
+    if !response.is_success() {
+        return None;
+    }
+
+    let tlvs = Tlv::parse(&response.data).ok()?;
+    let tlv = Tlv::find_by_tag(&tlvs, &tag.to_be_bytes())?;
+    match tlv.value.as_slice() {
+        [hi, lo] => Some(u16::from_be_bytes([*hi, *lo])),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manager(floor_limit: u64) -> TerminalRiskManager {
+        let config = TerminalConfig {
+            floor_limit,
+            random_selection: RandomSelection {
+                target_percentage: 10,
+                max_target_percentage: 50,
+                threshold: 5000,
+            },
+            ..TerminalConfig::default()
+        };
+        TerminalRiskManager::new(config)
+    }
+
+    struct FixedLog(u64);
+
+    impl SplitSalesLog for FixedLog {
+        fn recent_amount(&self, _pan: &Pan) -> u64 {
+            self.0
+        }
+    }
+
+    #[test]
+    fn test_floor_limit_with_split_sales() {
+        let data = RiskManagementData {
+            amount: 6000,
+            pan: Some(Pan::parse("6200000000000005").unwrap()),
+            ..Default::default()
+        };
+
+        let outcome = manager(10000).perform_with_random(&data, 99, Tvr::default(), Tsi::default());
+        assert!(!outcome.floor_limit_exceeded);
+        assert!(outcome.tsi.terminal_risk_management_performed());
+
+        let logged = manager(10000).with_split_sales_log(Arc::new(FixedLog(5000)));
+        let outcome = logged.perform_with_random(&data, 99, Tvr::default(), Tsi::default());
+        assert!(outcome.floor_limit_exceeded);
+        assert!(outcome.tvr.floor_limit_exceeded());
+    }
+
+    #[test]
+    fn test_biased_random_selection() {
+        let params = RandomSelection {
+            target_percentage: 10,
+            max_target_percentage: 50,
+            threshold: 5000,
+        };
+        assert!(random_selection(1000, 10000, &params, 10));
+        assert!(!random_selection(1000, 10000, &params, 11));
+        // Halfway between threshold and floor limit: 30%
+        assert!(random_selection(7500, 10000, &params, 30));
+        assert!(!random_selection(7500, 10000, &params, 31));
+        assert!(!random_selection(10000, 10000, &params, 1));
+    }
+
+    #[test]
+    fn test_velocity_checking() {
+        let mut data = RiskManagementData {
+            lower_consecutive_offline_limit: Some(2),
+            upper_consecutive_offline_limit: Some(5),
+            atc: Some(0x0010),
+            last_online_atc: Some(0x000D),
+            ..Default::default()
+        };
+        let result = velocity_check(&data);
+        assert!(result.lower_limit_exceeded);
+        assert!(!result.upper_limit_exceeded);
+
+        data.atc = None;
+        let result = velocity_check(&data);
+        assert!(result.lower_limit_exceeded && result.upper_limit_exceeded);
+
+        let response =
+            ApduResponse::from_bytes(&[0x9F, 0x36, 0x02, 0x00, 0x2A, 0x90, 0x00]).unwrap();
+        assert_eq!(parse_counter(&response, TAG_ATC), Some(42));
+    }
+}
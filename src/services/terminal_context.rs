@@ -0,0 +1,303 @@
+use std::path::PathBuf;
+
+use chrono::{DateTime, Datelike, FixedOffset, Local, Timelike};
+
+use crate::models::card_date::CardDate;
+use crate::models::emv::Tlv;
+
+/// Largest Transaction Sequence Counter value (n8)
+pub const MAX_SEQUENCE_COUNTER: u32 = 99_999_999;
+
+/// Source of unpredictable numbers
+pub trait RandomSource: Send {
+    fn fill(&mut self, buf: &mut [u8]) -> Result<(), String>;
+}
+
+/// Source of the transaction date and time, in the terminal's local time
+pub trait Clock: Send {
+    fn now(&self) -> DateTime<FixedOffset>;
+}
+
+/// Where the Transaction Sequence Counter survives restarts
+pub trait SequenceStore: Send {
+    /// Last counter value used, 0 if none
+    fn load(&self) -> Result<u32, String>;
+    fn store(&mut self, counter: u32) -> Result<(), String>;
+}
+
+/// Operating system RNG
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemRandom;
+
+impl RandomSource for SystemRandom {
+    fn fill(&mut self, buf: &mut [u8]) -> Result<(), String> {
+        getrandom::getrandom(buf).map_err(|e| format!("RNG failure: {}", e))
+    }
+}
+
+/// Seeded SplitMix64 generator, for reproducible tests only
+#[derive(Debug, Clone)]
+pub struct SeededRandom {
+    state: u64,
+}
+
+impl SeededRandom {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    fn next(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+}
+
+impl RandomSource for SeededRandom {
+    fn fill(&mut self, buf: &mut [u8]) -> Result<(), String> {
+        for chunk in buf.chunks_mut(8) {
+            let bytes = self.next().to_be_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+        Ok(())
+    }
+}
+
+/// Current local time of the system
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<FixedOffset> {
+        Local::now().fixed_offset()
+    }
+}
+
+/// Clock that always reads the same instant, for tests
+#[derive(Debug, Clone, Copy)]
+pub struct FixedClock(pub DateTime<FixedOffset>);
+
+impl Clock for FixedClock {
+    fn now(&self) -> DateTime<FixedOffset> {
+        self.0
+    }
+}
+
+/// Counter kept in memory only
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MemorySequenceStore(pub u32);
+
+impl SequenceStore for MemorySequenceStore {
+    fn load(&self) -> Result<u32, String> {
+        Ok(self.0)
+    }
+
+    fn store(&mut self, counter: u32) -> Result<(), String> {
+        self.0 = counter;
+        Ok(())
+    }
+}
+
+/// Counter kept as a decimal number in a file
+#[derive(Debug, Clone)]
+pub struct FileSequenceStore {
+    path: PathBuf,
+}
+
+impl FileSequenceStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl SequenceStore for FileSequenceStore {
+    fn load(&self) -> Result<u32, String> {
+        match std::fs::read_to_string(&self.path) {
+            Ok(content) => content.trim().parse().map_err(|_| {
+                format!(
+                    "Invalid sequence counter in {}: {}",
+                    self.path.display(),
+                    content.trim()
+                )
+            }),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(0),
+            Err(e) => Err(format!("Failed to read {}: {}", self.path.display(), e)),
+        }
+    }
+
+    fn store(&mut self, counter: u32) -> Result<(), String> {
+        // Write then rename so a crash never leaves a truncated counter
+        let temporary = self.path.with_extension("tmp");
+        std::fs::write(&temporary, counter.to_string())
+            .and_then(|_| std::fs::rename(&temporary, &self.path))
+            .map_err(|e| format!("Failed to write {}: {}", self.path.display(), e))
+    }
+}
+
+/// Terminal-generated data objects for one transaction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TerminalData {
+    /// Unpredictable Number (tag 0x9F37)
+    pub unpredictable_number: [u8; 4],
+    /// Local date and time, as 0x9A and 0x9F21 are sent
+    pub transacted_at: DateTime<FixedOffset>,
+    /// Transaction Sequence Counter (tag 0x9F41)
+    pub sequence_counter: u32,
+}
+
+impl TerminalData {
+    /// Transaction Date (tag 0x9A)
+    pub fn transaction_date(&self) -> CardDate {
+        CardDate {
+            year: self.transacted_at.year() as u16,
+            month: self.transacted_at.month() as u8,
+            day: self.transacted_at.day() as u8,
+        }
+    }
+
+    /// 0x9F37, 0x9A, Transaction Time (0x9F21) and 0x9F41, in BCD where
+    /// the tag is numeric
+    pub fn to_tlvs(&self) -> Vec<Tlv> {
+        let bcd = |n: u32| (((n / 10) << 4) | (n % 10)) as u8;
+        let time = [
+            bcd(self.transacted_at.hour()),
+            bcd(self.transacted_at.minute()),
+            bcd(self.transacted_at.second()),
+        ];
+        let counter = format!("{:08}", self.sequence_counter);
+        vec![
+            Tlv::new(&[0x9F, 0x37], self.unpredictable_number.to_vec()),
+            Tlv::new(&[0x9A], self.transaction_date().to_bcd().to_vec()),
+            Tlv::new(&[0x9F, 0x21], time.to_vec()),
+            Tlv::new(
+                &[0x9F, 0x41],
+                hex::decode(counter).expect("counter digits are hex"),
+            ),
+        ]
+    }
+}
+
+/// Produces the data objects the terminal itself contributes to each
+/// transaction, from a pluggable RNG, clock and counter store
+pub struct TerminalContext {
+    random: Box<dyn RandomSource>,
+    clock: Box<dyn Clock>,
+    sequence_store: Box<dyn SequenceStore>,
+}
+
+impl Default for TerminalContext {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TerminalContext {
+    /// System RNG and clock, counter in memory
+    pub fn new() -> Self {
+        Self {
+            random: Box::new(SystemRandom),
+            clock: Box::new(SystemClock),
+            sequence_store: Box::new(MemorySequenceStore::default()),
+        }
+    }
+
+    pub fn with_random(mut self, random: impl RandomSource + 'static) -> Self {
+        self.random = Box::new(random);
+        self
+    }
+
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Box::new(clock);
+        self
+    }
+
+    pub fn with_sequence_store(mut self, store: impl SequenceStore + 'static) -> Self {
+        self.sequence_store = Box::new(store);
+        self
+    }
+
+    /// Fresh Unpredictable Number
+    pub fn unpredictable_number(&mut self) -> Result<[u8; 4], String> {
+        let mut number = [0u8; 4];
+        self.random.fill(&mut number)?;
+        Ok(number)
+    }
+
+    /// Increment and persist the Transaction Sequence Counter, wrapping to
+    /// 1 after 99999999
+    ///
+    /// The new value is stored before it is used so a counter value is
+    /// never sent twice.
+    pub fn next_sequence_counter(&mut self) -> Result<u32, String> {
+        let counter = match self.sequence_store.load()? {
+            last if last >= MAX_SEQUENCE_COUNTER => 1,
+            last => last + 1,
+        };
+        self.sequence_store.store(counter)?;
+        Ok(counter)
+    }
+
+    /// Data for a new transaction
+    pub fn next_transaction(&mut self) -> Result<TerminalData, String> {
+        Ok(TerminalData {
+            unpredictable_number: self.unpredictable_number()?,
+            transacted_at: self.clock.now(),
+            sequence_counter: self.next_sequence_counter()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::dol::Dol;
+
+    fn context() -> TerminalContext {
+        // Shortly after midnight local time, still the previous day in UTC
+        let at = DateTime::parse_from_rfc3339("2026-10-18T01:05:42+08:00").unwrap();
+        TerminalContext::new()
+            .with_random(SeededRandom::new(7))
+            .with_clock(FixedClock(at))
+    }
+
+    #[test]
+    fn test_deterministic_terminal_data() {
+        let first = context().next_transaction().unwrap();
+        assert_eq!(first, context().next_transaction().unwrap());
+        assert_eq!(first.sequence_counter, 1);
+
+        let mut context = context().with_sequence_store(MemorySequenceStore(MAX_SEQUENCE_COUNTER));
+        let data = context.next_transaction().unwrap();
+        assert_eq!(data.sequence_counter, 1);
+        assert_ne!(data.unpredictable_number, [0; 4]);
+        assert_ne!(
+            context.next_transaction().unwrap().unpredictable_number,
+            data.unpredictable_number
+        );
+
+        // Date, time and counter as a PDOL asks for them
+        let pdol = Dol::parse(&hex::decode("9A039F21039F4104").unwrap()).unwrap();
+        assert_eq!(
+            hex::encode_upper(pdol.build(&data.to_tlvs())),
+            "26101801054200000001"
+        );
+    }
+
+    #[test]
+    fn test_file_sequence_store() {
+        let path = std::env::temp_dir().join(format!("tsc-{}", uuid::Uuid::new_v4()));
+        let mut context = context().with_sequence_store(FileSequenceStore::new(&path));
+        assert_eq!(context.next_sequence_counter().unwrap(), 1);
+        assert_eq!(context.next_sequence_counter().unwrap(), 2);
+
+        let mut restarted =
+            TerminalContext::new().with_sequence_store(FileSequenceStore::new(&path));
+        assert_eq!(restarted.next_sequence_counter().unwrap(), 3);
+
+        std::fs::write(&path, "garbage").unwrap();
+        assert!(restarted.next_sequence_counter().is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,4 +1,8 @@
+use des::cipher::generic_array::GenericArray;
+use des::cipher::{BlockDecrypt, BlockEncrypt, KeyInit};
+use des::{Des, TdesEde2};
 use hmac::{Hmac, Mac};
+use num_bigint::BigUint;
 use sha2::{Digest, Sha256};
 
 type HmacSha256 = Hmac<Sha256>;
@@ -10,9 +14,16 @@ pub fn sha256(data: &[u8]) -> String {
     format!("{:x}", hasher.finalize())
 }
 
+/// 计算 SHA-1 哈希（EMV 离线数据认证使用）
+pub fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut hasher = sha1::Sha1::new();
+    hasher.update(data);
+    hasher.finalize().into()
+}
+
 /// 生成 HMAC 签名（返回十六进制字符串）
 pub fn sign_data(data: &[u8], key: &[u8]) -> String {
-    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC can take key of any size");
+    let mut mac = <HmacSha256 as Mac>::new_from_slice(key).expect("HMAC can take key of any size");
     mac.update(data);
     let result = mac.finalize();
     hex::encode(result.into_bytes())
@@ -23,3 +34,119 @@ pub fn verify_signature(data: &[u8], signature: &str, key: &[u8]) -> bool {
     let expected = sign_data(data, key);
     expected == signature
 }
+
+/// RSA 公钥（模数与公钥指数，大端字节）
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct RsaPublicKey {
+    pub modulus: Vec<u8>,
+    pub exponent: Vec<u8>,
+}
+
+impl RsaPublicKey {
+    /// 模数长度（字节）
+    pub fn modulus_len(&self) -> usize {
+        self.modulus.iter().skip_while(|b| **b == 0).count()
+    }
+}
+
+/// RSA 公钥运算 data^e mod n，输出按模数长度左侧补零
+pub fn rsa_public(data: &[u8], key: &RsaPublicKey) -> Result<Vec<u8>, String> {
+    let n = BigUint::from_bytes_be(&key.modulus);
+    let e = BigUint::from_bytes_be(&key.exponent);
+    let m = BigUint::from_bytes_be(data);
+    if m >= n {
+        return Err("RSA input is not smaller than modulus".to_string());
+    }
+
+    let out = m.modpow(&e, &n).to_bytes_be();
+    let len = key.modulus_len();
+    let mut result = vec![0u8; len.saturating_sub(out.len())];
+    result.extend_from_slice(&out);
+    Ok(result)
+}
+
+/// EMV 通用会话密钥推导（EMV Book 2 Annex A1.3），以 ATC 作为分散数据
+pub fn derive_session_key(master_key: &[u8; 16], atc: u16) -> [u8; 16] {
+    let cipher = TdesEde2::new(GenericArray::from_slice(master_key));
+    let mut session_key = [0u8; 16];
+    for (half, marker) in session_key.chunks_mut(8).zip([0xF0, 0x0F]) {
+        let mut block = GenericArray::clone_from_slice(&[
+            (atc >> 8) as u8,
+            atc as u8,
+            marker,
+            0x00,
+            0x00,
+            0x00,
+            0x00,
+            0x00,
+        ]);
+        cipher.encrypt_block(&mut block);
+        half.copy_from_slice(&block);
+    }
+    session_key
+}
+
+/// ISO 9797-1 MAC 算法 3（Retail MAC，填充方式 2），双倍长 DES 密钥
+pub fn retail_mac(key: &[u8; 16], data: &[u8]) -> [u8; 8] {
+    let left = Des::new(GenericArray::from_slice(&key[..8]));
+    let right = Des::new(GenericArray::from_slice(&key[8..]));
+
+    let mut padded = data.to_vec();
+    padded.push(0x80);
+    padded.resize(padded.len().div_ceil(8) * 8, 0x00);
+
+    let mut mac = GenericArray::clone_from_slice(&[0u8; 8]);
+    for block in padded.chunks(8) {
+        for (m, b) in mac.iter_mut().zip(block) {
+            *m ^= b;
+        }
+        left.encrypt_block(&mut mac);
+    }
+    right.decrypt_block(&mut mac);
+    left.encrypt_block(&mut mac);
+    mac.into()
+}
+
+/// 应用密文（ARQC/TC/AAC，EMV Book 2 Section 8.1）：以 ATC 会话密钥对交易数据计算 MAC
+pub fn application_cryptogram(master_key: &[u8; 16], atc: u16, data: &[u8]) -> [u8; 8] {
+    retail_mac(&derive_session_key(master_key, atc), data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retail_mac() {
+        // 单个分组时 Retail MAC 即双倍长 3DES 加密
+        let key: [u8; 16] = hex::decode("0123456789ABCDEFFEDCBA9876543210")
+            .unwrap()
+            .try_into()
+            .unwrap();
+        let mut block = GenericArray::clone_from_slice(&hex::decode("4E6F772069738000").unwrap());
+        TdesEde2::new(GenericArray::from_slice(&key)).encrypt_block(&mut block);
+        assert_eq!(retail_mac(&key, b"Now is"), block.as_slice());
+
+        // 已知答案（以 openssl des-ede/des-cbc 独立计算）：ATC 0x002A 的会话密钥
+        let session_key = derive_session_key(&key, 0x002A);
+        assert_eq!(
+            hex::encode_upper(session_key),
+            "ED92685416E9205C595CF5F72EF7BE3E"
+        );
+        // 17 字节数据填充为三个分组
+        let data = hex::decode("00000000100008400000000000A1B2C3D4").unwrap();
+        assert_eq!(
+            hex::encode_upper(retail_mac(&session_key, &data)),
+            "BA5C31C5CD1DDA17"
+        );
+        assert_eq!(
+            application_cryptogram(&key, 0x002A, &data),
+            retail_mac(&session_key, &data)
+        );
+
+        assert_ne!(
+            application_cryptogram(&key, 1, b"data"),
+            application_cryptogram(&key, 2, b"data")
+        );
+    }
+}
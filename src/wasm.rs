@@ -1,7 +1,20 @@
-use crate::models::emv::{ApduCommand, ApduResponse};
+use crate::models::currency::{Amount, Currency};
+use crate::models::dol::Dol;
+use crate::models::emv::{ApduCommand, ApduResponse, CryptogramType, Tlv};
+use crate::models::issuer_script::{IssuerScript, ScriptTiming};
+use crate::models::secret::{expose, SecretString};
+use crate::models::track::Track2;
+use crate::models::tvr::{Tsi, Tvr};
+use crate::services::action_analysis::IssuerActionCodes;
+use crate::services::completion::{CompletionStep, IssuerResponse, OnlineCompletion, OnlineResult};
 use crate::services::emv_processor::EmvProcessor;
+use crate::services::field55::Field55Spec;
+use crate::services::iad::IadDecoders;
+use crate::services::offline_pin::PinVerifyResult;
+use crate::utils::crypto::RsaPublicKey;
 use serde::{Deserialize, Serialize};
 use wasm_bindgen::prelude::*;
+use zeroize::Zeroizing;
 
 // Import console.log from JavaScript
 #[wasm_bindgen]
@@ -22,6 +35,8 @@ macro_rules! console_log {
 #[wasm_bindgen]
 pub struct WasmEmvProcessor {
     processor: EmvProcessor,
+    /// Online completion in progress, from secondGenerateAc
+    completion: Option<OnlineCompletion>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -46,7 +61,9 @@ pub struct ApduCommandResult {
     pub ins: u8,
     pub p1: u8,
     pub p2: u8,
-    pub data: Option<String>,
+    /// Command data, hex; VERIFY carries the PIN block
+    #[serde(serialize_with = "expose::serialize")]
+    pub data: Option<SecretString>,
     pub le: Option<u8>,
 }
 
@@ -57,7 +74,10 @@ impl From<ApduCommand> for ApduCommandResult {
             ins: cmd.ins,
             p1: cmd.p1,
             p2: cmd.p2,
-            data: cmd.data.map(hex::encode),
+            data: cmd
+                .data
+                .as_deref()
+                .map(|data| SecretString::new(hex::encode(data))),
             le: cmd.le,
         }
     }
@@ -71,6 +91,7 @@ impl WasmEmvProcessor {
         console_log!("[WASM Kernel] Initializing EMV processor (Country: {}, Currency: {})", country_code, currency_code);
         Self {
             processor: EmvProcessor::new(country_code, currency_code),
+            completion: None,
         }
     }
 
@@ -131,22 +152,243 @@ impl WasmEmvProcessor {
     }
 
     /// Generate AC (Application Cryptogram)
+    ///
+    /// The cryptogram type is decided by Terminal Action Analysis from the
+    /// TVR and the card's Issuer Action Codes (empty string when absent).
     #[wasm_bindgen(js_name = generateAc)]
-    pub fn generate_ac(&self, ac_type: u8, cdol_hex: String) -> Result<JsValue, JsValue> {
-        console_log!("[WASM Kernel] GENERATE AC: Type={:02X}, CDOL={}", ac_type, cdol_hex);
+    pub fn generate_ac(
+        &self,
+        tvr_hex: String,
+        iac_default_hex: String,
+        iac_denial_hex: String,
+        iac_online_hex: String,
+        cdol_hex: String,
+    ) -> Result<JsValue, JsValue> {
+        console_log!("[WASM Kernel] GENERATE AC: TVR={}, CDOL={}", tvr_hex, cdol_hex);
         let cdol_data = hex::decode(&cdol_hex)
             .map_err(|e| {
                 console_log!("[WASM Kernel] ERROR: Invalid CDOL hex: {}", e);
                 JsValue::from_str(&format!("Invalid CDOL hex: {}", e))
             })?;
 
-        let cmd = self.processor.generate_ac(ac_type, &cdol_data);
-        console_log!("[WASM Kernel] AC Command: CLA={:02X} INS={:02X} P1={:02X} P2={:02X}", 
-            cmd.cla, cmd.ins, cmd.p1, cmd.p2);
+        let tvr = Tvr::from_hex(&tvr_hex).map_err(|e| JsValue::from_str(&e))?;
+        let iac = IssuerActionCodes {
+            default: decode_action_code(&iac_default_hex)?,
+            denial: decode_action_code(&iac_denial_hex)?,
+            online: decode_action_code(&iac_online_hex)?,
+        };
+
+        let (decision, cmd) = self.processor.first_generate_ac(&tvr, &iac, &cdol_data);
+        console_log!("[WASM Kernel] AC Command: CLA={:02X} INS={:02X} P1={:02X} P2={:02X} ({:?})", 
+            cmd.cla, cmd.ins, cmd.p1, cmd.p2, decision.reason);
         let result: ApduCommandResult = cmd.into();
         Ok(serde_wasm_bindgen::to_value(&result).unwrap())
     }
 
+    /// Parse the card's GENERATE AC response
+    ///
+    /// `requested_p1` is the P1 of the GENERATE AC command that was sent.
+    #[wasm_bindgen(js_name = parseGenerateAcResponse)]
+    pub fn parse_generate_ac_response(
+        &self,
+        response_hex: String,
+        requested_p1: u8,
+    ) -> Result<JsValue, JsValue> {
+        console_log!("[WASM Kernel] PARSE GENERATE AC RESPONSE: P1={:02X}", requested_p1);
+        let bytes = hex::decode(&response_hex)
+            .map_err(|e| JsValue::from_str(&format!("Invalid response hex: {}", e)))?;
+        let response = ApduResponse::from_bytes(&bytes).map_err(|e| JsValue::from_str(&e))?;
+        let requested = CryptogramType::from_bits(requested_p1).map_err(|e| JsValue::from_str(&e))?;
+
+        let parsed = self
+            .processor
+            .parse_generate_ac_response(&response, requested)
+            .map_err(|e| {
+                console_log!("[WASM Kernel] ERROR: Invalid GENERATE AC response: {}", e);
+                JsValue::from_str(&e)
+            })?;
+        Ok(serde_wasm_bindgen::to_value(&parsed).unwrap())
+    }
+
+    /// EXTERNAL AUTHENTICATE with the Issuer Authentication Data (tag 0x91)
+    #[wasm_bindgen(js_name = externalAuthenticate)]
+    pub fn external_authenticate(&self, iad_hex: String) -> Result<JsValue, JsValue> {
+        console_log!("[WASM Kernel] EXTERNAL AUTHENTICATE: IAD={}", iad_hex);
+        let iad = hex::decode(&iad_hex)
+            .map_err(|e| JsValue::from_str(&format!("Invalid IAD hex: {}", e)))?;
+        let result: ApduCommandResult = self.processor.external_authenticate(&iad).into();
+        Ok(serde_wasm_bindgen::to_value(&result).unwrap())
+    }
+
+    /// Second GENERATE AC after online authorisation
+    ///
+    /// `arc` is the issuer's Authorisation Response Code, or an empty string
+    /// when the terminal was unable to go online (default action analysis
+    /// then decides). `data_hex` holds TLV-coded data objects to fill CDOL2
+    /// and `scripts_hex` the Issuer Script Templates 2 (tag 0x72) to deliver
+    /// after the second GENERATE AC, or an empty string. Pass each card
+    /// response to `completionResponse` until the flow completes.
+    #[wasm_bindgen(js_name = secondGenerateAc)]
+    #[allow(clippy::too_many_arguments)]
+    pub fn second_generate_ac(
+        &mut self,
+        arc: String,
+        tvr_hex: String,
+        tsi_hex: String,
+        iac_default_hex: String,
+        cdol2_hex: String,
+        data_hex: String,
+        scripts_hex: String,
+    ) -> Result<JsValue, JsValue> {
+        console_log!(
+            "[WASM Kernel] SECOND GENERATE AC: ARC={}, TVR={}, TSI={}",
+            arc,
+            tvr_hex,
+            tsi_hex
+        );
+        let tvr = Tvr::from_hex(&tvr_hex).map_err(|e| JsValue::from_str(&e))?;
+        let tsi = Tsi::from_hex(&tsi_hex).map_err(|e| JsValue::from_str(&e))?;
+        let cdol2 = hex::decode(&cdol2_hex)
+            .map_err(|e| JsValue::from_str(&format!("Invalid CDOL2 hex: {}", e)))
+            .and_then(|bytes| Dol::parse(&bytes).map_err(|e| JsValue::from_str(&e)))?;
+        let data = hex::decode(&data_hex)
+            .map_err(|e| JsValue::from_str(&format!("Invalid data hex: {}", e)))
+            .and_then(|bytes| Tlv::parse(&bytes).map_err(|e| JsValue::from_str(&e)))?;
+        let iac = IssuerActionCodes {
+            default: decode_action_code(&iac_default_hex)?,
+            ..Default::default()
+        };
+
+        let scripts = hex::decode(&scripts_hex)
+            .map_err(|e| JsValue::from_str(&format!("Invalid issuer script hex: {}", e)))
+            .and_then(|bytes| IssuerScript::parse_all(&bytes).map_err(|e| JsValue::from_str(&e)))?;
+        if scripts
+            .iter()
+            .any(|script| script.timing != ScriptTiming::AfterFinalAc)
+        {
+            return Err(JsValue::from_str(
+                "Only Issuer Script Template 2 (tag 0x72) is sent after the second GENERATE AC",
+            ));
+        }
+
+        let result = if arc.is_empty() {
+            OnlineResult::UnableToGoOnline
+        } else {
+            OnlineResult::Authorised(IssuerResponse {
+                authorisation_response_code: <[u8; 2]>::try_from(arc.as_bytes())
+                    .map_err(|_| JsValue::from_str("ARC must be 2 characters"))?,
+                issuer_authentication_data: None,
+                issuer_scripts: scripts,
+            })
+        };
+
+        // Issuer authentication and Template 1 scripts, if any, are sent
+        // beforehand with externalAuthenticate and parseIssuerScripts
+        let mut completion = self
+            .processor
+            .online_completion(&[], cdol2, data, iac, tvr, tsi);
+        let step = completion.start(result).map_err(|e| JsValue::from_str(&e))?;
+        self.completion = Some(completion);
+        Ok(serde_wasm_bindgen::to_value(&step).unwrap())
+    }
+
+    /// Feed the card's response to the last command of online completion:
+    /// the second GENERATE AC, then each Template 2 script command
+    #[wasm_bindgen(js_name = completionResponse)]
+    pub fn completion_response(&mut self, response_hex: String) -> Result<JsValue, JsValue> {
+        console_log!("[WASM Kernel] COMPLETION RESPONSE: {}", response_hex);
+        let bytes = hex::decode(&response_hex)
+            .map_err(|e| JsValue::from_str(&format!("Invalid response hex: {}", e)))?;
+        let response = ApduResponse::from_bytes(&bytes).map_err(|e| JsValue::from_str(&e))?;
+        let completion = self
+            .completion
+            .as_mut()
+            .ok_or_else(|| JsValue::from_str("No online completion in progress"))?;
+        let step = completion
+            .on_response(&response)
+            .map_err(|e| JsValue::from_str(&e))?;
+        if matches!(step, CompletionStep::Complete(_)) {
+            self.completion = None;
+        }
+        Ok(serde_wasm_bindgen::to_value(&step).unwrap())
+    }
+
+    /// Split Issuer Script Templates (tags 0x71/0x72) into individual commands
+    #[wasm_bindgen(js_name = parseIssuerScripts)]
+    pub fn parse_issuer_scripts(&self, scripts_hex: String) -> Result<JsValue, JsValue> {
+        console_log!("[WASM Kernel] PARSE ISSUER SCRIPTS: {}", scripts_hex);
+        let bytes = hex::decode(&scripts_hex)
+            .map_err(|e| JsValue::from_str(&format!("Invalid issuer script hex: {}", e)))?;
+        let scripts = IssuerScript::parse_all(&bytes).map_err(|e| JsValue::from_str(&e))?;
+        Ok(serde_wasm_bindgen::to_value(&scripts).unwrap())
+    }
+
+    /// GET DATA for a primitive tag (e.g. 0x9F17 PIN Try Counter)
+    #[wasm_bindgen(js_name = getData)]
+    pub fn get_data(&self, tag: u16) -> JsValue {
+        console_log!("[WASM Kernel] GET DATA: Tag={:04X}", tag);
+        let result: ApduCommandResult = self.processor.get_data(tag).into();
+        serde_wasm_bindgen::to_value(&result).unwrap()
+    }
+
+    /// GET CHALLENGE
+    #[wasm_bindgen(js_name = getChallenge)]
+    pub fn get_challenge(&self) -> JsValue {
+        console_log!("[WASM Kernel] GET CHALLENGE");
+        let result: ApduCommandResult = self.processor.get_challenge().into();
+        serde_wasm_bindgen::to_value(&result).unwrap()
+    }
+
+    /// VERIFY with plaintext offline PIN
+    #[wasm_bindgen(js_name = verifyPlaintextPin)]
+    pub fn verify_plaintext_pin(&self, pin: String) -> Result<JsValue, JsValue> {
+        console_log!("[WASM Kernel] VERIFY (plaintext PIN)");
+        let pin = Zeroizing::new(pin);
+        let cmd = self
+            .processor
+            .verify_plaintext_pin(&pin)
+            .map_err(|e| JsValue::from_str(&e))?;
+        let result: ApduCommandResult = cmd.into();
+        Ok(serde_wasm_bindgen::to_value(&result).unwrap())
+    }
+
+    /// VERIFY with enciphered offline PIN
+    #[wasm_bindgen(js_name = verifyEncipheredPin)]
+    pub fn verify_enciphered_pin(
+        &self,
+        pin: String,
+        challenge_hex: String,
+        modulus_hex: String,
+        exponent_hex: String,
+    ) -> Result<JsValue, JsValue> {
+        console_log!("[WASM Kernel] VERIFY (enciphered PIN)");
+        let pin = Zeroizing::new(pin);
+        let decode = |value: &str| {
+            hex::decode(value).map_err(|e| JsValue::from_str(&format!("Invalid hex: {}", e)))
+        };
+        let key = RsaPublicKey {
+            modulus: decode(&modulus_hex)?,
+            exponent: decode(&exponent_hex)?,
+        };
+        let cmd = self
+            .processor
+            .verify_enciphered_pin(&pin, &decode(&challenge_hex)?, &key)
+            .map_err(|e| JsValue::from_str(&e))?;
+        let result: ApduCommandResult = cmd.into();
+        Ok(serde_wasm_bindgen::to_value(&result).unwrap())
+    }
+
+    /// Interpret a VERIFY response status word
+    #[wasm_bindgen(js_name = parseVerifyResponse)]
+    pub fn parse_verify_response(&self, response_hex: String) -> Result<JsValue, JsValue> {
+        let bytes = hex::decode(&response_hex)
+            .map_err(|e| JsValue::from_str(&format!("Invalid response hex: {}", e)))?;
+        let response = ApduResponse::from_bytes(&bytes).map_err(|e| JsValue::from_str(&e))?;
+        let result = PinVerifyResult::from_response(&response);
+        console_log!("[WASM Kernel] VERIFY result: {:?}", result);
+        Ok(serde_wasm_bindgen::to_value(&result).unwrap())
+    }
+
     /// Parse card data from TLV response
     #[wasm_bindgen(js_name = parseCardData)]
     pub fn parse_card_data(&self, tlv_hex: String, aid: String) -> Result<JsValue, JsValue> {
@@ -170,6 +412,83 @@ impl WasmEmvProcessor {
     }
 }
 
+/// Decode a 5-byte action code from hex; empty input means absent
+fn decode_action_code(value: &str) -> Result<Option<[u8; 5]>, JsValue> {
+    if value.is_empty() {
+        return Ok(None);
+    }
+    let bytes = hex::decode(value)
+        .map_err(|e| JsValue::from_str(&format!("Invalid action code hex: {}", e)))?;
+    <[u8; 5]>::try_from(bytes.as_slice())
+        .map(Some)
+        .map_err(|_| JsValue::from_str("Action codes must be 5 bytes"))
+}
+
+/// Break Issuer Application Data (tag 0x9F10) into scheme-specific fields
+#[wasm_bindgen(js_name = decodeIad)]
+pub fn decode_iad(aid_hex: String, iad_hex: String) -> Result<JsValue, JsValue> {
+    let aid =
+        hex::decode(&aid_hex).map_err(|e| JsValue::from_str(&format!("Invalid AID hex: {}", e)))?;
+    let iad =
+        hex::decode(&iad_hex).map_err(|e| JsValue::from_str(&format!("Invalid IAD hex: {}", e)))?;
+    let decoded = IadDecoders::default()
+        .decode(&aid, &iad)
+        .map_err(|e| JsValue::from_str(&e))?;
+    Ok(serde_wasm_bindgen::to_value(&decoded).unwrap())
+}
+
+/// List the TVR bits that are set, in plain language
+#[wasm_bindgen(js_name = explainTvr)]
+pub fn explain_tvr(tvr_hex: String) -> Result<JsValue, JsValue> {
+    let tvr = Tvr::from_hex(&tvr_hex).map_err(|e| JsValue::from_str(&e))?;
+    Ok(serde_wasm_bindgen::to_value(&tvr.explain()).unwrap())
+}
+
+/// List the TSI bits that are set, in plain language
+#[wasm_bindgen(js_name = explainTsi)]
+pub fn explain_tsi(tsi_hex: String) -> Result<JsValue, JsValue> {
+    let tsi = Tsi::from_hex(&tsi_hex).map_err(|e| JsValue::from_str(&e))?;
+    Ok(serde_wasm_bindgen::to_value(&tsi.explain()).unwrap())
+}
+
+/// Parse Track 2 Equivalent Data (tag 0x57) into PAN, expiry and service
+/// code; the discretionary data is redacted
+#[wasm_bindgen(js_name = parseTrack2)]
+pub fn parse_track2(track2_hex: String) -> Result<JsValue, JsValue> {
+    let data = hex::decode(&track2_hex)
+        .map_err(|e| JsValue::from_str(&format!("Invalid Track 2 hex: {}", e)))?;
+    let track = Track2::parse(&data).map_err(|e| JsValue::from_str(&e))?;
+    Ok(serde_wasm_bindgen::to_value(&track).unwrap())
+}
+
+/// Assemble ISO 8583 field 55 for the scheme of an AID from the kernel's
+/// data record, both hex
+#[wasm_bindgen(js_name = assembleField55)]
+pub fn assemble_field55(data_record_hex: String, aid_hex: String) -> Result<String, JsValue> {
+    let data = hex::decode(&data_record_hex)
+        .map_err(|e| e.to_string())
+        .and_then(|data| Tlv::parse_flattened(&data))
+        .map_err(|e| JsValue::from_str(&format!("Invalid data record: {}", e)))?;
+    let aid =
+        hex::decode(&aid_hex).map_err(|e| JsValue::from_str(&format!("Invalid AID hex: {}", e)))?;
+    let field55 = Field55Spec::for_aid(&aid)
+        .assemble(&data)
+        .map_err(|e| JsValue::from_str(&e))?;
+    Ok(hex::encode_upper(field55))
+}
+
+/// Format an amount in minor units for display, e.g. `formatAmount(123456, "EUR", "de-DE")`
+#[wasm_bindgen(js_name = formatAmount)]
+pub fn format_amount(amount: f64, currency: String, locale: String) -> Result<String, JsValue> {
+    let currency = Currency::find(&currency)
+        .ok_or_else(|| JsValue::from_str(&format!("Unknown currency: {}", currency)))?;
+    if amount < 0.0 || amount.fract() != 0.0 {
+        return Err(JsValue::from_str(&format!("Invalid amount: {}", amount)));
+    }
+    let amount = Amount::new(amount as u64, currency).map_err(|e| JsValue::from_str(&e))?;
+    Ok(amount.format(&locale))
+}
+
 /// Get the version of the kernel
 #[wasm_bindgen(js_name = getVersion)]
 pub fn get_version() -> String {